@@ -9,6 +9,8 @@ use std::fs::File;
 use std::io::{sink, BufReader};
 use std::time::Duration;
 
+use brrrr_lib::rejects::Rejects;
+
 extern crate brrrr_lib;
 
 fn criterion_benchmark(c: &mut Criterion) {
@@ -18,7 +20,12 @@ fn criterion_benchmark(c: &mut Criterion) {
         b.iter(|| {
             let filename = format!("./{}/10000.fasta", path);
             let f = File::open(filename).expect("Error opening file.");
-            let _ = brrrr_lib::json_writer::fa2jsonl(BufReader::new(f), &mut sink());
+            let _ = brrrr_lib::json_writer::fa2jsonl(
+                BufReader::new(f),
+                &mut sink(),
+                None,
+                &mut Rejects::default(),
+            );
         })
     });
 }
@@ -67,8 +74,9 @@ fn bench_fasta_parquet_output(c: &mut Criterion) {
                     let _ = brrrr_lib::parquet_writer::fa2pq(
                         &filename,
                         &out_file,
-                        pq_compression,
-                        f_compression,
+                        &pq_compression.into(),
+                        Some(f_compression),
+                        &mut Rejects::default(),
                     );
                 })
             },
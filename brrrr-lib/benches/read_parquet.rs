@@ -0,0 +1,76 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use parquet::file::properties::WriterProperties;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+
+use brrrr_lib::columns::ColumnMap;
+use brrrr_lib::input;
+use brrrr_lib::parquet_reader::ParquetReadOptions;
+use brrrr_lib::parquet_writer::ParquetRecordWriter;
+use brrrr_lib::reader::FastaReader;
+use brrrr_lib::rejects::Rejects;
+use brrrr_lib::writer::RecordWriter;
+
+extern crate brrrr_lib;
+
+fn bench_parquet_fasta_output(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bench_parquet_fasta_output");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(30))
+        .throughput(Throughput::Elements(10000));
+
+    let path: &'static str = env!("BENCH_DATA");
+    let temp_path = env::temp_dir();
+
+    // Write the records in row groups of 1000, so that there's more than one to read at once.
+    let filename = format!("./{}/10000.fasta.gz", path);
+    let parquet_file = temp_path.join("10000.fasta.parquet");
+
+    let f = File::open(filename).expect("Error opening file.");
+    let props = WriterProperties::builder()
+        .set_max_row_group_size(1000)
+        .build();
+    let mut writer = ParquetRecordWriter::new(
+        File::create(&parquet_file).expect("Error creating file."),
+        props,
+    );
+    let reader = FastaReader::new(input::decode(BufReader::new(f), None).unwrap());
+    for record in reader {
+        writer.write_serde_record(record.unwrap()).unwrap();
+    }
+    writer.close().unwrap();
+
+    for threads in [1, 4] {
+        group.bench_function(
+            format!("Write PQ to FA, {} threads", threads).as_str(),
+            |b| {
+                b.iter(|| {
+                    let out_file = temp_path.join("10000.parquet.fasta");
+                    let options = ParquetReadOptions {
+                        threads,
+                        ..Default::default()
+                    };
+
+                    let _ = brrrr_lib::parquet_reader::pq2fa(
+                        &parquet_file,
+                        &out_file,
+                        &ColumnMap::new(),
+                        None,
+                        &options,
+                        &mut Rejects::default(),
+                    );
+                })
+            },
+        );
+    }
+}
+
+criterion_group!(benches, bench_parquet_fasta_output);
+criterion_main!(benches);
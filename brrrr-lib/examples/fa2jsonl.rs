@@ -4,8 +4,9 @@
 use std::io::stdout;
 
 use brrrr_lib::json_writer::fa2jsonl;
+use brrrr_lib::rejects::Rejects;
 
 fn main() {
     let example_input = b">A\nATCG\n>B\nGCTA" as &[u8];
-    fa2jsonl(example_input, &mut stdout()).expect("Error... :(");
+    fa2jsonl(example_input, &mut stdout(), None, &mut Rejects::default()).expect("Error... :(");
 }
@@ -0,0 +1,469 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use arrow::array::*;
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::{DataType, Field};
+use arrow::ipc::reader::StreamReader;
+use arrow::record_batch::RecordBatch;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use noodles::fasta;
+use noodles::fastq;
+
+use crate::errors::BrrrrError;
+use crate::gff;
+use crate::parquet_writer::{GFF_DIALECT_METADATA_KEY, GFF_HEADER_METADATA_KEY};
+use crate::rejects::Rejects;
+use crate::types::{FastaRecord, FastqRecord, GffRecord, Quality};
+
+/// The magic bytes at the start of an Arrow IPC file, padded to eight bytes.
+const FILE_MAGIC: &[u8] = b"ARROW1\0\0";
+
+/// Returns a reader over the record batches of an Arrow IPC file or stream. A file holds the
+/// stream after its magic bytes, so both are read as a stream without seeking, e.g. from stdin.
+pub fn read_batches<R: BufRead>(mut input: R) -> Result<StreamReader<R>, BrrrrError> {
+    if input.fill_buf()?.starts_with(FILE_MAGIC) {
+        input.consume(FILE_MAGIC.len());
+    }
+
+    Ok(StreamReader::try_new(input, None)?)
+}
+
+/// Returns the column `name` cast to `data_type`, or None if the batch has no such column.
+pub(crate) fn optional_column(
+    batch: &RecordBatch,
+    name: &str,
+    data_type: &DataType,
+) -> Result<Option<ArrayRef>, BrrrrError> {
+    match batch.schema().column_with_name(name) {
+        Some((i, field)) if field.data_type() == data_type => Ok(Some(batch.column(i).clone())),
+        Some((i, field)) => match cast(batch.column(i), data_type) {
+            Ok(array) => Ok(Some(array)),
+            Err(_) => Err(BrrrrError::SchemaMismatch {
+                column: name.to_string(),
+                expected: format!("{:?}", data_type),
+                found: format!("{:?}", field.data_type()),
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns the column `name` as it is written.
+fn raw_column(batch: &RecordBatch, name: &str) -> Result<ArrayRef, BrrrrError> {
+    match batch.schema().column_with_name(name) {
+        Some((i, _)) => Ok(batch.column(i).clone()),
+        None => Err(BrrrrError::MissingColumn(name.to_string())),
+    }
+}
+
+fn column(batch: &RecordBatch, name: &str, data_type: &DataType) -> Result<ArrayRef, BrrrrError> {
+    optional_column(batch, name, data_type)?
+        .ok_or_else(|| BrrrrError::MissingColumn(name.to_string()))
+}
+
+pub(crate) fn downcast<'a, T: 'static>(
+    array: &'a ArrayRef,
+    name: &str,
+) -> Result<&'a T, BrrrrError> {
+    array
+        .as_any()
+        .downcast_ref::<T>()
+        .ok_or_else(|| BrrrrError::SchemaMismatch {
+            column: name.to_string(),
+            expected: std::any::type_name::<T>()
+                .rsplit("::")
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            found: format!("{:?}", array.data_type()),
+        })
+}
+
+pub(crate) fn string_value(array: &StringArray, i: usize) -> Option<String> {
+    if array.is_null(i) {
+        None
+    } else {
+        Some(array.value(i).to_string())
+    }
+}
+
+/// Returns the column `name` of `batch` as lists of text, or None if the batch has no such
+/// column.
+pub(crate) fn optional_string_lists(
+    batch: &RecordBatch,
+    name: &str,
+) -> Result<Option<ListArray>, BrrrrError> {
+    let item = Field::new("item", DataType::Utf8, true);
+    let column = optional_column(batch, name, &DataType::List(Box::new(item)))?;
+    Ok(column.map(|column| ListArray::from(column.data().clone())))
+}
+
+/// Returns the non-null values of the list at `i` of `array`, which is empty if the list is null.
+pub(crate) fn string_list_value(array: &ListArray, i: usize) -> Result<Vec<String>, BrrrrError> {
+    if array.is_null(i) {
+        return Ok(Vec::new());
+    }
+
+    let values = array.value(i);
+    let values = downcast::<StringArray>(&values, "list")?;
+    Ok((0..values.len())
+        .filter_map(|j| string_value(values, j))
+        .collect())
+}
+
+/// Returns the non-null value at `i` of `array`, read from `column`.
+fn required_string_value(array: &StringArray, column: &str, i: usize) -> io::Result<String> {
+    string_value(array, i).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is null in row {}", column, i + 1),
+        )
+    })
+}
+
+/// Returns the FASTA records of a batch with the columns of fa2pq or fa2arrow. A null ID or
+/// sequence is an InvalidData error.
+pub fn fasta_records(batch: &RecordBatch) -> Result<Vec<FastaRecord>, BrrrrError> {
+    let id = column(batch, "id", &DataType::Utf8)?;
+    let id = downcast::<StringArray>(&id, "id")?;
+    let description = optional_column(batch, "description", &DataType::Utf8)?;
+    let description = description
+        .as_ref()
+        .map(|d| downcast::<StringArray>(d, "description"))
+        .transpose()?;
+    let sequence = column(batch, "sequence", &DataType::Utf8)?;
+    let sequence = downcast::<StringArray>(&sequence, "sequence")?;
+
+    (0..batch.num_rows())
+        .map(|i| {
+            Ok(FastaRecord {
+                id: required_string_value(id, "id", i)?,
+                description: description.and_then(|d| string_value(d, i)),
+                sequence: required_string_value(sequence, "sequence", i)?,
+            })
+        })
+        .collect()
+}
+
+/// Returns the FASTQ records of a batch with the columns of fq2pq or fq2arrow. The quality is
+/// either an ASCII string or a list of Phred scores.
+pub fn fastq_records(batch: &RecordBatch) -> Result<Vec<FastqRecord>, BrrrrError> {
+    let records = fasta_records(batch)?;
+
+    let quality = raw_column(batch, "quality")?;
+    let (phred, ascii) = match quality.data_type() {
+        DataType::List(_) => (Some(downcast::<ListArray>(&quality, "quality")?), None),
+        _ => (None, Some(cast(&quality, &DataType::Utf8)?)),
+    };
+    let ascii = ascii
+        .as_ref()
+        .map(|a| downcast::<StringArray>(a, "quality"))
+        .transpose()?;
+
+    let quality_at = |i: usize| -> Result<Quality, BrrrrError> {
+        match (phred, ascii) {
+            (Some(phred), _) => {
+                let scores = cast(&phred.value(i), &DataType::UInt8)?;
+                let scores = downcast::<UInt8Array>(&scores, "quality")?;
+                Ok(Quality::Phred(scores.values().to_vec()))
+            }
+            (_, Some(ascii)) => Ok(Quality::Ascii(ascii.value(i).to_string())),
+            (None, None) => Err(BrrrrError::MissingColumn(String::from("quality"))),
+        }
+    };
+
+    records
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| {
+            Ok(FastqRecord {
+                id: record.id,
+                description: record.description,
+                sequence: record.sequence,
+                quality: quality_at(i)?,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Returns the GFF records of a batch with the columns of gff2pq or gff2arrow.
+pub fn gff_records(batch: &RecordBatch) -> Result<Vec<GffRecord>, BrrrrError> {
+    let strings =
+        |name: &str| -> Result<ArrayRef, BrrrrError> { column(batch, name, &DataType::Utf8) };
+
+    let seqname = strings("seqname")?;
+    let source = strings("source")?;
+    let feature = strings("feature")?;
+    let strand = strings("strand")?;
+    let frame = strings("frame")?;
+    let start = column(batch, "start", &DataType::Int64)?;
+    let end = column(batch, "end", &DataType::Int64)?;
+    let score = column(batch, "score", &DataType::Float64)?;
+    let attribute = raw_column(batch, "attribute")?;
+    let unquoted = optional_string_lists(batch, "unquoted_attributes")?;
+    let directives = optional_string_lists(batch, "directives")?;
+
+    let seqname = downcast::<StringArray>(&seqname, "seqname")?;
+    let source = downcast::<StringArray>(&source, "source")?;
+    let feature = downcast::<StringArray>(&feature, "feature")?;
+    let strand = downcast::<StringArray>(&strand, "strand")?;
+    let frame = downcast::<StringArray>(&frame, "frame")?;
+    let start = downcast::<Int64Array>(&start, "start")?;
+    let end = downcast::<Int64Array>(&end, "end")?;
+    let score = downcast::<Float64Array>(&score, "score")?;
+    let attribute = downcast::<MapArray>(&attribute, "attribute")?;
+
+    let keys = attribute.keys();
+    let keys = downcast::<StringArray>(&keys, "attribute")?;
+    let values = attribute.values();
+    let values = downcast::<ListArray>(&values, "attribute")?;
+
+    let mut records = Vec::with_capacity(batch.num_rows());
+
+    for i in 0..batch.num_rows() {
+        let offsets = attribute.value_offsets();
+        let mut attributes = IndexMap::new();
+
+        for j in offsets[i] as usize..offsets[i + 1] as usize {
+            let entry_values = values.value(j);
+            let entry_values = downcast::<StringArray>(&entry_values, "attribute")?;
+            let entry_values = (0..entry_values.len())
+                .filter_map(|k| string_value(entry_values, k))
+                .collect();
+
+            attributes.insert(keys.value(j).to_string(), entry_values);
+        }
+
+        records.push(GffRecord {
+            seqname: seqname.value(i).to_string(),
+            source: source.value(i).to_string(),
+            feature: feature.value(i).to_string(),
+            start: start.value(i) as usize,
+            end: end.value(i) as usize,
+            score: if score.is_null(i) {
+                None
+            } else {
+                Some(score.value(i))
+            },
+            strand: string_value(strand, i).unwrap_or_else(|| String::from(".")),
+            frame: string_value(frame, i),
+            attribute: attributes,
+            unquoted_attributes: match &unquoted {
+                Some(unquoted) => string_list_value(unquoted, i)?,
+                None => Vec::new(),
+            },
+            directives: match &directives {
+                Some(directives) => string_list_value(directives, i)?,
+                None => Vec::new(),
+            },
+        });
+    }
+
+    Ok(records)
+}
+
+/// Returns the GFF header stored in the schema metadata by gff2arrow.
+fn gff_header(metadata: &HashMap<String, String>) -> Result<gff::Header, BrrrrError> {
+    let dialect = metadata
+        .get(GFF_DIALECT_METADATA_KEY)
+        .map(|d| d.parse::<gff::Dialect>())
+        .transpose()?
+        .unwrap_or_default();
+
+    let lines = metadata
+        .get(GFF_HEADER_METADATA_KEY)
+        .map(|lines| lines.lines().map(String::from).collect())
+        .unwrap_or_default();
+
+    Ok(gff::Header { lines, dialect })
+}
+
+/// Converts an Arrow IPC file or stream written by fa2arrow or fa2pq's schema to FASTA.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `rejects` what to do with records that fail to parse.
+pub fn arrow2fa<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut writer = fasta::Writer::new(output);
+
+    let records = read_batches(input)?
+        .map(|batch| fasta_records(&batch?))
+        .flatten_ok()
+        .map_ok(|record| {
+            let definition = fasta::record::Definition::new(record.id, record.description);
+            let sequence = fasta::record::Sequence::from(record.sequence.into_bytes());
+            fasta::Record::new(definition, sequence)
+        });
+
+    for record in rejects.filter("Arrow", records) {
+        writer.write_record(&record?)?;
+    }
+
+    Ok(())
+}
+
+/// Converts an Arrow IPC file or stream written by fq2arrow to FASTQ.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `rejects` what to do with records that fail to parse.
+pub fn arrow2fq<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut writer = fastq::Writer::new(output);
+
+    let records = read_batches(input)?
+        .map(|batch| fastq_records(&batch?))
+        .flatten_ok()
+        .map(|record| -> Result<_, BrrrrError> {
+            let record = record?;
+            let quality = record.quality.to_ascii()?;
+            Ok(fastq::Record::new(record.id, record.sequence, quality))
+        });
+
+    for record in rejects.filter("Arrow", records) {
+        writer.write_record(&record?)?;
+    }
+
+    Ok(())
+}
+
+/// Converts an Arrow IPC file or stream written by gff2arrow to GFF3 or GTF.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `dialect` the GFF dialect to write, or None to use the one stored by gff2arrow.
+/// * `rejects` what to do with records that fail to parse.
+pub fn arrow2gff<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    dialect: Option<gff::Dialect>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let batches = read_batches(input)?;
+
+    let header = gff_header(batches.schema().metadata())?;
+    let dialect = dialect.unwrap_or(header.dialect);
+    gff::write_header(output, &header, dialect)?;
+
+    let records = batches.map(|batch| gff_records(&batch?)).flatten_ok();
+
+    for record in rejects.filter("Arrow", records) {
+        writeln!(output, "{}", gff::format_record(dialect, &record?))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::datatypes::Schema;
+    use arrow::ipc::writer::StreamWriter;
+
+    use crate::arrow_writer::{self, IpcFormat};
+    use crate::quality::QualityOptions;
+    use crate::rejects::ErrorPolicy;
+
+    use super::*;
+
+    #[test]
+    fn test_fasta_and_fastq_round_trip() {
+        let fasta = b">A desc\nATCG\n>B\nGG\n" as &[u8];
+
+        for format in [IpcFormat::FILE, IpcFormat::STREAM] {
+            let mut ipc = Vec::new();
+            arrow_writer::fa2arrow(fasta, &mut ipc, format, None, &mut Rejects::default()).unwrap();
+
+            let mut output = Vec::new();
+            arrow2fa(&ipc[..], &mut output, &mut Rejects::default()).unwrap();
+            assert_eq!(output, fasta);
+        }
+
+        let fastq = b"@r0\nACGT\n+\nII5+\n" as &[u8];
+        let quality_options = QualityOptions {
+            phred_scores: true,
+            ..Default::default()
+        };
+
+        let mut ipc = Vec::new();
+        arrow_writer::fq2arrow(
+            fastq,
+            &mut ipc,
+            IpcFormat::STREAM,
+            &quality_options,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        arrow2fq(&ipc[..], &mut output, &mut Rejects::default()).unwrap();
+        assert_eq!(output, fastq);
+    }
+
+    #[test]
+    fn test_gff_round_trip() {
+        let gff = b"##gff-version 3\n#!genome-build x\nchr1\tsrc\tgene\t1\t10\t.\t.\t.\tID=g1\n"
+            as &[u8];
+
+        let mut ipc = Vec::new();
+        arrow_writer::gff2arrow(
+            gff,
+            &mut ipc,
+            IpcFormat::FILE,
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        arrow2gff(&ipc[..], &mut output, None, &mut Rejects::default()).unwrap();
+        assert_eq!(output, gff);
+    }
+
+    #[test]
+    fn test_arrow2fa_rejects() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, true),
+            Field::new("sequence", DataType::Utf8, false),
+        ]));
+        let id: ArrayRef = Arc::new(StringArray::from(vec![None::<&str>]));
+        let sequence: ArrayRef = Arc::new(StringArray::from(vec!["AC"]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![id, sequence]).unwrap();
+
+        let mut ipc = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut ipc, &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        assert!(arrow2fa(&ipc[..], &mut Vec::new(), &mut Rejects::default()).is_err());
+
+        let mut rejects = Rejects::new(ErrorPolicy::Skip);
+        let mut output = Vec::new();
+        arrow2fa(&ipc[..], &mut output, &mut rejects).unwrap();
+        assert_eq!(rejects.total(), 1);
+        assert!(output.is_empty());
+    }
+}
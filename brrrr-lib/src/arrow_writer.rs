@@ -0,0 +1,2032 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+use std::sync::Arc;
+
+use arrow::array::*;
+use arrow::buffer::Buffer;
+use arrow::datatypes::*;
+use arrow::error::ArrowError;
+use arrow::ipc::writer::{FileWriter, StreamWriter};
+use arrow::record_batch::RecordBatch;
+use serde::ser::{self, Serialize};
+
+use crate::errors::BrrrrError;
+use crate::gff;
+use crate::input::{self, BioFileCompression};
+use crate::parquet_writer;
+use crate::quality::QualityOptions;
+use crate::reader::{self, BamReader, FastaReader, FastqReader, GffReader, RecordReader};
+use crate::rejects::Rejects;
+use crate::writer::RecordWriter;
+
+/// The number of records buffered before they are written as a record batch.
+pub const DEFAULT_BATCH_SIZE: usize = 1024 * 1024;
+
+/// A record, or a value within one, in the serde data model.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Struct(Vec<(&'static str, Value)>),
+    Map(Vec<(Value, Value)>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    /// Returns the value of the field `name`, which is null for a missing field.
+    fn field(&self, name: &str) -> &Value {
+        match self {
+            Value::Struct(fields) => fields
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map_or(&NULL, |(_, v)| v),
+            _ => &NULL,
+        }
+    }
+
+    fn integer(&self) -> Option<i128> {
+        match *self {
+            Value::I8(v) => Some(v.into()),
+            Value::I16(v) => Some(v.into()),
+            Value::I32(v) => Some(v.into()),
+            Value::I64(v) => Some(v.into()),
+            Value::U8(v) => Some(v.into()),
+            Value::U16(v) => Some(v.into()),
+            Value::U32(v) => Some(v.into()),
+            Value::U64(v) => Some(v.into()),
+            _ => None,
+        }
+    }
+
+    fn float(&self) -> Option<f64> {
+        match *self {
+            Value::F32(v) => Some(v.into()),
+            Value::F64(v) => Some(v),
+            _ => self.integer().map(|v| v as f64),
+        }
+    }
+
+    /// Returns the type of a scalar, or None for a null or nested value.
+    fn scalar_type(&self) -> Option<DataType> {
+        let data_type = match self {
+            Value::Bool(_) => DataType::Boolean,
+            Value::I8(_) => DataType::Int8,
+            Value::I16(_) => DataType::Int16,
+            Value::I32(_) => DataType::Int32,
+            Value::I64(_) => DataType::Int64,
+            Value::U8(_) => DataType::UInt8,
+            Value::U16(_) => DataType::UInt16,
+            Value::U32(_) => DataType::UInt32,
+            Value::U64(_) => DataType::UInt64,
+            Value::F32(_) => DataType::Float32,
+            Value::F64(_) => DataType::Float64,
+            Value::Str(_) => DataType::Utf8,
+            Value::Bytes(_) => DataType::Binary,
+            _ => return None,
+        };
+
+        Some(data_type)
+    }
+
+    /// Returns true if both values are lists, structs or maps, or are scalars of the same type.
+    fn same_shape(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::List(_), Value::List(_))
+            | (Value::Struct(_), Value::Struct(_))
+            | (Value::Map(_), Value::Map(_)) => true,
+            (a, b) => a.scalar_type().is_some() && a.scalar_type() == b.scalar_type(),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(b) => serde_json::Value::Bool(*b),
+            Value::F32(v) => serde_json::json!(v),
+            Value::F64(v) => serde_json::json!(v),
+            Value::Str(v) => serde_json::json!(v),
+            Value::Bytes(v) => serde_json::json!(v),
+            Value::List(items) => items.iter().map(Value::to_json).collect(),
+            Value::Struct(fields) => fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_json()))
+                .collect(),
+            Value::Map(entries) => entries
+                .iter()
+                .map(|(k, v)| match k {
+                    Value::Str(k) => (k.clone(), v.to_json()),
+                    k => (k.to_json().to_string(), v.to_json()),
+                })
+                .collect(),
+            v => match v.integer().map(i64::try_from) {
+                Some(Ok(i)) => serde_json::json!(i),
+                _ => serde_json::json!(v.integer().map(|i| i as u64)),
+            },
+        }
+    }
+
+    fn kind(&self) -> String {
+        match self {
+            Value::Null => String::from("null"),
+            Value::List(_) => String::from("list"),
+            Value::Struct(_) => String::from("struct"),
+            Value::Map(_) => String::from("map"),
+            v => format!("{:?}", v.scalar_type().unwrap_or(DataType::Null)),
+        }
+    }
+}
+
+/// The error of serializing a record to a `Value`.
+#[derive(Debug)]
+struct SerializeError(String);
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SerializeError {}
+
+impl ser::Error for SerializeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerializeError(msg.to_string())
+    }
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerializeError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, SerializeError> {
+        Ok(Value::I8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, SerializeError> {
+        Ok(Value::I16(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, SerializeError> {
+        Ok(Value::I32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, SerializeError> {
+        Ok(Value::I64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, SerializeError> {
+        Ok(Value::U8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, SerializeError> {
+        Ok(Value::U16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, SerializeError> {
+        Ok(Value::U32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, SerializeError> {
+        Ok(Value::U64(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, SerializeError> {
+        Ok(Value::F32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, SerializeError> {
+        Ok(Value::F64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, SerializeError> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, SerializeError> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerializeError> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value, SerializeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value, SerializeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerializeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SerializeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, SerializeError> {
+        Ok(Value::Str(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, SerializeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, SerializeError> {
+        let value = value.serialize(ValueSerializer)?;
+        Ok(Value::Struct(vec![(variant, value)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, SerializeError> {
+        Ok(SeqSerializer {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, SerializeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, SerializeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, SerializeError> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, SerializeError> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or_default()),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<StructSerializer, SerializeError> {
+        Ok(StructSerializer {
+            variant: None,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<StructSerializer, SerializeError> {
+        Ok(StructSerializer {
+            variant: Some(variant),
+            fields: Vec::with_capacity(len),
+        })
+    }
+}
+
+/// Wraps the value of an enum variant in a struct with a single field named for the variant, as
+/// serde_json does.
+fn variant_value(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(variant) => Value::Struct(vec![(variant, value)]),
+        None => value,
+    }
+}
+
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    items: Vec<Value>,
+}
+
+impl SeqSerializer {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, SerializeError> {
+        Ok(variant_value(self.variant, Value::List(self.items)))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<(Value, Value)>,
+    key: Option<Value>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| SerializeError(String::from("map value without a key")))?;
+        self.entries.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        Ok(Value::Map(self.entries))
+    }
+}
+
+struct StructSerializer {
+    variant: Option<&'static str>,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl StructSerializer {
+    fn push<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        self.fields.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, SerializeError> {
+        Ok(variant_value(self.variant, Value::Struct(self.fields)))
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        self.finish()
+    }
+}
+
+/// Returns the type of a column of values. A column of nulls, e.g. a description that is never
+/// set, is `Null` until it's resolved with `resolve_data_type`. A column of values with
+/// different types, e.g. an untagged enum, is a string, and its values are written as JSON.
+fn infer_data_type(values: &[&Value]) -> DataType {
+    let mut non_null = values.iter().filter(|v| ***v != Value::Null);
+    let first = non_null.next();
+
+    if let Some(first) = first {
+        if non_null.any(|v| !first.same_shape(v)) {
+            return DataType::Utf8;
+        }
+    }
+
+    match first {
+        Some(Value::List(_)) => {
+            let items: Vec<&Value> = values
+                .iter()
+                .filter_map(|v| match v {
+                    Value::List(items) => Some(items),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+
+            DataType::List(Box::new(Field::new("item", infer_data_type(&items), true)))
+        }
+        Some(Value::Struct(_)) => DataType::Struct(infer_fields(values)),
+        Some(Value::Map(_)) => {
+            let (keys, values): (Vec<&Value>, Vec<&Value>) = values
+                .iter()
+                .filter_map(|v| match v {
+                    Value::Map(entries) => Some(entries),
+                    _ => None,
+                })
+                .flatten()
+                .map(|(k, v)| (k, v))
+                .unzip();
+
+            let entries = DataType::Struct(vec![
+                Field::new("keys", infer_data_type(&keys), false),
+                Field::new("values", infer_data_type(&values), true),
+            ]);
+            DataType::Map(Box::new(Field::new("entries", entries, false)), false)
+        }
+        Some(v) => v.scalar_type().unwrap_or(DataType::Utf8),
+        None => DataType::Null,
+    }
+}
+
+/// Returns `data_type` with every `Null` type, i.e. every column that was only ever null,
+/// replaced by a nullable string.
+fn resolve_data_type(data_type: &DataType) -> DataType {
+    match data_type {
+        DataType::Null => DataType::Utf8,
+        DataType::List(field) => DataType::List(Box::new(resolve_field(field))),
+        DataType::Struct(fields) => DataType::Struct(fields.iter().map(resolve_field).collect()),
+        DataType::Map(field, sorted) => DataType::Map(Box::new(resolve_field(field)), *sorted),
+        data_type => data_type.clone(),
+    }
+}
+
+fn resolve_field(field: &Field) -> Field {
+    Field::new(
+        field.name(),
+        resolve_data_type(field.data_type()),
+        field.is_nullable(),
+    )
+}
+
+/// Checks the fields inferred from a later batch against `expected`, the fields inferred from
+/// the first batch, which the schema was resolved from. A column may be null throughout either
+/// batch, and a column that was null throughout the first batch may hold strings, which is the
+/// type it's written with, but any other change of type, or a field that isn't in `expected`,
+/// is an error rather than being written as JSON.
+fn check_fields(expected: &[Field], found: &[Field]) -> Result<(), ArrowError> {
+    for field in found {
+        let expected = expected
+            .iter()
+            .find(|f| f.name() == field.name())
+            .ok_or_else(|| {
+                ArrowError::SchemaError(format!(
+                    "field {} is not in the schema inferred from the first batch",
+                    field.name()
+                ))
+            })?;
+
+        check_data_type(field.name(), expected.data_type(), field.data_type())?;
+    }
+
+    Ok(())
+}
+
+fn check_data_type(name: &str, expected: &DataType, found: &DataType) -> Result<(), ArrowError> {
+    match (expected, found) {
+        (_, DataType::Null) | (DataType::Null, DataType::Utf8) => Ok(()),
+        (DataType::Null, found) => Err(ArrowError::SchemaError(format!(
+            "field {} was null throughout the first batch, so it's written as Utf8, but a later \
+             batch holds {:?}",
+            name, found
+        ))),
+        (DataType::List(expected), DataType::List(found)) => {
+            check_data_type(name, expected.data_type(), found.data_type())
+        }
+        (DataType::Map(expected, _), DataType::Map(found, _)) => {
+            check_data_type(name, expected.data_type(), found.data_type())
+        }
+        (DataType::Struct(expected), DataType::Struct(found)) => check_fields(expected, found),
+        (expected, found) if expected == found => Ok(()),
+        (expected, found) => Err(ArrowError::SchemaError(format!(
+            "field {} changed type from {:?} in the first batch to {:?}",
+            name, expected, found
+        ))),
+    }
+}
+
+/// Returns the fields of a column of structs, in the order they're first seen.
+fn infer_fields(values: &[&Value]) -> Vec<Field> {
+    let mut names: Vec<&str> = Vec::new();
+
+    for value in values {
+        if let Value::Struct(fields) = value {
+            for (name, _) in fields {
+                if !names.contains(name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            let column: Vec<&Value> = values.iter().map(|v| v.field(name)).collect();
+            Field::new(name, infer_data_type(&column), true)
+        })
+        .collect()
+}
+
+fn mismatch(data_type: &DataType, value: &Value) -> ArrowError {
+    ArrowError::InvalidArgumentError(format!(
+        "expected a value of type {:?}, found {}",
+        data_type,
+        value.kind()
+    ))
+}
+
+/// Returns the validity bitmap of `values`, or None if none of them are null.
+fn null_buffer(values: &[&Value]) -> Option<Buffer> {
+    if !values.iter().any(|v| **v == Value::Null) {
+        return None;
+    }
+
+    let mut builder = BooleanBufferBuilder::new(values.len());
+    for value in values {
+        builder.append(**value != Value::Null);
+    }
+
+    Some(builder.finish())
+}
+
+fn integers<T: TryFrom<i128>>(
+    data_type: &DataType,
+    values: &[&Value],
+) -> Result<Vec<Option<T>>, ArrowError> {
+    values
+        .iter()
+        .map(|v| match v {
+            Value::Null => Ok(None),
+            v => v
+                .integer()
+                .and_then(|i| T::try_from(i).ok())
+                .map(Some)
+                .ok_or_else(|| mismatch(data_type, v)),
+        })
+        .collect()
+}
+
+fn floats(data_type: &DataType, values: &[&Value]) -> Result<Vec<Option<f64>>, ArrowError> {
+    values
+        .iter()
+        .map(|v| match v {
+            Value::Null => Ok(None),
+            v => v.float().map(Some).ok_or_else(|| mismatch(data_type, v)),
+        })
+        .collect()
+}
+
+/// Returns the offsets and the flattened items of a column of lists or maps.
+fn offsets<'a, T, F>(
+    data_type: &DataType,
+    values: &[&'a Value],
+    items: F,
+) -> Result<(Buffer, Vec<&'a T>), ArrowError>
+where
+    F: Fn(&'a Value) -> Option<&'a Vec<T>>,
+{
+    let mut offsets = Vec::with_capacity(values.len() + 1);
+    let mut flattened = Vec::new();
+    offsets.push(0i32);
+
+    for value in values {
+        match (value, items(value)) {
+            (Value::Null, _) => {}
+            (_, Some(items)) => flattened.extend(items),
+            (v, None) => return Err(mismatch(data_type, v)),
+        }
+
+        let offset = i32::try_from(flattened.len()).map_err(|_| {
+            ArrowError::InvalidArgumentError(String::from("too many list items in a batch"))
+        })?;
+        offsets.push(offset);
+    }
+
+    Ok((Buffer::from_slice_ref(&offsets), flattened))
+}
+
+/// Builds the array of `data_type` that holds `values`. Integers are converted to any integer
+/// or float type that holds them, and values that aren't strings are written to string columns
+/// as JSON.
+fn build_array(data_type: &DataType, values: &[&Value]) -> Result<ArrayRef, ArrowError> {
+    let array: ArrayRef = match data_type {
+        DataType::Boolean => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Bool(b) => Ok(Some(*b)),
+                    v => Err(mismatch(data_type, v)),
+                })
+                .collect::<Result<BooleanArray, _>>()?,
+        ),
+        DataType::Int8 => Arc::new(Int8Array::from(integers(data_type, values)?)),
+        DataType::Int16 => Arc::new(Int16Array::from(integers(data_type, values)?)),
+        DataType::Int32 => Arc::new(Int32Array::from(integers(data_type, values)?)),
+        DataType::Int64 => Arc::new(Int64Array::from(integers(data_type, values)?)),
+        DataType::UInt8 => Arc::new(UInt8Array::from(integers(data_type, values)?)),
+        DataType::UInt16 => Arc::new(UInt16Array::from(integers(data_type, values)?)),
+        DataType::UInt32 => Arc::new(UInt32Array::from(integers(data_type, values)?)),
+        DataType::UInt64 => Arc::new(UInt64Array::from(integers(data_type, values)?)),
+        DataType::Float32 => Arc::new(
+            floats(data_type, values)?
+                .into_iter()
+                .map(|v| v.map(|v| v as f32))
+                .collect::<Float32Array>(),
+        ),
+        DataType::Float64 => Arc::new(Float64Array::from(floats(data_type, values)?)),
+        DataType::Utf8 => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => None,
+                    Value::Str(s) => Some(s.clone()),
+                    v => Some(v.to_json().to_string()),
+                })
+                .collect::<StringArray>(),
+        ),
+        DataType::Binary => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Bytes(b) => Ok(Some(b.as_slice())),
+                    v => Err(mismatch(data_type, v)),
+                })
+                .collect::<Result<BinaryArray, _>>()?,
+        ),
+        DataType::List(field) => {
+            let (offsets, items) = offsets(data_type, values, |v| match v {
+                Value::List(items) => Some(items),
+                _ => None,
+            })?;
+            let items = build_array(field.data_type(), &items)?;
+
+            let data = ArrayData::builder(data_type.clone())
+                .len(values.len())
+                .add_buffer(offsets)
+                .add_child_data(items.data().clone())
+                .null_bit_buffer(null_buffer(values))
+                .build()?;
+            make_array(data)
+        }
+        DataType::Struct(fields) => {
+            let children = fields
+                .iter()
+                .map(|field| {
+                    let column: Vec<&Value> = values
+                        .iter()
+                        .map(|v| match v {
+                            Value::Null | Value::Struct(_) => Ok(v.field(field.name())),
+                            v => Err(mismatch(data_type, v)),
+                        })
+                        .collect::<Result<_, _>>()?;
+                    Ok(build_array(field.data_type(), &column)?.data().clone())
+                })
+                .collect::<Result<Vec<_>, ArrowError>>()?;
+
+            let data = ArrayData::builder(data_type.clone())
+                .len(values.len())
+                .child_data(children)
+                .null_bit_buffer(null_buffer(values))
+                .build()?;
+            make_array(data)
+        }
+        DataType::Map(field, _) => {
+            let (key_field, value_field) = match field.data_type() {
+                DataType::Struct(fields) if fields.len() == 2 => (&fields[0], &fields[1]),
+                _ => {
+                    return Err(ArrowError::InvalidArgumentError(format!(
+                        "{:?} is not a map of keys and values",
+                        data_type
+                    )))
+                }
+            };
+
+            let (offsets, entries) = offsets(data_type, values, |v| match v {
+                Value::Map(entries) => Some(entries),
+                _ => None,
+            })?;
+            let keys: Vec<&Value> = entries.iter().map(|(k, _)| k).collect();
+            let entry_values: Vec<&Value> = entries.iter().map(|(_, v)| v).collect();
+
+            let entries = ArrayData::builder(field.data_type().clone())
+                .len(entries.len())
+                .add_child_data(build_array(key_field.data_type(), &keys)?.data().clone())
+                .add_child_data(
+                    build_array(value_field.data_type(), &entry_values)?
+                        .data()
+                        .clone(),
+                )
+                .build()?;
+
+            let data = ArrayData::builder(data_type.clone())
+                .len(values.len())
+                .add_buffer(offsets)
+                .add_child_data(entries)
+                .null_bit_buffer(null_buffer(values))
+                .build()?;
+            make_array(data)
+        }
+        data_type => {
+            return Err(ArrowError::NotYetImplemented(format!(
+                "writing {:?} columns from records",
+                data_type
+            )))
+        }
+    };
+
+    Ok(array)
+}
+
+/// The offsets, validity and children of a list, struct or map column. A list has the items as
+/// its one child, a struct has a child per field, and a map has the keys and the values.
+struct NestedBuilder {
+    data_type: DataType,
+    fields: Vec<Field>,
+    children: Vec<ColumnBuilder>,
+    offsets: Vec<i32>,
+    validity: BooleanBufferBuilder,
+    null_count: usize,
+}
+
+impl NestedBuilder {
+    fn new(data_type: &DataType, fields: Vec<Field>) -> Result<Self, ArrowError> {
+        Ok(Self {
+            data_type: data_type.clone(),
+            children: fields
+                .iter()
+                .map(|f| ColumnBuilder::new(f.data_type()))
+                .collect::<Result<_, _>>()?,
+            fields,
+            offsets: vec![0],
+            validity: BooleanBufferBuilder::new(0),
+            null_count: 0,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.validity.len()
+    }
+
+    /// Ends a list or map value at the current length of its first child.
+    fn append_offset(&mut self, valid: bool) -> Result<(), SerializeError> {
+        let offset = i32::try_from(self.children[0].len())
+            .map_err(|_| SerializeError(String::from("too many list items in a batch")))?;
+        self.offsets.push(offset);
+        self.append_validity(valid);
+        Ok(())
+    }
+
+    fn append_validity(&mut self, valid: bool) {
+        self.validity.append(valid);
+        if !valid {
+            self.null_count += 1;
+        }
+    }
+
+    /// Returns the index of the child named `name`, trying `next` first as fields are usually
+    /// serialized in the order of the schema.
+    fn position(&self, name: &str, next: usize) -> Option<usize> {
+        match self.fields.get(next) {
+            Some(field) if field.name() == name => Some(next),
+            _ => self.fields.iter().position(|f| f.name() == name),
+        }
+    }
+
+    /// Builds the children and returns the array data builder of the column, leaving the
+    /// builder empty.
+    fn finish(&mut self) -> Result<(Vec<ArrayData>, ArrayDataBuilder), ArrowError> {
+        let children = self
+            .children
+            .iter_mut()
+            .map(|c| c.finish().map(|a| a.data().clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let len = self.len();
+        let null_buffer = match self.null_count {
+            0 => None,
+            _ => Some(self.validity.finish()),
+        };
+
+        let data = ArrayData::builder(self.data_type.clone())
+            .len(len)
+            .null_bit_buffer(null_buffer);
+
+        self.validity = BooleanBufferBuilder::new(0);
+        self.null_count = 0;
+
+        Ok((children, data))
+    }
+
+    fn take_offsets(&mut self) -> Buffer {
+        let offsets = Buffer::from_slice_ref(&self.offsets);
+        self.offsets = vec![0];
+        offsets
+    }
+}
+
+/// Builds a column of a record batch from the values serialized into it, converting each to the
+/// type of the column as `build_array` does.
+enum ColumnBuilder {
+    Boolean(BooleanBuilder),
+    Int8(Int8Builder),
+    Int16(Int16Builder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    UInt8(UInt8Builder),
+    UInt16(UInt16Builder),
+    UInt32(UInt32Builder),
+    UInt64(UInt64Builder),
+    Float32(Float32Builder),
+    Float64(Float64Builder),
+    Utf8(StringBuilder),
+    Binary(BinaryBuilder),
+    List(Box<NestedBuilder>),
+    Struct(Box<NestedBuilder>),
+    Map(Box<NestedBuilder>),
+}
+
+/// The number of values each builder is created with room for.
+const BUILDER_CAPACITY: usize = 1024;
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> Result<Self, ArrowError> {
+        let builder = match data_type {
+            DataType::Boolean => Self::Boolean(BooleanBuilder::new(BUILDER_CAPACITY)),
+            DataType::Int8 => Self::Int8(Int8Builder::new(BUILDER_CAPACITY)),
+            DataType::Int16 => Self::Int16(Int16Builder::new(BUILDER_CAPACITY)),
+            DataType::Int32 => Self::Int32(Int32Builder::new(BUILDER_CAPACITY)),
+            DataType::Int64 => Self::Int64(Int64Builder::new(BUILDER_CAPACITY)),
+            DataType::UInt8 => Self::UInt8(UInt8Builder::new(BUILDER_CAPACITY)),
+            DataType::UInt16 => Self::UInt16(UInt16Builder::new(BUILDER_CAPACITY)),
+            DataType::UInt32 => Self::UInt32(UInt32Builder::new(BUILDER_CAPACITY)),
+            DataType::UInt64 => Self::UInt64(UInt64Builder::new(BUILDER_CAPACITY)),
+            DataType::Float32 => Self::Float32(Float32Builder::new(BUILDER_CAPACITY)),
+            DataType::Float64 => Self::Float64(Float64Builder::new(BUILDER_CAPACITY)),
+            DataType::Utf8 => Self::Utf8(StringBuilder::new(BUILDER_CAPACITY)),
+            DataType::Binary => Self::Binary(BinaryBuilder::new(BUILDER_CAPACITY)),
+            DataType::List(field) => Self::List(Box::new(NestedBuilder::new(
+                data_type,
+                vec![field.as_ref().clone()],
+            )?)),
+            DataType::Struct(fields) => {
+                Self::Struct(Box::new(NestedBuilder::new(data_type, fields.clone())?))
+            }
+            DataType::Map(field, _) => match field.data_type() {
+                DataType::Struct(fields) if fields.len() == 2 => {
+                    Self::Map(Box::new(NestedBuilder::new(data_type, fields.clone())?))
+                }
+                _ => {
+                    return Err(ArrowError::InvalidArgumentError(format!(
+                        "{:?} is not a map of keys and values",
+                        data_type
+                    )))
+                }
+            },
+            data_type => {
+                return Err(ArrowError::NotYetImplemented(format!(
+                    "writing {:?} columns from records",
+                    data_type
+                )))
+            }
+        };
+
+        Ok(builder)
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            Self::Boolean(_) => DataType::Boolean,
+            Self::Int8(_) => DataType::Int8,
+            Self::Int16(_) => DataType::Int16,
+            Self::Int32(_) => DataType::Int32,
+            Self::Int64(_) => DataType::Int64,
+            Self::UInt8(_) => DataType::UInt8,
+            Self::UInt16(_) => DataType::UInt16,
+            Self::UInt32(_) => DataType::UInt32,
+            Self::UInt64(_) => DataType::UInt64,
+            Self::Float32(_) => DataType::Float32,
+            Self::Float64(_) => DataType::Float64,
+            Self::Utf8(_) => DataType::Utf8,
+            Self::Binary(_) => DataType::Binary,
+            Self::List(b) | Self::Struct(b) | Self::Map(b) => b.data_type.clone(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Boolean(b) => b.len(),
+            Self::Int8(b) => b.len(),
+            Self::Int16(b) => b.len(),
+            Self::Int32(b) => b.len(),
+            Self::Int64(b) => b.len(),
+            Self::UInt8(b) => b.len(),
+            Self::UInt16(b) => b.len(),
+            Self::UInt32(b) => b.len(),
+            Self::UInt64(b) => b.len(),
+            Self::Float32(b) => b.len(),
+            Self::Float64(b) => b.len(),
+            Self::Utf8(b) => b.len(),
+            Self::Binary(b) => b.len(),
+            Self::List(b) | Self::Struct(b) | Self::Map(b) => b.len(),
+        }
+    }
+
+    fn mismatch(&self, found: &str) -> SerializeError {
+        SerializeError(format!(
+            "expected a value of type {:?}, found {}",
+            self.data_type(),
+            found
+        ))
+    }
+
+    fn append_null(&mut self) -> Result<(), SerializeError> {
+        match self {
+            Self::Boolean(b) => b.append_null()?,
+            Self::Int8(b) => b.append_null()?,
+            Self::Int16(b) => b.append_null()?,
+            Self::Int32(b) => b.append_null()?,
+            Self::Int64(b) => b.append_null()?,
+            Self::UInt8(b) => b.append_null()?,
+            Self::UInt16(b) => b.append_null()?,
+            Self::UInt32(b) => b.append_null()?,
+            Self::UInt64(b) => b.append_null()?,
+            Self::Float32(b) => b.append_null()?,
+            Self::Float64(b) => b.append_null()?,
+            Self::Utf8(b) => b.append_null()?,
+            Self::Binary(b) => b.append_null()?,
+            Self::List(b) | Self::Map(b) => b.append_offset(false)?,
+            Self::Struct(b) => {
+                for child in b.children.iter_mut() {
+                    child.append_null()?;
+                }
+                b.append_validity(false);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn append_integer(&mut self, v: i128) -> Result<(), SerializeError> {
+        fn append<T, B>(b: &mut PrimitiveBuilder<B>, v: i128) -> Option<Result<(), ArrowError>>
+        where
+            T: TryFrom<i128>,
+            B: ArrowPrimitiveType<Native = T>,
+        {
+            T::try_from(v).ok().map(|v| b.append_value(v))
+        }
+
+        let appended = match self {
+            Self::Int8(b) => append(b, v),
+            Self::Int16(b) => append(b, v),
+            Self::Int32(b) => append(b, v),
+            Self::Int64(b) => append(b, v),
+            Self::UInt8(b) => append(b, v),
+            Self::UInt16(b) => append(b, v),
+            Self::UInt32(b) => append(b, v),
+            Self::UInt64(b) => append(b, v),
+            Self::Float32(b) => Some(b.append_value(v as f32)),
+            Self::Float64(b) => Some(b.append_value(v as f64)),
+            Self::Utf8(b) => Some(b.append_value(v.to_string())),
+            _ => None,
+        };
+
+        match appended {
+            Some(result) => Ok(result?),
+            None => Err(self.mismatch(&format!("the integer {}", v))),
+        }
+    }
+
+    fn append_float(&mut self, v: f64) -> Result<(), SerializeError> {
+        match self {
+            Self::Float32(b) => b.append_value(v as f32)?,
+            Self::Float64(b) => b.append_value(v)?,
+            Self::Utf8(b) => b.append_value(serde_json::json!(v).to_string())?,
+            _ => return Err(self.mismatch("a float")),
+        }
+
+        Ok(())
+    }
+
+    fn append_str(&mut self, v: &str) -> Result<(), SerializeError> {
+        match self {
+            Self::Utf8(b) => b.append_value(v)?,
+            _ => return Err(self.mismatch("a string")),
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<ArrayRef, ArrowError> {
+        let array: ArrayRef = match self {
+            Self::Boolean(b) => Arc::new(b.finish()),
+            Self::Int8(b) => Arc::new(b.finish()),
+            Self::Int16(b) => Arc::new(b.finish()),
+            Self::Int32(b) => Arc::new(b.finish()),
+            Self::Int64(b) => Arc::new(b.finish()),
+            Self::UInt8(b) => Arc::new(b.finish()),
+            Self::UInt16(b) => Arc::new(b.finish()),
+            Self::UInt32(b) => Arc::new(b.finish()),
+            Self::UInt64(b) => Arc::new(b.finish()),
+            Self::Float32(b) => Arc::new(b.finish()),
+            Self::Float64(b) => Arc::new(b.finish()),
+            Self::Utf8(b) => Arc::new(b.finish()),
+            Self::Binary(b) => Arc::new(b.finish()),
+            Self::List(b) => {
+                let offsets = b.take_offsets();
+                let (children, data) = b.finish()?;
+                make_array(data.add_buffer(offsets).child_data(children).build()?)
+            }
+            Self::Struct(b) => {
+                let (children, data) = b.finish()?;
+                make_array(data.child_data(children).build()?)
+            }
+            Self::Map(b) => {
+                let offsets = b.take_offsets();
+                let entries_type = match &b.data_type {
+                    DataType::Map(field, _) => field.data_type().clone(),
+                    data_type => data_type.clone(),
+                };
+                let (children, data) = b.finish()?;
+                let entries = ArrayData::builder(entries_type)
+                    .len(children[0].len())
+                    .child_data(children)
+                    .build()?;
+                make_array(data.add_buffer(offsets).add_child_data(entries).build()?)
+            }
+        };
+
+        Ok(array)
+    }
+}
+
+impl From<ArrowError> for SerializeError {
+    fn from(e: ArrowError) -> Self {
+        SerializeError(e.to_string())
+    }
+}
+
+/// Prefixes an error with the name of the field it was found in.
+fn field_error(name: &str, e: SerializeError) -> SerializeError {
+    SerializeError(format!("{}: {}", name, e.0))
+}
+
+/// Appends a single value to a column, so that records are built straight into the columns of
+/// a known schema rather than into `Value`s first.
+impl<'a> ser::Serializer for &'a mut ColumnBuilder {
+    type Ok = ();
+    type Error = SerializeError;
+    type SerializeSeq = ListAppender<'a>;
+    type SerializeTuple = ListAppender<'a>;
+    type SerializeTupleStruct = ListAppender<'a>;
+    type SerializeTupleVariant = ser::Impossible<(), SerializeError>;
+    type SerializeMap = MapAppender<'a>;
+    type SerializeStruct = StructAppender<'a>;
+    type SerializeStructVariant = ser::Impossible<(), SerializeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), SerializeError> {
+        match self {
+            ColumnBuilder::Boolean(b) => Ok(b.append_value(v)?),
+            ColumnBuilder::Utf8(b) => Ok(b.append_value(v.to_string())?),
+            b => Err(b.mismatch("a bool")),
+        }
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), SerializeError> {
+        self.append_integer(v.into())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), SerializeError> {
+        self.append_float(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), SerializeError> {
+        self.append_float(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), SerializeError> {
+        self.append_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), SerializeError> {
+        self.append_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SerializeError> {
+        match self {
+            ColumnBuilder::Binary(b) => Ok(b.append_value(v)?),
+            b => Err(b.mismatch("bytes")),
+        }
+    }
+
+    fn serialize_none(self) -> Result<(), SerializeError> {
+        self.append_null()
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), SerializeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SerializeError> {
+        self.append_null()
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SerializeError> {
+        self.append_null()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), SerializeError> {
+        self.append_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        let mut fields = ser::Serializer::serialize_struct(self, name, 1)?;
+        ser::SerializeStruct::serialize_field(&mut fields, variant, value)?;
+        ser::SerializeStruct::end(fields)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<ListAppender<'a>, SerializeError> {
+        match self {
+            ColumnBuilder::List(b) => Ok(ListAppender(b)),
+            b => Err(b.mismatch("a list")),
+        }
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ListAppender<'a>, SerializeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ListAppender<'a>, SerializeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerializeError> {
+        Err(self.mismatch(&format!("the tuple variant {}", variant)))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapAppender<'a>, SerializeError> {
+        match self {
+            ColumnBuilder::Map(b) => Ok(MapAppender::Map(b)),
+            ColumnBuilder::Struct(b) => Ok(MapAppender::Struct(StructAppender::new(b), None)),
+            b => Err(b.mismatch("a map")),
+        }
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<StructAppender<'a>, SerializeError> {
+        match self {
+            ColumnBuilder::Struct(b) => Ok(StructAppender::new(b)),
+            b => Err(b.mismatch("a struct")),
+        }
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerializeError> {
+        Err(self.mismatch(&format!("the struct variant {}", variant)))
+    }
+}
+
+/// Appends the items of a sequence to a list column.
+struct ListAppender<'a>(&'a mut NestedBuilder);
+
+impl<'a> ListAppender<'a> {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        value.serialize(&mut self.0.children[0])
+    }
+
+    fn finish(self) -> Result<(), SerializeError> {
+        self.0.append_offset(true)
+    }
+}
+
+impl<'a> ser::SerializeSeq for ListAppender<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), SerializeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for ListAppender<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), SerializeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for ListAppender<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), SerializeError> {
+        self.finish()
+    }
+}
+
+/// Appends the fields of a struct to a struct column. Fields that aren't in the column are
+/// ignored, and fields of the column that aren't serialized are null.
+struct StructAppender<'a> {
+    builder: &'a mut NestedBuilder,
+    next: usize,
+}
+
+impl<'a> StructAppender<'a> {
+    fn new(builder: &'a mut NestedBuilder) -> Self {
+        Self { builder, next: 0 }
+    }
+
+    fn push<T: ?Sized + Serialize>(
+        &mut self,
+        index: Option<usize>,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        let index = match index {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+
+        let len = self.builder.len();
+        let name = self.builder.fields[index].name();
+        let child = &mut self.builder.children[index];
+
+        if child.len() > len {
+            return Err(SerializeError(format!(
+                "{} is serialized more than once",
+                name
+            )));
+        }
+
+        value.serialize(child).map_err(|e| field_error(name, e))?;
+        self.next = index + 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), SerializeError> {
+        let len = self.builder.len();
+
+        for (field, child) in self.builder.fields.iter().zip(&mut self.builder.children) {
+            if child.len() == len {
+                child
+                    .append_null()
+                    .map_err(|e| field_error(field.name(), e))?;
+            }
+        }
+
+        self.builder.append_validity(true);
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for StructAppender<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        let index = self.builder.position(key, self.next);
+        self.push(index, value)
+    }
+
+    fn end(self) -> Result<(), SerializeError> {
+        self.finish()
+    }
+}
+
+/// Appends the entries of a map to a map column, or to a struct column with a field per key,
+/// for structs whose field names aren't known until runtime.
+enum MapAppender<'a> {
+    Map(&'a mut NestedBuilder),
+    Struct(StructAppender<'a>, Option<usize>),
+}
+
+impl<'a> ser::SerializeMap for MapAppender<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        match self {
+            MapAppender::Map(b) => key.serialize(&mut b.children[0]),
+            MapAppender::Struct(fields, index) => match key.serialize(ValueSerializer)? {
+                Value::Str(key) => {
+                    *index = fields.builder.position(&key, fields.next);
+                    Ok(())
+                }
+                key => Err(SerializeError(format!(
+                    "expected a string key for a struct field, found {}",
+                    key.kind()
+                ))),
+            },
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        match self {
+            MapAppender::Map(b) => value.serialize(&mut b.children[1]),
+            MapAppender::Struct(fields, index) => fields.push(index.take(), value),
+        }
+    }
+
+    fn end(self) -> Result<(), SerializeError> {
+        match self {
+            MapAppender::Map(b) => b.append_offset(true),
+            MapAppender::Struct(fields, _) => fields.finish(),
+        }
+    }
+}
+
+/// RecordBatchBuffer buffers serializable records and builds them into Arrow record batches.
+///
+/// The schema follows the serde representation of the records: each field of a struct is a
+/// column, options are nullable, sequences are lists and maps are maps. A schema set with
+/// `with_schema` is built into directly as each record is pushed. Otherwise the schema is
+/// inferred from the first batch, and a later batch whose types don't match it is an error.
+pub struct RecordBatchBuffer {
+    schema: Option<SchemaRef>,
+    inferred: Option<Vec<Field>>,
+    rows: Vec<Value>,
+    columns: Option<ColumnBuilder>,
+    batch_size: usize,
+}
+
+impl RecordBatchBuffer {
+    /// Creates a new RecordBatchBuffer that is full after `batch_size` records.
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            schema: None,
+            inferred: None,
+            rows: Vec::new(),
+            columns: None,
+            batch_size,
+        }
+    }
+
+    /// Sets the number of records after which the buffer is full.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the schema rather than inferring it. Integer values are converted to the integer
+    /// or float type of their column.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(Arc::new(schema));
+        self
+    }
+
+    /// Returns the builder of the columns of a set schema, or None if the schema is inferred.
+    fn columns(&mut self) -> Result<Option<&mut ColumnBuilder>, ArrowError> {
+        match (&self.schema, &self.inferred, &mut self.columns) {
+            (Some(_), None, Some(columns)) => Ok(Some(columns)),
+            (Some(schema), None, columns) => {
+                let data_type = DataType::Struct(schema.fields().clone());
+                Ok(Some(columns.insert(ColumnBuilder::new(&data_type)?)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Adds a record, which must serialize as a struct. If the schema is set and the record
+    /// doesn't match it, the buffer is left part way through the record and shouldn't be used.
+    pub fn push<S: Serialize>(&mut self, record: S) -> io::Result<()> {
+        let columns = self
+            .columns()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        if let Some(columns) = columns {
+            return record
+                .serialize(columns)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e));
+        }
+
+        let value = record
+            .serialize(ValueSerializer)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+        match value {
+            Value::Struct(_) => {
+                self.rows.push(value);
+                Ok(())
+            }
+            value => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("expected a record that is a struct, found {}", value.kind()),
+            )),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match &self.columns {
+            Some(columns) => columns.len(),
+            None => self.rows.len(),
+        }
+    }
+
+    /// Returns true if the buffer holds a full batch.
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.batch_size
+    }
+
+    /// Returns the schema, inferring it from the buffered records if it isn't already set.
+    pub fn schema(&mut self) -> SchemaRef {
+        match &self.schema {
+            Some(schema) => schema.clone(),
+            None => {
+                let rows: Vec<&Value> = self.rows.iter().collect();
+                let inferred = infer_fields(&rows);
+                let schema = Arc::new(Schema::new(inferred.iter().map(resolve_field).collect()));
+                self.schema = Some(schema.clone());
+                self.inferred = Some(inferred);
+                schema
+            }
+        }
+    }
+
+    /// Builds the buffered records into a record batch, or returns None if the buffer is empty.
+    /// Returns an error if the schema was inferred and the types of the records don't match it.
+    pub fn flush(&mut self) -> Result<Option<RecordBatch>, ArrowError> {
+        if self.len() == 0 {
+            return Ok(None);
+        }
+
+        let schema = self.schema();
+
+        if let Some(columns) = &mut self.columns {
+            let batch = StructArray::from(columns.finish()?.data().clone());
+            return RecordBatch::try_new(schema, batch.columns().into_iter().cloned().collect())
+                .map(Some);
+        }
+
+        let rows: Vec<&Value> = self.rows.iter().collect();
+
+        if let Some(inferred) = &self.inferred {
+            check_fields(inferred, &infer_fields(&rows))?;
+        }
+
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let column: Vec<&Value> = rows.iter().map(|v| v.field(field.name())).collect();
+                build_array(field.data_type(), &column)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.rows.clear();
+        RecordBatch::try_new(schema, columns).map(Some)
+    }
+}
+
+/// The Arrow IPC format to write.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IpcFormat {
+    /// The random access file format, also known as Feather V2.
+    FILE,
+    /// The streaming format, which can be piped to another process.
+    STREAM,
+}
+
+/// Writes record batches in the Arrow IPC file or stream format.
+pub(crate) enum IpcWriter<W: Write> {
+    File(FileWriter<W>),
+    Stream(StreamWriter<W>),
+}
+
+impl<W: Write> IpcWriter<W> {
+    /// Creates a new IpcWriter and writes the schema.
+    pub(crate) fn try_new(w: W, schema: &Schema, format: IpcFormat) -> Result<Self, ArrowError> {
+        let writer = match format {
+            IpcFormat::FILE => IpcWriter::File(FileWriter::try_new(w, schema)?),
+            IpcFormat::STREAM => IpcWriter::Stream(StreamWriter::try_new(w, schema)?),
+        };
+
+        Ok(writer)
+    }
+
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> Result<(), ArrowError> {
+        match self {
+            IpcWriter::File(writer) => writer.write(batch),
+            IpcWriter::Stream(writer) => writer.write(batch),
+        }
+    }
+
+    /// Writes the end of the stream, or the file footer, and returns the underlying writer.
+    pub(crate) fn into_inner(self) -> Result<W, ArrowError> {
+        match self {
+            IpcWriter::File(writer) => writer.into_inner(),
+            IpcWriter::Stream(writer) => writer.into_inner(),
+        }
+    }
+}
+
+/// IpcRecordWriter writes records in the Arrow IPC file or stream format, buffering them into
+/// record batches with a `RecordBatchBuffer`.
+pub struct IpcRecordWriter<W: Write> {
+    inner: Option<W>,
+    format: IpcFormat,
+    writer: Option<IpcWriter<W>>,
+    batches: RecordBatchBuffer,
+}
+
+impl<W: Write> IpcRecordWriter<W> {
+    /// Creates a new IpcRecordWriter with a writer, inferring the schema from the records.
+    pub fn new(w: W, format: IpcFormat) -> Self {
+        Self {
+            inner: Some(w),
+            format,
+            writer: None,
+            batches: RecordBatchBuffer::new(DEFAULT_BATCH_SIZE),
+        }
+    }
+
+    /// Sets the schema of the file rather than inferring it from the records.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.batches = self.batches.with_schema(schema);
+        self
+    }
+
+    fn writer(&mut self) -> Result<&mut IpcWriter<W>, ArrowError> {
+        if let Some(inner) = self.inner.take() {
+            let schema = self.batches.schema();
+            self.writer = Some(IpcWriter::try_new(inner, &schema, self.format)?);
+        }
+
+        self.writer
+            .as_mut()
+            .ok_or_else(|| ArrowError::IoError(String::from("the writer is finished")))
+    }
+
+    fn write_batch(&mut self) -> Result<(), ArrowError> {
+        if let Some(batch) = self.batches.flush()? {
+            self.writer()?.write(&batch)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffered records and the end of the stream or file, and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, BrrrrError> {
+        self.write_batch()?;
+        self.writer()?;
+
+        match self.writer {
+            Some(writer) => Ok(writer.into_inner()?),
+            None => Err(ArrowError::IoError(String::from("the writer is finished")).into()),
+        }
+    }
+}
+
+impl<W: Write> RecordWriter for IpcRecordWriter<W> {
+    /// Buffers the record, writing a record batch once a batch is full.
+    fn write_serde_record<S: Serialize>(&mut self, r: S) -> io::Result<()> {
+        self.batches.push(r)?;
+
+        if self.batches.is_full() {
+            self.write_batch().map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a FASTA input to Arrow IPC, with the columns of fa2pq.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `format` the Arrow IPC format to write.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn fa2arrow<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    format: IpcFormat,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = FastaReader::new(input::decode(input, bio_file_compression)?);
+    let mut writer =
+        IpcRecordWriter::new(output, format).with_schema(parquet_writer::fasta_schema());
+
+    reader::convert(rejects.filter("FASTA", reader), &mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Converts a FASTQ input to Arrow IPC, with the columns of fq2pq.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `format` the Arrow IPC format to write.
+/// * `quality_options` how the quality scores are written.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn fq2arrow<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    format: IpcFormat,
+    quality_options: &QualityOptions,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = FastqReader::new(input::decode(input, bio_file_compression)?, quality_options);
+    let mut writer = IpcRecordWriter::new(output, format)
+        .with_schema(parquet_writer::fastq_schema(quality_options));
+
+    reader::convert(rejects.filter("FASTQ", reader), &mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Converts a GFF3 or GTF input to Arrow IPC, with the columns of gff2pq. The dialect and the
+/// directives before the first record are stored in the schema metadata under the same keys as
+/// gff2pq uses.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `format` the Arrow IPC format to write.
+/// * `dialect` the GFF dialect, or None to detect it from the input.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn gff2arrow<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    format: IpcFormat,
+    dialect: Option<gff::Dialect>,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = GffReader::new(input::decode(input, bio_file_compression)?, dialect)?;
+
+    let metadata = parquet_writer::gff_metadata(reader.header());
+    let schema = parquet_writer::gff_schema().with_metadata(metadata.into_iter().collect());
+
+    let mut writer = IpcRecordWriter::new(output, format).with_schema(schema);
+
+    reader::convert(rejects.filter("GFF", reader), &mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Converts a BAM input to Arrow IPC, with the columns of bam2pq. The SAM header is stored in
+/// the schema metadata under the same key as bam2pq uses.
+///
+/// # Arguments
+///
+/// * `input` an input BAM file to convert to Arrow IPC.
+/// * `output` an output that implements the Write trait.
+/// * `format` the Arrow IPC format to write.
+/// * `rejects` what to do with records that fail to parse.
+pub fn bam2arrow<R: Read, W: Write>(
+    input: R,
+    output: &mut W,
+    format: IpcFormat,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = BamReader::new(input)?;
+
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        parquet_writer::SAM_HEADER_METADATA_KEY.to_string(),
+        reader.header().to_string(),
+    );
+    let schema = parquet_writer::sam_schema().with_metadata(metadata);
+
+    let mut writer = IpcRecordWriter::new(output, format).with_schema(schema);
+
+    for record in rejects.filter("BAM", reader) {
+        writer.write_serde_record(parquet_writer::SamRow::from(&record?))?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    use arrow::ipc::reader::FileReader;
+    use serde::Serialize;
+
+    use crate::types::{FastqRecord, Quality};
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Feature {
+        name: String,
+        score: Option<f64>,
+        tags: BTreeMap<String, Vec<String>>,
+        blocks: Vec<(u32, u32)>,
+    }
+
+    #[test]
+    fn test_schema() {
+        let mut batches = RecordBatchBuffer::new(DEFAULT_BATCH_SIZE);
+
+        for (name, score) in [("a", None), ("b", Some(1.5))] {
+            let mut tags = BTreeMap::new();
+            tags.insert(String::from("Parent"), vec![String::from("g1")]);
+
+            let feature = Feature {
+                name: String::from(name),
+                score,
+                tags,
+                blocks: vec![(0, 10)],
+            };
+            batches.push(&feature).unwrap();
+        }
+
+        let batch = batches.flush().unwrap().unwrap();
+        let schema = batch.schema();
+
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(schema.field(0).data_type(), &DataType::Utf8);
+        assert_eq!(schema.field(1).data_type(), &DataType::Float64);
+        assert!(matches!(schema.field(2).data_type(), DataType::Map(_, _)));
+        assert_eq!(
+            schema.field(3).data_type(),
+            &DataType::List(Box::new(Field::new(
+                "item",
+                DataType::List(Box::new(Field::new("item", DataType::UInt32, true))),
+                true
+            )))
+        );
+        assert_eq!(batch.column(1).null_count(), 1);
+
+        assert!(batches.push(vec!["not", "a", "struct"]).is_err());
+    }
+
+    #[test]
+    fn test_schema_type_change() {
+        let feature = |name: &str, score: Option<f64>| Feature {
+            name: String::from(name),
+            score,
+            tags: BTreeMap::new(),
+            blocks: Vec::new(),
+        };
+
+        let mut batches = RecordBatchBuffer::new(DEFAULT_BATCH_SIZE);
+        batches.push(feature("a", None)).unwrap();
+        let batch = batches.flush().unwrap().unwrap();
+        assert_eq!(batch.schema().field(1).data_type(), &DataType::Utf8);
+
+        // Later batches may be null where the first wasn't, but the score can't become a float
+        // after it was written as a string.
+        batches.push(feature("b", None)).unwrap();
+        assert!(batches.flush().unwrap().is_some());
+
+        batches.push(feature("c", Some(1.5))).unwrap();
+        let err = batches.flush().unwrap_err();
+        assert!(err.to_string().contains("score"), "{}", err);
+    }
+
+    #[test]
+    fn test_ipc_record_writer() {
+        let mut writer = IpcRecordWriter::new(Cursor::new(Vec::new()), IpcFormat::FILE);
+
+        for quality in [vec![40, 30], vec![20]] {
+            let record = FastqRecord {
+                id: String::from("r0"),
+                sequence: String::from("AC"),
+                quality: Quality::Phred(quality),
+                ..Default::default()
+            };
+            writer.write_serde_record(record).unwrap();
+        }
+
+        let output = writer.finish().unwrap();
+
+        let mut reader = FileReader::try_new(Cursor::new(output.into_inner()), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch
+                .schema()
+                .field_with_name("quality")
+                .unwrap()
+                .data_type(),
+            &DataType::List(Box::new(Field::new("item", DataType::UInt8, true)))
+        );
+        assert_eq!(
+            batch
+                .schema()
+                .field_with_name("description")
+                .unwrap()
+                .data_type(),
+            &DataType::Utf8
+        );
+    }
+}
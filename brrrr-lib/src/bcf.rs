@@ -0,0 +1,438 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+use noodles::bgzf;
+
+use crate::types::{VcfRecord, VcfValue};
+use crate::vcf::{Definition, Header, ValueType};
+
+const MAGIC: &[u8] = b"BCF\x02";
+
+/// The largest header or record block read, which bounds what a corrupt length can allocate.
+const MAX_BLOCK_LEN: u32 = 1 << 28;
+
+const FLOAT_MISSING: u32 = 0x7F80_0001;
+const FLOAT_END_OF_VECTOR: u32 = 0x7F80_0002;
+
+/// A BCF typed value, decoded from its type descriptor. Integer and float vectors are truncated
+/// at the end-of-vector marker and keep missing elements as `None`.
+#[derive(Debug, PartialEq)]
+enum Typed {
+    Missing,
+    Integer(Vec<Option<i32>>),
+    Float(Vec<Option<f32>>),
+    String(String),
+}
+
+/// Reads a BCF header and its records from a BGZF-compressed source.
+pub struct Reader<R> {
+    inner: bgzf::Reader<R>,
+}
+
+impl<R: Read> Reader<R> {
+    /// Creates a new BCF reader.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: bgzf::Reader::new(inner),
+        }
+    }
+
+    /// Reads the magic number and the VCF header text.
+    pub fn read_header(&mut self) -> io::Result<Header> {
+        let mut magic = [0; 5];
+        self.inner.read_exact(&mut magic)?;
+
+        if &magic[..4] != MAGIC {
+            return Err(invalid_data("invalid BCF magic number".to_string()));
+        }
+
+        let l_text = read_u32(&mut self.inner)?;
+        let text = read_block(&mut self.inner, l_text)?;
+
+        let text = std::str::from_utf8(&text)
+            .map_err(|e| invalid_data(format!("invalid BCF header: {}", e)))?;
+
+        text.trim_end_matches('\0').parse()
+    }
+
+    /// Returns an iterator over the records, typed by `header`.
+    pub fn records<'a>(&'a mut self, header: &'a Header) -> Records<'a, R> {
+        Records {
+            reader: self,
+            header,
+        }
+    }
+
+    fn read_record(&mut self, header: &Header) -> io::Result<Option<VcfRecord>> {
+        let mut lengths = [0; 8];
+
+        match self.inner.read(&mut lengths[..1])? {
+            0 => return Ok(None),
+            _ => self.inner.read_exact(&mut lengths[1..])?,
+        }
+
+        let l_shared = u32::from_le_bytes([lengths[0], lengths[1], lengths[2], lengths[3]]);
+        let l_indiv = u32::from_le_bytes([lengths[4], lengths[5], lengths[6], lengths[7]]);
+
+        let shared = read_block(&mut self.inner, l_shared)?;
+        let indiv = read_block(&mut self.inner, l_indiv)?;
+
+        decode_record(header, &shared, &indiv).map(Some)
+    }
+}
+
+/// An iterator over the records of a BCF.
+pub struct Records<'a, R> {
+    reader: &'a mut Reader<R>,
+    header: &'a Header,
+}
+
+impl<'a, R: Read> Iterator for Records<'a, R> {
+    type Item = io::Result<VcfRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.read_record(self.header).transpose()
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Reads a block of `len` bytes. The buffer grows with what is actually read, so a corrupt
+/// length fails at the end of the input instead of allocating it up front.
+fn read_block<R: Read>(reader: &mut R, len: u32) -> io::Result<Vec<u8>> {
+    if len > MAX_BLOCK_LEN {
+        return Err(invalid_data(format!("invalid BCF block length: {}", len)));
+    }
+
+    let mut buf = Vec::new();
+    reader.take(u64::from(len)).read_to_end(&mut buf)?;
+
+    if buf.len() < len as usize {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+
+    Ok(buf)
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if buf.len() < n {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+
+    let (head, tail) = buf.split_at(n);
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_i32(buf: &mut &[u8]) -> io::Result<i32> {
+    let b = take(buf, 4)?;
+    Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn dictionary_entry(dictionary: &[String], i: i32) -> io::Result<&str> {
+    usize::try_from(i)
+        .ok()
+        .and_then(|i| dictionary.get(i))
+        .map(|s| s.as_str())
+        .ok_or_else(|| invalid_data(format!("invalid BCF dictionary index: {}", i)))
+}
+
+/// Reads a type descriptor byte, returning the type and the number of values that follow.
+fn read_type_descriptor(buf: &mut &[u8]) -> io::Result<(u8, usize)> {
+    let descriptor = take(buf, 1)?[0];
+    let ty = descriptor & 0x0F;
+    let mut len = usize::from(descriptor >> 4);
+
+    if len == 15 {
+        len = match read_typed(buf)? {
+            Typed::Integer(values) => match values.first() {
+                Some(Some(n)) => *n as usize,
+                _ => return Err(invalid_data("invalid BCF vector length".to_string())),
+            },
+            _ => return Err(invalid_data("invalid BCF vector length".to_string())),
+        };
+    }
+
+    Ok((ty, len))
+}
+
+fn read_values(buf: &mut &[u8], ty: u8, len: usize) -> io::Result<Typed> {
+    fn integers<const N: usize>(
+        buf: &mut &[u8],
+        len: usize,
+        decode: fn([u8; N]) -> i32,
+        missing: i32,
+    ) -> io::Result<Typed> {
+        let mut values = Vec::with_capacity(len);
+        let mut ended = false;
+
+        for chunk in take(buf, len * N)?.chunks_exact(N) {
+            let mut bytes = [0; N];
+            bytes.copy_from_slice(chunk);
+            let value = decode(bytes);
+
+            if value == missing + 1 || ended {
+                ended = true;
+            } else if value == missing {
+                values.push(None);
+            } else {
+                values.push(Some(value));
+            }
+        }
+
+        Ok(Typed::Integer(values))
+    }
+
+    match ty {
+        0 => Ok(Typed::Missing),
+        1 => integers::<1>(buf, len, |b| i32::from(b[0] as i8), i32::from(i8::MIN)),
+        2 => integers::<2>(
+            buf,
+            len,
+            |b| i32::from(i16::from_le_bytes(b)),
+            i32::from(i16::MIN),
+        ),
+        3 => integers::<4>(buf, len, i32::from_le_bytes, i32::MIN),
+        5 => {
+            let mut values = Vec::with_capacity(len);
+
+            for chunk in take(buf, len * 4)?.chunks_exact(4) {
+                let bits = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                match bits {
+                    FLOAT_END_OF_VECTOR => break,
+                    FLOAT_MISSING => values.push(None),
+                    b => values.push(Some(f32::from_bits(b))),
+                }
+            }
+
+            Ok(Typed::Float(values))
+        }
+        7 => {
+            let bytes = take(buf, len)?;
+            let s = std::str::from_utf8(bytes)
+                .map_err(|e| invalid_data(format!("invalid BCF string: {}", e)))?;
+            Ok(Typed::String(s.trim_end_matches('\0').to_string()))
+        }
+        t => Err(invalid_data(format!("invalid BCF value type: {}", t))),
+    }
+}
+
+fn read_typed(buf: &mut &[u8]) -> io::Result<Typed> {
+    let (ty, len) = read_type_descriptor(buf)?;
+    read_values(buf, ty, len)
+}
+
+fn read_typed_int(buf: &mut &[u8]) -> io::Result<i32> {
+    match read_typed(buf)? {
+        Typed::Integer(values) if values.len() == 1 => {
+            values[0].ok_or_else(|| invalid_data("missing BCF dictionary index".to_string()))
+        }
+        _ => Err(invalid_data("expected a BCF integer".to_string())),
+    }
+}
+
+fn read_typed_string(buf: &mut &[u8]) -> io::Result<String> {
+    match read_typed(buf)? {
+        Typed::String(s) => Ok(s),
+        Typed::Missing => Ok(String::new()),
+        _ => Err(invalid_data("expected a BCF string".to_string())),
+    }
+}
+
+/// Converts a typed value to a `VcfValue` using the header definition, or the encoded type
+/// when the key is undefined.
+fn to_vcf_value(definition: Option<&Definition>, typed: Typed) -> Option<VcfValue> {
+    let scalar = definition.is_some_and(|d| d.is_scalar());
+
+    if definition.is_some_and(|d| d.ty == ValueType::Flag) {
+        return Some(VcfValue::Flag(true));
+    }
+
+    match typed {
+        Typed::Missing => None,
+        Typed::Integer(values) if values.iter().all(Option::is_none) => None,
+        Typed::Integer(values) if scalar || (definition.is_none() && values.len() == 1) => {
+            values[0].map(VcfValue::Integer)
+        }
+        Typed::Integer(values) => Some(VcfValue::IntegerArray(values)),
+        Typed::Float(values) if values.iter().all(Option::is_none) => None,
+        Typed::Float(values) if scalar || (definition.is_none() && values.len() == 1) => {
+            values[0].map(VcfValue::Float)
+        }
+        Typed::Float(values) => Some(VcfValue::FloatArray(values)),
+        Typed::String(s) if s.is_empty() || s == "." => None,
+        Typed::String(s) if scalar || (definition.is_none() && !s.contains(',')) => {
+            Some(VcfValue::String(s))
+        }
+        Typed::String(s) => Some(VcfValue::StringArray(
+            s.split(',')
+                .map(|v| match v {
+                    "." => None,
+                    v => Some(v.to_string()),
+                })
+                .collect(),
+        )),
+    }
+}
+
+/// Converts BCF genotype integers, i.e. `(allele + 1) << 1 | phased`, back to a `GT` string.
+fn genotype(values: &[Option<i32>]) -> Option<String> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut gt = String::new();
+
+    for (i, value) in values.iter().enumerate() {
+        let value = value.unwrap_or(0);
+
+        if i > 0 {
+            gt.push(if value & 1 == 1 { '|' } else { '/' });
+        }
+
+        match (value >> 1) - 1 {
+            -1 => gt.push('.'),
+            allele => gt.push_str(&allele.to_string()),
+        }
+    }
+
+    Some(gt)
+}
+
+fn decode_record(header: &Header, shared: &[u8], indiv: &[u8]) -> io::Result<VcfRecord> {
+    let mut buf = shared;
+
+    let chrom = dictionary_entry(&header.contig_dictionary, take_i32(&mut buf)?)?.to_string();
+    let pos = take_i32(&mut buf)? + 1;
+    let _rlen = take_i32(&mut buf)?;
+
+    let quality = match take_i32(&mut buf)? as u32 {
+        FLOAT_MISSING => None,
+        bits => Some(f32::from_bits(bits)),
+    };
+
+    let n_allele_info = take_i32(&mut buf)? as u32;
+    let n_allele = (n_allele_info >> 16) as usize;
+    let n_info = (n_allele_info & 0xFFFF) as usize;
+
+    let n_fmt_sample = take_i32(&mut buf)? as u32;
+    let n_fmt = (n_fmt_sample >> 24) as usize;
+    let n_sample = (n_fmt_sample & 0x00FF_FFFF) as usize;
+
+    let id = match read_typed_string(&mut buf)? {
+        s if s.is_empty() || s == "." => None,
+        s => Some(s),
+    };
+
+    let mut alleles = (0..n_allele)
+        .map(|_| read_typed_string(&mut buf))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter();
+    let reference = alleles.next().unwrap_or_default();
+    let alternate = alleles.collect();
+
+    let filter = match read_typed(&mut buf)? {
+        Typed::Integer(indices) => indices
+            .into_iter()
+            .flatten()
+            .map(|i| dictionary_entry(&header.string_dictionary, i).map(String::from))
+            .collect::<io::Result<_>>()?,
+        _ => Vec::new(),
+    };
+
+    let mut info = BTreeMap::new();
+    for _ in 0..n_info {
+        let key = dictionary_entry(&header.string_dictionary, read_typed_int(&mut buf)?)?;
+        let typed = read_typed(&mut buf)?;
+
+        if let Some(value) = to_vcf_value(header.info(key), typed) {
+            info.insert(key.to_string(), value);
+        }
+    }
+
+    let mut samples: BTreeMap<String, BTreeMap<String, VcfValue>> = header
+        .sample_names
+        .iter()
+        .take(n_sample)
+        .map(|name| (name.clone(), BTreeMap::new()))
+        .collect();
+
+    let mut buf = indiv;
+    for _ in 0..n_fmt {
+        let key = dictionary_entry(&header.string_dictionary, read_typed_int(&mut buf)?)?;
+        let (ty, len) = read_type_descriptor(&mut buf)?;
+
+        for name in header.sample_names.iter().take(n_sample) {
+            let typed = read_values(&mut buf, ty, len)?;
+
+            let value = match (key, typed) {
+                ("GT", Typed::Integer(values)) => genotype(&values).map(VcfValue::String),
+                (_, typed) => to_vcf_value(header.format(key), typed),
+            };
+
+            if let (Some(value), Some(sample)) = (value, samples.get_mut(name)) {
+                sample.insert(key.to_string(), value);
+            }
+        }
+    }
+
+    Ok(VcfRecord {
+        chrom,
+        pos: pos as usize,
+        id,
+        reference,
+        alternate,
+        quality,
+        filter,
+        info,
+        samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_values() {
+        let mut buf = &[0x31, 0x05, 0x80, 0x81][..];
+        assert_eq!(
+            read_typed(&mut buf).unwrap(),
+            Typed::Integer(vec![Some(5), None])
+        );
+
+        let mut buf = &[0x37, b'A', b'C', 0x00][..];
+        assert_eq!(read_typed(&mut buf).unwrap(), Typed::String("AC".into()));
+    }
+
+    #[test]
+    fn test_read_block() {
+        let mut input = &b"ACGT"[..];
+        assert_eq!(read_block(&mut input, 2).unwrap(), b"AC");
+
+        let error = read_block(&mut input, 3).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let error = read_block(&mut &b"ACGT"[..], u32::MAX).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_genotype() {
+        assert_eq!(genotype(&[Some(2), Some(4)]), Some("0/1".into()));
+        assert_eq!(genotype(&[Some(2), Some(5)]), Some("0|1".into()));
+        assert_eq!(genotype(&[Some(0), Some(0)]), Some("./.".into()));
+    }
+}
@@ -0,0 +1,372 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::convert::COMPRESSION_EXTENSIONS;
+use crate::types::BedRecord;
+
+/// The BED column names, in file order.
+pub const BED_COLUMNS: [&str; 12] = [
+    "chrom",
+    "start",
+    "end",
+    "name",
+    "score",
+    "strand",
+    "thick_start",
+    "thick_end",
+    "item_rgb",
+    "block_count",
+    "block_sizes",
+    "block_starts",
+];
+
+/// The bedGraph column names, in file order.
+pub const BEDGRAPH_COLUMNS: [&str; 4] = ["chrom", "start", "end", "value"];
+
+/// The flavor of a BED-like file, i.e. BED3 through BED12 or bedGraph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BedKind {
+    Bed(usize),
+    BedGraph,
+}
+
+impl BedKind {
+    /// Returns the column names for this kind of file.
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            BedKind::Bed(width) => &BED_COLUMNS[..*width],
+            BedKind::BedGraph => &BEDGRAPH_COLUMNS,
+        }
+    }
+}
+
+/// Whether a file is read as BED or bedGraph, overriding the detection in `Reader::read_header`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BedFormat {
+    Bed,
+    BedGraph,
+}
+
+impl BedFormat {
+    /// Infers the format from the extension of `path`, skipping a compression extension such as
+    /// `.gz`, i.e. `.bedgraph` or `.bg` is bedGraph and `.bed` is BED.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<BedFormat> {
+        let path = path.as_ref();
+        let mut extension = path.extension()?.to_str()?;
+
+        if COMPRESSION_EXTENSIONS.contains(&extension) {
+            extension = Path::new(path.file_stem()?).extension()?.to_str()?;
+        }
+
+        match extension.to_ascii_lowercase().as_str() {
+            "bedgraph" | "bg" => Some(BedFormat::BedGraph),
+            "bed" => Some(BedFormat::Bed),
+            _ => None,
+        }
+    }
+}
+
+/// The `track`, `browser` and comment lines that precede the records, along with the detected
+/// kind of file.
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub lines: Vec<String>,
+    pub kind: BedKind,
+}
+
+fn is_header_line(line: &str) -> bool {
+    line.starts_with("track") || line.starts_with("browser") || line.starts_with('#')
+}
+
+fn split_fields(line: &str) -> Vec<&str> {
+    if line.contains('\t') {
+        line.split('\t').collect()
+    } else {
+        line.split_whitespace().collect()
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Detects the kind of file from the header lines and the first record. Unless `format` says
+/// otherwise, only a file with a `type=bedGraph` track line is taken to be bedGraph, and any
+/// other file is BED with as many columns as its first record.
+fn detect_kind(
+    lines: &[String],
+    first_record: Option<&str>,
+    format: Option<BedFormat>,
+) -> io::Result<BedKind> {
+    let is_bedgraph = match format {
+        Some(format) => format == BedFormat::BedGraph,
+        None => lines
+            .iter()
+            .any(|l| l.starts_with("track") && l.contains("type=bedGraph")),
+    };
+
+    if is_bedgraph {
+        return Ok(BedKind::BedGraph);
+    }
+
+    let fields = match first_record {
+        Some(line) => split_fields(line),
+        None => return Ok(BedKind::Bed(3)),
+    };
+
+    match fields.len() {
+        n @ 3..=12 => Ok(BedKind::Bed(n)),
+        n => Err(invalid_data(format!(
+            "expected between 3 and 12 BED columns, found {}",
+            n
+        ))),
+    }
+}
+
+/// Reads BED or bedGraph header lines and records from a buffered reader.
+pub struct Reader<R> {
+    inner: R,
+    pending: Option<String>,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a new BED reader.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending: None,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            if self.inner.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            let trimmed = line.trim_end_matches(&['\n', '\r'][..]);
+            if !trimmed.trim().is_empty() {
+                return Ok(Some(trimmed.to_string()));
+            }
+        }
+    }
+
+    /// Reads the header lines up to the first record, and detects the kind of file, unless it is
+    /// given by `format`.
+    pub fn read_header(&mut self, format: Option<BedFormat>) -> io::Result<Header> {
+        let mut lines = Vec::new();
+
+        while let Some(line) = self.read_line()? {
+            if is_header_line(&line) {
+                lines.push(line);
+            } else {
+                self.pending = Some(line);
+                break;
+            }
+        }
+
+        let kind = detect_kind(&lines, self.pending.as_deref(), format)?;
+        Ok(Header { lines, kind })
+    }
+
+    /// Returns an iterator over the records, parsed according to `header`.
+    pub fn records<'a>(&'a mut self, header: &'a Header) -> Records<'a, R> {
+        Records {
+            reader: self,
+            kind: header.kind,
+        }
+    }
+}
+
+/// An iterator over the records of a BED file.
+pub struct Records<'a, R> {
+    reader: &'a mut Reader<R>,
+    kind: BedKind,
+}
+
+impl<'a, R: BufRead> Iterator for Records<'a, R> {
+    type Item = io::Result<BedRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.reader.pending.take() {
+                Some(line) => line,
+                None => match self.reader.read_line() {
+                    Ok(Some(line)) => line,
+                    Ok(None) => return None,
+                    Err(e) => return Some(Err(e)),
+                },
+            };
+
+            if !is_header_line(&line) {
+                return Some(parse_record(self.kind, &line));
+            }
+        }
+    }
+}
+
+fn parse_field<T: FromStr>(name: &str, s: &str) -> io::Result<T> {
+    s.parse()
+        .map_err(|_| invalid_data(format!("invalid {}: {}", name, s)))
+}
+
+fn parse_optional<T: FromStr>(name: &str, s: &str) -> io::Result<Option<T>> {
+    match s {
+        "." => Ok(None),
+        s => parse_field(name, s).map(Some),
+    }
+}
+
+fn parse_list(name: &str, s: &str) -> io::Result<Vec<u64>> {
+    s.split(',')
+        .filter(|v| !v.is_empty())
+        .map(|v| parse_field(name, v))
+        .collect()
+}
+
+/// Parses a single BED or bedGraph line. Columns past the width of `kind` are ignored.
+pub fn parse_record(kind: BedKind, line: &str) -> io::Result<BedRecord> {
+    let fields = split_fields(line);
+    let columns = kind.columns();
+
+    if fields.len() < columns.len() {
+        return Err(invalid_data(format!(
+            "expected {} BED columns, found {}: {}",
+            columns.len(),
+            fields.len(),
+            line
+        )));
+    }
+
+    let mut record = BedRecord {
+        chrom: fields[0].to_string(),
+        start: parse_field("start", fields[1])?,
+        end: parse_field("end", fields[2])?,
+        ..Default::default()
+    };
+
+    if kind == BedKind::BedGraph {
+        record.value = Some(parse_field("value", fields[3])?);
+        return Ok(record);
+    }
+
+    for (name, field) in columns.iter().zip(fields.iter()).skip(3) {
+        match *name {
+            "name" => record.name = Some(field.to_string()),
+            "score" => record.score = parse_optional(name, field)?,
+            "strand" => record.strand = Some(field.to_string()),
+            "thick_start" => record.thick_start = Some(parse_field(name, field)?),
+            "thick_end" => record.thick_end = Some(parse_field(name, field)?),
+            "item_rgb" => record.item_rgb = Some(field.to_string()),
+            "block_count" => record.block_count = Some(parse_field(name, field)?),
+            "block_sizes" => record.block_sizes = Some(parse_list(name, field)?),
+            "block_starts" => record.block_starts = Some(parse_list(name, field)?),
+            _ => continue,
+        }
+    }
+
+    Ok(record)
+}
+
+fn join_list(values: &Option<Vec<u64>>) -> String {
+    match values {
+        Some(values) => values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        None => String::from("."),
+    }
+}
+
+/// Returns the values of `record` as strings, in column order for `kind`.
+pub fn record_fields(kind: BedKind, record: &BedRecord) -> Vec<String> {
+    fn or_missing<T: ToString>(value: &Option<T>) -> String {
+        value.as_ref().map_or(String::from("."), |v| v.to_string())
+    }
+
+    kind.columns()
+        .iter()
+        .map(|name| match *name {
+            "chrom" => record.chrom.clone(),
+            "start" => record.start.to_string(),
+            "end" => record.end.to_string(),
+            "name" => or_missing(&record.name),
+            "score" => or_missing(&record.score),
+            "strand" => or_missing(&record.strand),
+            "thick_start" => or_missing(&record.thick_start),
+            "thick_end" => or_missing(&record.thick_end),
+            "item_rgb" => or_missing(&record.item_rgb),
+            "block_count" => or_missing(&record.block_count),
+            "block_sizes" => join_list(&record.block_sizes),
+            "block_starts" => join_list(&record.block_starts),
+            "value" => or_missing(&record.value),
+            _ => String::from("."),
+        })
+        .collect()
+}
+
+/// Formats `record` as a tab-delimited BED or bedGraph line.
+pub fn format_record(kind: BedKind, record: &BedRecord) -> String {
+    record_fields(kind, record).join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_kind() {
+        let mut reader = Reader::new(&b"chr1\t0\t10\n"[..]);
+        assert_eq!(reader.read_header(None).unwrap().kind, BedKind::Bed(3));
+
+        let mut reader = Reader::new(&b"browser position chr1:1-100\nchr1\t0\t10\tx\t5\t+\n"[..]);
+        let header = reader.read_header(None).unwrap();
+        assert_eq!(header.kind, BedKind::Bed(6));
+        assert_eq!(header.lines, vec!["browser position chr1:1-100"]);
+
+        // A numeric name column is still BED4 unless bedGraph is asked for.
+        let bed4 = b"chr1\t0\t10\t0.75\n";
+        let mut reader = Reader::new(&bed4[..]);
+        assert_eq!(reader.read_header(None).unwrap().kind, BedKind::Bed(4));
+
+        let mut reader = Reader::new(&bed4[..]);
+        let header = reader.read_header(Some(BedFormat::BedGraph)).unwrap();
+        assert_eq!(header.kind, BedKind::BedGraph);
+
+        let mut reader = Reader::new(&b"track type=bedGraph\nchr1\t0\t10\t0.75\n"[..]);
+        assert_eq!(reader.read_header(None).unwrap().kind, BedKind::BedGraph);
+
+        let mut reader = Reader::new(&b"chr1\t0\n"[..]);
+        assert!(reader.read_header(None).is_err());
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(
+            BedFormat::from_extension("a.bedGraph.gz"),
+            Some(BedFormat::BedGraph)
+        );
+        assert_eq!(BedFormat::from_extension("a.bg"), Some(BedFormat::BedGraph));
+        assert_eq!(BedFormat::from_extension("a.bed"), Some(BedFormat::Bed));
+        assert_eq!(BedFormat::from_extension("a.txt"), None);
+    }
+
+    #[test]
+    fn test_bed12_round_trip() {
+        let line = "chr1\t10\t200\tfeat\t0\t-\t20\t180\t255,0,0\t2\t50,40\t0,150";
+        let record = parse_record(BedKind::Bed(12), line).unwrap();
+
+        assert_eq!(record.strand, Some(String::from("-")));
+        assert_eq!(record.block_sizes, Some(vec![50, 40]));
+        assert_eq!(record.block_starts, Some(vec![0, 150]));
+        assert_eq!(format_record(BedKind::Bed(12), &record), line);
+    }
+}
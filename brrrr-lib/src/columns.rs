@@ -0,0 +1,225 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind};
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+
+use crate::errors::BrrrrError;
+use crate::types::{FastaRecord, FastqRecord, GffRecord};
+
+/// A record that can be read from named columns, e.g. the keys of jsonl objects or the header
+/// row of a csv.
+pub trait FromColumns: DeserializeOwned {
+    /// The fields that every input must have a column for.
+    const REQUIRED: &'static [&'static str];
+}
+
+impl FromColumns for FastaRecord {
+    const REQUIRED: &'static [&'static str] = &["id", "sequence"];
+}
+
+impl FromColumns for FastqRecord {
+    const REQUIRED: &'static [&'static str] = &["id", "sequence", "quality"];
+}
+
+impl FromColumns for GffRecord {
+    const REQUIRED: &'static [&'static str] =
+        &["seqname", "source", "feature", "start", "end", "strand"];
+}
+
+/// ColumnMap maps the fields of brrrr records to the columns of an input that names them
+/// differently, e.g. `seq` rather than `sequence`. Fields that aren't mapped are read from the
+/// column of the same name.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::columns::ColumnMap;
+///
+/// let columns = ColumnMap::new().with_column("sequence", "seq");
+/// assert_eq!(columns.column("sequence"), "seq");
+/// assert_eq!(columns.field("seq"), Some("sequence"));
+/// assert_eq!(columns.field("sequence"), None);
+/// assert_eq!(columns.field("id"), Some("id"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColumnMap {
+    columns: BTreeMap<String, String>,
+}
+
+impl ColumnMap {
+    /// Creates a new ColumnMap that reads every field from the column of the same name.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `field` from `column`.
+    pub fn with_column(mut self, field: &str, column: &str) -> Self {
+        self.columns.insert(field.to_string(), column.to_string());
+        self
+    }
+
+    /// Returns true if every field is read from the column of the same name.
+    pub fn is_empty(&self) -> bool {
+        self.columns.iter().all(|(field, column)| field == column)
+    }
+
+    /// Returns the column that `field` is read from.
+    pub fn column<'a>(&'a self, field: &'a str) -> &'a str {
+        self.columns.get(field).map_or(field, String::as_str)
+    }
+
+    /// Returns the field that `column` is read into, or None if the field of the same name is
+    /// read from another column.
+    pub fn field<'a>(&'a self, column: &'a str) -> Option<&'a str> {
+        match self.columns.iter().find(|(_, c)| *c == column) {
+            Some((field, _)) => Some(field),
+            None if self.columns.contains_key(column) => None,
+            None => Some(column),
+        }
+    }
+
+    /// Returns a MissingColumn error for the first of the fields of `T` that `columns` doesn't
+    /// have.
+    pub(crate) fn check<T, S>(&self, columns: &[S]) -> Result<(), BrrrrError>
+    where
+        T: FromColumns,
+        S: AsRef<str>,
+    {
+        for field in T::REQUIRED {
+            let column = self.column(field);
+
+            if !columns.iter().any(|c| c.as_ref() == column) {
+                return Err(BrrrrError::MissingColumn(column.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Column(String),
+}
+
+/// Template composes a value from the columns of a record, e.g. a FASTA description of
+/// `{organism} len={length}`. `{{` and `}}` are written as literal braces.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::columns::Template;
+///
+/// let template: Template = "{organism} len={length}".parse().unwrap();
+/// let value = template
+///     .render(|column| Ok(if column == "organism" { "E. coli" } else { "42" }.to_string()))
+///     .unwrap();
+/// assert_eq!(value, "E. coli len=42");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Returns the columns the template reads, in the order they appear.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Column(column) => Some(column.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// Returns the template with each column replaced by its value from `value`.
+    pub fn render<F>(&self, mut value: F) -> Result<String, BrrrrError>
+    where
+        F: FnMut(&str) -> Result<String, BrrrrError>,
+    {
+        let mut rendered = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Column(column) => rendered.push_str(&value(column)?),
+            }
+        }
+
+        Ok(rendered)
+    }
+}
+
+impl FromStr for Template {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || io::Error::new(ErrorKind::InvalidInput, format!("invalid template: {}", s));
+
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut column = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') if !column.is_empty() => break,
+                            Some(c) if c != '{' && c != '}' => column.push(c),
+                            _ => return Err(invalid()),
+                        }
+                    }
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Column(column));
+                }
+                '}' => return Err(invalid()),
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let template: Template = "{{{id}}} {organism}".parse().unwrap();
+        assert_eq!(
+            template.columns().collect::<Vec<_>>(),
+            vec!["id", "organism"]
+        );
+
+        let value = template.render(|column| Ok(column.to_uppercase()));
+        assert_eq!(value.unwrap(), "{ID} ORGANISM");
+
+        for invalid in ["{id", "{}", "id}", "{a{b}"] {
+            assert!(invalid.parse::<Template>().is_err(), "{}", invalid);
+        }
+    }
+}
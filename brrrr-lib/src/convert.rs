@@ -0,0 +1,601 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use noodles::bam;
+use noodles::fasta;
+use noodles::fastq;
+use noodles::sam;
+
+use crate::csv_writer;
+use crate::errors::BrrrrError;
+use crate::gff;
+use crate::input;
+use crate::output;
+use crate::parquet_writer::{self, ParquetOptions};
+use crate::quality::QualityOptions;
+use crate::reader::{
+    BamReader, CsvReader, FastaReader, FastqReader, GffReader, JsonlReader, ParquetReader,
+    RecordReader,
+};
+pub use crate::reader::{Header, Record};
+use crate::rejects::Rejects;
+use crate::types::{FastaRecord, Quality};
+
+/// The extensions of compressed files, which are skipped when inferring a format.
+pub(crate) const COMPRESSION_EXTENSIONS: &[&str] =
+    &["gz", "bgz", "bgzf", "zst", "zstd", "bz2", "xz"];
+
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+const BAM_MAGIC: &[u8] = b"BAM\x01";
+
+/// A file format that `convert` reads or writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Fasta,
+    Fastq,
+    Gff,
+    Gtf,
+    Bam,
+    Parquet,
+    Jsonl,
+    Csv,
+}
+
+impl Format {
+    /// Returns the name of the format.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Fasta => "fasta",
+            Format::Fastq => "fastq",
+            Format::Gff => "gff",
+            Format::Gtf => "gtf",
+            Format::Bam => "bam",
+            Format::Parquet => "parquet",
+            Format::Jsonl => "jsonl",
+            Format::Csv => "csv",
+        }
+    }
+
+    /// Infers the format from the extension of `path`, skipping a compression extension such as
+    /// `.gz`, e.g. `reads.fq.gz` is FASTQ.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let path = path.as_ref();
+        let mut extension = path.extension()?.to_str()?;
+
+        if COMPRESSION_EXTENSIONS.contains(&extension) {
+            extension = Path::new(path.file_stem()?).extension()?.to_str()?;
+        }
+
+        extension.parse().ok()
+    }
+
+    /// Detects the format of the file at `path` from its first bytes, falling back to its
+    /// extension when the content is ambiguous, e.g. a GFF file without a version directive.
+    pub fn detect<P: AsRef<Path>>(path: P) -> io::Result<Option<Format>> {
+        let mut magic = Vec::new();
+        File::open(&path)?.take(4).read_to_end(&mut magic)?;
+
+        if magic == PARQUET_MAGIC {
+            return Ok(Some(Format::Parquet));
+        }
+
+        let mut reader = input::open(&path, None)?;
+        let buf = reader.fill_buf()?;
+
+        if buf.starts_with(BAM_MAGIC) {
+            return Ok(Some(Format::Bam));
+        }
+
+        let detected = match buf.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'>') => Some(Format::Fasta),
+            Some(b'@') => Some(Format::Fastq),
+            Some(b'{') => Some(Format::Jsonl),
+            _ if buf.starts_with(format!("{} 3", gff::VERSION_DIRECTIVE).as_bytes()) => {
+                Some(Format::Gff)
+            }
+            _ if buf.starts_with(format!("{} 2", gff::VERSION_DIRECTIVE).as_bytes()) => {
+                Some(Format::Gtf)
+            }
+            _ => None,
+        };
+
+        Ok(detected.or_else(|| Format::from_extension(&path)))
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = BrrrrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fasta" | "fa" | "fna" | "faa" => Ok(Format::Fasta),
+            "fastq" | "fq" => Ok(Format::Fastq),
+            "gff" | "gff3" => Ok(Format::Gff),
+            "gtf" | "gff2" => Ok(Format::Gtf),
+            "bam" => Ok(Format::Bam),
+            "parquet" | "pq" => Ok(Format::Parquet),
+            "jsonl" | "ndjson" | "json" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            _ => Err(BrrrrError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// The records of an input, which are read lazily.
+pub struct Source {
+    pub header: Header,
+    pub records: Box<dyn Iterator<Item = Result<Record, BrrrrError>>>,
+}
+
+impl Iterator for Source {
+    type Item = Result<Record, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next()
+    }
+}
+
+impl RecordReader for Source {
+    type Header = Header;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
+
+impl Source {
+    fn new<R>(header: Header, reader: R) -> Self
+    where
+        R: Iterator<Item = Result<Record, BrrrrError>> + 'static,
+    {
+        Self {
+            header,
+            records: Box::new(reader),
+        }
+    }
+
+    fn from_reader<R>(reader: R) -> Self
+    where
+        R: RecordReader<Header = Header, Item = Result<Record, BrrrrError>> + 'static,
+    {
+        Self::new(reader.header().clone(), reader)
+    }
+}
+
+/// Opens the file at `path` as `format`. Text inputs may be compressed as with `input::open`.
+pub fn read<P: AsRef<Path>>(path: P, format: Format) -> Result<Source, BrrrrError> {
+    let path = path.as_ref();
+
+    let source = match format {
+        Format::Fasta => {
+            let reader = FastaReader::new(input::open(path, None)?);
+            Source::new(Header::Fasta, reader.map(|r| r.map(Record::Fasta)))
+        }
+        Format::Fastq => {
+            let reader = FastqReader::new(input::open(path, None)?, &QualityOptions::default());
+            Source::new(Header::Fastq, reader.map(|r| r.map(Record::Fastq)))
+        }
+        Format::Gff | Format::Gtf => {
+            let dialect = match format {
+                Format::Gtf => Some(gff::Dialect::Gtf),
+                _ => None,
+            };
+
+            let reader = GffReader::new(input::open(path, None)?, dialect)?;
+            let header = Header::Gff(reader.header().clone());
+            Source::new(header, reader.map(|r| r.map(Record::Gff)))
+        }
+        Format::Bam => {
+            let reader = BamReader::new(File::open(path)?)?;
+            let header = Header::Sam(Box::new(reader.header().clone()));
+            Source::new(header, reader.map(|r| r.map(Record::Sam)))
+        }
+        Format::Parquet => Source::from_reader(ParquetReader::open(path)?),
+        Format::Jsonl => Source::from_reader(JsonlReader::new(input::open(path, None)?)?),
+        Format::Csv => Source::from_reader(CsvReader::new(input::open(path, None)?)?),
+    };
+
+    Ok(source)
+}
+
+fn unsupported(record: &Record, format: Format) -> BrrrrError {
+    BrrrrError::UnsupportedConversion {
+        records: record.kind().to_string(),
+        format: format.to_string(),
+    }
+}
+
+/// Writes the records of `source` to `path` as `format`. Text outputs are compressed according
+/// to the extension of `path`, as with `output::create`.
+///
+/// FASTQ records may be written to FASTA, and every kind of record may be written to jsonl and
+/// parquet. Writing any other kind of record to a format, e.g. GFF records to FASTQ, is an
+/// `UnsupportedConversion` error.
+pub fn write<P: AsRef<Path>>(
+    source: Source,
+    path: P,
+    format: Format,
+    parquet_options: &ParquetOptions,
+) -> Result<(), BrrrrError> {
+    let Source { header, records } = source;
+    write_records(header, records, path.as_ref(), format, parquet_options)
+}
+
+fn write_records<I>(
+    header: Header,
+    records: I,
+    path: &Path,
+    format: Format,
+    parquet_options: &ParquetOptions,
+) -> Result<(), BrrrrError>
+where
+    I: Iterator<Item = Result<Record, BrrrrError>>,
+{
+    match format {
+        Format::Fasta => {
+            let mut out = output::create(path, None)?;
+            let mut writer = fasta::Writer::new(&mut out);
+
+            for record in records {
+                let record = match record? {
+                    Record::Fasta(r) => r,
+                    Record::Fastq(r) => FastaRecord {
+                        id: r.id,
+                        description: r.description,
+                        sequence: r.sequence,
+                    },
+                    record => return Err(unsupported(&record, format)),
+                };
+
+                let definition = fasta::record::Definition::new(record.id, record.description);
+                let sequence = fasta::record::Sequence::from(record.sequence.into_bytes());
+                writer.write_record(&fasta::Record::new(definition, sequence))?;
+            }
+
+            out.finish()?;
+        }
+        Format::Fastq => {
+            let mut out = output::create(path, None)?;
+            let mut writer = fastq::Writer::new(&mut out);
+
+            for record in records {
+                let record = match record? {
+                    Record::Fastq(r) => r,
+                    record => return Err(unsupported(&record, format)),
+                };
+
+                let quality = record.quality.to_ascii()?;
+                writer.write_record(&fastq::Record::new(record.id, record.sequence, quality))?;
+            }
+
+            out.finish()?;
+        }
+        Format::Gff | Format::Gtf => {
+            let dialect = match format {
+                Format::Gtf => gff::Dialect::Gtf,
+                _ => gff::Dialect::Gff3,
+            };
+
+            let mut out = output::create(path, None)?;
+
+            if let Header::Gff(header) = &header {
+                gff::write_header(&mut out, header, dialect)?;
+            }
+
+            for record in records {
+                match record? {
+                    Record::Gff(r) => writeln!(out, "{}", gff::format_record(dialect, &r))?,
+                    record => return Err(unsupported(&record, format)),
+                }
+            }
+
+            out.finish()?;
+        }
+        Format::Bam => {
+            let header = match header {
+                Header::Sam(header) => *header,
+                _ => sam::Header::default(),
+            };
+
+            let mut writer = bam::Writer::new(File::create(path)?);
+            writer.write_header(&header)?;
+            writer.write_reference_sequences(header.reference_sequences())?;
+
+            for record in records {
+                match record? {
+                    Record::Sam(r) => {
+                        let record = r.to_alignment_record(header.reference_sequences())?;
+                        writer.write_record(&header, &record)?;
+                    }
+                    record => return Err(unsupported(&record, format)),
+                }
+            }
+
+            writer.try_finish()?;
+        }
+        Format::Parquet => write_parquet(header, records, path, parquet_options)?,
+        Format::Jsonl => {
+            let mut out = output::create(path, None)?;
+
+            for record in records {
+                serde_json::to_writer(&mut out, &record?).map_err(io::Error::from)?;
+                out.write_all(b"\n")?;
+            }
+
+            out.finish()?;
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(output::create(path, None)?);
+
+            for record in records {
+                match record? {
+                    Record::Fastq(r) => writer
+                        .serialize(csv_writer::fastq_row(r))
+                        .map_err(io::Error::from)?,
+                    record @ Record::Fasta(_) => {
+                        writer.serialize(record).map_err(io::Error::from)?
+                    }
+                    record => return Err(unsupported(&record, format)),
+                }
+            }
+
+            writer.into_inner().map_err(|e| e.into_error())?.finish()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_parquet<I>(
+    header: Header,
+    records: I,
+    path: &Path,
+    parquet_options: &ParquetOptions,
+) -> Result<(), BrrrrError>
+where
+    I: Iterator<Item = Result<Record, BrrrrError>>,
+{
+    let format = Format::Parquet;
+
+    match header {
+        Header::Fasta => {
+            let records = records.map(|record| match record? {
+                Record::Fasta(r) => Ok(r),
+                record => Err(unsupported(&record, format)),
+            });
+            parquet_writer::write_fasta_records_to_file(records, path, parquet_options)
+        }
+        Header::Fastq => {
+            let mut records = records.peekable();
+
+            // The columns follow the quality of the first record, as fq2pq would write it.
+            let quality_options = match records.peek() {
+                Some(Ok(Record::Fastq(r))) => QualityOptions {
+                    phred_scores: matches!(r.quality, Quality::Phred(_)),
+                    summary: r.expected_errors.is_some(),
+                    ..Default::default()
+                },
+                _ => QualityOptions::default(),
+            };
+
+            let records = records.map(|record| match record? {
+                Record::Fastq(r) => Ok(r),
+                record => Err(unsupported(&record, format)),
+            });
+            parquet_writer::write_fastq_records_to_file(
+                records,
+                path,
+                parquet_options,
+                &quality_options,
+            )
+        }
+        Header::Gff(header) => {
+            let records = records.map(|record| match record? {
+                Record::Gff(r) => Ok(r),
+                record => Err(unsupported(&record, format)),
+            });
+            parquet_writer::write_gff_records_to_file(&header, records, path, parquet_options)
+        }
+        Header::Sam(header) => {
+            let records = records.map(|record| match record? {
+                Record::Sam(r) => Ok(r),
+                record => Err(unsupported(&record, format)),
+            });
+            parquet_writer::write_sam_records_to_file(&header, records, path, parquet_options)
+        }
+    }
+}
+
+/// Converts the file at `input` to `output`. The input format is detected with
+/// `Format::detect`, and the output format is `to` or is inferred from the extension of
+/// `output`.
+///
+/// # Arguments
+///
+/// * `input` - The path to the input file.
+/// * `output` - The path to the output file.
+/// * `to` - The output format, or None to infer it from `output`.
+/// * `parquet_options` - How parquet output is written.
+/// * `rejects` - What to do with records that fail to parse.
+pub fn convert<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    to: Option<Format>,
+    parquet_options: &ParquetOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let from = Format::detect(&input)?.ok_or_else(|| {
+        BrrrrError::UnknownFormat(format!("unable to detect {}", input.as_ref().display()))
+    })?;
+
+    let to = match to {
+        Some(to) => to,
+        None => Format::from_extension(&output).ok_or_else(|| {
+            BrrrrError::UnknownFormat(format!(
+                "unable to infer the format of {}, set it with --to",
+                output.as_ref().display()
+            ))
+        })?,
+    };
+
+    let Source { header, records } = read(input, from)?;
+    write_records(
+        header,
+        rejects.filter(from.as_str(), records),
+        output.as_ref(),
+        to,
+        parquet_options,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use parquet::basic::Compression;
+
+    use super::*;
+
+    #[test]
+    fn test_from_extension() {
+        let cases = [
+            ("reads.fq.gz", Some(Format::Fastq)),
+            ("genes.gtf", Some(Format::Gtf)),
+            ("genes.gff3.zst", Some(Format::Gff)),
+            ("out.parquet", Some(Format::Parquet)),
+            ("out.jsonl.bgz", Some(Format::Jsonl)),
+            ("out.gz", None),
+            ("out.txt", None),
+        ];
+
+        for (path, format) in cases {
+            assert_eq!(Format::from_extension(path), format, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_convert_round_trip() {
+        let dir = env::temp_dir().join("brrrr_convert_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let fastq = dir.join("in.fastq");
+        fs::write(&fastq, "@r0 desc\nACGT\n+\nII5+\n@r1\nTT\n+\n!!\n").unwrap();
+
+        // An extension that doesn't match the content is ignored when the content is clear.
+        let parquet = dir.join("reads.data");
+        convert(
+            &fastq,
+            &parquet,
+            Some(Format::Parquet),
+            &Compression::SNAPPY.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(Format::detect(&parquet).unwrap(), Some(Format::Parquet));
+
+        let jsonl = dir.join("reads.jsonl.gz");
+        convert(
+            &parquet,
+            &jsonl,
+            None,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(Format::detect(&jsonl).unwrap(), Some(Format::Jsonl));
+
+        let fastq_out = dir.join("reads.fq");
+        convert(
+            &jsonl,
+            &fastq_out,
+            None,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&fastq_out).unwrap(),
+            "@r0 desc\nACGT\n+\nII5+\n@r1\nTT\n+\n!!\n"
+        );
+
+        let fasta_out = dir.join("reads.fa");
+        convert(
+            &fastq_out,
+            &fasta_out,
+            None,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&fasta_out).unwrap(),
+            ">r0 desc\nACGT\n>r1\nTT\n"
+        );
+
+        let csv_out = dir.join("reads.csv");
+        convert(
+            &fasta_out,
+            &csv_out,
+            None,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            convert(
+                &csv_out,
+                &fastq_out,
+                None,
+                &Compression::UNCOMPRESSED.into(),
+                &mut Rejects::default()
+            ),
+            Err(BrrrrError::UnsupportedConversion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_convert_gff() {
+        let dir = env::temp_dir().join("brrrr_convert_gff_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let gtf = dir.join("genes.gtf");
+        fs::write(&gtf, "chr1\tsrc\tgene\t1\t10\t.\t.\t.\tgene_id \"g1\";\n").unwrap();
+
+        let parquet = dir.join("genes.parquet");
+        convert(
+            &gtf,
+            &parquet,
+            None,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let gff = dir.join("genes.gff3");
+        convert(
+            &parquet,
+            &gff,
+            None,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&gff).unwrap(),
+            "##gff-version 3\nchr1\tsrc\tgene\t1\t10\t.\t.\t.\tgene_id=g1\n"
+        );
+    }
+}
@@ -0,0 +1,1208 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+mod codec;
+mod rans;
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use indexmap::IndexMap;
+use noodles::sam;
+use xz2::read::XzDecoder;
+
+use crate::errors::BrrrrError;
+use crate::types::{Cigar, Data, Operation, SamField, SamRecord, SamValue};
+
+use codec::{
+    invalid_data, read_byte_array, read_i32_le, read_itf8, read_itf8_array, read_itf8_usize,
+    read_ltf8, read_u8, BitReader, DataSource, Encoding,
+};
+
+const MAGIC: &[u8] = b"CRAM";
+
+const FILE_HEADER: u8 = 0;
+const COMPRESSION_HEADER: u8 = 1;
+const SLICE_HEADER: u8 = 2;
+const EXTERNAL_DATA: u8 = 4;
+const CORE_DATA: u8 = 5;
+
+const MULTI_REFERENCE: i32 = -2;
+
+const CF_QUALITY_SCORES: i32 = 0x1;
+const CF_DETACHED: i32 = 0x2;
+const CF_MATE_DOWNSTREAM: i32 = 0x4;
+const CF_UNKNOWN_BASES: i32 = 0x8;
+
+const MF_MATE_REVERSE: i32 = 0x1;
+const MF_MATE_UNMAPPED: i32 = 0x2;
+
+const FLAG_MATE_UNMAPPED: u16 = 0x8;
+const FLAG_UNMAPPED: u16 = 0x4;
+const FLAG_REVERSE: u16 = 0x10;
+const FLAG_MATE_REVERSE: u16 = 0x20;
+
+const BASES: [u8; 5] = *b"ACGTN";
+
+struct FaiRecord {
+    length: u64,
+    offset: u64,
+    line_bases: u64,
+    line_width: u64,
+}
+
+/// A reference FASTA with a `.fai` index, used to restore the bases CRAM leaves out. One
+/// reference sequence is loaded at a time.
+pub struct Reference {
+    file: fs::File,
+    index: HashMap<String, FaiRecord>,
+    cached: Option<(String, Vec<u8>)>,
+}
+
+impl Reference {
+    /// Opens a FASTA file and the `.fai` index next to it.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, BrrrrError> {
+        let path = path.as_ref();
+
+        let mut fai_path = path.as_os_str().to_owned();
+        fai_path.push(".fai");
+
+        let file = fs::File::open(path)
+            .map_err(|_| BrrrrError::MissingReference(path.display().to_string()))?;
+        let fai = fs::File::open(&fai_path).map_err(|_| {
+            BrrrrError::MissingReference(format!("{} has no .fai index", path.display()))
+        })?;
+
+        let mut index = HashMap::new();
+        for line in BufReader::new(fai).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            let parse = |i: usize| {
+                fields
+                    .get(i)
+                    .and_then(|f| f.parse::<u64>().ok())
+                    .ok_or_else(|| invalid_data(format!("invalid .fai line: {}", line)))
+            };
+
+            let record = FaiRecord {
+                length: parse(1)?,
+                offset: parse(2)?,
+                line_bases: parse(3)?,
+                line_width: parse(4)?,
+            };
+            index.insert(fields[0].to_string(), record);
+        }
+
+        Ok(Self {
+            file,
+            index,
+            cached: None,
+        })
+    }
+
+    /// Returns the uppercased bases of the named reference sequence.
+    fn sequence(&mut self, name: &str) -> Result<&[u8], BrrrrError> {
+        if !matches!(&self.cached, Some((cached, _)) if cached == name) {
+            let record = self
+                .index
+                .get(name)
+                .ok_or_else(|| BrrrrError::MissingReference(name.to_string()))?;
+
+            let line_breaks = match record.line_bases {
+                0 => 0,
+                n => record.length / n,
+            };
+            let span = record.length + line_breaks * (record.line_width - record.line_bases);
+
+            self.file.seek(SeekFrom::Start(record.offset))?;
+            let mut bases = Vec::with_capacity(span as usize);
+            (&mut self.file).take(span).read_to_end(&mut bases)?;
+
+            bases.retain(|b| !b.is_ascii_whitespace());
+            bases.truncate(record.length as usize);
+            bases.make_ascii_uppercase();
+
+            if bases.len() as u64 != record.length {
+                return Err(BrrrrError::MissingReference(format!(
+                    "{} is shorter than its .fai length",
+                    name
+                )));
+            }
+
+            self.cached = Some((name.to_string(), bases));
+        }
+
+        Ok(self.cached.as_ref().map_or(&[][..], |(_, bases)| bases))
+    }
+}
+
+struct ContainerHeader {
+    length: usize,
+    landmarks: Vec<i32>,
+}
+
+struct Block {
+    content_type: u8,
+    content_id: i32,
+    data: Vec<u8>,
+}
+
+/// The substitution codes of each reference base, in `ACGTN` order.
+type SubstitutionMatrix = [[u8; 4]; 5];
+
+struct CompressionHeader {
+    read_names_included: bool,
+    ap_delta: bool,
+    reference_required: bool,
+    substitution_matrix: SubstitutionMatrix,
+    tag_ids: Vec<Vec<([u8; 2], u8)>>,
+    data_series: HashMap<[u8; 2], Encoding>,
+    tags: HashMap<i32, Encoding>,
+}
+
+struct SliceHeader {
+    reference_sequence_id: i32,
+    alignment_start: i32,
+    alignment_span: i32,
+    n_records: usize,
+    n_blocks: usize,
+    embedded_reference_id: i32,
+    md5: [u8; 16],
+}
+
+/// A read feature, which places read bases relative to the reference.
+enum Feature {
+    Bases(Vec<u8>),
+    Substitution(u8),
+    Insertion(Vec<u8>),
+    SoftClip(Vec<u8>),
+    Deletion(usize),
+    ReferenceSkip(usize),
+    Padding(usize),
+    HardClip(usize),
+}
+
+/// Reads a CRAM header and its records, which are decoded against a local reference.
+pub struct Reader<R> {
+    inner: R,
+    reference: Reference,
+    major_version: u8,
+    records: VecDeque<SamRecord>,
+}
+
+impl<R: Read> Reader<R> {
+    /// Creates a new CRAM reader.
+    pub fn new(inner: R, reference: Reference) -> Self {
+        Self {
+            inner,
+            reference,
+            major_version: 3,
+            records: VecDeque::new(),
+        }
+    }
+
+    /// Reads the file definition and the SAM header from the header container.
+    pub fn read_header(&mut self) -> Result<sam::Header, BrrrrError> {
+        let mut definition = [0; 26];
+        self.inner.read_exact(&mut definition)?;
+
+        if &definition[..4] != MAGIC {
+            return Err(invalid_data("invalid CRAM magic number".to_string()).into());
+        }
+
+        self.major_version = definition[4];
+        if !(2..=3).contains(&self.major_version) {
+            return Err(invalid_data(format!(
+                "unsupported CRAM version: {}.{}",
+                definition[4], definition[5]
+            ))
+            .into());
+        }
+
+        let container_header = self
+            .read_container_header()?
+            .ok_or_else(|| invalid_data("missing CRAM header container".to_string()))?;
+
+        let data = self.read_container_data(&container_header)?;
+        let block = read_block(&mut &data[..], self.major_version)?;
+
+        if block.content_type != FILE_HEADER {
+            return Err(invalid_data("missing CRAM header block".to_string()).into());
+        }
+
+        let mut buf = &block.data[..];
+        let len = usize::try_from(read_i32_le(&mut buf)?)
+            .map_err(|_| invalid_data("invalid CRAM header length".to_string()))?;
+        let text = buf
+            .get(..len)
+            .ok_or_else(|| invalid_data("truncated CRAM header".to_string()))?;
+
+        let text = std::str::from_utf8(text)
+            .map_err(|e| invalid_data(format!("invalid CRAM header: {}", e)))?;
+
+        let header = text
+            .trim_end_matches('\0')
+            .parse()
+            .map_err(|e| invalid_data(format!("invalid CRAM header: {}", e)))?;
+
+        Ok(header)
+    }
+
+    /// Returns an iterator over the records, with reference names resolved by `header`.
+    pub fn records<'a>(&'a mut self, header: &'a sam::Header) -> Records<'a, R> {
+        Records {
+            reader: self,
+            header,
+        }
+    }
+
+    /// Reads a container header, or `None` at the end of the input.
+    fn read_container_header(&mut self) -> Result<Option<ContainerHeader>, BrrrrError> {
+        let mut length = [0; 4];
+
+        match self.inner.read(&mut length[..1])? {
+            0 => return Ok(None),
+            _ => self.inner.read_exact(&mut length[1..])?,
+        }
+
+        let length = usize::try_from(i32::from_le_bytes(length))
+            .map_err(|_| invalid_data("invalid CRAM container length".to_string()))?;
+
+        let _reference_sequence_id = read_itf8(&mut self.inner)?;
+        let _alignment_start = read_itf8(&mut self.inner)?;
+        let _alignment_span = read_itf8(&mut self.inner)?;
+        let _n_records = read_itf8(&mut self.inner)?;
+        let _record_counter = read_ltf8(&mut self.inner)?;
+        let _n_bases = read_ltf8(&mut self.inner)?;
+        let _n_blocks = read_itf8(&mut self.inner)?;
+        let landmarks = read_itf8_array(&mut self.inner)?;
+
+        if self.major_version >= 3 {
+            let _crc32 = read_i32_le(&mut self.inner)?;
+        }
+
+        Ok(Some(ContainerHeader { length, landmarks }))
+    }
+
+    fn read_container_data(&mut self, header: &ContainerHeader) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; header.length];
+        self.inner.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Decodes the next container's records into the buffer, returning false at the end of
+    /// the input. Containers without slices, such as the EOF container, decode to nothing.
+    fn read_container(&mut self, header: &sam::Header) -> Result<bool, BrrrrError> {
+        let container_header = match self.read_container_header()? {
+            Some(container_header) => container_header,
+            None => return Ok(false),
+        };
+
+        let data = self.read_container_data(&container_header)?;
+        if container_header.landmarks.is_empty() {
+            return Ok(true);
+        }
+
+        let block = read_block(&mut &data[..], self.major_version)?;
+        if block.content_type != COMPRESSION_HEADER {
+            return Err(invalid_data("missing CRAM compression header".to_string()).into());
+        }
+        let compression_header = CompressionHeader::read(&block.data)?;
+
+        for landmark in container_header.landmarks {
+            let mut buf = usize::try_from(landmark)
+                .ok()
+                .and_then(|l| data.get(l..))
+                .ok_or_else(|| invalid_data(format!("invalid CRAM landmark: {}", landmark)))?;
+
+            let block = read_block(&mut buf, self.major_version)?;
+            if block.content_type != SLICE_HEADER {
+                return Err(invalid_data("missing CRAM slice header".to_string()).into());
+            }
+            let slice_header = SliceHeader::read(&block.data)?;
+
+            let blocks = (0..slice_header.n_blocks)
+                .map(|_| read_block(&mut buf, self.major_version))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            let records = decode_slice(
+                header,
+                &compression_header,
+                &slice_header,
+                &blocks,
+                &mut self.reference,
+            )?;
+            self.records.extend(records);
+        }
+
+        Ok(true)
+    }
+}
+
+/// An iterator over the records of a CRAM file.
+pub struct Records<'a, R> {
+    reader: &'a mut Reader<R>,
+    header: &'a sam::Header,
+}
+
+impl<'a, R: Read> Iterator for Records<'a, R> {
+    type Item = Result<SamRecord, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.reader.records.pop_front() {
+                return Some(Ok(record));
+            }
+
+            match self.reader.read_container(self.header) {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+fn read_block<R: Read>(reader: &mut R, major_version: u8) -> io::Result<Block> {
+    let method = read_u8(reader)?;
+    let content_type = read_u8(reader)?;
+    let content_id = read_itf8(reader)?;
+    let compressed_size = read_itf8_usize(reader)?;
+    let raw_size = read_itf8_usize(reader)?;
+
+    let mut compressed = vec![0; compressed_size];
+    reader.read_exact(&mut compressed)?;
+
+    if major_version >= 3 {
+        let _crc32 = read_i32_le(reader)?;
+    }
+
+    let data = match method {
+        0 => compressed,
+        1 => decompress(MultiGzDecoder::new(&compressed[..]), raw_size)?,
+        2 => decompress(BzDecoder::new(&compressed[..]), raw_size)?,
+        3 => decompress(XzDecoder::new(&compressed[..]), raw_size)?,
+        4 => rans::decode(&compressed)?,
+        m => {
+            return Err(invalid_data(format!(
+                "unsupported CRAM block compression method: {}",
+                m
+            )))
+        }
+    };
+
+    if data.len() != raw_size {
+        return Err(invalid_data(format!(
+            "CRAM block size mismatch: expected {}, found {}",
+            raw_size,
+            data.len()
+        )));
+    }
+
+    Ok(Block {
+        content_type,
+        content_id,
+        data,
+    })
+}
+
+fn decompress<R: Read>(mut decoder: R, raw_size: usize) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(raw_size);
+    decoder.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+impl CompressionHeader {
+    fn read(src: &[u8]) -> io::Result<Self> {
+        let mut buf = src;
+
+        let mut header = CompressionHeader {
+            read_names_included: true,
+            ap_delta: true,
+            reference_required: true,
+            substitution_matrix: [[b'N'; 4]; 5],
+            tag_ids: Vec::new(),
+            data_series: HashMap::new(),
+            tags: HashMap::new(),
+        };
+
+        let _size = read_itf8(&mut buf)?;
+        for _ in 0..read_itf8_usize(&mut buf)? {
+            let key = read_key(&mut buf)?;
+
+            match &key {
+                b"RN" => header.read_names_included = read_u8(&mut buf)? != 0,
+                b"AP" => header.ap_delta = read_u8(&mut buf)? != 0,
+                b"RR" => header.reference_required = read_u8(&mut buf)? != 0,
+                b"SM" => {
+                    let mut sm = [0; 5];
+                    buf.read_exact(&mut sm)?;
+                    header.substitution_matrix = substitution_matrix(sm);
+                }
+                b"TD" => header.tag_ids = tag_ids(&read_byte_array(&mut buf)?),
+                k => {
+                    return Err(invalid_data(format!(
+                        "unsupported CRAM preservation key: {}",
+                        String::from_utf8_lossy(k)
+                    )))
+                }
+            }
+        }
+
+        let _size = read_itf8(&mut buf)?;
+        for _ in 0..read_itf8_usize(&mut buf)? {
+            let key = read_key(&mut buf)?;
+            header.data_series.insert(key, Encoding::read(&mut buf)?);
+        }
+
+        let _size = read_itf8(&mut buf)?;
+        for _ in 0..read_itf8_usize(&mut buf)? {
+            let key = read_itf8(&mut buf)?;
+            header.tags.insert(key, Encoding::read(&mut buf)?);
+        }
+
+        Ok(header)
+    }
+}
+
+fn read_key(buf: &mut &[u8]) -> io::Result<[u8; 2]> {
+    let mut key = [0; 2];
+    buf.read_exact(&mut key)?;
+    Ok(key)
+}
+
+/// Expands the packed substitution matrix. Each byte holds the 2-bit codes of the four
+/// alternatives to a reference base, in `ACGTN` order.
+fn substitution_matrix(sm: [u8; 5]) -> SubstitutionMatrix {
+    let mut matrix = [[b'N'; 4]; 5];
+
+    for (i, byte) in sm.iter().enumerate() {
+        let alternatives = BASES.iter().filter(|b| **b != BASES[i]);
+
+        for (j, base) in alternatives.enumerate() {
+            let code = (byte >> (6 - 2 * j)) & 0x03;
+            matrix[i][usize::from(code)] = *base;
+        }
+    }
+
+    matrix
+}
+
+/// Splits the tag dictionary into lines of `(tag, BAM type)`, one line per tag set.
+fn tag_ids(td: &[u8]) -> Vec<Vec<([u8; 2], u8)>> {
+    let td = td.strip_suffix(&[0]).unwrap_or(td);
+
+    td.split(|b| *b == 0)
+        .map(|line| {
+            line.chunks_exact(3)
+                .map(|id| ([id[0], id[1]], id[2]))
+                .collect()
+        })
+        .collect()
+}
+
+impl SliceHeader {
+    fn read(src: &[u8]) -> io::Result<Self> {
+        let mut buf = src;
+
+        let reference_sequence_id = read_itf8(&mut buf)?;
+        let alignment_start = read_itf8(&mut buf)?;
+        let alignment_span = read_itf8(&mut buf)?;
+        let n_records = read_itf8_usize(&mut buf)?;
+        let _record_counter = read_ltf8(&mut buf)?;
+        let n_blocks = read_itf8_usize(&mut buf)?;
+        let _block_content_ids = read_itf8_array(&mut buf)?;
+        let embedded_reference_id = read_itf8(&mut buf)?;
+
+        let mut md5 = [0; 16];
+        buf.read_exact(&mut md5)?;
+
+        Ok(SliceHeader {
+            reference_sequence_id,
+            alignment_start,
+            alignment_span,
+            n_records,
+            n_blocks,
+            embedded_reference_id,
+            md5,
+        })
+    }
+}
+
+fn reference_sequence_name(header: &sam::Header, id: i32) -> Result<Option<String>, BrrrrError> {
+    if id < 0 {
+        return Ok(None);
+    }
+
+    header
+        .reference_sequences()
+        .get_index(id as usize)
+        .map(|(name, _)| Some(name.clone()))
+        .ok_or_else(|| {
+            invalid_data(format!("reference sequence {} is not in the header", id)).into()
+        })
+}
+
+fn decode_slice(
+    header: &sam::Header,
+    compression_header: &CompressionHeader,
+    slice_header: &SliceHeader,
+    blocks: &[Block],
+    reference: &mut Reference,
+) -> Result<Vec<SamRecord>, BrrrrError> {
+    let mut core = &[][..];
+    let mut external = HashMap::new();
+
+    for block in blocks {
+        match block.content_type {
+            CORE_DATA => core = &block.data[..],
+            EXTERNAL_DATA => {
+                external.insert(block.content_id, &block.data[..]);
+            }
+            _ => {}
+        }
+    }
+
+    let embedded_reference = match slice_header.embedded_reference_id {
+        id if id >= 0 => Some(*external.get(&id).ok_or_else(|| {
+            invalid_data(format!("missing CRAM embedded reference block {}", id))
+        })?),
+        _ => None,
+    };
+
+    if embedded_reference.is_none()
+        && compression_header.reference_required
+        && slice_header.md5 != [0; 16]
+    {
+        if let Some(name) = reference_sequence_name(header, slice_header.reference_sequence_id)? {
+            check_md5(reference, &name, slice_header)?;
+        }
+    }
+
+    let mut decoder = SliceDecoder {
+        header,
+        compression_header,
+        slice_header,
+        src: DataSource {
+            core: BitReader::new(core),
+            external,
+        },
+        embedded_reference,
+        previous_alignment_start: slice_header.alignment_start,
+    };
+
+    let mut records = Vec::with_capacity(slice_header.n_records);
+    let mut next_fragments = Vec::with_capacity(slice_header.n_records);
+
+    for _ in 0..slice_header.n_records {
+        let (record, next_fragment) = decoder.decode_record(reference)?;
+        records.push(record);
+        next_fragments.push(next_fragment);
+    }
+
+    for (i, next_fragment) in next_fragments.into_iter().enumerate() {
+        if let Some(n) = next_fragment {
+            let j = i + n + 1;
+            if j < records.len() {
+                let (left, right) = records.split_at_mut(j);
+                set_mates(&mut left[i], &mut right[0]);
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Compares the slice's reference MD5 with the bases of the local reference it spans.
+fn check_md5(
+    reference: &mut Reference,
+    name: &str,
+    slice_header: &SliceHeader,
+) -> Result<(), BrrrrError> {
+    let bases = reference.sequence(name)?;
+
+    let start = (slice_header.alignment_start.max(1) - 1) as usize;
+    let end = (start + slice_header.alignment_span.max(0) as usize).min(bases.len());
+    let actual = md5::compute(bases.get(start..end).unwrap_or_default());
+
+    if actual.0 != slice_header.md5 {
+        return Err(BrrrrError::ReferenceMd5Mismatch {
+            name: name.to_string(),
+            expected: format!("{:x}", md5::Digest(slice_header.md5)),
+            actual: format!("{:x}", actual),
+        });
+    }
+
+    Ok(())
+}
+
+struct SliceDecoder<'a> {
+    header: &'a sam::Header,
+    compression_header: &'a CompressionHeader,
+    slice_header: &'a SliceHeader,
+    src: DataSource<'a>,
+    embedded_reference: Option<&'a [u8]>,
+    previous_alignment_start: i32,
+}
+
+impl<'a> SliceDecoder<'a> {
+    fn series(&self, key: &[u8; 2]) -> io::Result<&'a Encoding> {
+        self.compression_header.data_series.get(key).ok_or_else(|| {
+            invalid_data(format!(
+                "missing CRAM data series: {}",
+                String::from_utf8_lossy(key)
+            ))
+        })
+    }
+
+    fn int(&mut self, key: &[u8; 2]) -> io::Result<i32> {
+        self.series(key)?.decode_int(&mut self.src)
+    }
+
+    fn usize(&mut self, key: &[u8; 2]) -> io::Result<usize> {
+        let value = self.int(key)?;
+        usize::try_from(value).map_err(|_| {
+            invalid_data(format!(
+                "invalid CRAM {} value: {}",
+                String::from_utf8_lossy(key),
+                value
+            ))
+        })
+    }
+
+    fn byte(&mut self, key: &[u8; 2]) -> io::Result<u8> {
+        self.series(key)?.decode_byte(&mut self.src)
+    }
+
+    fn bytes(&mut self, key: &[u8; 2]) -> io::Result<Vec<u8>> {
+        self.series(key)?.decode_bytes(&mut self.src)
+    }
+
+    /// Decodes a record, along with the number of records to skip to its mate when the mate
+    /// is in the same slice.
+    fn decode_record(
+        &mut self,
+        reference: &mut Reference,
+    ) -> Result<(SamRecord, Option<usize>), BrrrrError> {
+        let compression_header = self.compression_header;
+
+        let mut flags = self.int(b"BF")? as u16;
+        let cram_flags = self.int(b"CF")?;
+
+        let reference_sequence_id = match self.slice_header.reference_sequence_id {
+            MULTI_REFERENCE => self.int(b"RI")?,
+            id => id,
+        };
+        let reference_sequence = reference_sequence_name(self.header, reference_sequence_id)?;
+
+        let read_length = self.usize(b"RL")?;
+
+        let alignment_start = if compression_header.ap_delta {
+            self.previous_alignment_start += self.int(b"AP")?;
+            self.previous_alignment_start
+        } else {
+            self.int(b"AP")?
+        };
+
+        let read_group = self.int(b"RG")?;
+
+        let mut read_name = match compression_header.read_names_included {
+            true => Some(self.bytes(b"RN")?),
+            false => None,
+        };
+
+        let mut mate_reference_sequence = None;
+        let mut mate_alignment_start = None;
+        let mut template_length = 0;
+        let mut next_fragment = None;
+
+        if cram_flags & CF_DETACHED != 0 {
+            let mate_flags = self.int(b"MF")?;
+            if mate_flags & MF_MATE_REVERSE != 0 {
+                flags |= FLAG_MATE_REVERSE;
+            }
+            if mate_flags & MF_MATE_UNMAPPED != 0 {
+                flags |= FLAG_MATE_UNMAPPED;
+            }
+
+            if !compression_header.read_names_included {
+                read_name = Some(self.bytes(b"RN")?);
+            }
+
+            let mate_reference_sequence_id = self.int(b"NS")?;
+            mate_reference_sequence =
+                reference_sequence_name(self.header, mate_reference_sequence_id)?;
+            mate_alignment_start = usize::try_from(self.int(b"NP")?).ok().filter(|p| *p > 0);
+            template_length = self.int(b"TS")?;
+        } else if cram_flags & CF_MATE_DOWNSTREAM != 0 {
+            next_fragment = Some(self.usize(b"NF")?);
+        }
+
+        let tag_line = self.usize(b"TL")?;
+        let tag_ids = compression_header
+            .tag_ids
+            .get(tag_line)
+            .ok_or_else(|| invalid_data(format!("invalid CRAM tag line: {}", tag_line)))?;
+
+        let mut fields = IndexMap::new();
+        for (tag, ty) in tag_ids {
+            let key = i32::from(tag[0]) << 16 | i32::from(tag[1]) << 8 | i32::from(*ty);
+            let encoding = compression_header.tags.get(&key).ok_or_else(|| {
+                invalid_data(format!(
+                    "missing CRAM tag encoding: {}",
+                    String::from_utf8_lossy(tag)
+                ))
+            })?;
+
+            let value = encoding.decode_bytes(&mut self.src)?;
+            fields.insert(
+                String::from_utf8_lossy(tag).to_string(),
+                sam_field(*ty, &value)?,
+            );
+        }
+
+        if let Ok(i) = usize::try_from(read_group) {
+            if let Some((id, _)) = self.header.read_groups().get_index(i) {
+                let field = SamField {
+                    ty: "Z".to_string(),
+                    value: SamValue::String(id.clone()),
+                };
+                fields.insert("RG".to_string(), field);
+            }
+        }
+
+        let (mut bases, operations, mapping_quality) = if flags & FLAG_UNMAPPED == 0 {
+            let features = self.read_features()?;
+            let mapping_quality = self.int(b"MQ")?;
+
+            let (reference_bases, offset) = match self.embedded_reference {
+                Some(bases) => (bases, (self.slice_header.alignment_start - 1) as usize),
+                None => match (&reference_sequence, compression_header.reference_required) {
+                    (Some(name), true) => (reference.sequence(name)?, 0),
+                    _ => (&[][..], 0),
+                },
+            };
+
+            let (bases, operations) = build_alignment(
+                read_length,
+                alignment_start as usize,
+                &features,
+                &compression_header.substitution_matrix,
+                reference_bases,
+                offset,
+            );
+
+            let mapping_quality = u8::try_from(mapping_quality).ok().filter(|q| *q != 255);
+            (bases, operations, mapping_quality)
+        } else {
+            let bases = match cram_flags & CF_UNKNOWN_BASES {
+                0 => (0..read_length)
+                    .map(|_| self.byte(b"BA"))
+                    .collect::<io::Result<Vec<_>>>()?,
+                _ => Vec::new(),
+            };
+
+            (bases, Vec::new(), None)
+        };
+
+        if cram_flags & CF_UNKNOWN_BASES != 0 {
+            bases.clear();
+        }
+
+        let quality_scores = match cram_flags & CF_QUALITY_SCORES {
+            0 => Vec::new(),
+            _ => (0..read_length)
+                .map(|_| self.byte(b"QS"))
+                .collect::<io::Result<Vec<_>>>()?,
+        };
+
+        let record = SamRecord {
+            read_name: read_name.map(|n| String::from_utf8_lossy(&n).to_string()),
+            flags,
+            reference_sequence,
+            alignment_start: usize::try_from(alignment_start).ok().filter(|p| *p > 0),
+            mapping_quality,
+            cigar: Cigar::new(operations),
+            mate_reference_sequence,
+            mate_alignment_start,
+            template_length,
+            sequence: String::from_utf8_lossy(&bases).to_string(),
+            quality_scores,
+            data: Data::new(fields),
+        };
+
+        Ok((record, next_fragment))
+    }
+
+    /// Reads the read features, keyed by their 1-based read position. Quality score features
+    /// are read but dropped, since scores are only kept when the record stores all of them.
+    fn read_features(&mut self) -> io::Result<Vec<(usize, Feature)>> {
+        let n_features = self.usize(b"FN")?;
+        let mut features = Vec::with_capacity(n_features);
+        let mut position = 0;
+
+        for _ in 0..n_features {
+            let code = self.byte(b"FC")?;
+            position += self.usize(b"FP")?;
+
+            let feature = match code {
+                b'B' => {
+                    let base = self.byte(b"BA")?;
+                    self.byte(b"QS")?;
+                    Feature::Bases(vec![base])
+                }
+                b'X' => Feature::Substitution(self.byte(b"BS")?),
+                b'I' => Feature::Insertion(self.bytes(b"IN")?),
+                b'i' => Feature::Insertion(vec![self.byte(b"BA")?]),
+                b'S' => Feature::SoftClip(self.bytes(b"SC")?),
+                b'b' => Feature::Bases(self.bytes(b"BB")?),
+                b'D' => Feature::Deletion(self.usize(b"DL")?),
+                b'N' => Feature::ReferenceSkip(self.usize(b"RS")?),
+                b'P' => Feature::Padding(self.usize(b"PD")?),
+                b'H' => Feature::HardClip(self.usize(b"HC")?),
+                b'q' => {
+                    self.bytes(b"QQ")?;
+                    continue;
+                }
+                b'Q' => {
+                    self.byte(b"QS")?;
+                    continue;
+                }
+                c => {
+                    return Err(invalid_data(format!(
+                        "invalid CRAM read feature: {}",
+                        char::from(c)
+                    )))
+                }
+            };
+
+            features.push((position, feature));
+        }
+
+        Ok(features)
+    }
+}
+
+fn push_operation(operations: &mut Vec<Operation>, kind: &str, len: usize) {
+    if len == 0 {
+        return;
+    }
+
+    match operations.last_mut() {
+        Some(op) if op.kind == kind => op.len += len,
+        _ => operations.push(Operation::new(kind.to_string(), len)),
+    }
+}
+
+/// Restores the read bases and CIGAR from the read features. Bases between features match
+/// the reference, which starts at the 1-based position `offset + 1`.
+fn build_alignment(
+    read_length: usize,
+    alignment_start: usize,
+    features: &[(usize, Feature)],
+    substitution_matrix: &SubstitutionMatrix,
+    reference_bases: &[u8],
+    offset: usize,
+) -> (Vec<u8>, Vec<Operation>) {
+    let reference_base = |position: usize| {
+        position
+            .checked_sub(offset + 1)
+            .and_then(|i| reference_bases.get(i))
+            .copied()
+            .unwrap_or(b'N')
+    };
+
+    let mut bases = Vec::with_capacity(read_length);
+    let mut operations = Vec::new();
+
+    let mut read_position = 1;
+    let mut reference_position = alignment_start;
+
+    let match_to = |bases: &mut Vec<u8>,
+                    operations: &mut Vec<Operation>,
+                    read_position: &mut usize,
+                    reference_position: &mut usize,
+                    end: usize| {
+        let len = end.saturating_sub(*read_position);
+        for _ in 0..len {
+            bases.push(reference_base(*reference_position));
+            *reference_position += 1;
+        }
+        *read_position += len;
+        push_operation(operations, "M", len);
+    };
+
+    for (position, feature) in features {
+        match_to(
+            &mut bases,
+            &mut operations,
+            &mut read_position,
+            &mut reference_position,
+            *position,
+        );
+
+        match feature {
+            Feature::Bases(read_bases) => {
+                bases.extend_from_slice(read_bases);
+                read_position += read_bases.len();
+                reference_position += read_bases.len();
+                push_operation(&mut operations, "M", read_bases.len());
+            }
+            Feature::Substitution(code) => {
+                let base = reference_base(reference_position);
+                let i = BASES.iter().position(|b| *b == base).unwrap_or(4);
+                bases.push(substitution_matrix[i][usize::from(code & 0x03)]);
+                read_position += 1;
+                reference_position += 1;
+                push_operation(&mut operations, "M", 1);
+            }
+            Feature::Insertion(read_bases) => {
+                bases.extend_from_slice(read_bases);
+                read_position += read_bases.len();
+                push_operation(&mut operations, "I", read_bases.len());
+            }
+            Feature::SoftClip(read_bases) => {
+                bases.extend_from_slice(read_bases);
+                read_position += read_bases.len();
+                push_operation(&mut operations, "S", read_bases.len());
+            }
+            Feature::Deletion(len) => {
+                reference_position += len;
+                push_operation(&mut operations, "D", *len);
+            }
+            Feature::ReferenceSkip(len) => {
+                reference_position += len;
+                push_operation(&mut operations, "N", *len);
+            }
+            Feature::Padding(len) => push_operation(&mut operations, "P", *len),
+            Feature::HardClip(len) => push_operation(&mut operations, "H", *len),
+        }
+    }
+
+    match_to(
+        &mut bases,
+        &mut operations,
+        &mut read_position,
+        &mut reference_position,
+        read_length + 1,
+    );
+
+    (bases, operations)
+}
+
+/// Decodes a BAM-typed tag value.
+fn sam_field(ty: u8, src: &[u8]) -> io::Result<SamField> {
+    let mut buf = src;
+
+    fn read_array<const N: usize>(buf: &mut &[u8]) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        buf.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_integer(buf: &mut &[u8], subtype: u8) -> io::Result<i64> {
+        let value = match subtype {
+            b'c' => i64::from(read_array::<1>(buf)?[0] as i8),
+            b'C' => i64::from(read_array::<1>(buf)?[0]),
+            b's' => i64::from(i16::from_le_bytes(read_array(buf)?)),
+            b'S' => i64::from(u16::from_le_bytes(read_array(buf)?)),
+            b'i' => i64::from(i32::from_le_bytes(read_array(buf)?)),
+            b'I' => i64::from(u32::from_le_bytes(read_array(buf)?)),
+            t => {
+                return Err(invalid_data(format!(
+                    "invalid CRAM tag type: {}",
+                    char::from(t)
+                )))
+            }
+        };
+
+        Ok(value)
+    }
+
+    let string = |buf: &[u8]| {
+        let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+        String::from_utf8_lossy(&buf[..end]).to_string()
+    };
+
+    let (ty, value) = match ty {
+        b'A' => ("A".to_string(), SamValue::String(string(buf))),
+        b'Z' | b'H' => (char::from(ty).to_string(), SamValue::String(string(buf))),
+        b'f' => (
+            "f".to_string(),
+            SamValue::Float(f32::from_le_bytes(read_array(&mut buf)?)),
+        ),
+        b'B' => {
+            let subtype = read_u8(&mut buf)?;
+            let len = u32::from_le_bytes(read_array(&mut buf)?) as usize;
+
+            let value = match subtype {
+                b'f' => SamValue::FloatArray(
+                    (0..len)
+                        .map(|_| Ok(f32::from_le_bytes(read_array(&mut buf)?)))
+                        .collect::<io::Result<_>>()?,
+                ),
+                _ => SamValue::IntegerArray(
+                    (0..len)
+                        .map(|_| read_integer(&mut buf, subtype))
+                        .collect::<io::Result<_>>()?,
+                ),
+            };
+
+            (format!("B:{}", char::from(subtype)), value)
+        }
+        t => (
+            char::from(t).to_string(),
+            SamValue::Integer(read_integer(&mut buf, t)?),
+        ),
+    };
+
+    Ok(SamField { ty, value })
+}
+
+/// The number of reference bases a CIGAR covers.
+fn reference_span(cigar: &Cigar) -> usize {
+    cigar
+        .operations
+        .iter()
+        .filter(|op| matches!(op.kind.as_str(), "M" | "D" | "N" | "=" | "X"))
+        .map(|op| op.len)
+        .sum()
+}
+
+/// Fills in the mate fields of two records stored together in a slice. The template length
+/// is only set when both are mapped to the same reference sequence, and is positive for the
+/// leftmost record.
+fn set_mates(a: &mut SamRecord, b: &mut SamRecord) {
+    fn set_mate(record: &mut SamRecord, mate: &SamRecord) {
+        record.mate_reference_sequence = mate.reference_sequence.clone();
+        record.mate_alignment_start = mate.alignment_start;
+
+        if mate.flags & FLAG_REVERSE != 0 {
+            record.flags |= FLAG_MATE_REVERSE;
+        }
+        if mate.flags & FLAG_UNMAPPED != 0 {
+            record.flags |= FLAG_MATE_UNMAPPED;
+        }
+    }
+
+    set_mate(a, b);
+    set_mate(b, a);
+
+    let mapped = |r: &SamRecord| r.flags & FLAG_UNMAPPED == 0;
+    if !mapped(a) || !mapped(b) || a.reference_sequence != b.reference_sequence {
+        return;
+    }
+
+    if let (Some(a_start), Some(b_start)) = (a.alignment_start, b.alignment_start) {
+        let a_end = a_start + reference_span(&a.cigar);
+        let b_end = b_start + reference_span(&b.cigar);
+
+        let template_length = (a_end.max(b_end) - a_start.min(b_start)) as i32;
+
+        if a_start <= b_start {
+            a.template_length = template_length;
+            b.template_length = -template_length;
+        } else {
+            a.template_length = -template_length;
+            b.template_length = template_length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitution_matrix() {
+        // A: C=0 G=1 T=2 N=3, the rest reversed.
+        let matrix = substitution_matrix([0x1b, 0xe4, 0xe4, 0xe4, 0xe4]);
+
+        assert_eq!(matrix[0], *b"CGTN");
+        assert_eq!(matrix[1], *b"NTGA");
+        assert_eq!(matrix[4], *b"TGCA");
+    }
+
+    #[test]
+    fn test_build_alignment() {
+        // 2S 3M 1I 2M 2D 2M against ACGTACGTAC
+        let features = vec![
+            (1, Feature::SoftClip(b"NN".to_vec())),
+            (5, Feature::Substitution(0)),
+            (6, Feature::Insertion(b"T".to_vec())),
+            (9, Feature::Deletion(2)),
+        ];
+
+        let matrix = substitution_matrix([0x1b; 5]);
+        let (bases, operations) = build_alignment(10, 1, &features, &matrix, b"ACGTACGTAC", 0);
+
+        assert_eq!(bases, b"NNACATTATA");
+        let cigar: Vec<String> = operations
+            .iter()
+            .map(|op| format!("{}{}", op.len, op.kind))
+            .collect();
+        assert_eq!(cigar, vec!["2S", "3M", "1I", "2M", "2D", "2M"]);
+    }
+
+    fn resources() -> std::path::PathBuf {
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources")
+    }
+
+    fn read_all(reference: Reference) -> Result<Vec<SamRecord>, BrrrrError> {
+        let input = fs::File::open(resources().join("little.cram")).unwrap();
+        let mut reader = Reader::new(BufReader::new(input), reference);
+
+        let header = reader.read_header()?;
+        let records = reader.records(&header).collect();
+        records
+    }
+
+    #[test]
+    fn test_read_records() {
+        let reference = Reference::from_path(resources().join("little.fa")).unwrap();
+        let records = read_all(reference).unwrap();
+
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[2].read_name.as_deref(), Some("r0"));
+        assert_eq!(records[2].mate_alignment_start, Some(3));
+        assert_eq!(records[2].template_length, -35);
+        assert_eq!(records[3].reference_sequence.as_deref(), Some("chr2"));
+        assert_eq!(records[4].reference_sequence, None);
+    }
+
+    #[test]
+    fn test_missing_reference() {
+        let result = Reference::from_path(resources().join("missing.fa"));
+        assert!(matches!(result, Err(BrrrrError::MissingReference(_))));
+    }
+
+    #[test]
+    fn test_reference_md5_mismatch() {
+        let temp_dir = std::env::temp_dir();
+        let fasta = temp_dir.join("test_reference_md5_mismatch.fa");
+
+        let mut bases = fs::read(resources().join("little.fa")).unwrap();
+        bases[30] = if bases[30] == b'A' { b'C' } else { b'A' };
+        fs::write(&fasta, bases).unwrap();
+        fs::copy(
+            resources().join("little.fa.fai"),
+            temp_dir.join("test_reference_md5_mismatch.fa.fai"),
+        )
+        .unwrap();
+
+        let result = read_all(Reference::from_path(&fasta).unwrap());
+        match result {
+            Err(BrrrrError::ReferenceMd5Mismatch { name, .. }) => assert_eq!(name, "chr1"),
+            r => panic!("expected an MD5 mismatch, found {:?}", r.map(|r| r.len())),
+        }
+    }
+}
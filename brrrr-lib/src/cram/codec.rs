@@ -0,0 +1,356 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_i32_le<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+/// Reads a CRAM ITF8 integer, where the number of leading set bits in the first byte gives the
+/// number of bytes that follow.
+pub(crate) fn read_itf8<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let b0 = u32::from(read_u8(reader)?);
+
+    let value = if b0 & 0x80 == 0 {
+        b0
+    } else if b0 & 0x40 == 0 {
+        ((b0 & 0x3f) << 8) | u32::from(read_u8(reader)?)
+    } else if b0 & 0x20 == 0 {
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf)?;
+        ((b0 & 0x1f) << 16) | u32::from(buf[0]) << 8 | u32::from(buf[1])
+    } else if b0 & 0x10 == 0 {
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf)?;
+        ((b0 & 0x0f) << 24) | u32::from(buf[0]) << 16 | u32::from(buf[1]) << 8 | u32::from(buf[2])
+    } else {
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        ((b0 & 0x0f) << 28)
+            | u32::from(buf[0]) << 20
+            | u32::from(buf[1]) << 12
+            | u32::from(buf[2]) << 4
+            | u32::from(buf[3] & 0x0f)
+    };
+
+    Ok(value as i32)
+}
+
+/// Reads a CRAM LTF8 integer, the 64-bit counterpart of ITF8.
+pub(crate) fn read_ltf8<R: Read>(reader: &mut R) -> io::Result<i64> {
+    let b0 = read_u8(reader)?;
+    let n = b0.leading_ones() as usize;
+
+    let mut value = match n {
+        0..=6 => u64::from(b0 & (0x7f >> n)),
+        _ => 0,
+    };
+
+    for _ in 0..n {
+        value = (value << 8) | u64::from(read_u8(reader)?);
+    }
+
+    Ok(value as i64)
+}
+
+pub(crate) fn read_itf8_usize<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let value = read_itf8(reader)?;
+    usize::try_from(value).map_err(|_| invalid_data(format!("invalid CRAM length: {}", value)))
+}
+
+/// Reads an ITF8 length followed by that many bytes.
+pub(crate) fn read_byte_array<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_itf8_usize(reader)?;
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads an ITF8 count followed by that many ITF8 integers.
+pub(crate) fn read_itf8_array<R: Read>(reader: &mut R) -> io::Result<Vec<i32>> {
+    let len = read_itf8_usize(reader)?;
+    (0..len).map(|_| read_itf8(reader)).collect()
+}
+
+/// Reads the core data block, most significant bit first.
+pub(crate) struct BitReader<'a> {
+    buf: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf, position: 0 }
+    }
+
+    fn read_bit(&mut self) -> io::Result<u32> {
+        let byte = self
+            .buf
+            .get(self.position / 8)
+            .ok_or_else(|| invalid_data("unexpected end of CRAM core data".to_string()))?;
+
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+
+        Ok(u32::from(bit))
+    }
+
+    fn read_bits(&mut self, n: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()?;
+        }
+        Ok(value)
+    }
+}
+
+/// The core bit stream and external blocks of a slice, which the data series are decoded from.
+pub(crate) struct DataSource<'a> {
+    pub(crate) core: BitReader<'a>,
+    pub(crate) external: HashMap<i32, &'a [u8]>,
+}
+
+impl<'a> DataSource<'a> {
+    fn external(&mut self, id: i32) -> io::Result<&mut &'a [u8]> {
+        self.external
+            .get_mut(&id)
+            .ok_or_else(|| invalid_data(format!("missing CRAM external block {}", id)))
+    }
+}
+
+/// A CRAM data series encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Encoding {
+    Null,
+    External(i32),
+    /// Canonical Huffman codes as `(bit length, code, symbol)`, sorted by length.
+    Huffman(Vec<(u32, u32, i32)>),
+    ByteArrayLen(Box<Encoding>, Box<Encoding>),
+    ByteArrayStop(u8, i32),
+    Beta {
+        offset: i32,
+        len: u32,
+    },
+    Subexp {
+        offset: i32,
+        k: u32,
+    },
+    Gamma {
+        offset: i32,
+    },
+}
+
+impl Encoding {
+    /// Reads an encoding from a compression header: a codec ID, then its parameters.
+    pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let id = read_itf8(reader)?;
+        let params = read_byte_array(reader)?;
+        let mut params = &params[..];
+
+        let encoding = match id {
+            0 => Encoding::Null,
+            1 => Encoding::External(read_itf8(&mut params)?),
+            3 => {
+                let symbols = read_itf8_array(&mut params)?;
+                let lens = read_itf8_array(&mut params)?;
+                Encoding::Huffman(canonical_codes(symbols, lens)?)
+            }
+            4 => {
+                let len_encoding = Encoding::read(&mut params)?;
+                let value_encoding = Encoding::read(&mut params)?;
+                Encoding::ByteArrayLen(Box::new(len_encoding), Box::new(value_encoding))
+            }
+            5 => Encoding::ByteArrayStop(read_u8(&mut params)?, read_itf8(&mut params)?),
+            6 => Encoding::Beta {
+                offset: read_itf8(&mut params)?,
+                len: read_itf8(&mut params)? as u32,
+            },
+            7 => Encoding::Subexp {
+                offset: read_itf8(&mut params)?,
+                k: read_itf8(&mut params)? as u32,
+            },
+            9 => Encoding::Gamma {
+                offset: read_itf8(&mut params)?,
+            },
+            id => return Err(invalid_data(format!("unsupported CRAM encoding: {}", id))),
+        };
+
+        Ok(encoding)
+    }
+
+    pub(crate) fn decode_int(&self, src: &mut DataSource) -> io::Result<i32> {
+        match self {
+            Encoding::External(id) => read_itf8(src.external(*id)?),
+            Encoding::Huffman(codes) => decode_huffman(codes, &mut src.core),
+            Encoding::Beta { offset, len } => Ok(src.core.read_bits(*len)? as i32 - offset),
+            Encoding::Subexp { offset, k } => {
+                let mut n = 0;
+                while src.core.read_bit()? == 1 {
+                    n += 1;
+                }
+
+                let value = if n == 0 {
+                    src.core.read_bits(*k)?
+                } else {
+                    let b = n + k - 1;
+                    (1 << b) | src.core.read_bits(b)?
+                };
+
+                Ok(value as i32 - offset)
+            }
+            Encoding::Gamma { offset } => {
+                let mut n = 0;
+                while src.core.read_bit()? == 0 {
+                    n += 1;
+                }
+                Ok(((1 << n) | src.core.read_bits(n)?) as i32 - offset)
+            }
+            e => Err(invalid_data(format!(
+                "CRAM encoding {:?} cannot decode integers",
+                e
+            ))),
+        }
+    }
+
+    pub(crate) fn decode_byte(&self, src: &mut DataSource) -> io::Result<u8> {
+        match self {
+            Encoding::External(id) => read_u8(src.external(*id)?),
+            e => Ok(e.decode_int(src)? as u8),
+        }
+    }
+
+    pub(crate) fn decode_bytes(&self, src: &mut DataSource) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::ByteArrayLen(len_encoding, value_encoding) => {
+                let len = len_encoding.decode_int(src)?;
+                let len = usize::try_from(len)
+                    .map_err(|_| invalid_data(format!("invalid CRAM array length: {}", len)))?;
+
+                match value_encoding.as_ref() {
+                    Encoding::External(id) => {
+                        let mut buf = vec![0; len];
+                        src.external(*id)?.read_exact(&mut buf)?;
+                        Ok(buf)
+                    }
+                    e => (0..len).map(|_| e.decode_byte(src)).collect(),
+                }
+            }
+            Encoding::ByteArrayStop(stop, id) => {
+                let block = src.external(*id)?;
+                let end = block
+                    .iter()
+                    .position(|b| b == stop)
+                    .ok_or_else(|| invalid_data("missing CRAM byte array stop".to_string()))?;
+
+                let value = block[..end].to_vec();
+                *block = &block[end + 1..];
+                Ok(value)
+            }
+            e => Err(invalid_data(format!(
+                "CRAM encoding {:?} cannot decode byte arrays",
+                e
+            ))),
+        }
+    }
+}
+
+/// Assigns canonical Huffman codes, ordering symbols by code length and then by value.
+fn canonical_codes(symbols: Vec<i32>, lens: Vec<i32>) -> io::Result<Vec<(u32, u32, i32)>> {
+    if symbols.len() != lens.len() {
+        return Err(invalid_data(
+            "CRAM Huffman symbols and lengths differ".to_string(),
+        ));
+    }
+
+    let mut entries: Vec<(u32, i32)> = lens.into_iter().map(|l| l as u32).zip(symbols).collect();
+    entries.sort_unstable();
+
+    let mut codes = Vec::with_capacity(entries.len());
+    let mut code = 0;
+    let mut previous_len = entries.first().map_or(0, |(len, _)| *len);
+
+    for (len, symbol) in entries {
+        code <<= len - previous_len;
+        codes.push((len, code, symbol));
+        code += 1;
+        previous_len = len;
+    }
+
+    Ok(codes)
+}
+
+fn decode_huffman(codes: &[(u32, u32, i32)], core: &mut BitReader) -> io::Result<i32> {
+    if let [(0, _, symbol)] = codes {
+        return Ok(*symbol);
+    }
+
+    let mut code = 0;
+    let mut len = 0;
+
+    for (code_len, value, symbol) in codes {
+        while len < *code_len {
+            code = (code << 1) | core.read_bit()?;
+            len += 1;
+        }
+
+        if code == *value {
+            return Ok(*symbol);
+        }
+    }
+
+    Err(invalid_data("invalid CRAM Huffman code".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_itf8() {
+        let cases: [(&[u8], i32); 5] = [
+            (&[0x00], 0),
+            (&[0x7f], 127),
+            (&[0x80, 0x80], 128),
+            (&[0xe0, 0x40, 0x00, 0x00], 0x400000),
+            (&[0xff, 0xff, 0xff, 0xff, 0x0f], -1),
+        ];
+
+        for (mut buf, expected) in cases {
+            assert_eq!(read_itf8(&mut buf).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_huffman() {
+        let codes = canonical_codes(vec![65, 66, 67], vec![1, 2, 2]).unwrap();
+        assert_eq!(codes, vec![(1, 0, 65), (2, 2, 66), (2, 3, 67)]);
+
+        // 0 | 10 | 11 -> A B C
+        let core = [0b0101_1000];
+        let mut src = DataSource {
+            core: BitReader::new(&core),
+            external: HashMap::new(),
+        };
+        let encoding = Encoding::Huffman(codes);
+
+        let decoded: Vec<i32> = (0..3)
+            .map(|_| encoding.decode_int(&mut src).unwrap())
+            .collect();
+        assert_eq!(decoded, vec![65, 66, 67]);
+    }
+}
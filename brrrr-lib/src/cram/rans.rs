@@ -0,0 +1,234 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::io;
+
+use super::codec::{invalid_data, read_u8};
+
+const TF_SHIFT: u32 = 12;
+const TOTFREQ: u32 = 1 << TF_SHIFT;
+const RANS_BYTE_L: u32 = 1 << 23;
+
+fn read_u32_le(buf: &mut &[u8]) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    io::Read::read_exact(buf, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// The frequencies of one context, along with the cumulative frequencies and a lookup from a
+/// cumulative frequency slot to its symbol.
+struct Frequencies {
+    freq: [u32; 256],
+    cum: [u32; 256],
+    lookup: Vec<u8>,
+}
+
+impl Frequencies {
+    /// Reads a run-length encoded frequency table.
+    fn read(buf: &mut &[u8]) -> io::Result<Self> {
+        let mut freq = [0; 256];
+        let mut cum = [0; 256];
+        let mut lookup = vec![0; TOTFREQ as usize];
+
+        let mut rle = 0;
+        let mut total = 0;
+        let mut symbol = read_u8(buf)?;
+
+        loop {
+            let mut f = u32::from(read_u8(buf)?);
+            if f >= 128 {
+                f = ((f & 0x7f) << 8) | u32::from(read_u8(buf)?);
+            }
+
+            if total + f > TOTFREQ {
+                return Err(invalid_data("invalid rANS frequency table".to_string()));
+            }
+
+            freq[usize::from(symbol)] = f;
+            cum[usize::from(symbol)] = total;
+            for slot in &mut lookup[total as usize..(total + f) as usize] {
+                *slot = symbol;
+            }
+            total += f;
+
+            symbol = next_symbol(buf, symbol, &mut rle)?;
+            if symbol == 0 {
+                break;
+            }
+        }
+
+        Ok(Self { freq, cum, lookup })
+    }
+
+    fn decode(&self, state: &mut u32, buf: &mut &[u8]) -> io::Result<u8> {
+        let m = *state & (TOTFREQ - 1);
+        let symbol = self.lookup[m as usize];
+        let s = usize::from(symbol);
+
+        // A table whose frequencies sum to less than TOTFREQ leaves slots that no symbol covers.
+        if m < self.cum[s] || m - self.cum[s] >= self.freq[s] {
+            return Err(invalid_data("invalid rANS state".to_string()));
+        }
+
+        *state = self.freq[s] * (*state >> TF_SHIFT) + m - self.cum[s];
+
+        while *state < RANS_BYTE_L {
+            *state = (*state << 8) | u32::from(read_u8(buf)?);
+        }
+
+        Ok(symbol)
+    }
+}
+
+/// Steps to the next symbol of a run-length encoded table. A symbol that directly follows its
+/// predecessor is followed by the length of the run of consecutive symbols.
+fn next_symbol(buf: &mut &[u8], symbol: u8, rle: &mut u8) -> io::Result<u8> {
+    if *rle > 0 {
+        *rle -= 1;
+        Ok(symbol.wrapping_add(1))
+    } else {
+        let next = read_u8(buf)?;
+        if next != 0 && u16::from(next) == u16::from(symbol) + 1 {
+            *rle = read_u8(buf)?;
+        }
+        Ok(next)
+    }
+}
+
+/// Decodes a CRAM rANS 4x8 block, of either order 0 or order 1.
+pub(crate) fn decode(src: &[u8]) -> io::Result<Vec<u8>> {
+    let mut buf = src;
+
+    let order = read_u8(&mut buf)?;
+    let _compressed_len = read_u32_le(&mut buf)?;
+    let raw_len = read_u32_le(&mut buf)? as usize;
+
+    match order {
+        0 => decode_order_0(&mut buf, raw_len),
+        1 => decode_order_1(&mut buf, raw_len),
+        n => Err(invalid_data(format!("invalid rANS order: {}", n))),
+    }
+}
+
+fn read_states(buf: &mut &[u8]) -> io::Result<[u32; 4]> {
+    Ok([
+        read_u32_le(buf)?,
+        read_u32_le(buf)?,
+        read_u32_le(buf)?,
+        read_u32_le(buf)?,
+    ])
+}
+
+fn decode_order_0(buf: &mut &[u8], raw_len: usize) -> io::Result<Vec<u8>> {
+    let frequencies = Frequencies::read(buf)?;
+    let mut states = read_states(buf)?;
+
+    let mut output = Vec::with_capacity(raw_len);
+    for i in 0..raw_len {
+        output.push(frequencies.decode(&mut states[i % 4], buf)?);
+    }
+
+    Ok(output)
+}
+
+fn decode_order_1(buf: &mut &[u8], raw_len: usize) -> io::Result<Vec<u8>> {
+    let mut contexts: Vec<Option<Frequencies>> = (0..256).map(|_| None).collect();
+
+    let mut rle = 0;
+    let mut context = read_u8(buf)?;
+
+    loop {
+        contexts[usize::from(context)] = Some(Frequencies::read(buf)?);
+
+        context = next_symbol(buf, context, &mut rle)?;
+        if context == 0 {
+            break;
+        }
+    }
+
+    let mut states = read_states(buf)?;
+    let mut output = vec![0; raw_len];
+
+    let quarter = raw_len / 4;
+    let mut last = [0u8; 4];
+
+    let mut decode_at = |j: usize, position: usize, states: &mut [u32; 4], buf: &mut &[u8]| {
+        let frequencies = contexts[usize::from(last[j])]
+            .as_ref()
+            .ok_or_else(|| invalid_data("missing rANS context".to_string()))?;
+
+        let symbol = frequencies.decode(&mut states[j], buf)?;
+        output[position] = symbol;
+        last[j] = symbol;
+
+        Ok::<(), io::Error>(())
+    };
+
+    for i in 0..quarter {
+        for j in 0..4 {
+            decode_at(j, j * quarter + i, &mut states, buf)?;
+        }
+    }
+
+    for position in 4 * quarter..raw_len {
+        decode_at(3, position, &mut states, buf)?;
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_order_0() {
+        // A single symbol with the full frequency leaves the state unchanged, so every output
+        // byte is that symbol.
+        let mut src = vec![0, 0, 0, 0, 0, 5, 0, 0, 0];
+        src.extend_from_slice(&[b'A', 0x90, 0x00, 0x00]);
+        for _ in 0..4 {
+            src.extend_from_slice(&RANS_BYTE_L.to_le_bytes());
+        }
+
+        assert_eq!(decode(&src).unwrap(), b"AAAAA");
+    }
+
+    #[test]
+    fn test_decode_invalid_frequencies() {
+        // A, then B with a run of one more (C), each with a frequency of 0x0800, which sums past
+        // TOTFREQ.
+        let mut src = vec![0, 0, 0, 0, 0, 5, 0, 0, 0];
+        src.extend_from_slice(&[b'A', 0x88, 0x00, b'B', 1, 0x88, 0x00, 0x88, 0x00, 0]);
+        let error = decode(&src).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A single symbol with half of TOTFREQ leaves the upper slots uncovered.
+        let mut src = vec![0, 0, 0, 0, 0, 5, 0, 0, 0];
+        src.extend_from_slice(&[b'A', 0x88, 0x00, 0]);
+        for _ in 0..4 {
+            src.extend_from_slice(&(RANS_BYTE_L | 0xfff).to_le_bytes());
+        }
+        let error = decode(&src).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_next_symbol() {
+        // A, B with a run of two more (C, D), then the terminator.
+        let table = [b'B', 2, 0];
+        let mut buf = &table[..];
+        let mut rle = 0;
+
+        let mut symbols = vec![b'A'];
+        loop {
+            let symbol = next_symbol(&mut buf, *symbols.last().unwrap(), &mut rle).unwrap();
+            if symbol == 0 {
+                break;
+            }
+            symbols.push(symbol);
+        }
+
+        assert_eq!(symbols, b"ABCD");
+    }
+}
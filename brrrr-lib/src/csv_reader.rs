@@ -0,0 +1,285 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::io::{self, BufRead, Read, Write};
+use std::marker::PhantomData;
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::columns::{ColumnMap, FromColumns};
+use crate::errors::{BrrrrError, RecordLocation};
+use crate::gff;
+use crate::input::{self, BioFileCompression};
+use crate::json_reader::{fastq_record, write_fasta, write_fastq, write_gff};
+use crate::reader::{self, RecordReader};
+use crate::rejects::Rejects;
+use crate::types::{FastqRecord, GffRecord};
+
+/// Reads records of type `T` from csv with a header row, with the columns the fields are read
+/// from set by a ColumnMap.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::columns::ColumnMap;
+/// use brrrr_lib::csv_reader::CsvRecordReader;
+/// use brrrr_lib::types::FastaRecord;
+///
+/// let input = b"id,seq\nA,ATCG\n" as &[u8];
+/// let columns = ColumnMap::new().with_column("sequence", "seq");
+///
+/// let mut reader = CsvRecordReader::<_, FastaRecord>::new(input, &columns).unwrap();
+/// assert_eq!(reader.next().unwrap().unwrap().sequence, "ATCG");
+/// ```
+pub struct CsvRecordReader<R, T> {
+    records: csv::StringRecordsIntoIter<R>,
+    fields: csv::StringRecord,
+    record: PhantomData<T>,
+}
+
+impl<R: Read, T: FromColumns> CsvRecordReader<R, T> {
+    /// Creates a new CsvRecordReader, checking that the header row has a column for each field
+    /// that `T` requires.
+    pub fn new(inner: R, columns: &ColumnMap) -> Result<Self, BrrrrError> {
+        let mut reader = csv::Reader::from_reader(inner);
+        let header = reader.headers().map_err(io::Error::from)?.clone();
+
+        columns.check::<T, _>(&header.iter().collect::<Vec<_>>())?;
+
+        // Columns that aren't read into a field get an empty name, which no field has.
+        let fields = header
+            .iter()
+            .map(|column| columns.field(column).unwrap_or(""))
+            .collect();
+
+        Ok(Self {
+            records: reader.into_records(),
+            fields,
+            record: PhantomData,
+        })
+    }
+}
+
+impl<R: Read, T: FromColumns> Iterator for CsvRecordReader<R, T> {
+    type Item = Result<T, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, raw) = match self.records.next()? {
+            Ok(row) => match row.deserialize(Some(&self.fields)) {
+                Ok(record) => return Some(Ok(record)),
+                Err(e) => (e, Some(row.iter().collect::<Vec<_>>().join(","))),
+            },
+            Err(e) => (e, None),
+        };
+
+        let position = e.position().cloned();
+        let location = RecordLocation {
+            // The header row is record 0, so the data records count from 1.
+            index: position.as_ref().map_or(0, |p| p.record() as usize),
+            offset: position.as_ref().map(|p| p.byte()),
+            id: None,
+        };
+        Some(Err(
+            BrrrrError::from(io::Error::from(e)).at("csv", location, raw)
+        ))
+    }
+}
+
+impl<R: Read, T: FromColumns> RecordReader for CsvRecordReader<R, T> {
+    type Header = ();
+
+    fn header(&self) -> &Self::Header {
+        &()
+    }
+}
+
+/// A GFF record as a csv row, with the attributes in a single column as they are written in
+/// the GFF file.
+#[derive(Deserialize)]
+struct GffRow {
+    seqname: String,
+    source: String,
+    feature: String,
+    start: usize,
+    end: usize,
+    score: Option<f64>,
+    strand: Option<String>,
+    frame: Option<String>,
+    attribute: Option<String>,
+}
+
+impl FromColumns for GffRow {
+    const REQUIRED: &'static [&'static str] = &["seqname", "source", "feature", "start", "end"];
+}
+
+/// Returns the GffRecord of `row`, parsing its attributes in `dialect`.
+fn gff_record(row: GffRow, dialect: gff::Dialect) -> io::Result<GffRecord> {
+    let (attribute, unquoted_attributes) = match (row.attribute.as_deref(), dialect) {
+        (None, _) => (IndexMap::new(), Vec::new()),
+        (Some(attribute), gff::Dialect::Gff3) => (gff::parse_attributes(attribute)?, Vec::new()),
+        (Some(attribute), gff::Dialect::Gtf) => gff::parse_gtf_attributes(attribute)?,
+    };
+
+    Ok(GffRecord {
+        seqname: row.seqname,
+        source: row.source,
+        feature: row.feature,
+        start: row.start,
+        end: row.end,
+        score: row.score,
+        strand: row.strand.unwrap_or_else(|| String::from(".")),
+        frame: row.frame,
+        attribute,
+        unquoted_attributes,
+        directives: Vec::new(),
+    })
+}
+
+/// Converts a csv to FASTA
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `columns` the columns the `id`, `description` and `sequence` fields are read from.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn csv2fa<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    columns: &ColumnMap,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = CsvRecordReader::new(input::decode(input, bio_file_compression)?, columns)?;
+    write_fasta(rejects.filter("csv", reader), output)
+}
+
+/// Converts a csv to FASTQ. A quality with spaces is read as the Phred scores written by fq2csv
+/// with `phred_scores` set, and the quality is written as Phred+33.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `columns` the columns the `id`, `description`, `sequence` and `quality` fields are read
+///   from.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn csv2fq<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    columns: &ColumnMap,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader: CsvRecordReader<_, FastqRecord> =
+        CsvRecordReader::new(input::decode(input, bio_file_compression)?, columns)?;
+    let records = reader.map(|r| r.map(reader::fastq_from_csv).and_then(fastq_record));
+    write_fastq(rejects.filter("csv", records), output)
+}
+
+/// Converts a csv to GFF3 or GTF. The `attribute` column holds the attributes as they are
+/// written in `dialect`, e.g. `ID=g0;Name=a` for GFF3, and an empty strand is written as `.`.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `dialect` the GFF dialect to write.
+/// * `columns` the columns the GFF fields are read from.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn csv2gff<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    dialect: gff::Dialect,
+    columns: &ColumnMap,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader: CsvRecordReader<_, GffRow> =
+        CsvRecordReader::new(input::decode(input, bio_file_compression)?, columns)?;
+    let records = reader.map(|r| r.and_then(|row| Ok(gff_record(row, dialect)?)));
+    write_gff(rejects.filter("csv", records), output, dialect)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv_writer;
+    use crate::quality::QualityOptions;
+
+    use super::*;
+
+    #[test]
+    fn test_csv2fa() {
+        let input = b"name,sequence,seq\nA,N,ATCG\nB,N,GC\n" as &[u8];
+        let columns = ColumnMap::new()
+            .with_column("id", "name")
+            .with_column("sequence", "seq");
+
+        let mut output = Vec::new();
+        csv2fa(input, &mut output, &columns, None, &mut Rejects::default()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), ">A\nATCG\n>B\nGC\n");
+
+        let input = b"name,seq\nA,ATCG\n" as &[u8];
+        assert!(matches!(
+            csv2fa(input, &mut Vec::new(), &columns.with_column("id", "ID"), None, &mut Rejects::default()),
+            Err(BrrrrError::MissingColumn(column)) if column == "ID"
+        ));
+    }
+
+    #[test]
+    fn test_fastq_round_trip() {
+        let fastq = "@r0\nACGT\n+\n+5?I\n@r1\nTT\n+\n!!\n";
+        let quality_options = QualityOptions {
+            phred_scores: true,
+            ..Default::default()
+        };
+
+        let mut csv = Vec::new();
+        csv_writer::fq2csv(
+            fastq.as_bytes(),
+            &mut csv,
+            &quality_options,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        csv2fq(
+            &csv[..],
+            &mut output,
+            &ColumnMap::new(),
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), fastq);
+    }
+
+    #[test]
+    fn test_csv2gff() {
+        let input = b"chrom,source,feature,start,end,score,strand,frame,attribute
+chr1,src,gene,1,10,,,,gene_id \"g1\";
+" as &[u8];
+        let columns = ColumnMap::new().with_column("seqname", "chrom");
+
+        let mut output = Vec::new();
+        csv2gff(
+            input,
+            &mut output,
+            gff::Dialect::Gtf,
+            &columns,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "chr1\tsrc\tgene\t1\t10\t.\t.\t.\tgene_id \"g1\";\n"
+        );
+    }
+}
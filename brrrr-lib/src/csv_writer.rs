@@ -2,19 +2,24 @@
 // All Rights Reserved
 /// The `csv_writer` module provides an implementation for the `RecordWriter` interface to read
 /// and write from csvs.
-use std::io::{self, BufRead, ErrorKind, Write};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
 
 use serde::ser::Serialize;
 
+use crate::bed;
 use crate::errors::BrrrrError;
-use crate::types::{FastaRecord, FastqRecord};
+use crate::input::{self, BioFileCompression};
+use crate::output::{self, OutputCompression};
+use crate::quality::QualityOptions;
+use crate::reader::{self, FastaReader, FastqReader};
+use crate::rejects::Rejects;
+use crate::types::{FastqRecord, Quality, VcfRecord, VcfValue};
+use crate::vcf;
 use crate::writer;
 
 use writer::RecordWriter;
 
-use noodles::fasta;
-use noodles::fastq;
-
 /// CsvRecordWriter holds a writer, and outputs FASTA records as newline delimited json.
 pub struct CsvRecordWriter<W: Write> {
     csv_writer: csv::Writer<W>,
@@ -28,6 +33,19 @@ impl<W: Write> CsvRecordWriter<W> {
     }
 }
 
+impl<W: Write> CsvRecordWriter<output::Writer<W>> {
+    /// Creates a new CsvRecordWriter that compresses its output with `compression`.
+    pub fn with_compression(w: W, compression: OutputCompression) -> io::Result<Self> {
+        Ok(Self::new(output::Writer::new(w, compression)?))
+    }
+
+    /// Finishes the compressed output and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        let writer = self.csv_writer.into_inner().map_err(|e| e.into_error())?;
+        writer.finish()
+    }
+}
+
 impl<W: Write> writer::RecordWriter for CsvRecordWriter<W> {
     /// Writes an input serializable object to the underlying writer.
     fn write_serde_record<S: Serialize>(&mut self, r: S) -> io::Result<()> {
@@ -42,50 +60,186 @@ impl<W: Write> writer::RecordWriter for CsvRecordWriter<W> {
 ///
 /// * `input` an input that implements the Read trait.
 /// * `output` an output that implements the Write trait.
-pub fn fa2csv<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), BrrrrError> {
-    let mut reader = fasta::Reader::new(input);
-    let record_writer = &mut CsvRecordWriter::new(output);
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn fa2csv<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = FastaReader::new(input::decode(input, bio_file_compression)?);
+    reader::convert(
+        rejects.filter("FASTA", reader),
+        &mut CsvRecordWriter::new(output),
+    )
+}
 
-    for read_record in reader.records() {
-        let record = read_record?;
-        let write_op = record_writer.write_serde_record(FastaRecord::from(record));
+/// Converts a FASTQ file to CSV
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `quality_options` how the quality scores are written. CSV has no list type, so Phred scores
+///   are written as a space-separated field.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn fq2csv<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    quality_options: &QualityOptions,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = FastqReader::new(input::decode(input, bio_file_compression)?, quality_options);
+    let records = rejects.filter("FASTQ", reader).map(|r| r.map(fastq_row));
+    reader::convert(records, &mut CsvRecordWriter::new(output))
+}
 
-        if let Err(e) = write_op {
-            match e.kind() {
-                ErrorKind::BrokenPipe => break,
-                _ => return Err(BrrrrError::IOError(e)),
-            }
-        }
+/// Returns `record` as fq2csv writes it, with Phred scores space separated.
+pub(crate) fn fastq_row(mut record: FastqRecord) -> FastqRecord {
+    if let Quality::Phred(scores) = &record.quality {
+        let scores: Vec<String> = scores.iter().map(|s| s.to_string()).collect();
+        record.quality = Quality::Ascii(scores.join(" "));
     }
-    Ok(())
+
+    record
 }
 
-/// Converts a FASTQ file to CSV
+/// Returns the CSV header row for VCF records described by `header`. INFO fields are named
+/// `info.{ID}` and FORMAT fields `{sample}.{ID}`. The INFO and FORMAT keys that aren't declared
+/// in the header are written to `info_extra` and `{sample}.format_extra` as `KEY=VALUE` entries
+/// joined by `;`.
+fn vcf_csv_header(header: &vcf::Header) -> Vec<String> {
+    let mut row: Vec<String> = [
+        "chrom",
+        "pos",
+        "id",
+        "reference",
+        "alternate",
+        "quality",
+        "filter",
+    ]
+    .iter()
+    .map(|c| c.to_string())
+    .collect();
+
+    row.extend(header.infos.iter().map(|d| format!("info.{}", d.id)));
+    row.push(String::from("info_extra"));
+
+    for name in &header.sample_names {
+        row.extend(header.formats.iter().map(|d| format!("{}.{}", name, d.id)));
+        row.push(format!("{}.format_extra", name));
+    }
+
+    row
+}
+
+fn vcf_csv_row(header: &vcf::Header, record: &VcfRecord) -> Vec<String> {
+    let mut row = vec![
+        record.chrom.clone(),
+        record.pos.to_string(),
+        record.id.clone().unwrap_or_default(),
+        record.reference.clone(),
+        record.alternate.join(","),
+        record.quality.map(|q| q.to_string()).unwrap_or_default(),
+        record.filter.join(";"),
+    ];
+
+    let cell = |values: Option<&BTreeMap<String, VcfValue>>, d: &vcf::Definition| match values
+        .and_then(|v| v.get(&d.id))
+    {
+        Some(value) => value.to_string(),
+        None if d.ty == vcf::ValueType::Flag => String::from("false"),
+        None => String::new(),
+    };
+
+    let extra = |definitions: &[vcf::Definition], values: Option<&BTreeMap<String, VcfValue>>| {
+        values
+            .into_iter()
+            .flat_map(|v| vcf::undeclared(definitions, v))
+            .map(|(key, value)| match value {
+                Some(value) => format!("{}={}", key, value),
+                None => key.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(";")
+    };
+
+    row.extend(header.infos.iter().map(|d| cell(Some(&record.info), d)));
+    row.push(extra(&header.infos, Some(&record.info)));
+
+    for name in &header.sample_names {
+        let sample = record.samples.get(name);
+        row.extend(header.formats.iter().map(|d| cell(sample, d)));
+        row.push(extra(&header.formats, sample));
+    }
+
+    row
+}
+
+/// Converts a VCF file to CSV
 ///
 /// # Arguments
 ///
 /// * `input` an input that implements the BufRead trait.
 /// * `output` an output that implements the Write trait.
-pub fn fq2csv<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), BrrrrError> {
-    let mut reader = fastq::Reader::new(input);
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn vcf2csv<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = vcf::Reader::new(input::decode(input, bio_file_compression)?);
     let record_writer = &mut CsvRecordWriter::new(output);
 
-    for read_record in reader.records() {
-        let record = read_record?;
-        let write_op = record_writer.write_serde_record(FastqRecord::from(record));
+    let header = reader.read_header()?;
+    record_writer.write_serde_record(vcf_csv_header(&header))?;
 
-        if let Err(e) = write_op {
-            match e.kind() {
-                ErrorKind::BrokenPipe => break,
-                _ => return Err(BrrrrError::IOError(e)),
-            }
-        }
-    }
-    Ok(())
+    let records = rejects
+        .filter("VCF", reader.records(&header))
+        .map(|r| r.map(|record| vcf_csv_row(&header, &record)));
+    reader::convert(records, record_writer)
+}
+
+/// Converts a BED or bedGraph file to CSV. The header row follows the detected number of
+/// columns, and BED12 block lists are written comma separated.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `format` whether the input is BED or bedGraph, or `None` to detect it from a track line.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn bed2csv<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    format: Option<bed::BedFormat>,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = bed::Reader::new(input::decode(input, bio_file_compression)?);
+    let record_writer = &mut CsvRecordWriter::new(output);
+
+    let header = reader.read_header(format)?;
+    record_writer.write_serde_record(header.kind.columns())?;
+
+    let records = rejects
+        .filter("BED", reader.records(&header))
+        .map(|r| r.map(|record| bed::record_fields(header.kind, &record)));
+    reader::convert(records, record_writer)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Read;
+
+    use crate::types::FastaRecord;
+
     use super::*;
 
     #[test]
@@ -93,10 +247,106 @@ mod tests {
         let input = b">A\nATCG\n" as &[u8];
 
         let mut output = Vec::new();
-        fa2csv(input, &mut output).unwrap();
+        fa2csv(input, &mut output, None, &mut Rejects::default()).unwrap();
 
         let output_str = String::from_utf8(output).unwrap();
         let expected_output = "id,description,sequence\nA,,ATCG\n".to_string();
         assert_eq!(output_str, expected_output);
     }
+
+    #[test]
+    fn test_compressed_csv_writer() {
+        let record = FastaRecord {
+            id: "A".to_string(),
+            description: None,
+            sequence: "ATCG".to_string(),
+        };
+
+        let mut writer =
+            CsvRecordWriter::with_compression(Vec::new(), OutputCompression::GZIP).unwrap();
+        writer.write_serde_record(record).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut decoded = String::new();
+        input::decode(&output[..], None)
+            .unwrap()
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "id,description,sequence\nA,,ATCG\n");
+    }
+
+    #[test]
+    fn test_fq2csv() {
+        let input = b"@r0\nACGT\n+\n+5?I\n" as &[u8];
+
+        let mut output = Vec::new();
+        fq2csv(
+            input,
+            &mut output,
+            &QualityOptions::default(),
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,description,sequence,quality\nr0,,ACGT,+5?I\n"
+        );
+
+        let quality_options = QualityOptions {
+            phred_scores: true,
+            summary: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        fq2csv(
+            input,
+            &mut output,
+            &quality_options,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,description,sequence,quality,mean_quality,min_quality,expected_errors\n\
+             r0,,ACGT,10 20 30 40,25.0,10,0.1111\n"
+        );
+    }
+
+    #[test]
+    fn test_vcf2csv() {
+        let input = b"##fileformat=VCFv4.3
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP membership\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1
+sq0\t1\t.\tA\tC,G\t13\tPASS\tAF=0.25,.;XX=a,b;YY\tGT:ZZ\t0/1:7
+" as &[u8];
+
+        let mut output = Vec::new();
+        vcf2csv(input, &mut output, None, &mut Rejects::default()).unwrap();
+
+        let output_str = String::from_utf8(output).unwrap();
+        let expected_output =
+            "chrom,pos,id,reference,alternate,quality,filter,info.AF,info.DB,info_extra,NA1.GT,NA1.format_extra
+sq0,1,,A,\"C,G\",13,PASS,\"0.25,.\",false,\"XX=a,b;YY\",0/1,ZZ=7
+";
+        assert_eq!(output_str, expected_output);
+    }
+
+    #[test]
+    fn test_bed2csv() {
+        let input = b"chr1\t10\t200\tfeat\t0\t-\t20\t180\t0\t2\t50,40\t0,150\n" as &[u8];
+
+        let mut output = Vec::new();
+        bed2csv(input, &mut output, None, None, &mut Rejects::default()).unwrap();
+
+        let output_str = String::from_utf8(output).unwrap();
+        let expected_output = "chrom,start,end,name,score,strand,thick_start,thick_end,item_rgb,block_count,block_sizes,block_starts
+chr1,10,200,feat,0,-,20,180,0,2,\"50,40\",\"0,150\"
+";
+        assert_eq!(output_str, expected_output);
+    }
 }
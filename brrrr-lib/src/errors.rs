@@ -1,16 +1,103 @@
 use arrow;
 use parquet;
+use std::fmt;
 use std::io;
 use thiserror;
 
 #[derive(Debug, thiserror::Error)]
 pub enum BrrrrError {
-    #[error("io error")]
+    #[error("io error: {0}")]
     IOError(#[from] io::Error),
 
-    #[error("arrow error")]
+    #[error("arrow error: {0}")]
     ArrowError(#[from] arrow::error::ArrowError),
 
-    #[error("parquet error")]
+    #[error("parquet error: {0}")]
     ParquetError(#[from] parquet::errors::ParquetError),
+
+    #[error("missing reference: {0}")]
+    MissingReference(String),
+
+    #[error("reference MD5 mismatch for {name}: expected {expected}, found {actual}")]
+    ReferenceMd5Mismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("paired reads out of sync: {0} has no mate")]
+    MissingMate(String),
+
+    #[error("paired reads out of sync: {r1} does not match {r2}")]
+    MateNameMismatch { r1: String, r2: String },
+
+    #[error("unknown format: {0}")]
+    UnknownFormat(String),
+
+    #[error("cannot write {records} records as {format}")]
+    UnsupportedConversion { records: String, format: String },
+
+    #[error("cannot parse {format} {location}: {source}")]
+    ParseError {
+        format: String,
+        location: RecordLocation,
+        raw: Option<String>,
+        source: Box<BrrrrError>,
+    },
+
+    #[error("schema mismatch: expected {column} to be {expected}, found {found}")]
+    SchemaMismatch {
+        column: String,
+        expected: String,
+        found: String,
+    },
+
+    #[error("missing column: {0}")]
+    MissingColumn(String),
+
+    #[error("invalid encoding in {field}: {message}")]
+    InvalidEncoding { field: String, message: String },
+}
+
+impl BrrrrError {
+    /// Wraps the error in a ParseError at `location` of a `format` input, keeping the `raw` text
+    /// of the record if there is one. An error that is already a ParseError is returned as is.
+    pub fn at(self, format: &str, location: RecordLocation, raw: Option<String>) -> BrrrrError {
+        match self {
+            BrrrrError::ParseError { .. } => self,
+            source => BrrrrError::ParseError {
+                format: format.to_string(),
+                location,
+                raw,
+                source: Box::new(source),
+            },
+        }
+    }
+}
+
+/// Where a record that failed to parse is in its input.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecordLocation {
+    /// The 1-based index of the record.
+    pub index: usize,
+    /// The byte offset of the start of the record, if known.
+    pub offset: Option<u64>,
+    /// The ID of the record, if it was read before the error.
+    pub id: Option<String>,
+}
+
+impl fmt::Display for RecordLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "record {}", self.index)?;
+
+        if let Some(id) = &self.id {
+            write!(f, " ({})", id)?;
+        }
+
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {}", offset)?;
+        }
+
+        Ok(())
+    }
 }
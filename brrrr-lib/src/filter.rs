@@ -0,0 +1,371 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, ErrorKind};
+use std::ops::Bound;
+use std::str::FromStr;
+
+/// RecordFilter selects the records a converter writes, by ID, by length and by a predicate on
+/// the other columns. An empty filter keeps every record.
+#[derive(Clone, Debug, Default)]
+pub struct RecordFilter {
+    /// The IDs to keep, or None to keep every ID.
+    pub ids: Option<BTreeSet<String>>,
+    /// The shortest length to keep.
+    pub min_length: Option<usize>,
+    /// The longest length to keep.
+    pub max_length: Option<usize>,
+    /// The predicate the other columns must match.
+    pub predicate: Option<Predicate>,
+}
+
+impl RecordFilter {
+    /// Returns true if the filter keeps every record.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_none()
+            && self.min_length.is_none()
+            && self.max_length.is_none()
+            && self.predicate.is_none()
+    }
+
+    /// Returns true if a record with `id` is kept.
+    pub fn id_matches(&self, id: &str) -> bool {
+        self.ids.as_ref().is_none_or(|ids| ids.contains(id))
+    }
+
+    /// Returns true if any of the IDs that are kept is between `min` and `max`.
+    pub fn ids_may_match(&self, min: &str, max: &str) -> bool {
+        match &self.ids {
+            Some(ids) if min <= max => ids
+                .range::<str, _>((Bound::Included(min), Bound::Included(max)))
+                .next()
+                .is_some(),
+            _ => true,
+        }
+    }
+
+    /// Returns true if a record of `length` is kept.
+    pub fn length_matches(&self, length: usize) -> bool {
+        self.min_length.is_none_or(|min| length >= min)
+            && self.max_length.is_none_or(|max| length <= max)
+    }
+}
+
+/// Reads an ID allow-list, with one ID per line. Blank lines are skipped.
+pub fn read_ids<R: BufRead>(input: R) -> io::Result<BTreeSet<String>> {
+    let mut ids = BTreeSet::new();
+
+    for line in input.lines() {
+        let line = line?;
+        let id = line.trim();
+
+        if !id.is_empty() {
+            ids.insert(id.to_string());
+        }
+    }
+
+    Ok(ids)
+}
+
+/// How a column is compared to a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    /// Returns true if a column that compares to the value as `ordering` matches.
+    pub fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// The value a column is compared to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+}
+
+/// Comparison compares a column to a value, e.g. `length >= 1000`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub column: String,
+    pub op: Op,
+    pub value: Value,
+}
+
+impl Comparison {
+    /// Returns true if a column of `value` matches. Numbers that can't be ordered, i.e. NaN,
+    /// never match.
+    pub fn matches_number(&self, value: f64) -> bool {
+        match &self.value {
+            Value::Number(n) => value.partial_cmp(n).is_some_and(|o| self.op.matches(o)),
+            Value::Text(_) => false,
+        }
+    }
+
+    /// Returns true if a column of `value` matches.
+    pub fn matches_text(&self, value: &str) -> bool {
+        match &self.value {
+            Value::Text(text) => self.op.matches(value.cmp(text.as_str())),
+            Value::Number(_) => false,
+        }
+    }
+
+    /// Returns true if any value between `min` and `max` could match, where `value` is the
+    /// value being compared to.
+    pub fn may_match<T: PartialOrd>(&self, value: &T, min: &T, max: &T) -> bool {
+        let (low, high) = match (min.partial_cmp(value), max.partial_cmp(value)) {
+            (Some(low), Some(high)) => (low, high),
+            _ => return true,
+        };
+
+        match self.op {
+            Op::Eq => low != Ordering::Greater && high != Ordering::Less,
+            Op::Ne => !(low == Ordering::Equal && high == Ordering::Equal),
+            Op::Lt => low == Ordering::Less,
+            Op::Le => low != Ordering::Greater,
+            Op::Gt => high == Ordering::Greater,
+            Op::Ge => high != Ordering::Less,
+        }
+    }
+}
+
+/// Predicate is one or more comparisons joined with `and`, e.g.
+/// `length >= 1000 and organism == "E. coli"`. A value in quotes is compared as text, and an
+/// unquoted value as a number if it is one. `or`, `not` and parentheses aren't supported and are
+/// an error, rather than being read as part of a value.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::filter::{Op, Predicate, Value};
+///
+/// let predicate: Predicate = "length >= 1000 and organism == 'E. coli'".parse().unwrap();
+/// let comparisons = predicate.comparisons();
+///
+/// assert_eq!(comparisons[0].column, "length");
+/// assert_eq!(comparisons[0].op, Op::Ge);
+/// assert_eq!(comparisons[1].value, Value::Text(String::from("E. coli")));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Predicate {
+    comparisons: Vec<Comparison>,
+}
+
+impl Predicate {
+    /// Returns the comparisons, all of which a record must match.
+    pub fn comparisons(&self) -> &[Comparison] {
+        &self.comparisons
+    }
+
+    /// Returns the columns the predicate reads.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.comparisons.iter().map(|c| c.column.as_str())
+    }
+}
+
+/// The operators. Where two start at the same place, the longer is read, so that `<=` isn't read
+/// as `<`.
+const OPS: &[(&str, Op)] = &[
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+impl FromStr for Comparison {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid comparison: {}", s),
+            )
+        };
+
+        let (start, symbol, op) = OPS
+            .iter()
+            .filter_map(|(symbol, op)| Some((s.find(symbol)?, *symbol, *op)))
+            .min_by_key(|(start, symbol, _)| (*start, std::cmp::Reverse(symbol.len())))
+            .ok_or_else(invalid)?;
+
+        let column = s[..start].trim();
+        let value = s[start + symbol.len()..].trim();
+
+        if column.is_empty() || value.is_empty() {
+            return Err(invalid());
+        }
+
+        // A column is a single word, and so is a value that isn't in quotes, so that e.g.
+        // `id == b or id == a` isn't read as comparing id to `b or id == a`.
+        let is_word = |s: &str| !s.contains(|c: char| c.is_whitespace() || "\"'()=!<>".contains(c));
+
+        if !is_word(column) {
+            return Err(invalid());
+        }
+
+        let quoted = ['"', '\'']
+            .iter()
+            .find_map(|q| Some((value.strip_prefix(*q)?.strip_suffix(*q)?, *q)));
+
+        let value = match (quoted, value.parse::<f64>()) {
+            (Some((text, q)), _) if !text.contains(q) => Value::Text(text.to_string()),
+            (Some(_), _) => return Err(invalid()),
+            (None, _) if !is_word(value) => return Err(invalid()),
+            (None, Ok(number)) => Value::Number(number),
+            (None, Err(_)) => Value::Text(value.to_string()),
+        };
+
+        Ok(Self {
+            column: column.to_string(),
+            op,
+            value,
+        })
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let comparisons = split_and(s)?
+            .into_iter()
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { comparisons })
+    }
+}
+
+/// Splits `s` at each `and` that's a word of its own and not in quotes. An `or` or `not`, a
+/// parenthesis or an unclosed quote is an error.
+fn split_and(s: &str) -> io::Result<Vec<&str>> {
+    let unsupported = |what: &str| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid predicate: {} isn't supported: {}", what, s),
+        )
+    };
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut previous = ' ';
+
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '(' || c == ')' => return Err(unsupported("a parenthesis")),
+            None if previous.is_whitespace() || i == 0 => {
+                let word = s[i..].split(char::is_whitespace).next().unwrap_or_default();
+
+                match word {
+                    "and" if i > 0 => {
+                        parts.push(&s[start..i]);
+                        start = i + 3;
+                    }
+                    "or" | "not" => return Err(unsupported(&format!("`{}`", word))),
+                    _ => {}
+                }
+            }
+            None => {}
+        }
+
+        previous = c;
+    }
+
+    if quote.is_some() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid predicate: unclosed quote: {}", s),
+        ));
+    }
+
+    parts.push(&s[start..]);
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predicate() {
+        let predicate: Predicate = "length>=1000 and name != \"a and b\"".parse().unwrap();
+        let comparisons = predicate.comparisons();
+        assert_eq!(comparisons.len(), 2);
+
+        assert!(comparisons[0].matches_number(1000.0));
+        assert!(!comparisons[0].matches_number(999.0));
+        assert!(!comparisons[0].matches_text("1000"));
+        assert!(comparisons[0].may_match(&1000.0, &10.0, &1000.0));
+        assert!(!comparisons[0].may_match(&1000.0, &10.0, &999.0));
+
+        assert_eq!(comparisons[1].value, Value::Text(String::from("a and b")));
+
+        for invalid in ["length", ">= 5", "length >=", "length >= 5 and"] {
+            assert!(invalid.parse::<Predicate>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_predicate_rejects_unsupported() {
+        for invalid in [
+            "id == 'b' or id == 'a'",
+            "id == b or id == a",
+            "not id == 'a'",
+            "(length > 5)",
+            "length > 5 and (id == 'a')",
+            "id == 'a'b'",
+            "id == 'a",
+            "id == a == b",
+            "id == \"a' or id == 'b\"x",
+        ] {
+            let error = invalid.parse::<Predicate>().unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", invalid);
+        }
+
+        let predicate: Predicate = "name == 'a or b' and note != \"(x)\"".parse().unwrap();
+        let comparisons = predicate.comparisons();
+        assert_eq!(comparisons[0].value, Value::Text(String::from("a or b")));
+        assert_eq!(comparisons[1].value, Value::Text(String::from("(x)")));
+    }
+
+    #[test]
+    fn test_record_filter() {
+        let filter = RecordFilter {
+            ids: Some(read_ids(b"b\n\n d \n" as &[u8]).unwrap()),
+            min_length: Some(2),
+            ..Default::default()
+        };
+
+        assert!(filter.id_matches("d"));
+        assert!(!filter.id_matches("a"));
+        assert!(filter.ids_may_match("a", "c"));
+        assert!(!filter.ids_may_match("e", "z"));
+        assert!(filter.length_matches(2));
+        assert!(!filter.length_matches(1));
+    }
+}
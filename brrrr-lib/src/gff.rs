@@ -0,0 +1,689 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use indexmap::IndexMap;
+use noodles::fasta;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::types::GffRecord;
+
+/// The characters with a reserved meaning in GFF3 column 9, which are percent-encoded in
+/// attribute keys and values.
+const ATTRIBUTE_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b'\t')
+    .add(b'\n')
+    .add(b'\r')
+    .add(b'%')
+    .add(b';')
+    .add(b'=')
+    .add(b'&')
+    .add(b',');
+
+const FASTA_DIRECTIVE: &str = "##FASTA";
+/// The directive that starts a GFF3 file, i.e. `##gff-version 3`.
+pub const VERSION_DIRECTIVE: &str = "##gff-version";
+
+/// The dialect of a GFF file, which decides how column 9 is parsed and written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// GFF3, e.g. `ID=a;Parent=b,c`.
+    #[default]
+    Gff3,
+    /// GTF and GFF2, e.g. `gene_id "a"; transcript_id "b";`.
+    Gtf,
+}
+
+impl Dialect {
+    /// Returns the name of the dialect, as stored in parquet metadata.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dialect::Gff3 => "gff3",
+            Dialect::Gtf => "gtf",
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gff3" => Ok(Dialect::Gff3),
+            "gtf" | "gff2" => Ok(Dialect::Gtf),
+            _ => Err(invalid_data(format!("invalid GFF dialect: {}", s))),
+        }
+    }
+}
+
+/// The directives and comments that precede the first record, along with the dialect either
+/// given by the caller or detected from those lines.
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub lines: Vec<String>,
+    pub dialect: Dialect,
+}
+
+/// Writes the directives and comments of `header` for a file in `dialect`. A version directive
+/// from another dialect would be misleading and is dropped, and GFF3 output always starts with a
+/// `##gff-version 3` directive.
+pub fn write_header<W: Write + ?Sized>(
+    writer: &mut W,
+    header: &Header,
+    dialect: Dialect,
+) -> io::Result<()> {
+    let lines = header
+        .lines
+        .iter()
+        .filter(|l| header.dialect == dialect || !l.starts_with(VERSION_DIRECTIVE))
+        .collect::<Vec<_>>();
+
+    let has_version = lines
+        .first()
+        .is_some_and(|l| l.starts_with(VERSION_DIRECTIVE));
+    if dialect == Dialect::Gff3 && !has_version {
+        writeln!(writer, "{} 3", VERSION_DIRECTIVE)?;
+    }
+
+    for line in lines {
+        writeln!(writer, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Detects the dialect from a `##gff-version` directive, falling back to the attributes of the
+/// first record. A first attribute with whitespace before any `=`, e.g. `gene_id "a"`, is taken
+/// to be GTF.
+fn detect_dialect(lines: &[String], first_record: Option<&str>) -> Dialect {
+    let version = lines
+        .iter()
+        .find_map(|l| l.strip_prefix(VERSION_DIRECTIVE))
+        .map(str::trim);
+
+    match version {
+        Some(v) if v.starts_with('2') => return Dialect::Gtf,
+        Some(v) if v.starts_with('3') => return Dialect::Gff3,
+        _ => {}
+    }
+
+    let attribute = first_record
+        .and_then(|line| line.splitn(9, '\t').nth(8))
+        .and_then(|s| s.split(';').map(str::trim).find(|e| !e.is_empty()));
+
+    match attribute {
+        Some(entry) => match (entry.find(char::is_whitespace), entry.find('=')) {
+            (Some(ws), Some(eq)) if ws < eq => Dialect::Gtf,
+            (Some(_), None) => Dialect::Gtf,
+            _ => Dialect::Gff3,
+        },
+        None => Dialect::Gff3,
+    }
+}
+
+/// Reads GFF3 or GTF records from a buffered reader. Directives and comments before the first
+/// record are kept in the header, and later ones, e.g. a `##sequence-region` between records or
+/// the `###` forward-reference separator, in the `directives` of the record they follow. Reading
+/// records stops at a `##FASTA` directive, after which the embedded sequences can be read with
+/// `fasta_reader`.
+pub struct Reader<R> {
+    inner: R,
+    pending: Option<(u64, String)>,
+    offset: u64,
+    done: bool,
+    fasta: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a new GFF reader.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending: None,
+            offset: 0,
+            done: false,
+            fasta: false,
+        }
+    }
+
+    /// Reads the next non-empty line, along with the byte offset it starts at.
+    fn read_line(&mut self) -> io::Result<Option<(u64, String)>> {
+        let mut line = String::new();
+
+        while !self.done {
+            line.clear();
+            let offset = self.offset;
+
+            match self.inner.read_line(&mut line)? {
+                0 => {
+                    self.done = true;
+                    break;
+                }
+                n => self.offset += n as u64,
+            }
+
+            let trimmed = line.trim_end_matches(&['\n', '\r'][..]);
+            if trimmed.starts_with(FASTA_DIRECTIVE) {
+                self.done = true;
+                self.fasta = true;
+            } else if !trimmed.is_empty() {
+                return Ok(Some((offset, trimmed.to_string())));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the directives and comments up to the first record. The dialect is `dialect` if
+    /// given, and is otherwise detected.
+    pub fn read_header(&mut self, dialect: Option<Dialect>) -> io::Result<Header> {
+        let lines = self.read_directives()?;
+        let pending = self.pending.as_ref().map(|(_, line)| line.as_str());
+        let dialect = dialect.unwrap_or_else(|| detect_dialect(&lines, pending));
+        Ok(Header { lines, dialect })
+    }
+
+    /// Reads the directives and comments up to the next record, which follow the record that was
+    /// read last.
+    pub fn read_directives(&mut self) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+
+        while let Some((offset, line)) = self.read_line()? {
+            if line.starts_with('#') {
+                lines.push(line);
+            } else {
+                self.pending = Some((offset, line));
+                break;
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// Reads the line of the next record, along with the byte offset it starts at. Directives and
+    /// comments that haven't been read with `read_directives` are skipped.
+    pub fn read_record_line(&mut self) -> io::Result<Option<(u64, String)>> {
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => match self.read_line()? {
+                    Some(line) => line,
+                    None => return Ok(None),
+                },
+            };
+
+            if !line.1.starts_with('#') {
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    /// Returns an iterator over the records, parsed according to `header`.
+    pub fn records<'a>(&'a mut self, header: &'a Header) -> Records<'a, R> {
+        Records {
+            reader: self,
+            dialect: header.dialect,
+        }
+    }
+
+    /// Returns a FASTA reader over the sequences that follow a `##FASTA` directive, or None if
+    /// the records have not been read to a `##FASTA` directive.
+    pub fn fasta_reader(&mut self) -> Option<fasta::Reader<&mut R>> {
+        if self.fasta {
+            Some(fasta::Reader::new(&mut self.inner))
+        } else {
+            None
+        }
+    }
+}
+
+/// An iterator over the records of a GFF file.
+pub struct Records<'a, R> {
+    reader: &'a mut Reader<R>,
+    dialect: Dialect,
+}
+
+impl<'a, R: BufRead> Iterator for Records<'a, R> {
+    type Item = io::Result<GffRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.reader.read_record_line() {
+            Ok(Some((_, line))) => line,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let record = parse_record(self.dialect, &line).and_then(|mut record| {
+            record.directives = self.reader.read_directives()?;
+            Ok(record)
+        });
+        Some(record)
+    }
+}
+fn parse_field<T: FromStr>(name: &str, s: &str) -> io::Result<T> {
+    s.parse()
+        .map_err(|_| invalid_data(format!("invalid {}: {}", name, s)))
+}
+
+fn percent_decode(s: &str) -> io::Result<String> {
+    percent_decode_str(s)
+        .decode_utf8()
+        .map(|v| v.into_owned())
+        .map_err(|e| invalid_data(format!("invalid GFF attribute {}: {}", s, e)))
+}
+
+fn percent_encode(s: &str) -> String {
+    utf8_percent_encode(s, ATTRIBUTE_ENCODE_SET).to_string()
+}
+
+/// GFF attributes, with the values of each key in the order of the file.
+pub type Attributes = IndexMap<String, Vec<String>>;
+
+/// Parses GFF3 attributes, e.g. `ID=a;Parent=b,c`. Values are split on unescaped commas and
+/// percent-decoded, and the values of a repeated key are appended to the same list.
+pub fn parse_attributes(s: &str) -> io::Result<Attributes> {
+    let mut attributes = Attributes::new();
+
+    if s == "." {
+        return Ok(attributes);
+    }
+
+    for entry in s.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| invalid_data(format!("invalid GFF attribute: {}", entry)))?;
+
+        let values = attributes.entry(percent_decode(key)?).or_default();
+        for v in value.split(',') {
+            values.push(percent_decode(v)?);
+        }
+    }
+
+    Ok(attributes)
+}
+
+/// Formats GFF3 attributes, percent-encoding reserved characters and joining multiple values
+/// with commas.
+pub fn format_attributes<'a, I>(attributes: I) -> String
+where
+    I: IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+{
+    let entries: Vec<String> = attributes
+        .into_iter()
+        .map(|(key, values)| {
+            let values: Vec<String> = values.iter().map(|v| percent_encode(v)).collect();
+            format!("{}={}", percent_encode(key), values.join(","))
+        })
+        .collect();
+
+    if entries.is_empty() {
+        ".".to_string()
+    } else {
+        entries.join(";")
+    }
+}
+
+/// Parses GTF attributes, e.g. `gene_id "a"; tag "basic"; tag "CCDS";`. Quotes are stripped and
+/// `\"` and `\\` inside them unescaped, unquoted values such as `exon_number 1` are kept as-is,
+/// and the values of a repeated key are appended to the same list. Returns the attributes and
+/// the keys whose values weren't quoted, which format_gtf_attributes writes without quotes.
+pub fn parse_gtf_attributes(s: &str) -> io::Result<(Attributes, Vec<String>)> {
+    let mut attributes = Attributes::new();
+    let mut unquoted = Vec::new();
+    let mut rest = s.trim();
+
+    if rest == "." {
+        return Ok((attributes, unquoted));
+    }
+
+    while !rest.is_empty() {
+        let (key, after_key) = rest
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| invalid_data(format!("invalid GTF attribute: {}", rest)))?;
+        let after_key = after_key.trim_start();
+
+        let (value, after_value) = match after_key.strip_prefix('"') {
+            Some(quoted) => unquote_gtf_value(quoted)
+                .ok_or_else(|| invalid_data(format!("unterminated GTF value: {}", rest)))?,
+            None => {
+                if !unquoted.iter().any(|k| k == key) {
+                    unquoted.push(key.to_string());
+                }
+
+                let end = after_key.find(';').unwrap_or(after_key.len());
+                (after_key[..end].trim_end().to_string(), &after_key[end..])
+            }
+        };
+
+        attributes.entry(key.to_string()).or_default().push(value);
+
+        rest = after_value.trim_start();
+        rest = rest.strip_prefix(';').unwrap_or(rest).trim_start();
+    }
+
+    Ok((attributes, unquoted))
+}
+
+/// Reads a quoted GTF value up to its closing quote, unescaping `\"` and `\\`. Returns the value
+/// and the text after the closing quote, or None if the value isn't closed.
+fn unquote_gtf_value(quoted: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &quoted[i + 1..])),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+/// The GTF attributes that must come first, in this order.
+const GTF_LEADING_ATTRIBUTES: &[&str] = &["gene_id", "transcript_id"];
+
+/// Formats GTF attributes, writing one `key "value";` entry per value. `gene_id` and
+/// `transcript_id` are written first as GTF requires, and the other attributes keep their order.
+/// The values of the keys in `unquoted`, e.g. `level 2`, are written without quotes, and `"` and
+/// `\` in quoted values are escaped.
+pub fn format_gtf_attributes<'a, I>(attributes: I, unquoted: &[String]) -> String
+where
+    I: IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+{
+    let (leading, rest): (Vec<_>, Vec<_>) = attributes
+        .into_iter()
+        .partition(|(key, _)| GTF_LEADING_ATTRIBUTES.contains(&key.as_str()));
+
+    let leading = GTF_LEADING_ATTRIBUTES
+        .iter()
+        .filter_map(|name| leading.iter().find(|(key, _)| key == name).copied());
+
+    let entries: Vec<String> = leading
+        .chain(rest)
+        .flat_map(|(key, values)| {
+            let quoted = !unquoted.contains(key);
+
+            values.iter().map(move |v| {
+                if !quoted {
+                    format!("{} {};", key, v)
+                } else {
+                    let escaped = v.replace('\\', "\\\\").replace('"', "\\\"");
+                    format!("{} \"{}\";", key, escaped)
+                }
+            })
+        })
+        .collect();
+
+    if entries.is_empty() {
+        ".".to_string()
+    } else {
+        entries.join(" ")
+    }
+}
+
+/// Parses a single GFF3 or GTF record line.
+pub fn parse_record(dialect: Dialect, line: &str) -> io::Result<GffRecord> {
+    let fields: Vec<&str> = line.splitn(9, '\t').collect();
+
+    if fields.len() < 8 {
+        return Err(invalid_data(format!(
+            "expected 9 GFF columns, found {}",
+            fields.len()
+        )));
+    }
+
+    let score = match fields[5] {
+        "." => None,
+        s => Some(parse_field("score", s)?),
+    };
+
+    let frame = match fields[7] {
+        "." => None,
+        s @ ("0" | "1" | "2") => Some(s.to_string()),
+        s => return Err(invalid_data(format!("invalid phase: {}", s))),
+    };
+
+    let attribute = fields.get(8).copied().unwrap_or(".");
+    let (attribute, unquoted_attributes) = match dialect {
+        Dialect::Gff3 => (parse_attributes(attribute)?, Vec::new()),
+        Dialect::Gtf => parse_gtf_attributes(attribute)?,
+    };
+
+    Ok(GffRecord {
+        seqname: fields[0].to_string(),
+        source: fields[1].to_string(),
+        feature: fields[2].to_string(),
+        start: parse_field("start", fields[3])?,
+        end: parse_field("end", fields[4])?,
+        score,
+        strand: fields[6].to_string(),
+        frame,
+        attribute,
+        unquoted_attributes,
+        directives: Vec::new(),
+    })
+}
+
+/// Formats a GFF score as its shortest decimal form, e.g. `12.5` or `-3`, or in scientific
+/// notation when that would need leading or trailing zeros, e.g. `1.2e-10` for an E-value.
+pub fn format_score(score: f64) -> String {
+    let magnitude = score.abs();
+
+    if magnitude != 0.0 && magnitude.is_finite() && !(1e-4..1e16).contains(&magnitude) {
+        format!("{:e}", score)
+    } else {
+        score.to_string()
+    }
+}
+
+/// Formats a record as a GFF3 or GTF line, followed by a line for each of its directives,
+/// without the trailing newline.
+pub fn format_record(dialect: Dialect, record: &GffRecord) -> String {
+    let score = record.score.map_or_else(|| ".".to_string(), format_score);
+
+    let attribute = match dialect {
+        Dialect::Gff3 => format_attributes(&record.attribute),
+        Dialect::Gtf => format_gtf_attributes(&record.attribute, &record.unquoted_attributes),
+    };
+
+    let mut line = format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        record.seqname,
+        record.source,
+        record.feature,
+        record.start,
+        record.end,
+        score,
+        record.strand,
+        record.frame.as_deref().unwrap_or("."),
+        attribute,
+    );
+
+    for directive in &record.directives {
+        line.push('\n');
+        line.push_str(directive);
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attributes() {
+        let attributes =
+            parse_attributes("ID=cds0;Parent=mrna0,mrna1;Note=a%2Cb%3Bc;Parent=mrna2").unwrap();
+
+        assert_eq!(attributes["ID"], vec!["cds0"]);
+        assert_eq!(attributes["Parent"], vec!["mrna0", "mrna1", "mrna2"]);
+        assert_eq!(attributes["Note"], vec!["a,b;c"]);
+        assert!(parse_attributes(".").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let line = "sq0\tNOODLES\tCDS\t8\t13\t.\t+\t0\tNote=a%2Cb,c%3Dd";
+        let record = parse_record(Dialect::Gff3, line).unwrap();
+
+        assert_eq!(record.attribute["Note"], vec!["a,b", "c=d"]);
+        assert_eq!(format_record(Dialect::Gff3, &record), line);
+        assert_eq!(
+            parse_record(Dialect::Gff3, &format_record(Dialect::Gff3, &record)).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn test_parse_gtf_attributes() {
+        let (attributes, unquoted) = parse_gtf_attributes(
+            r#"gene_id "g1"; transcript_id "t1; a"; exon_number 2; tag "basic"; tag "CCDS";"#,
+        )
+        .unwrap();
+
+        assert_eq!(attributes["gene_id"], vec!["g1"]);
+        assert_eq!(attributes["transcript_id"], vec!["t1; a"]);
+        assert_eq!(attributes["exon_number"], vec!["2"]);
+        assert_eq!(attributes["tag"], vec!["basic", "CCDS"]);
+        assert_eq!(unquoted, vec!["exon_number"]);
+        assert!(parse_gtf_attributes(r#"gene_id "g1"#).is_err());
+    }
+
+    #[test]
+    fn test_gtf_record_round_trip() {
+        let line = "X7\tAUGUSTUS\tintron\t1\t102\t1\t+\t.\ttag \"a\"; tag \"b\";";
+        let record = parse_record(Dialect::Gtf, line).unwrap();
+
+        assert_eq!(record.attribute["tag"], vec!["a", "b"]);
+        assert_eq!(format_record(Dialect::Gtf, &record), line);
+
+        let line = "chr1\tHAVANA\texon\t11869\t12227\t.\t+\t.\t\
+            gene_name \"DDX11L1\"; transcript_id \"t1\"; gene_id \"g1\"; exon_number 1; \
+            note \"a \\\"b\\\" \\\\ c\"; level 2;";
+        let record = parse_record(Dialect::Gtf, line).unwrap();
+
+        assert_eq!(record.attribute["note"], vec![r#"a "b" \ c"#]);
+        assert_eq!(
+            format_record(Dialect::Gtf, &record),
+            "chr1\tHAVANA\texon\t11869\t12227\t.\t+\t.\t\
+            gene_id \"g1\"; transcript_id \"t1\"; gene_name \"DDX11L1\"; exon_number 1; \
+            note \"a \\\"b\\\" \\\\ c\"; level 2;"
+        );
+    }
+
+    #[test]
+    fn test_gtf_quoted_numbers_round_trip() {
+        // An Ensembl line, which quotes every value, with transcript_id moved up after gene_id
+        // as format_gtf_attributes writes it.
+        let line = "1\thavana\texon\t65419\t65433\t.\t+\t.\t\
+            gene_id \"ENSG00000186092\"; transcript_id \"ENST00000641515\"; gene_version \"7\"; \
+            transcript_version \"2\"; exon_number \"1\"; gene_name \"OR4F5\"; \
+            transcript_support_level \"1\"; tag \"basic\";";
+        let record = parse_record(Dialect::Gtf, line).unwrap();
+
+        assert_eq!(record.attribute["exon_number"], vec!["1"]);
+        assert!(record.unquoted_attributes.is_empty());
+        assert_eq!(format_record(Dialect::Gtf, &record), line);
+        assert_eq!(
+            parse_record(Dialect::Gtf, &format_record(Dialect::Gtf, &record)).unwrap(),
+            record
+        );
+
+        // GFF3 has no unquoted values, so they are all quoted in GTF.
+        let gff3 = parse_record(Dialect::Gff3, &format_record(Dialect::Gff3, &record)).unwrap();
+        assert_eq!(format_record(Dialect::Gtf, &gff3), line);
+    }
+
+    #[test]
+    fn test_read_header_detects_dialect() {
+        let gtf = b"X7\tAUGUSTUS\tintron\t1\t102\t1\t+\t.\tgene_id \"g1\";\n";
+        let mut reader = Reader::new(&gtf[..]);
+        let header = reader.read_header(None).unwrap();
+        assert_eq!(header.dialect, Dialect::Gtf);
+
+        let records: Vec<GffRecord> = reader.records(&header).map(|r| r.unwrap()).collect();
+        assert_eq!(records[0].attribute["gene_id"], vec!["g1"]);
+
+        let gff3 = b"##gff-version 3\nsq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tNote=a b\n";
+        let mut reader = Reader::new(&gff3[..]);
+        assert_eq!(reader.read_header(None).unwrap().dialect, Dialect::Gff3);
+
+        let mut reader = Reader::new(&gff3[..]);
+        let header = reader.read_header(Some(Dialect::Gtf)).unwrap();
+        assert_eq!(header.dialect, Dialect::Gtf);
+    }
+
+    #[test]
+    fn test_read_header_and_fasta() {
+        let data = b"##gff-version 3\n##sequence-region sq0 1 20\n# a comment\n\
+sq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tID=g0\n###\n\
+##FASTA\n>sq0 first\nACGTACGTAC\nGTACGTACGT\n";
+        let mut reader = Reader::new(&data[..]);
+
+        let header = reader.read_header(None).unwrap();
+        assert_eq!(
+            header.lines,
+            vec![
+                "##gff-version 3",
+                "##sequence-region sq0 1 20",
+                "# a comment"
+            ]
+        );
+        assert!(reader.fasta_reader().is_none());
+
+        let records: Vec<GffRecord> = reader.records(&header).map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 1);
+
+        let sequences: Vec<fasta::Record> = reader
+            .fasta_reader()
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(sequences[0].name(), "sq0");
+        assert_eq!(sequences[0].sequence().as_ref(), b"ACGTACGTACGTACGTACGT");
+    }
+
+    #[test]
+    fn test_directives_after_first_record_round_trip() {
+        let data = "##gff-version 3\nsq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tID=g0\n\
+##sequence-region sq1 1 20\n###\n# a comment\nsq1\tNOODLES\tgene\t1\t5\t.\t-\t.\tID=g1\n###\n";
+        let mut reader = Reader::new(data.as_bytes());
+
+        let header = reader.read_header(None).unwrap();
+        assert_eq!(header.lines, vec!["##gff-version 3"]);
+
+        let records: Vec<GffRecord> = reader.records(&header).map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].directives,
+            vec!["##sequence-region sq1 1 20", "###", "# a comment"]
+        );
+        assert_eq!(records[1].seqname, "sq1");
+        assert_eq!(records[1].directives, vec!["###"]);
+
+        let mut output = Vec::new();
+        write_header(&mut output, &header, Dialect::Gff3).unwrap();
+        for record in &records {
+            writeln!(output, "{}", format_record(Dialect::Gff3, record)).unwrap();
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), data);
+    }
+}
@@ -0,0 +1,168 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use bzip2::bufread::BzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+
+/// The compression of an input file.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BioFileCompression {
+    UNCOMPRESSED,
+    GZIP,
+    BGZF,
+    ZSTD,
+    BZIP2,
+    XZ,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Detects the compression of an input from its first bytes. A gzip member with a `BC` extra
+/// subfield is BGZF.
+pub fn detect(buf: &[u8]) -> BioFileCompression {
+    if buf.starts_with(GZIP_MAGIC) {
+        let has_extra = buf.get(3).is_some_and(|flags| flags & 0x04 != 0);
+
+        if has_extra && buf.get(12..14) == Some(b"BC") {
+            BioFileCompression::BGZF
+        } else {
+            BioFileCompression::GZIP
+        }
+    } else if buf.starts_with(ZSTD_MAGIC) {
+        BioFileCompression::ZSTD
+    } else if buf.starts_with(BZIP2_MAGIC) {
+        BioFileCompression::BZIP2
+    } else if buf.starts_with(XZ_MAGIC) {
+        BioFileCompression::XZ
+    } else {
+        BioFileCompression::UNCOMPRESSED
+    }
+}
+
+/// Wraps `reader` in a decoder for `compression`, or for the compression detected from its first
+/// bytes if None. Gzip and BGZF are read through every member, so concatenated files decode in
+/// full.
+pub fn decode<'a, R: BufRead + 'a>(
+    mut reader: R,
+    compression: Option<BioFileCompression>,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let compression = match compression {
+        Some(compression) => compression,
+        None => detect(reader.fill_buf()?),
+    };
+
+    let decoded: Box<dyn BufRead + 'a> = match compression {
+        BioFileCompression::UNCOMPRESSED => Box::new(reader),
+        BioFileCompression::GZIP | BioFileCompression::BGZF => {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        }
+        BioFileCompression::ZSTD => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        BioFileCompression::BZIP2 => Box::new(BufReader::new(BzDecoder::new(reader))),
+        BioFileCompression::XZ => Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))),
+    };
+
+    Ok(decoded)
+}
+
+/// The path that stands for stdin when reading, and stdout when writing.
+pub const STDIO_PATH: &str = "-";
+
+/// Returns true if `path` is `-`, which stands for stdin or stdout.
+pub fn is_stdio<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref() == Path::new(STDIO_PATH)
+}
+
+/// Opens the file at `path` for reading, or stdin if `path` is `-`, without decoding it.
+pub fn open_raw<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn BufRead>> {
+    if is_stdio(&path) {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+/// Opens the file at `path` for reading, or stdin if `path` is `-`, decoding it as with `decode`.
+pub fn open<P: AsRef<Path>>(
+    path: P,
+    compression: Option<BioFileCompression>,
+) -> io::Result<Box<dyn BufRead>> {
+    decode(open_raw(path)?, compression)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use noodles::bgzf;
+
+    use super::*;
+
+    const DATA: &[u8] = b">A\nATCG\n";
+
+    fn read_all(input: &[u8], compression: Option<BioFileCompression>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        decode(input, compression)
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_detect_and_decode() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(DATA).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let mut bgzf_writer = bgzf::Writer::new(Vec::new());
+        bgzf_writer.write_all(DATA).unwrap();
+        let bgzf_data = bgzf_writer.finish().unwrap();
+
+        let zst = zstd::encode_all(DATA, 0).unwrap();
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(DATA).unwrap();
+        let bz = bz.finish().unwrap();
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(DATA).unwrap();
+        let xz = xz.finish().unwrap();
+
+        let cases = [
+            (DATA, BioFileCompression::UNCOMPRESSED),
+            (&gz[..], BioFileCompression::GZIP),
+            (&bgzf_data[..], BioFileCompression::BGZF),
+            (&zst[..], BioFileCompression::ZSTD),
+            (&bz[..], BioFileCompression::BZIP2),
+            (&xz[..], BioFileCompression::XZ),
+        ];
+
+        for (input, compression) in cases {
+            assert_eq!(detect(input), compression);
+            assert_eq!(read_all(input, None), DATA);
+            assert_eq!(read_all(input, Some(compression)), DATA);
+        }
+    }
+
+    #[test]
+    fn test_decode_multiple_members() {
+        let mut data = Vec::new();
+
+        for _ in 0..2 {
+            let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            gz.write_all(DATA).unwrap();
+            data.extend(gz.finish().unwrap());
+        }
+
+        assert_eq!(read_all(&data, None), [DATA, DATA].concat());
+    }
+}
@@ -0,0 +1,346 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::iter::Peekable;
+use std::marker::PhantomData;
+
+use noodles::fasta;
+use noodles::fastq;
+use serde_json::Value;
+
+use crate::columns::{ColumnMap, FromColumns};
+use crate::errors::{BrrrrError, RecordLocation};
+use crate::gff;
+use crate::input::{self, BioFileCompression};
+use crate::reader::RecordReader;
+use crate::rejects::Rejects;
+use crate::types::{FastaRecord, FastqRecord, GffRecord};
+
+/// Reads records of type `T` from jsonl, one object per line, with the keys the fields are read
+/// from set by a ColumnMap. Empty lines are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::columns::ColumnMap;
+/// use brrrr_lib::json_reader::JsonlRecordReader;
+/// use brrrr_lib::types::FastaRecord;
+///
+/// let input = b"{\"name\":\"A\",\"seq\":\"ATCG\"}\n" as &[u8];
+/// let columns = ColumnMap::new()
+///     .with_column("id", "name")
+///     .with_column("sequence", "seq");
+///
+/// let mut reader = JsonlRecordReader::<_, FastaRecord>::new(input, &columns).unwrap();
+/// assert_eq!(reader.next().unwrap().unwrap().sequence, "ATCG");
+/// ```
+pub struct JsonlRecordReader<R: BufRead, T> {
+    lines: Peekable<io::Lines<R>>,
+    columns: ColumnMap,
+    index: usize,
+    record: PhantomData<T>,
+}
+
+impl<R: BufRead, T: FromColumns> JsonlRecordReader<R, T> {
+    /// Creates a new JsonlRecordReader, checking that the first object has a key for each field
+    /// that `T` requires.
+    pub fn new(inner: R, columns: &ColumnMap) -> Result<Self, BrrrrError> {
+        let mut lines = inner.lines().peekable();
+
+        while lines
+            .peek()
+            .is_some_and(|l| l.as_ref().is_ok_and(|l| l.trim().is_empty()))
+        {
+            lines.next();
+        }
+
+        // A first line that doesn't parse is left for the iterator to report.
+        if let Some(Ok(line)) = lines.peek() {
+            if let Ok(Value::Object(object)) = serde_json::from_str(line) {
+                columns.check::<T, _>(&object.keys().collect::<Vec<_>>())?;
+            }
+        }
+
+        Ok(Self {
+            lines,
+            columns: columns.clone(),
+            index: 0,
+            record: PhantomData,
+        })
+    }
+
+    fn read_record(&self, line: &str) -> serde_json::Result<T> {
+        if self.columns.is_empty() {
+            return serde_json::from_str(line);
+        }
+
+        let value = match serde_json::from_str(line)? {
+            Value::Object(object) => Value::Object(
+                object
+                    .into_iter()
+                    .filter_map(|(key, value)| {
+                        let field = self.columns.field(&key)?.to_string();
+                        Some((field, value))
+                    })
+                    .collect(),
+            ),
+            value => value,
+        };
+
+        serde_json::from_value(value)
+    }
+}
+
+impl<R: BufRead, T: FromColumns> Iterator for JsonlRecordReader<R, T> {
+    type Item = Result<T, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+
+            if !line.trim().is_empty() {
+                self.index += 1;
+
+                let record = self.read_record(&line).map_err(|e| {
+                    let location = RecordLocation {
+                        index: self.index,
+                        ..Default::default()
+                    };
+                    BrrrrError::from(io::Error::from(e)).at("jsonl", location, Some(line))
+                });
+                return Some(record);
+            }
+        }
+    }
+}
+
+impl<R: BufRead, T: FromColumns> RecordReader for JsonlRecordReader<R, T> {
+    type Header = ();
+
+    fn header(&self) -> &Self::Header {
+        &()
+    }
+}
+
+/// Writes FASTA records with the format `>{id} {description}\n{sequence}`.
+pub(crate) fn write_fasta<I, W>(records: I, output: &mut W) -> Result<(), BrrrrError>
+where
+    I: Iterator<Item = Result<FastaRecord, BrrrrError>>,
+    W: Write,
+{
+    let mut writer = fasta::Writer::new(output);
+
+    for record in records {
+        let record = record?;
+
+        let definition = fasta::record::Definition::new(record.id, record.description);
+        let sequence = fasta::record::Sequence::from(record.sequence.into_bytes());
+        writer.write_record(&fasta::Record::new(definition, sequence))?;
+    }
+
+    Ok(())
+}
+
+/// Returns `record` as a noodles FASTQ record, with the description after the ID and the
+/// quality as Phred+33. The sequence and quality must be the same length.
+pub(crate) fn fastq_record(record: FastqRecord) -> Result<fastq::Record, BrrrrError> {
+    let quality = record.quality.to_ascii()?;
+
+    if record.sequence.len() != quality.len() {
+        return Err(BrrrrError::from(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "the sequence has {} bases but the quality has {} scores",
+                record.sequence.len(),
+                quality.len()
+            ),
+        )));
+    }
+
+    let name = match record.description {
+        Some(description) => format!("{} {}", record.id, description),
+        None => record.id,
+    };
+
+    Ok(fastq::Record::new(name, record.sequence, quality))
+}
+
+/// Writes FASTQ records.
+pub(crate) fn write_fastq<I, W>(records: I, output: &mut W) -> Result<(), BrrrrError>
+where
+    I: Iterator<Item = Result<fastq::Record, BrrrrError>>,
+    W: Write,
+{
+    let mut writer = fastq::Writer::new(output);
+
+    for record in records {
+        writer.write_record(&record?)?;
+    }
+
+    Ok(())
+}
+
+/// Writes GFF records in `dialect`, after a version directive for GFF3.
+pub(crate) fn write_gff<I, W>(
+    records: I,
+    output: &mut W,
+    dialect: gff::Dialect,
+) -> Result<(), BrrrrError>
+where
+    I: Iterator<Item = Result<GffRecord, BrrrrError>>,
+    W: Write,
+{
+    let header = gff::Header {
+        lines: Vec::new(),
+        dialect,
+    };
+    gff::write_header(output, &header, dialect)?;
+
+    for record in records {
+        writeln!(output, "{}", gff::format_record(dialect, &record?))?;
+    }
+
+    Ok(())
+}
+
+/// Converts jsonl to FASTA
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `columns` the keys the `id`, `description` and `sequence` fields are read from.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn jsonl2fa<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    columns: &ColumnMap,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = JsonlRecordReader::new(input::decode(input, bio_file_compression)?, columns)?;
+    write_fasta(rejects.filter("jsonl", reader), output)
+}
+
+/// Converts jsonl to FASTQ. The quality may be either an ASCII string or a list of Phred
+/// scores, and is written as Phred+33.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `columns` the keys the `id`, `description`, `sequence` and `quality` fields are read from.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn jsonl2fq<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    columns: &ColumnMap,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = JsonlRecordReader::new(input::decode(input, bio_file_compression)?, columns)?;
+    let records = reader.map(|r| r.and_then(fastq_record));
+    write_fastq(rejects.filter("jsonl", records), output)
+}
+
+/// Converts jsonl to GFF3 or GTF, with the attributes as an object of lists as gff2jsonl writes
+/// them.
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `dialect` the GFF dialect to write.
+/// * `columns` the keys the GFF fields are read from.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn jsonl2gff<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    dialect: gff::Dialect,
+    columns: &ColumnMap,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = JsonlRecordReader::new(input::decode(input, bio_file_compression)?, columns)?;
+    write_gff(rejects.filter("jsonl", reader), output, dialect)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json_writer;
+    use crate::rejects::ErrorPolicy;
+
+    use super::*;
+
+    #[test]
+    fn test_jsonl2fa() {
+        let input = b"{\"name\":\"A\",\"desc\":\"x y\",\"seq\":\"ATCG\",\"sequence\":\"N\"}\n\n{\"name\":\"B\",\"seq\":\"GC\"}\n" as &[u8];
+        let columns = ColumnMap::new()
+            .with_column("id", "name")
+            .with_column("description", "desc")
+            .with_column("sequence", "seq");
+
+        let mut output = Vec::new();
+        jsonl2fa(input, &mut output, &columns, None, &mut Rejects::default()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), ">A x y\nATCG\n>B\nGC\n");
+
+        let input = b"{\"id\":\"A\",\"seq\":\"ATCG\"}\n" as &[u8];
+        assert!(matches!(
+            jsonl2fa(input, &mut Vec::new(), &ColumnMap::new(), None, &mut Rejects::default()),
+            Err(BrrrrError::MissingColumn(column)) if column == "sequence"
+        ));
+    }
+
+    #[test]
+    fn test_jsonl2fq() {
+        let input = b"{\"id\":\"r0\",\"sequence\":\"AC\",\"quality\":[10,20]}
+{\"id\":\"r1\",\"sequence\":\"AC\",\"quality\":\"I\"}
+{\"id\":\"r2\",\"description\":\"d\",\"sequence\":\"GT\",\"quality\":\"II\"}
+" as &[u8];
+
+        let mut rejects = Rejects::new(ErrorPolicy::Skip);
+        let mut output = Vec::new();
+        jsonl2fq(input, &mut output, &ColumnMap::new(), None, &mut rejects).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "@r0\nAC\n+\n+5\n@r2 d\nGT\n+\nII\n"
+        );
+        assert_eq!(rejects.total(), 1);
+    }
+
+    #[test]
+    fn test_gff_round_trip() {
+        let gff = "##gff-version 3\nsq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tID=g0\n";
+
+        let mut jsonl = Vec::new();
+        json_writer::gff2jsonl(
+            gff.as_bytes(),
+            &mut jsonl,
+            None,
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        jsonl2gff(
+            &jsonl[..],
+            &mut output,
+            gff::Dialect::Gff3,
+            &ColumnMap::new(),
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), gff);
+    }
+}
@@ -2,25 +2,23 @@
 // All Rights Reserved
 /// The `json_writer` module provides an implementation for the `RecordWriter` interface to read
 /// and write from JSON.
-use std::io::{self, BufRead, ErrorKind, Write};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 
 use serde::ser::Serialize;
 
+use crate::bed;
+use crate::cram;
 use crate::errors::BrrrrError;
-use crate::types::FastaRecord;
-use crate::types::FastqRecord;
-use crate::types::GffRecord;
-use crate::types::SamRecord;
+use crate::gff;
+use crate::input::{self, BioFileCompression};
+use crate::output::{self, OutputCompression};
+use crate::quality::QualityOptions;
+use crate::reader::{self, BamReader, FastaReader, FastqReader, GffReader};
+use crate::rejects::Rejects;
+use crate::vcf;
 use crate::writer;
 
-use writer::RecordWriter;
-
-use noodles::bam;
-use noodles::fasta;
-use noodles::fastq;
-use noodles::gff;
-use noodles::sam;
-
 /// JsonRecordWriter holds a writer, and outputs FASTA records as newline delimited JSON.
 pub struct JsonRecordWriter<W: Write> {
     writer: W,
@@ -33,6 +31,18 @@ impl<W: Write> JsonRecordWriter<W> {
     }
 }
 
+impl<W: Write> JsonRecordWriter<output::Writer<W>> {
+    /// Creates a new JsonRecordWriter that compresses its output with `compression`.
+    pub fn with_compression(w: W, compression: OutputCompression) -> io::Result<Self> {
+        Ok(Self::new(output::Writer::new(w, compression)?))
+    }
+
+    /// Finishes the compressed output and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        self.writer.finish()
+    }
+}
+
 impl<W: Write> writer::RecordWriter for JsonRecordWriter<W> {
     /// Writes an input FASTA to the underlying writer.
     fn write_serde_record<S: Serialize>(&mut self, r: S) -> io::Result<()> {
@@ -49,22 +59,21 @@ impl<W: Write> writer::RecordWriter for JsonRecordWriter<W> {
 ///
 /// * `input` an input that implements the BufRead trait.
 /// * `output` an output that implements the Write trait.
-pub fn fq2jsonl<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), BrrrrError> {
-    let mut reader = fastq::Reader::new(input);
-    let record_writer = &mut JsonRecordWriter::new(output);
-
-    for read_record in reader.records() {
-        let record = read_record?;
-        let write_op = record_writer.write_serde_record(FastqRecord::from(record));
-
-        if let Err(e) = write_op {
-            match e.kind() {
-                ErrorKind::BrokenPipe => break,
-                _ => return Err(BrrrrError::from(e)),
-            }
-        }
-    }
-    Ok(())
+/// * `quality_options` how the quality scores are written.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn fq2jsonl<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    quality_options: &QualityOptions,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = FastqReader::new(input::decode(input, bio_file_compression)?, quality_options);
+    reader::convert(
+        rejects.filter("FASTQ", reader),
+        &mut JsonRecordWriter::new(output),
+    )
 }
 
 /// Converts a FASTA to JSONL
@@ -73,45 +82,53 @@ pub fn fq2jsonl<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), Br
 ///
 /// * `input` an input that implements the Read trait.
 /// * `output` an output that implements the Write trait.
-pub fn fa2jsonl<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), BrrrrError> {
-    let mut reader = fasta::Reader::new(input);
-    let record_writer = &mut JsonRecordWriter::new(output);
-
-    for read_record in reader.records() {
-        let record = read_record?;
-        let write_op = record_writer.write_serde_record(FastaRecord::from(record));
-
-        if let Err(e) = write_op {
-            match e.kind() {
-                ErrorKind::BrokenPipe => break,
-                _ => return Err(BrrrrError::from(e)),
-            }
-        }
-    }
-    Ok(())
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn fa2jsonl<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = FastaReader::new(input::decode(input, bio_file_compression)?);
+    reader::convert(
+        rejects.filter("FASTA", reader),
+        &mut JsonRecordWriter::new(output),
+    )
 }
 
-/// Converts a GFF file to JSONL
+/// Converts a GFF3 or GTF file to JSONL
 ///
 /// # Arguments
 ///
 /// * `input` an input that implements the Read trait.
 /// * `output` an output that implements the Write trait.
-pub fn gff2jsonl<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), BrrrrError> {
-    let mut reader = gff::Reader::new(input);
-    let record_writer = &mut JsonRecordWriter::new(output);
-
-    for read_record in reader.records() {
-        let record = read_record?;
-        let write_op = record_writer.write_serde_record(GffRecord::from(record));
-
-        if let Err(e) = write_op {
-            match e.kind() {
-                ErrorKind::BrokenPipe => break,
-                _ => return Err(BrrrrError::from(e)),
-            }
-        }
+/// * `dialect` the GFF dialect, or None to detect it from the input.
+/// * `fasta_output` an output for the sequences after a `##FASTA` directive, written as JSONL
+///   in the same shape as fa2jsonl. Without it, the sequences are skipped.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn gff2jsonl<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    dialect: Option<gff::Dialect>,
+    fasta_output: Option<&mut dyn Write>,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = GffReader::new(input::decode(input, bio_file_compression)?, dialect)?;
+    reader::convert(
+        rejects.filter("GFF", &mut reader),
+        &mut JsonRecordWriter::new(output),
+    )?;
+
+    if let (Some(fasta_output), Some(fasta_reader)) = (fasta_output, reader.fasta_reader()) {
+        reader::convert(
+            rejects.filter("FASTA", fasta_reader),
+            &mut JsonRecordWriter::new(fasta_output),
+        )?;
     }
+
     Ok(())
 }
 
@@ -121,27 +138,85 @@ pub fn gff2jsonl<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), B
 ///
 /// * `input` an input BAM file to convert to JSONL
 /// * `output` an output that implements the Write trait.
-pub fn bam2jsonl<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), BrrrrError> {
-    let mut reader = bam::Reader::new(input);
-    let record_writer = &mut JsonRecordWriter::new(output);
-
-    let _: sam::Header = reader.read_header().expect("ERROR").parse().expect("E");
-    reader.read_reference_sequences()?;
-
-    for eiii in reader.records() {
-        let record = eiii?;
+/// * `rejects` what to do with records that fail to parse.
+pub fn bam2jsonl<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = BamReader::new(input)?;
+    reader::convert(
+        rejects.filter("BAM", reader),
+        &mut JsonRecordWriter::new(output),
+    )
+}
 
-        let write_op = record_writer.write_serde_record(SamRecord::from(record));
+/// Converts a CRAM file to JSONL, decoding the records against a local reference FASTA.
+///
+/// # Arguments
+///
+/// * `input` an input CRAM file to convert to JSONL
+/// * `reference` the path to the reference FASTA, which needs a `.fai` index next to it.
+/// * `output` an output that implements the Write trait.
+/// * `rejects` what to do with records that fail to parse.
+pub fn cram2jsonl<R: BufRead, P: AsRef<Path>, W: Write>(
+    input: R,
+    reference: P,
+    output: &mut W,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = cram::Reader::new(input, cram::Reference::from_path(reference)?);
+    let header = reader.read_header()?;
+    reader::convert(
+        rejects.filter("CRAM", reader.records(&header)),
+        &mut JsonRecordWriter::new(output),
+    )
+}
 
-        if let Err(e) = write_op {
-            match e.kind() {
-                ErrorKind::BrokenPipe => break,
-                _ => return Err(BrrrrError::from(e)),
-            }
-        }
-    }
+/// Converts a VCF file to JSONL
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn vcf2jsonl<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = vcf::Reader::new(input::decode(input, bio_file_compression)?);
+    let header = reader.read_header()?;
+    reader::convert(
+        rejects.filter("VCF", reader.records(&header)),
+        &mut JsonRecordWriter::new(output),
+    )
+}
 
-    Ok(())
+/// Converts a BED or bedGraph file to JSONL
+///
+/// # Arguments
+///
+/// * `input` an input that implements the BufRead trait.
+/// * `output` an output that implements the Write trait.
+/// * `format` whether the input is BED or bedGraph, or `None` to detect it from a track line.
+/// * `bio_file_compression` the compression of the input, or None to detect it.
+/// * `rejects` what to do with records that fail to parse.
+pub fn bed2jsonl<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    format: Option<bed::BedFormat>,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = bed::Reader::new(input::decode(input, bio_file_compression)?);
+    let header = reader.read_header(format)?;
+    reader::convert(
+        rejects.filter("BED", reader.records(&header)),
+        &mut JsonRecordWriter::new(output),
+    )
 }
 
 #[cfg(test)]
@@ -155,7 +230,7 @@ mod tests {
         let input = b">A\nATCG\n" as &[u8];
 
         let mut output = Vec::new();
-        fa2jsonl(input, &mut output).unwrap();
+        fa2jsonl(input, &mut output, None, &mut Rejects::default()).unwrap();
 
         let output_str = String::from_utf8(output).unwrap();
         let expected_output =
@@ -163,6 +238,71 @@ mod tests {
         assert_eq!(output_str, expected_output);
     }
 
+    #[test]
+    fn test_fa2jsonl_input_compression() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(b">A\nATCG\n").unwrap();
+        let input = gz.finish().unwrap();
+
+        let mut output = Vec::new();
+        fa2jsonl(
+            &input[..],
+            &mut output,
+            Some(BioFileCompression::GZIP),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"id\":\"A\",\"description\":null,\"sequence\":\"ATCG\"}\n"
+        );
+
+        // An explicit compression overrides the detection, so gzip read as plain text fails.
+        let result = fa2jsonl(
+            &input[..],
+            &mut Vec::new(),
+            Some(BioFileCompression::UNCOMPRESSED),
+            &mut Rejects::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_vcf2jsonl() {
+        let input = b"##fileformat=VCFv4.3
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1
+sq0\t1\trs1\tA\tC\t.\tPASS\tDP=8\tGT\t0/1
+" as &[u8];
+
+        let mut output = Vec::new();
+        vcf2jsonl(input, &mut output, None, &mut Rejects::default()).unwrap();
+
+        let record: serde_json::Value =
+            serde_json::from_slice(&output).expect("invalid JSON output");
+
+        assert_eq!(record["chrom"], "sq0");
+        assert_eq!(record["id"], "rs1");
+        assert_eq!(record["alternate"], serde_json::json!(["C"]));
+        assert_eq!(record["quality"], serde_json::Value::Null);
+        assert_eq!(record["info"]["DP"], 8);
+        assert_eq!(record["samples"]["NA1"]["GT"], "0/1");
+    }
+
+    #[test]
+    fn test_bed2jsonl() {
+        let input = b"track name=genes\nchr1\t10\t20\tgene0\n" as &[u8];
+
+        let mut output = Vec::new();
+        bed2jsonl(input, &mut output, None, None, &mut Rejects::default()).unwrap();
+
+        let output_str = String::from_utf8(output).unwrap();
+        let expected_output =
+            "{\"chrom\":\"chr1\",\"start\":10,\"end\":20,\"name\":\"gene0\"}\n".to_string();
+        assert_eq!(output_str, expected_output);
+    }
+
     #[test]
     fn test_bam2jsonl() {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -172,7 +312,7 @@ mod tests {
 
         let mut output = Vec::new();
 
-        bam2jsonl(reader, &mut output).unwrap();
+        bam2jsonl(reader, &mut output, &mut Rejects::default()).unwrap();
 
         let output_str = String::from_utf8(output).unwrap();
         let records = &output_str
@@ -183,4 +323,114 @@ mod tests {
 
         assert_eq!(records[0]["read_name"], "8");
     }
+
+    #[test]
+    fn test_cram2jsonl() {
+        let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+
+        let reader = std::fs::File::open(resources.join("little.cram"))
+            .map(std::io::BufReader::new)
+            .unwrap();
+
+        let mut output = Vec::new();
+
+        cram2jsonl(
+            reader,
+            resources.join("little.fa"),
+            &mut output,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let output_str = String::from_utf8(output).unwrap();
+        let records = &output_str
+            .lines()
+            .map(|line| serde_json::from_str(line).expect(line))
+            .collect::<Vec<serde_json::Value>>();
+
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0]["read_name"], "r0");
+        assert_eq!(records[0]["sequence"], "GGGTAGCATNGGTAAC");
+        assert_eq!(records[0]["template_length"], 35);
+        assert_eq!(records[1]["data"]["fields"]["RG"]["value"], "grp1");
+        assert_eq!(records[3]["mate_reference_sequence"], "chr1");
+        assert_eq!(records[4]["sequence"], "ACGTN");
+    }
+
+    #[test]
+    fn test_fq2jsonl_phred() {
+        let input = b"@r0\nACGT\n+\n+5?I\n" as &[u8];
+        let quality_options = QualityOptions {
+            phred_scores: true,
+            summary: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        fq2jsonl(
+            input,
+            &mut output,
+            &quality_options,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let record: serde_json::Value =
+            serde_json::from_str(String::from_utf8(output).unwrap().trim_end()).unwrap();
+
+        assert_eq!(record["quality"], serde_json::json!([10, 20, 30, 40]));
+        assert_eq!(record["mean_quality"], 25.0);
+        assert_eq!(record["min_quality"], 10);
+    }
+
+    #[test]
+    fn test_gff2jsonl() {
+        let input =
+            b"##gff-version 3\nsq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tID=g0;Dbxref=a:1,b%2C2\n";
+
+        let mut output = Vec::new();
+        gff2jsonl(
+            &input[..],
+            &mut output,
+            None,
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let record: serde_json::Value =
+            serde_json::from_str(String::from_utf8(output).unwrap().trim_end()).unwrap();
+
+        assert_eq!(record["attribute"]["ID"], serde_json::json!(["g0"]));
+        assert_eq!(
+            record["attribute"]["Dbxref"],
+            serde_json::json!(["a:1", "b,2"])
+        );
+    }
+
+    #[test]
+    fn test_gff2jsonl_fasta() {
+        let input = b"sq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tID=g0\n##FASTA\n>sq0\nACGT\n";
+
+        let mut output = Vec::new();
+        let mut fasta_output = Vec::new();
+        gff2jsonl(
+            &input[..],
+            &mut output,
+            None,
+            Some(&mut fasta_output),
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 1);
+
+        let record: serde_json::Value =
+            serde_json::from_str(String::from_utf8(fasta_output).unwrap().trim_end()).unwrap();
+        assert_eq!(record["id"], "sq0");
+        assert_eq!(record["sequence"], "ACGT");
+    }
 }
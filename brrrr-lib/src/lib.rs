@@ -20,10 +20,11 @@
 //! use std::io::stdout;
 //!
 //! use brrrr_lib::json_writer::fa2jsonl;
+//! use brrrr_lib::rejects::Rejects;
 //!
 //! fn main() {
 //!     let example_input = b">A\nATCG\n>B\nGCTA" as &[u8];
-//!     fa2jsonl(example_input, &mut stdout()).expect("Error... :(");
+//!     fa2jsonl(example_input, &mut stdout(), None, &mut Rejects::default()).expect("Error... :(");
 //! }
 //! ```
 //!
@@ -32,21 +33,80 @@
 //!
 //! If you're interested in the command-lind tool, see: <https://github.com/tshauck/brrrr/releases/latest>
 
-/// json_writer holds a writer, and outputs FASTA and GFF records as newline delimited JSON.
+/// json_writer holds a writer, and outputs FASTA, GFF and VCF records as newline delimited JSON.
 pub mod json_writer;
 
-/// csv_writer holds a writer, and outputs FASTA and GFF records as csv.
+/// csv_writer holds a writer, and outputs FASTA, FASTQ and VCF records as csv.
 pub mod csv_writer;
 
-/// parquet_writer holds a writer, and outputs FASTA and GFF records as parquet.
+/// parquet_writer holds a writer, and outputs FASTA, GFF, VCF and any serializable records as
+/// parquet.
 pub mod parquet_writer;
 
+/// arrow_writer derives Arrow schemas from serde records, and outputs FASTA, FASTQ, GFF and BAM
+/// records as Arrow IPC.
+pub mod arrow_writer;
+
+/// arrow_reader is like arrow_writer, but for reading Arrow IPC in.
+pub mod arrow_reader;
+
 /// parquet_reader is like parquet_writer, but for reading parquet in.
 pub mod parquet_reader;
 
+/// json_reader reads FASTA, FASTQ and GFF records from jsonl, and writes them out in their
+/// native formats.
+pub mod json_reader;
+
+/// csv_reader is like json_reader, but for reading csv in.
+pub mod csv_reader;
+
+/// columns maps the fields of records to differently named columns of jsonl and csv inputs.
+pub mod columns;
+
+/// filter selects the records that the parquet readers write, by ID, length and predicate.
+pub mod filter;
+
 /// Interface for the generic writer object.
 pub mod writer;
 
+/// reader holds the RecordReader interface, its implementations for each format, and a driver
+/// that writes the records of any reader with any writer.
+pub mod reader;
+
+/// vcf holds a VCF header and record reader that types INFO and FORMAT values.
+pub mod vcf;
+
+/// bcf is like vcf, but for reading the binary BCF encoding.
+pub mod bcf;
+
+/// bed holds a BED and bedGraph reader that adapts to the number of columns.
+pub mod bed;
+
+/// gff holds a GFF3 and GTF reader that keeps every value of multi-valued attributes.
+pub mod gff;
+
+/// quality decodes FASTQ quality scores into Phred scores and per-read statistics.
+pub mod quality;
+
+/// paired reads paired-end FASTQ from R1 and R2 files or an interleaved file.
+pub mod paired;
+
+/// cram holds a CRAM reader that decodes records against a local reference FASTA.
+pub mod cram;
+
+/// input opens files and readers, detecting and decoding gzip, BGZF, zstd, bzip2 and xz.
+pub mod input;
+
+/// output creates files and writers that compress with gzip, BGZF or zstd.
+pub mod output;
+
+/// convert reads and writes records in any supported format, detecting the input format.
+pub mod convert;
+
+/// rejects holds the error policy that decides whether converters stop at or skip records that
+/// fail to parse, and counts and logs the records they skip.
+pub mod rejects;
+
 /// Types used within the library.
 pub mod types;
 
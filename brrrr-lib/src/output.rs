@@ -0,0 +1,175 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use noodles::bgzf;
+
+use crate::input;
+
+/// The compression of an output file.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputCompression {
+    UNCOMPRESSED,
+    GZIP,
+    BGZF,
+    ZSTD,
+}
+
+impl OutputCompression {
+    /// Infers the compression from the extension of `path`, i.e. `.gz`, `.bgz` or `.bgzf`, and
+    /// `.zst`. Any other extension is uncompressed.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") => OutputCompression::GZIP,
+            Some("bgz" | "bgzf") => OutputCompression::BGZF,
+            Some("zst" | "zstd") => OutputCompression::ZSTD,
+            _ => OutputCompression::UNCOMPRESSED,
+        }
+    }
+}
+
+/// Writer compresses everything written to it before passing it to the inner writer. The
+/// compressed stream is only complete once `finish` is called.
+pub enum Writer<W: Write> {
+    Uncompressed(W),
+    Gzip(GzEncoder<W>),
+    Bgzf(bgzf::Writer<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a new Writer that compresses to `inner` with `compression`.
+    pub fn new(inner: W, compression: OutputCompression) -> io::Result<Self> {
+        let writer = match compression {
+            OutputCompression::UNCOMPRESSED => Writer::Uncompressed(inner),
+            OutputCompression::GZIP => {
+                Writer::Gzip(GzEncoder::new(inner, flate2::Compression::default()))
+            }
+            OutputCompression::BGZF => Writer::Bgzf(bgzf::Writer::new(inner)),
+            OutputCompression::ZSTD => Writer::Zstd(zstd::Encoder::new(inner, 0)?),
+        };
+
+        Ok(writer)
+    }
+
+    /// Writes the end of the compressed stream, flushes the inner writer and returns it.
+    pub fn finish(self) -> io::Result<W> {
+        let mut inner = match self {
+            Writer::Uncompressed(inner) => inner,
+            Writer::Gzip(writer) => writer.finish()?,
+            Writer::Bgzf(writer) => writer.finish()?,
+            Writer::Zstd(writer) => writer.finish()?,
+        };
+
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Uncompressed(writer) => writer.write(buf),
+            Writer::Gzip(writer) => writer.write(buf),
+            Writer::Bgzf(writer) => writer.write(buf),
+            Writer::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Uncompressed(writer) => writer.flush(),
+            Writer::Gzip(writer) => writer.flush(),
+            Writer::Bgzf(writer) => writer.flush(),
+            Writer::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Creates the file at `path` for writing, or writes to stdout if `path` is `-`, without
+/// compressing or buffering it.
+pub fn create_raw<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Write>> {
+    if input::is_stdio(&path) {
+        Ok(Box::new(io::stdout()))
+    } else {
+        Ok(Box::new(File::create(path)?))
+    }
+}
+
+/// Creates the file at `path` for writing with `compression`, or with the compression inferred
+/// from its extension if None.
+pub fn create<P: AsRef<Path>>(
+    path: P,
+    compression: Option<OutputCompression>,
+) -> io::Result<Writer<BufWriter<File>>> {
+    let compression = compression.unwrap_or_else(|| OutputCompression::from_path(&path));
+    let file = File::create(path)?;
+    Writer::new(BufWriter::new(file), compression)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::input::{self, BioFileCompression};
+    use crate::json_writer;
+    use crate::rejects::Rejects;
+
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        let cases = [
+            ("out.jsonl", OutputCompression::UNCOMPRESSED),
+            ("out.jsonl.gz", OutputCompression::GZIP),
+            ("out.csv.bgz", OutputCompression::BGZF),
+            ("out.jsonl.zst", OutputCompression::ZSTD),
+        ];
+
+        for (path, compression) in cases {
+            assert_eq!(OutputCompression::from_path(path), compression);
+        }
+    }
+
+    #[test]
+    fn test_compressed_jsonl() {
+        let cases = [
+            (
+                OutputCompression::UNCOMPRESSED,
+                BioFileCompression::UNCOMPRESSED,
+            ),
+            (OutputCompression::GZIP, BioFileCompression::GZIP),
+            (OutputCompression::BGZF, BioFileCompression::BGZF),
+            (OutputCompression::ZSTD, BioFileCompression::ZSTD),
+        ];
+
+        for (compression, expected) in cases {
+            let mut writer = Writer::new(Vec::new(), compression).unwrap();
+            json_writer::fa2jsonl(
+                b">A\nATCG\n" as &[u8],
+                &mut writer,
+                None,
+                &mut Rejects::default(),
+            )
+            .unwrap();
+            let data = writer.finish().unwrap();
+
+            assert_eq!(input::detect(&data), expected);
+
+            let mut jsonl = String::new();
+            input::decode(&data[..], None)
+                .unwrap()
+                .read_to_string(&mut jsonl)
+                .unwrap();
+            assert_eq!(
+                jsonl,
+                "{\"id\":\"A\",\"description\":null,\"sequence\":\"ATCG\"}\n"
+            );
+        }
+    }
+}
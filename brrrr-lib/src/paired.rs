@@ -0,0 +1,162 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use noodles::fastq;
+
+use crate::errors::BrrrrError;
+use crate::input::{self, BioFileCompression};
+
+/// Returns the fragment name of a read, i.e. its name up to the first whitespace with any `/1` or
+/// `/2` mate suffix removed.
+pub fn fragment_name(name: &[u8]) -> &[u8] {
+    let name = name
+        .split(|b| b.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default();
+
+    match name {
+        [rest @ .., b'/', b'1' | b'2'] => rest,
+        _ => name,
+    }
+}
+
+/// Returns a read name as a string, replacing any invalid UTF-8.
+pub fn name_string(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).into_owned()
+}
+
+/// Reads paired-end FASTQ records, either from R1 and R2 files in lockstep or from a single
+/// interleaved file where each R1 read is followed by its mate.
+pub enum Reader<R> {
+    Files(fastq::Reader<R>, fastq::Reader<R>),
+    Interleaved(fastq::Reader<R>),
+}
+
+impl Reader<Box<dyn BufRead>> {
+    /// Opens R1 and R2 files, or stdin for `-`, decompressing each as with `input::open`.
+    pub fn open<P: AsRef<Path>>(
+        r1: P,
+        r2: P,
+        compression: Option<BioFileCompression>,
+    ) -> io::Result<Self> {
+        Ok(Self::new(
+            input::open(r1, compression)?,
+            input::open(r2, compression)?,
+        ))
+    }
+
+    /// Opens an interleaved file, or stdin for `-`, decompressing it as with `input::open`.
+    pub fn open_interleaved<P: AsRef<Path>>(
+        path: P,
+        compression: Option<BioFileCompression>,
+    ) -> io::Result<Self> {
+        Ok(Self::interleaved(input::open(path, compression)?))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a reader over R1 and R2 inputs.
+    pub fn new(r1: R, r2: R) -> Self {
+        Reader::Files(fastq::Reader::new(r1), fastq::Reader::new(r2))
+    }
+
+    /// Creates a reader over an interleaved input.
+    pub fn interleaved(inner: R) -> Self {
+        Reader::Interleaved(fastq::Reader::new(inner))
+    }
+
+    fn read_pair(&mut self) -> Result<Option<(fastq::Record, fastq::Record)>, BrrrrError> {
+        let mut r1 = fastq::Record::default();
+        let mut r2 = fastq::Record::default();
+
+        let (r1_len, r2_len) = match self {
+            Reader::Files(r1_reader, r2_reader) => (
+                r1_reader.read_record(&mut r1)?,
+                r2_reader.read_record(&mut r2)?,
+            ),
+            Reader::Interleaved(reader) => match reader.read_record(&mut r1)? {
+                0 => (0, 0),
+                n => (n, reader.read_record(&mut r2)?),
+            },
+        };
+
+        match (r1_len, r2_len) {
+            (0, 0) => Ok(None),
+            (_, 0) => Err(BrrrrError::MissingMate(name_string(r1.name()))),
+            (0, _) => Err(BrrrrError::MissingMate(name_string(r2.name()))),
+            _ if fragment_name(r1.name()) != fragment_name(r2.name()) => {
+                Err(BrrrrError::MateNameMismatch {
+                    r1: name_string(r1.name()),
+                    r2: name_string(r2.name()),
+                })
+            }
+            _ => Ok(Some((r1, r2))),
+        }
+    }
+
+    /// Returns an iterator over the R1 and R2 records of each fragment.
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records { reader: self }
+    }
+}
+
+/// An iterator over the read pairs of paired-end FASTQ input.
+pub struct Records<'a, R> {
+    reader: &'a mut Reader<R>,
+}
+
+impl<'a, R: BufRead> Iterator for Records<'a, R> {
+    type Item = Result<(fastq::Record, fastq::Record), BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.read_pair().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fragment_name() {
+        assert_eq!(fragment_name(b"r0/1"), b"r0");
+        assert_eq!(fragment_name(b"r0/2 extra"), b"r0");
+        assert_eq!(fragment_name(b"r0 1:N:0:ACGT"), b"r0");
+        assert_eq!(fragment_name(b"r0/3"), b"r0/3");
+    }
+
+    #[test]
+    fn test_read_pairs() {
+        let r1 = b"@a/1\nAC\n+\nII\n@b/1\nGT\n+\nII\n" as &[u8];
+        let r2 = b"@a/2\nTT\n+\nII\n@b/2\nGG\n+\nII\n" as &[u8];
+
+        let mut reader = Reader::new(r1, r2);
+        let pairs: Vec<_> = reader.records().map(|p| p.unwrap()).collect();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[1].1.sequence(), b"GG");
+
+        let interleaved = b"@a 1:N\nAC\n+\nII\n@a 2:N\nTT\n+\nII\n" as &[u8];
+        let mut reader = Reader::interleaved(interleaved);
+        assert_eq!(reader.records().count(), 1);
+    }
+
+    #[test]
+    fn test_read_pairs_desync() {
+        let r1 = b"@a/1\nAC\n+\nII\n@b/1\nGT\n+\nII\n" as &[u8];
+        let r2 = b"@b/2\nTT\n+\nII\n" as &[u8];
+
+        let mut reader = Reader::new(r1, r2);
+        let mut records = reader.records();
+        assert!(matches!(
+            records.next(),
+            Some(Err(BrrrrError::MateNameMismatch { .. }))
+        ));
+        assert!(matches!(
+            records.next(),
+            Some(Err(BrrrrError::MissingMate(name))) if name == "b/1"
+        ));
+    }
+}
@@ -1,21 +1,854 @@
 // (c) Copyright 2022 Trent Hauck
 // All Rights Reserved
 
+use arrow::array::{Array, Float64Array, Int64Array, ListArray, StringArray, UInt8Array};
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use indexmap::IndexMap;
 use noodles::core;
-use noodles::fasta;
+use noodles::csi::index::reference_sequence::bin::Chunk;
 use noodles::fastq;
-use noodles::gff;
-use noodles::gff::record::attributes::Entry;
-use noodles::gff::record::Attributes;
 use noodles::gff::record::Phase;
 use noodles::gff::record::Strand;
-use parquet::file::reader::SerializedFileReader;
-use parquet::record::RowAccessor;
+use noodles::{bam, sam};
+use parquet::arrow::{
+    parquet_to_arrow_schema, ArrowReader, ParquetFileArrowReader, ProjectionMask,
+};
+use parquet::file::metadata::RowGroupMetaData;
+use parquet::file::reader::{ChunkReader, FileReader, Length, SerializedFileReader};
+use parquet::file::serialized_reader::{ReadOptionsBuilder, SliceableCursor};
+use parquet::file::statistics::Statistics;
+use parquet::record::reader::RowIter;
+use parquet::record::{Field, ListAccessor, Map, Row, RowAccessor};
+use parquet::schema::types::Type;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::Arc;
 use std::{fs::File, path::Path};
 
-use crate::errors::BrrrrError;
+use crate::arrow_reader;
+use crate::bed;
+use crate::columns::{ColumnMap, FromColumns, Template};
+use crate::errors::{BrrrrError, RecordLocation};
+use crate::filter::{Comparison, Predicate, RecordFilter, Value};
+use crate::gff;
+use crate::input;
+use crate::json_reader;
+use crate::output;
+use crate::parquet_writer::{
+    self, BED_HEADER_METADATA_KEY, GFF_DIALECT_METADATA_KEY, GFF_HEADER_METADATA_KEY,
+    SAM_HEADER_METADATA_KEY,
+};
+use crate::reader::{Header, Record};
+use crate::rejects::Rejects;
+use crate::types::{
+    BedRecord, Cigar, Data, FastaRecord, FastqRecord, GffRecord, Operation, Quality, SamField,
+    SamRecord, SamValue,
+};
+
+/// ParquetSource is the input to the parquet readers, either a file or stdin read into memory.
+/// Stdin is buffered because the parquet footer is at the end of the input.
+pub enum ParquetSource {
+    File(File),
+    Memory(SliceableCursor),
+}
+
+impl ParquetSource {
+    /// Opens the parquet file at `path`, or reads stdin into memory if `path` is `-`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        if input::is_stdio(&path) {
+            let mut buf = Vec::new();
+            io::stdin().lock().read_to_end(&mut buf)?;
+            Ok(ParquetSource::Memory(SliceableCursor::new(buf)))
+        } else {
+            Ok(ParquetSource::File(File::open(path)?))
+        }
+    }
+
+    /// Opens `path` again, or shares the buffered stdin, for a reader of its own.
+    pub(crate) fn reopen<P: AsRef<Path>>(&self, path: P) -> io::Result<Self> {
+        match self {
+            ParquetSource::File(_) => Ok(ParquetSource::File(File::open(path)?)),
+            ParquetSource::Memory(cursor) => Ok(ParquetSource::Memory(
+                cursor.slice(0, cursor.len() as usize)?,
+            )),
+        }
+    }
+}
+
+impl Length for ParquetSource {
+    fn len(&self) -> u64 {
+        match self {
+            ParquetSource::File(file) => Length::len(file),
+            ParquetSource::Memory(cursor) => Length::len(cursor),
+        }
+    }
+}
+
+impl ChunkReader for ParquetSource {
+    type T = Box<dyn Read + Send>;
+
+    fn get_read(&self, start: u64, length: usize) -> parquet::errors::Result<Self::T> {
+        match self {
+            ParquetSource::File(file) => Ok(Box::new(file.get_read(start, length)?)),
+            ParquetSource::Memory(cursor) => Ok(Box::new(cursor.get_read(start, length)?)),
+        }
+    }
+}
+
+/// The number of rows in each record batch that pq2fa, pq2fq and pq2gff read.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// ParquetReadOptions sets how pq2fa, pq2fq and pq2gff read the parquet file.
+#[derive(Clone, Debug)]
+pub struct ParquetReadOptions {
+    /// The number of rows in each record batch.
+    pub batch_size: usize,
+    /// The number of row groups decoded at once, each on its own thread. With 1, the row groups
+    /// are read one after another on the calling thread.
+    pub threads: usize,
+    /// The records to write. Row groups whose statistics show that none of their records match
+    /// are skipped without being decoded. Parquet 15 doesn't read page indexes, so pages within
+    /// a row group that is read are all decoded.
+    pub filter: RecordFilter,
+}
+
+impl Default for ParquetReadOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            threads: 1,
+            filter: RecordFilter::default(),
+        }
+    }
+}
+
+/// Returns the mask of the root columns of `reader` that are named in `names`.
+fn projection<R: FileReader>(reader: &R, names: &[String]) -> ProjectionMask {
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let indices = schema
+        .root_schema()
+        .get_fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| names.iter().any(|name| name == field.name()))
+        .map(|(i, _)| i);
+
+    ProjectionMask::roots(schema, indices)
+}
+
+/// Returns the schema of the root columns of `reader` that are named in `names`, or None if
+/// there are none.
+fn row_projection<R: FileReader>(reader: &R, names: &[String]) -> Result<Option<Type>, BrrrrError> {
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let mut fields: Vec<_> = schema
+        .root_schema()
+        .get_fields()
+        .iter()
+        .filter(|field| names.iter().any(|name| name == field.name()))
+        .cloned()
+        .collect();
+
+    if fields.is_empty() {
+        return Ok(None);
+    }
+
+    let projection = Type::group_type_builder(schema.root_schema().name())
+        .with_fields(&mut fields)
+        .build()?;
+    Ok(Some(projection))
+}
+
+/// The columns that are read in record batches, and the columns that are read row by row
+/// alongside them.
+///
+/// The arrow reader of parquet 15 swaps the definition and repetition levels of maps, so every
+/// map comes back empty, and map columns such as the GFF attributes have to be read by row.
+#[derive(Clone)]
+struct Columns {
+    batch: Vec<String>,
+    row: Vec<String>,
+}
+
+/// A row of a record batch, with its index in the batch and its 0-based index in the file.
+#[derive(Clone, Copy, Debug)]
+struct BatchRow {
+    index: usize,
+    file_row: usize,
+}
+
+/// The columns that the ID and length a RecordFilter selects on are read from.
+#[derive(Clone, Copy)]
+enum Selection<'a> {
+    /// A FASTA or FASTQ record, whose length is that of its sequence.
+    Sequence { id: &'a str, sequence: &'a str },
+    /// A GFF record, whose length is from its start to its end. Its ID is its `ID` attribute,
+    /// which is read by row, so it's selected on once the record is read.
+    Gff { start: &'a str, end: &'a str },
+}
+
+/// Returns the statistics of the column `name` in `row_group`, if it has them.
+fn column_statistics<'a>(row_group: &'a RowGroupMetaData, name: &str) -> Option<&'a Statistics> {
+    row_group
+        .columns()
+        .iter()
+        .find(|column| column.column_path().string() == name)
+        .and_then(|column| column.statistics())
+}
+
+/// Returns the minimum and maximum of the statistics of an integer column.
+fn integer_bounds(statistics: &Statistics) -> Option<(i64, i64)> {
+    match statistics {
+        Statistics::Int32(s) if s.has_min_max_set() => Some((*s.min() as i64, *s.max() as i64)),
+        Statistics::Int64(s) if s.has_min_max_set() => Some((*s.min(), *s.max())),
+        _ => None,
+    }
+}
+
+/// Returns the minimum and maximum of the statistics of a string column.
+fn text_bounds(statistics: &Statistics) -> Option<(&str, &str)> {
+    match statistics {
+        Statistics::ByteArray(s) if s.has_min_max_set() => {
+            Some((s.min().as_utf8().ok()?, s.max().as_utf8().ok()?))
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if a value of a column with `statistics`, in a row group of `num_rows`, could
+/// match `comparison`.
+fn comparison_may_match(comparison: &Comparison, statistics: &Statistics, num_rows: i64) -> bool {
+    // Null never matches, so a column of only nulls can't.
+    if num_rows > 0 && statistics.null_count() == num_rows as u64 {
+        return false;
+    }
+
+    match (&comparison.value, statistics) {
+        (Value::Number(n), Statistics::Float(s)) if s.has_min_max_set() => {
+            comparison.may_match(n, &(*s.min() as f64), &(*s.max() as f64))
+        }
+        (Value::Number(n), Statistics::Double(s)) if s.has_min_max_set() => {
+            comparison.may_match(n, s.min(), s.max())
+        }
+        (Value::Number(n), statistics) => match integer_bounds(statistics) {
+            Some((min, max)) => comparison.may_match(n, &(min as f64), &(max as f64)),
+            None => true,
+        },
+        (Value::Text(text), statistics) => match text_bounds(statistics) {
+            Some((min, max)) => comparison.may_match(&text.as_str(), &min, &max),
+            None => true,
+        },
+    }
+}
+
+/// Returns true if `filter` could keep a record of `row_group`, going by the statistics of its
+/// columns.
+fn row_group_may_match(
+    row_group: &RowGroupMetaData,
+    filter: &RecordFilter,
+    selection: &Selection,
+) -> bool {
+    let statistics = |name: &str| column_statistics(row_group, name);
+
+    let ids = match selection {
+        Selection::Sequence { id, .. } => match statistics(id).and_then(text_bounds) {
+            Some((min, max)) => filter.ids_may_match(min, max),
+            None => true,
+        },
+        Selection::Gff { .. } => true,
+    };
+
+    // A feature is no longer than from the first start to the last end, and no shorter than
+    // from the last start to the first end.
+    let lengths = match selection {
+        Selection::Gff { start, end } => match (
+            statistics(start).and_then(integer_bounds),
+            statistics(end).and_then(integer_bounds),
+        ) {
+            (Some((min_start, max_start)), Some((min_end, max_end))) => {
+                let (longest, shortest) = (max_end - min_start + 1, min_end - max_start + 1);
+
+                filter.min_length.is_none_or(|min| longest >= min as i64)
+                    && filter.max_length.is_none_or(|max| shortest <= max as i64)
+            }
+            _ => true,
+        },
+        Selection::Sequence { .. } => true,
+    };
+
+    let predicate = filter
+        .predicate
+        .iter()
+        .flat_map(Predicate::comparisons)
+        .all(|comparison| match statistics(&comparison.column) {
+            Some(s) => comparison_may_match(comparison, s, row_group.num_rows()),
+            None => true,
+        });
+
+    ids && lengths && predicate
+}
+
+/// Returns the `rows` of `batch` that `filter` keeps by their ID, length and predicate columns.
+fn select_rows(
+    batch: &RecordBatch,
+    rows: Vec<BatchRow>,
+    filter: &RecordFilter,
+    selection: &Selection,
+) -> Result<Vec<BatchRow>, BrrrrError> {
+    if filter.is_empty() {
+        return Ok(rows);
+    }
+
+    let mut keep = vec![true; batch.num_rows()];
+    let has_length = filter.min_length.is_some() || filter.max_length.is_some();
+
+    match selection {
+        Selection::Sequence { id, sequence } => {
+            if filter.ids.is_some() {
+                let ids = required_text_column(batch, id)?;
+                for (i, keep) in keep.iter_mut().enumerate() {
+                    *keep &= ids.is_valid(i) && filter.id_matches(ids.value(i));
+                }
+            }
+
+            if has_length {
+                let sequences = required_text_column(batch, sequence)?;
+                for (i, keep) in keep.iter_mut().enumerate() {
+                    *keep &=
+                        sequences.is_valid(i) && filter.length_matches(sequences.value(i).len());
+                }
+            }
+        }
+        Selection::Gff { start, end } if has_length => {
+            let integers = |column: &str| -> Result<Int64Array, BrrrrError> {
+                let array = arrow_reader::optional_column(batch, column, &DataType::Int64)?
+                    .ok_or_else(|| missing_column(column))?;
+                Ok(Int64Array::from(array.data().clone()))
+            };
+            let (starts, ends) = (integers(start)?, integers(end)?);
+
+            for (i, keep) in keep.iter_mut().enumerate() {
+                let length = match starts.is_valid(i) && ends.is_valid(i) {
+                    true => usize::try_from(ends.value(i) - starts.value(i) + 1).ok(),
+                    false => None,
+                };
+                *keep &= length.is_some_and(|length| filter.length_matches(length));
+            }
+        }
+        Selection::Gff { .. } => {}
+    }
+
+    for comparison in filter.predicate.iter().flat_map(Predicate::comparisons) {
+        let column = comparison.column.as_str();
+
+        match comparison.value {
+            Value::Number(_) => {
+                let values = arrow_reader::optional_column(batch, column, &DataType::Float64)?
+                    .ok_or_else(|| missing_column(column))?;
+                let values = Float64Array::from(values.data().clone());
+
+                for (i, keep) in keep.iter_mut().enumerate() {
+                    *keep &= values.is_valid(i) && comparison.matches_number(values.value(i));
+                }
+            }
+            Value::Text(_) => {
+                let values = required_text_column(batch, column)?;
+
+                for (i, keep) in keep.iter_mut().enumerate() {
+                    *keep &= values.is_valid(i) && comparison.matches_text(values.value(i));
+                }
+            }
+        }
+    }
+
+    Ok(rows.into_iter().filter(|row| keep[row.index]).collect())
+}
+
+/// Returns the row groups of `reader`, each with its index and the index of its first row in the
+/// file.
+fn row_groups<R: FileReader>(
+    reader: &R,
+) -> impl Iterator<Item = (usize, usize, &RowGroupMetaData)> + '_ {
+    reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .enumerate()
+        .scan(0, |first_row, (i, row_group)| {
+            let row_group_start = *first_row;
+            *first_row += row_group.num_rows() as usize;
+            Some((i, row_group_start, row_group))
+        })
+}
+
+/// Reads the record batches of `row_groups`, each given with its index and the index of its
+/// first row in the file, and converts each with `convert`. `convert` is passed the rows of the
+/// batch's row columns and the rows of the batch that the filter of `options` keeps.
+#[allow(clippy::too_many_arguments)]
+fn convert_batches<'a, T, F>(
+    input: &Path,
+    source: &ParquetSource,
+    row_groups: Vec<(usize, usize)>,
+    columns: Columns,
+    selection: Selection<'a>,
+    options: &'a ParquetReadOptions,
+    convert: F,
+) -> Result<impl Iterator<Item = Result<T, BrrrrError>> + 'a, BrrrrError>
+where
+    T: 'a,
+    F: Fn(&RecordBatch, &[Row], &[BatchRow]) -> Result<Vec<Result<T, BrrrrError>>, BrrrrError> + 'a,
+{
+    let indices: Vec<usize> = row_groups.iter().map(|(i, _)| *i).collect();
+    let open = || -> Result<_, BrrrrError> {
+        let indices = indices.clone();
+        let options = ReadOptionsBuilder::new()
+            .with_predicate(Box::new(move |_, i| indices.contains(&i)))
+            .build();
+        Ok(SerializedFileReader::new_with_options(
+            source.reopen(input)?,
+            options,
+        )?)
+    };
+
+    let reader = open()?;
+
+    let mut rows = match row_projection(&reader, &columns.row)? {
+        Some(projection) => {
+            let rows = RowIter::from_file_into(Box::new(open()?));
+            Some(rows.project(Some(projection))?)
+        }
+        None => None,
+    };
+
+    // The first row of each row group, counted in the rows that are read and in the file.
+    let starts: Vec<(usize, usize)> = reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .zip(&row_groups)
+        .scan(0, |read, (row_group, (_, file_row))| {
+            let start = *read;
+            *read += row_group.num_rows() as usize;
+            Some((start, *file_row))
+        })
+        .collect();
+    let file_row = move |row: usize| {
+        let (start, file_start) = starts[starts.partition_point(|(s, _)| *s <= row) - 1];
+        file_start + row - start
+    };
+
+    let mask = projection(&reader, &columns.batch);
+    let batches = ParquetFileArrowReader::new(Arc::new(reader))
+        .get_record_reader_by_columns(mask, options.batch_size)?;
+
+    let mut first_row = 0;
+    Ok(batches.flat_map(move |batch| {
+        let records = batch.map_err(BrrrrError::from).and_then(|batch| {
+            let batch_rows: Vec<Row> = match rows.as_mut() {
+                Some(rows) => rows.take(batch.num_rows()).collect(),
+                None => Vec::new(),
+            };
+
+            let selected = (0..batch.num_rows())
+                .map(|index| BatchRow {
+                    index,
+                    file_row: file_row(first_row + index),
+                })
+                .collect();
+            first_row += batch.num_rows();
+
+            let selected = select_rows(&batch, selected, &options.filter, &selection)?;
+            convert(&batch, &batch_rows, &selected)
+        });
+
+        records.unwrap_or_else(|e| vec![Err(e)])
+    }))
+}
+
+/// Reads the records of the parquet file at `input`, which `reader` has the metadata of, in
+/// record batches of `columns`, converting each batch with `convert`. Row groups that the filter
+/// of `options` can't keep a record of are skipped. With more than one thread, that many row
+/// groups are converted at once, and their records are returned in the order of the file.
+fn read_batches<'a, T, F>(
+    input: &'a Path,
+    source: &'a ParquetSource,
+    reader: &SerializedFileReader<ParquetSource>,
+    mut columns: Columns,
+    selection: Selection<'a>,
+    options: &'a ParquetReadOptions,
+    convert: F,
+) -> Result<Box<dyn Iterator<Item = Result<T, BrrrrError>> + 'a>, BrrrrError>
+where
+    T: Send + 'a,
+    F: Fn(&RecordBatch, &[Row], &[BatchRow]) -> Result<Vec<Result<T, BrrrrError>>, BrrrrError>
+        + Sync
+        + 'a,
+{
+    let predicate = options.filter.predicate.iter().flat_map(Predicate::columns);
+    columns.batch.extend(predicate.map(String::from));
+
+    // The row groups that may have a record to keep, each with the index of its first row.
+    let row_groups: Vec<(usize, usize)> = row_groups(reader)
+        .filter(|(_, _, row_group)| row_group_may_match(row_group, &options.filter, &selection))
+        .map(|(i, first_row, _)| (i, first_row))
+        .collect();
+
+    if options.threads <= 1 {
+        let records = convert_batches(
+            input, source, row_groups, columns, selection, options, convert,
+        )?;
+        return Ok(Box::new(records));
+    }
+
+    let read_row_group = move |row_group: (usize, usize)| {
+        let records = convert_batches(
+            input,
+            source,
+            vec![row_group],
+            columns.clone(),
+            selection,
+            options,
+            &convert,
+        )?;
+
+        Ok(records.collect::<Vec<_>>())
+    };
+
+    let windows: Vec<Vec<(usize, usize)>> = row_groups
+        .chunks(options.threads)
+        .map(<[_]>::to_vec)
+        .collect();
+
+    Ok(Box::new(windows.into_iter().flat_map(move |window| {
+        let read_row_group = &read_row_group;
+
+        let row_groups: Vec<Result<Vec<Result<T, BrrrrError>>, BrrrrError>> =
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = window
+                    .into_iter()
+                    .map(|row_group| scope.spawn(move || read_row_group(row_group)))
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle.join().unwrap_or_else(|_| {
+                            Err(io::Error::other("a parquet reader thread panicked").into())
+                        })
+                    })
+                    .collect()
+            });
+
+        row_groups
+            .into_iter()
+            .flat_map(|records| records.unwrap_or_else(|e| vec![Err(e)]))
+    })))
+}
+
+/// Reads each of `rows` with `read`, which is passed its index in the batch, wrapping any error
+/// with the row it's in.
+fn batch_rows<T, F>(rows: &[BatchRow], read: F) -> Vec<Result<T, BrrrrError>>
+where
+    F: Fn(usize) -> Result<T, BrrrrError>,
+{
+    rows.iter()
+        .map(|row| read(row.index).map_err(|e| e.at("parquet", row_location(row.file_row), None)))
+        .collect()
+}
+
+/// Returns the column `name` of `batch` as text, or None if the batch has no such column.
+fn text_column(batch: &RecordBatch, name: &str) -> Result<Option<StringArray>, BrrrrError> {
+    let column = arrow_reader::optional_column(batch, name, &DataType::Utf8)?;
+    Ok(column.map(|column| StringArray::from(column.data().clone())))
+}
+
+/// Returns the text column `name` of `batch`, which must be there.
+fn required_text_column(batch: &RecordBatch, name: &str) -> Result<StringArray, BrrrrError> {
+    text_column(batch, name)?.ok_or_else(|| missing_column(name))
+}
+
+/// Returns the non-null value at `i` of `array`, read from `column`.
+fn required_value(column: &str, array: &StringArray, i: usize) -> Result<String, BrrrrError> {
+    arrow_reader::string_value(array, i).ok_or_else(|| null_value(column))
+}
+
+fn null_value(column: &str) -> BrrrrError {
+    io::Error::new(ErrorKind::InvalidData, format!("{} is null", column)).into()
+}
+
+/// Returns a function that reads the FASTA record at each row of `batch`, like
+/// fasta_record_from_row.
+fn fasta_reader(
+    batch: &RecordBatch,
+    columns: &ColumnMap,
+    template: Option<&Template>,
+) -> Result<impl Fn(usize) -> Result<FastaRecord, BrrrrError>, BrrrrError> {
+    let id_column = columns.column("id").to_string();
+    let sequence_column = columns.column("sequence").to_string();
+
+    let id = required_text_column(batch, &id_column)?;
+    let sequence = required_text_column(batch, &sequence_column)?;
+    let description = match template {
+        Some(_) => None,
+        None => text_column(batch, columns.column("description"))?,
+    };
+
+    let template = template.cloned();
+    let template_columns = template
+        .iter()
+        .flat_map(Template::columns)
+        .map(|column| Ok((column.to_string(), required_text_column(batch, column)?)))
+        .collect::<Result<HashMap<_, _>, BrrrrError>>()?;
+
+    Ok(move |i| {
+        let description = match &template {
+            Some(template) => Some(template.render(|column| {
+                let values = &template_columns[column];
+                Ok(arrow_reader::string_value(values, i).unwrap_or_default())
+            })?),
+            None => description
+                .as_ref()
+                .and_then(|d| arrow_reader::string_value(d, i)),
+        };
+
+        Ok(FastaRecord {
+            id: required_value(&id_column, &id, i)?,
+            description,
+            sequence: required_value(&sequence_column, &sequence, i)?,
+        })
+    })
+}
+
+/// Returns a function that reads the quality at each row of `batch` from either a string
+/// column or a list of Phred scores.
+fn quality_reader(
+    batch: &RecordBatch,
+    column: &str,
+) -> Result<impl Fn(usize) -> Result<Quality, BrrrrError>, BrrrrError> {
+    let quality = batch
+        .schema()
+        .column_with_name(column)
+        .map(|(i, _)| batch.column(i).clone())
+        .ok_or_else(|| missing_column(column))?;
+
+    let phred = match quality.data_type() {
+        DataType::List(_) => Some(ListArray::from(quality.data().clone())),
+        _ => None,
+    };
+    let ascii = match phred {
+        Some(_) => None,
+        None => required_text_column(batch, column).map(Some)?,
+    };
+    let column = column.to_string();
+
+    Ok(move |i| match (&phred, &ascii) {
+        (Some(phred), _) if phred.is_valid(i) => {
+            let scores = cast(&phred.value(i), &DataType::UInt8)?;
+            let scores = arrow_reader::downcast::<UInt8Array>(&scores, &column)?;
+            Ok(Quality::Phred(scores.values().to_vec()))
+        }
+        (_, Some(ascii)) => Ok(Quality::Ascii(required_value(&column, ascii, i)?)),
+        _ => Err(null_value(&column)),
+    })
+}
+
+/// Returns a function that reads the GFF record at each row of `batch`, like
+/// gff_record_from_row, but without its attributes.
+fn gff_reader<'a>(
+    batch: &'a RecordBatch,
+    columns: &'a ColumnMap,
+) -> Result<impl Fn(usize) -> Result<GffRecord, BrrrrError> + 'a, BrrrrError> {
+    let text = |field: &str| text_column(batch, columns.column(field));
+    let integers = |field: &str| -> Result<Int64Array, BrrrrError> {
+        let column = columns.column(field);
+        arrow_reader::optional_column(batch, column, &DataType::Int64)?
+            .map(|array| Int64Array::from(array.data().clone()))
+            .ok_or_else(|| missing_column(column))
+    };
+
+    let seqname = text("seqname")?.ok_or_else(|| missing_column(columns.column("seqname")))?;
+    let feature = text("feature")?.ok_or_else(|| missing_column(columns.column("feature")))?;
+    let source = text("source")?;
+    let strand = text("strand")?;
+    let frame = text("frame")?;
+    let start = integers("start")?;
+    let end = integers("end")?;
+    let score = arrow_reader::optional_column(batch, columns.column("score"), &DataType::Float64)?
+        .map(|array| Float64Array::from(array.data().clone()));
+    let lists = |field: &str| arrow_reader::optional_string_lists(batch, columns.column(field));
+    let unquoted = lists("unquoted_attributes")?;
+    let directives = lists("directives")?;
+    let list = |array: &Option<ListArray>, i| match array {
+        Some(array) => arrow_reader::string_list_value(array, i),
+        None => Ok(Vec::new()),
+    };
+
+    let value = |array: &Option<StringArray>, i| {
+        array
+            .as_ref()
+            .and_then(|array| arrow_reader::string_value(array, i))
+    };
+
+    Ok(move |i| {
+        let position = |field: &str, array: &Int64Array| match array.is_valid(i) {
+            true => position_from_value(columns.column(field), array.value(i)),
+            false => Err(null_value(columns.column(field))),
+        };
+
+        Ok(GffRecord {
+            seqname: required_value(columns.column("seqname"), &seqname, i)?,
+            source: value(&source, i).unwrap_or_default(),
+            feature: required_value(columns.column("feature"), &feature, i)?,
+            start: position("start", &start)?,
+            end: position("end", &end)?,
+            score: score
+                .as_ref()
+                .filter(|score| score.is_valid(i))
+                .map(|score| score.value(i)),
+            strand: match value(&strand, i) {
+                Some(strand) => gff_strand(&strand)?,
+                None => Strand::default().as_ref().to_string(),
+            },
+            frame: value(&frame, i).map(|f| gff_phase(&f)).transpose()?,
+            attribute: IndexMap::new(),
+            unquoted_attributes: list(&unquoted, i)?,
+            directives: list(&directives, i)?,
+        })
+    })
+}
+
+/// The options that `read_records` reads with, which keep every record.
+static READ_ALL: ParquetReadOptions = ParquetReadOptions {
+    batch_size: DEFAULT_BATCH_SIZE,
+    threads: 1,
+    filter: RecordFilter {
+        ids: None,
+        min_length: None,
+        max_length: None,
+        predicate: None,
+    },
+};
+
+/// Reads the records of the parquet file at `input`, whose kind is given by `header`. FASTA,
+/// FASTQ and GFF records are read in record batches of only their columns, like pq2fa, pq2fq and
+/// pq2gff, and alignment records are read by row.
+pub(crate) fn read_records(
+    input: &Path,
+    source: &ParquetSource,
+    header: &Header,
+) -> Result<Box<dyn Iterator<Item = Result<Record, BrrrrError>>>, BrrrrError> {
+    let reader = SerializedFileReader::new(source.reopen(input)?)?;
+    let row_groups: Vec<(usize, usize)> = row_groups(&reader).map(|(i, r, _)| (i, r)).collect();
+    let columns = |batch: &[&str], row: &[&str]| Columns {
+        batch: batch.iter().map(|c| c.to_string()).collect(),
+        row: row.iter().map(|c| c.to_string()).collect(),
+    };
+    let sequence = Selection::Sequence {
+        id: "id",
+        sequence: "sequence",
+    };
+    let gff_batch: Vec<&str> = GFF_FIELDS
+        .iter()
+        .copied()
+        .filter(|field| *field != "attribute")
+        .collect();
+
+    let records: Box<dyn Iterator<Item = Result<Record, BrrrrError>>> = match header {
+        Header::Fasta => Box::new(convert_batches(
+            input,
+            source,
+            row_groups,
+            columns(&["id", "description", "sequence"], &[]),
+            sequence,
+            &READ_ALL,
+            |batch, _, rows| {
+                let fasta = fasta_reader(batch, &ColumnMap::new(), None)?;
+                Ok(batch_rows(rows, |i| fasta(i).map(Record::Fasta)))
+            },
+        )?),
+        Header::Fastq => Box::new(convert_batches(
+            input,
+            source,
+            row_groups,
+            columns(&FASTQ_FIELDS, &[]),
+            sequence,
+            &READ_ALL,
+            |batch, _, rows| {
+                let fasta = fasta_reader(batch, &ColumnMap::new(), None)?;
+                let quality = quality_reader(batch, "quality")?;
+                let float = |name| -> Result<Option<Float64Array>, BrrrrError> {
+                    let array = arrow_reader::optional_column(batch, name, &DataType::Float64)?;
+                    Ok(array.map(|array| Float64Array::from(array.data().clone())))
+                };
+                let mean_quality = float("mean_quality")?;
+                let expected_errors = float("expected_errors")?;
+                let min_quality =
+                    arrow_reader::optional_column(batch, "min_quality", &DataType::UInt8)?
+                        .map(|array| UInt8Array::from(array.data().clone()));
+
+                Ok(batch_rows(rows, |i| {
+                    let record = fasta(i)?;
+                    Ok(Record::Fastq(FastqRecord {
+                        id: record.id,
+                        description: record.description,
+                        sequence: record.sequence,
+                        quality: quality(i)?,
+                        mean_quality: mean_quality
+                            .as_ref()
+                            .filter(|a| a.is_valid(i))
+                            .map(|a| a.value(i)),
+                        min_quality: min_quality
+                            .as_ref()
+                            .filter(|a| a.is_valid(i))
+                            .map(|a| a.value(i)),
+                        expected_errors: expected_errors
+                            .as_ref()
+                            .filter(|a| a.is_valid(i))
+                            .map(|a| a.value(i)),
+                    }))
+                }))
+            },
+        )?),
+        Header::Gff(_) => Box::new(convert_batches(
+            input,
+            source,
+            row_groups,
+            columns(&gff_batch, &["attribute"]),
+            Selection::Gff {
+                start: "start",
+                end: "end",
+            },
+            &READ_ALL,
+            |batch, rows, selected| {
+                let columns = ColumnMap::new();
+                let read = gff_reader(batch, &columns)?;
+
+                Ok(batch_rows(selected, |i| {
+                    let mut record = read(i)?;
+
+                    let attribute = rows.get(i).and_then(|row| row.get_column_iter().next());
+                    if let Some((_, Field::MapInternal(map))) = attribute {
+                        record.attribute = gff_attributes_from_map(map)?;
+                    }
+
+                    Ok(Record::Gff(record))
+                }))
+            },
+        )?),
+        Header::Sam(_) => Box::new(RowIter::from_file_into(Box::new(reader)).enumerate().map(
+            |(i, row)| {
+                sam_record_from_row(&row)
+                    .map(Record::Sam)
+                    .map_err(|e| e.at("parquet", row_location(i), Some(row.to_string())))
+            },
+        )),
+    };
+
+    Ok(records)
+}
 
 /// pq2fa reads an input parquet file, and converts the `id`, `sequence`, and `description` columns
 /// into a FASTA file with the format: `>{id} {description}\n{sequence}`.
@@ -24,220 +857,1106 @@ use crate::errors::BrrrrError;
 ///
 /// * `input` - The path to the input Parquet file.
 /// * `output` - The path to the output FASTA file.
-pub fn pq2fa<P: AsRef<Path>>(input: P, output: P) -> Result<(), BrrrrError> {
-    let output_file = File::create(output)?;
-    let handle = io::BufWriter::new(output_file);
-    let mut writer = fasta::Writer::new(handle);
-
-    if let Ok(file) = File::open(&input) {
-        let reader = SerializedFileReader::new(file)?;
-
-        for row in reader.into_iter() {
-            let mut id = None;
-            let mut description = None;
-            let mut sequence = None;
-
-            for (e, (key, _)) in row.get_column_iter().enumerate() {
-                match key.as_str() {
-                    "id" => id = Some(row.get_string(e)?),
-                    "sequence" => sequence = Some(row.get_string(e)?),
-                    "description" => {
-                        description = match row.get_string(e) {
-                            Ok(v) => Some(v.to_string()),
-                            Err(_) => None,
-                        };
-                    }
-                    _ => continue,
+/// * `columns` - The columns the `id`, `description` and `sequence` fields are read from.
+/// * `description` - A template the description is composed from instead of a column.
+/// * `options` - How the parquet file is read.
+/// * `rejects` - What to do with records that fail to parse.
+///
+/// Rows with a null ID or sequence are rejected, and a null description is left out. Only the
+/// columns that the record is read from are decoded.
+pub fn pq2fa<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    columns: &ColumnMap,
+    description: Option<&Template>,
+    options: &ParquetReadOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let source = ParquetSource::open(&input)?;
+    let reader = SerializedFileReader::new(source.reopen(&input)?)?;
+    check_columns::<_, FastaRecord>(&reader, columns, description, options)?;
+
+    let names = sequence_columns(columns, description, &["id", "sequence"]);
+    let records = read_batches(
+        input.as_ref(),
+        &source,
+        &reader,
+        names,
+        sequence_selection(columns),
+        options,
+        |batch, _, rows| Ok(batch_rows(rows, fasta_reader(batch, columns, description)?)),
+    )?;
+
+    let mut handle = io::BufWriter::new(output::create_raw(output)?);
+    json_reader::write_fasta(rejects.filter("parquet", records), &mut handle)?;
+    handle.flush()?;
+
+    Ok(())
+}
+
+/// Returns where the ID and length of a FASTA or FASTQ record are read from.
+fn sequence_selection(columns: &ColumnMap) -> Selection<'_> {
+    Selection::Sequence {
+        id: columns.column("id"),
+        sequence: columns.column("sequence"),
+    }
+}
+
+/// Returns the columns that `fields` and the description are read from.
+fn sequence_columns(
+    columns: &ColumnMap,
+    description: Option<&Template>,
+    fields: &[&str],
+) -> Columns {
+    let description: Vec<&str> = match description {
+        Some(template) => template.columns().collect(),
+        None => vec![columns.column("description")],
+    };
+
+    let batch = fields
+        .iter()
+        .map(|field| columns.column(field))
+        .chain(description)
+        .map(String::from)
+        .collect();
+
+    Columns {
+        batch,
+        row: Vec::new(),
+    }
+}
+
+/// Returns a MissingColumn error if the schema of `reader` lacks a column that the fields of
+/// `T`, the `description` template or the predicate of `options` read.
+fn check_columns<R: FileReader, T: FromColumns>(
+    reader: &R,
+    columns: &ColumnMap,
+    description: Option<&Template>,
+    options: &ParquetReadOptions,
+) -> Result<(), BrrrrError> {
+    let names: Vec<&str> = reader
+        .metadata()
+        .file_metadata()
+        .schema_descr()
+        .root_schema()
+        .get_fields()
+        .iter()
+        .map(|f| f.name())
+        .collect();
+
+    columns.check::<T, _>(&names)?;
+    check_read_columns(
+        &names,
+        description.into_iter().flat_map(Template::columns),
+        options,
+    )
+}
+
+/// Returns a MissingColumn error if `names` lacks one of `columns` or a column that the
+/// predicate of `options` reads.
+fn check_read_columns<'a, I>(
+    names: &[&str],
+    columns: I,
+    options: &'a ParquetReadOptions,
+) -> Result<(), BrrrrError>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let predicate = options.filter.predicate.iter().flat_map(Predicate::columns);
+    let mut read = columns.into_iter().chain(predicate);
+
+    match read.find(|c| !names.contains(c)) {
+        Some(column) => Err(missing_column(column)),
+        None => Ok(()),
+    }
+}
+
+fn missing_column(name: &str) -> BrrrrError {
+    BrrrrError::MissingColumn(name.to_string())
+}
+
+/// Returns the location of the row at `i`, counting from 0, to wrap the errors reading it.
+fn row_location(i: usize) -> RecordLocation {
+    RecordLocation {
+        index: i + 1,
+        ..Default::default()
+    }
+}
+
+/// Returns the rows of `reader` read with `read`, wrapping any error with the row it's in.
+fn rows<'a, R, T, F>(
+    reader: &'a R,
+    read: F,
+) -> Result<impl Iterator<Item = Result<T, BrrrrError>> + 'a, BrrrrError>
+where
+    R: FileReader,
+    F: Fn(&Row) -> Result<T, BrrrrError> + 'a,
+{
+    let rows = reader.get_row_iter(None)?.enumerate();
+
+    Ok(rows.map(move |(i, row)| {
+        read(&row).map_err(|e| e.at("parquet", row_location(i), Some(row.to_string())))
+    }))
+}
+
+/// pq2fq reads an input parquet file and converts it to FASTQ. The `quality` column may be
+/// either the ASCII string or the list of Phred scores written by fq2pq, and is written as
+/// Phred+33.
+///
+/// # Arguments
+///
+/// * `input` - The path to the input Parquet file.
+/// * `output` - The path to the output FASTQ file.
+/// * `columns` - The columns the `id`, `description`, `sequence` and `quality` fields are read
+///   from.
+/// * `description` - A template the description is composed from instead of a column.
+/// * `options` - How the parquet file is read.
+/// * `rejects` - What to do with records that fail to parse.
+///
+/// Rows with a null ID, sequence or quality are rejected, and a null description is left out.
+/// Only the columns that the record is read from are decoded.
+pub fn pq2fq<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    columns: &ColumnMap,
+    description: Option<&Template>,
+    options: &ParquetReadOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let source = ParquetSource::open(&input)?;
+    let reader = SerializedFileReader::new(source.reopen(&input)?)?;
+    check_columns::<_, FastqRecord>(&reader, columns, description, options)?;
+
+    let names = sequence_columns(columns, description, &["id", "sequence", "quality"]);
+    let records = read_batches(
+        input.as_ref(),
+        &source,
+        &reader,
+        names,
+        sequence_selection(columns),
+        options,
+        |batch, _, rows| {
+            let fasta = fasta_reader(batch, columns, description)?;
+            let quality = quality_reader(batch, columns.column("quality"))?;
+
+            Ok(batch_rows(rows, |i| {
+                let record = fasta(i)?;
+                json_reader::fastq_record(FastqRecord {
+                    id: record.id,
+                    description: record.description,
+                    sequence: record.sequence,
+                    quality: quality(i)?,
+                    ..Default::default()
+                })
+            }))
+        },
+    )?;
+
+    let mut handle = io::BufWriter::new(output::create_raw(output)?);
+    json_reader::write_fastq(rejects.filter("parquet", records), &mut handle)?;
+    handle.flush()?;
+
+    Ok(())
+}
+
+/// The columns of a paired-end parquet file written by paired_fq2pq that paired_pq2fq reads.
+const PAIRED_FASTQ_COLUMNS: &[&str] = &[
+    "id",
+    "r1_sequence",
+    "r1_quality",
+    "r2_sequence",
+    "r2_quality",
+];
+
+/// paired_pq2fq reads a paired-end parquet file written by paired_fq2pq, and splits it back into
+/// R1 and R2 FASTQ files. Both reads of a fragment are named with its `id`.
+///
+/// # Arguments
+///
+/// * `input` - The path to the input Parquet file.
+/// * `r1_output` - The path to the output R1 FASTQ file.
+/// * `r2_output` - The path to the output R2 FASTQ file.
+/// * `options` - How the parquet file is read. The length a fragment is filtered on is that of
+///   its R1 read.
+/// * `rejects` - What to do with records that fail to parse.
+pub fn paired_pq2fq<P: AsRef<Path>>(
+    input: P,
+    r1_output: P,
+    r2_output: P,
+    options: &ParquetReadOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let source = ParquetSource::open(&input)?;
+    let reader = SerializedFileReader::new(source.reopen(&input)?)?;
+
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let names: Vec<&str> = schema
+        .root_schema()
+        .get_fields()
+        .iter()
+        .map(|f| f.name())
+        .collect();
+    check_read_columns(&names, PAIRED_FASTQ_COLUMNS.iter().copied(), options)?;
+
+    let columns = Columns {
+        batch: PAIRED_FASTQ_COLUMNS.iter().map(|c| c.to_string()).collect(),
+        row: Vec::new(),
+    };
+    let selection = Selection::Sequence {
+        id: "id",
+        sequence: "r1_sequence",
+    };
+
+    let pairs = read_batches(
+        input.as_ref(),
+        &source,
+        &reader,
+        columns,
+        selection,
+        options,
+        |batch, _, rows| {
+            let id = required_text_column(batch, "id")?;
+            let r1_sequence = required_text_column(batch, "r1_sequence")?;
+            let r2_sequence = required_text_column(batch, "r2_sequence")?;
+            let r1_quality = quality_reader(batch, "r1_quality")?;
+            let r2_quality = quality_reader(batch, "r2_quality")?;
+
+            Ok(batch_rows(rows, |i| {
+                let id = required_value("id", &id, i)?;
+                let r1_sequence = required_value("r1_sequence", &r1_sequence, i)?;
+                let r2_sequence = required_value("r2_sequence", &r2_sequence, i)?;
+
+                Ok((
+                    fastq::Record::new(id.as_bytes(), r1_sequence, r1_quality(i)?.to_ascii()?),
+                    fastq::Record::new(id.as_bytes(), r2_sequence, r2_quality(i)?.to_ascii()?),
+                ))
+            }))
+        },
+    )?;
+
+    let mut r1_handle = io::BufWriter::new(output::create_raw(r1_output)?);
+    let mut r2_handle = io::BufWriter::new(output::create_raw(r2_output)?);
+
+    {
+        let mut r1_writer = fastq::Writer::new(&mut r1_handle);
+        let mut r2_writer = fastq::Writer::new(&mut r2_handle);
+
+        for pair in rejects.filter("parquet", pairs) {
+            let (r1, r2) = pair?;
+            r1_writer.write_record(&r1)?;
+            r2_writer.write_record(&r2)?;
+        }
+    }
+
+    r1_handle.flush()?;
+    r2_handle.flush()?;
+
+    Ok(())
+}
+
+/// Reads a GFF attribute map, where each key has a list of values.
+fn gff_attributes_from_map(map: &Map) -> Result<IndexMap<String, Vec<String>>, BrrrrError> {
+    let invalid = |field: &Field| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid GFF attribute: {}", field),
+        )
+    };
+
+    let mut attributes = IndexMap::new();
+
+    for (key, value) in map.entries() {
+        let key = match key {
+            Field::Str(key) => key.clone(),
+            field => return Err(invalid(field).into()),
+        };
+
+        let values = match value {
+            Field::ListInternal(list) => list
+                .elements()
+                .iter()
+                .map(|v| match v {
+                    Field::Str(v) => Ok(v.clone()),
+                    field => Err(invalid(field)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            Field::Null => Vec::new(),
+            field => return Err(invalid(field).into()),
+        };
+
+        attributes.insert(key, values);
+    }
+
+    Ok(attributes)
+}
+
+/// Returns `position`, read from `column`, as a 1-based GFF position.
+fn position_from_value(column: &str, position: i64) -> Result<usize, BrrrrError> {
+    usize::try_from(position)
+        .ok()
+        .and_then(core::Position::new)
+        .map(usize::from)
+        .ok_or_else(|| {
+            let message = format!("invalid {}: {}", column, position);
+            io::Error::new(ErrorKind::InvalidData, message).into()
+        })
+}
+
+/// Returns `strand` as it's written in GFF, after checking it's a valid strand.
+fn gff_strand(strand: &str) -> Result<String, BrrrrError> {
+    let strand = strand
+        .parse::<Strand>()
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    Ok(strand.as_ref().to_string())
+}
+
+/// Returns `phase` as it's written in GFF, after checking it's a valid phase.
+fn gff_phase(phase: &str) -> Result<String, BrrrrError> {
+    let phase = phase
+        .parse::<Phase>()
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    Ok(phase.to_string())
+}
+
+/// The columns of a FASTQ record written by fq2pq, including the quality summaries.
+const FASTQ_FIELDS: [&str; 7] = [
+    "id",
+    "description",
+    "sequence",
+    "quality",
+    "mean_quality",
+    "min_quality",
+    "expected_errors",
+];
+
+/// The fields of a GFF record, in the order of `parquet_writer::gff_schema`.
+const GFF_FIELDS: &[&str] = &[
+    "seqname",
+    "source",
+    "feature",
+    "start",
+    "end",
+    "score",
+    "strand",
+    "frame",
+    "attribute",
+    "unquoted_attributes",
+    "directives",
+];
+
+fn metadata_value<'a, R: FileReader>(reader: &'a R, key: &str) -> Option<&'a str> {
+    reader
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|kvs| kvs.iter().find(|kv| kv.key == key))
+        .and_then(|kv| kv.value.as_deref())
+}
+
+/// Reads the GFF dialect and directives stored by gff2pq, falling back to GFF3 with no
+/// directives.
+pub(crate) fn gff_header<R: FileReader>(reader: &R) -> Result<gff::Header, BrrrrError> {
+    let dialect = metadata_value(reader, GFF_DIALECT_METADATA_KEY)
+        .map(str::parse::<gff::Dialect>)
+        .transpose()?
+        .unwrap_or_default();
+
+    let lines = metadata_value(reader, GFF_HEADER_METADATA_KEY)
+        .map(|lines| lines.lines().map(String::from).collect())
+        .unwrap_or_default();
+
+    Ok(gff::Header { lines, dialect })
+}
+
+/// pq2gff reads an input parquet file and convers it to GFF3 or GTF. In GFF3, attribute values
+/// are percent-encoded and multiple values are joined with commas; in GTF, each value is written
+/// as its own quoted `key "value";` entry.
+///
+/// # Arguments
+///
+/// * `input` - The path to the input Parquet file.
+/// * `output` - The path to the output GFF file.
+/// * `dialect` - The GFF dialect to write, or None to use the one stored by gff2pq, falling back
+///   to GFF3.
+/// * `columns` - The columns the GFF fields are read from.
+/// * `options` - How the parquet file is read.
+/// * `rejects` - What to do with records that fail to parse.
+///
+/// The directives and comments stored by gff2pq are written before the records, and GFF3
+/// output always starts with a `##gff-version` directive.
+pub fn pq2gff<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    dialect: Option<gff::Dialect>,
+    columns: &ColumnMap,
+    options: &ParquetReadOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let source = ParquetSource::open(&input)?;
+    let reader = SerializedFileReader::new(source.reopen(&input)?)?;
+    check_columns::<_, GffRecord>(&reader, columns, None, options)?;
+
+    let header = gff_header(&reader)?;
+    let dialect = dialect.unwrap_or(header.dialect);
+
+    let attribute = columns.column("attribute").to_string();
+    let names = Columns {
+        batch: GFF_FIELDS
+            .iter()
+            .map(|field| columns.column(field).to_string())
+            .filter(|column| *column != attribute)
+            .collect(),
+        row: vec![attribute],
+    };
+    let selection = Selection::Gff {
+        start: columns.column("start"),
+        end: columns.column("end"),
+    };
+    let filter = &options.filter;
+
+    let records = read_batches(
+        input.as_ref(),
+        &source,
+        &reader,
+        names,
+        selection,
+        options,
+        |batch, rows, selected| {
+            let read = gff_reader(batch, columns)?;
+
+            let records = batch_rows(selected, |i| {
+                let mut record = read(i)?;
+
+                let attribute = rows.get(i).and_then(|row| row.get_column_iter().next());
+                if let Some((_, Field::MapInternal(map))) = attribute {
+                    record.attribute = gff_attributes_from_map(map)?;
                 }
+
+                let ids = record.attribute.get("ID");
+                let kept = filter.ids.is_none()
+                    || ids.is_some_and(|ids| ids.iter().any(|id| filter.id_matches(id)));
+
+                Ok(kept.then(|| gff::format_record(dialect, &record)))
+            });
+
+            Ok(records.into_iter().filter_map(Result::transpose).collect())
+        },
+    )?;
+
+    let mut handle = io::BufWriter::new(output::create_raw(output)?);
+    gff::write_header(&mut handle, &header, dialect)?;
+
+    for line in rejects.filter("parquet", records) {
+        writeln!(handle, "{}", line?)?;
+    }
+
+    handle.flush()?;
+
+    Ok(())
+}
+
+/// Reads a BED or bedGraph record from a row with the columns written by bed2pq.
+fn bed_record_from_row(row: &Row) -> Result<BedRecord, BrrrrError> {
+    let mut record = BedRecord::default();
+
+    for (e, (key, field)) in row.get_column_iter().enumerate() {
+        let null = *field == Field::Null;
+
+        match key.as_str() {
+            "chrom" => record.chrom = row.get_string(e)?.to_string(),
+            "start" => record.start = bed_coordinate(key, row.get_long(e)?)?,
+            "end" => record.end = bed_coordinate(key, row.get_long(e)?)?,
+            _ if null => continue,
+            "name" => record.name = Some(row.get_string(e)?.to_string()),
+            "score" => record.score = Some(row.get_long(e)?),
+            "strand" => record.strand = Some(row.get_string(e)?.to_string()),
+            "thick_start" => record.thick_start = Some(bed_coordinate(key, row.get_long(e)?)?),
+            "thick_end" => record.thick_end = Some(bed_coordinate(key, row.get_long(e)?)?),
+            "item_rgb" => record.item_rgb = Some(row.get_string(e)?.to_string()),
+            "block_count" => record.block_count = Some(bed_coordinate(key, row.get_long(e)?)?),
+            "block_sizes" | "block_starts" => {
+                let list = row.get_list(e)?;
+                let values = (0..list.len())
+                    .map(|i| bed_coordinate(key, list.get_long(i)?))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if key == "block_sizes" {
+                    record.block_sizes = Some(values);
+                } else {
+                    record.block_starts = Some(values);
+                }
+            }
+            "value" => record.value = Some(row.get_double(e)?),
+            _ => continue,
+        }
+    }
+
+    Ok(record)
+}
+
+/// Returns `value`, read from `column`, as a BED coordinate or count, which can't be negative.
+fn bed_coordinate(column: &str, value: i64) -> Result<u64, BrrrrError> {
+    u64::try_from(value).map_err(|_| {
+        let message = format!("invalid {}: {}", column, value);
+        io::Error::new(ErrorKind::InvalidData, message).into()
+    })
+}
+
+/// Checks the BED columns present in `schema` have the types `bed_schema` writes them with, so
+/// a column of another type is a schema mismatch rather than read as null.
+fn check_bed_schema(schema: &Schema, kind: bed::BedKind) -> Result<(), BrrrrError> {
+    for expected in parquet_writer::bed_schema(kind).fields() {
+        let found = match schema.field_with_name(expected.name()) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+
+        let matches = match (expected.data_type(), found.data_type()) {
+            (DataType::List(expected), DataType::List(found)) => {
+                expected.data_type() == found.data_type()
             }
+            (expected, found) => expected == found,
+        };
+
+        if !matches {
+            return Err(BrrrrError::SchemaMismatch {
+                column: expected.name().to_string(),
+                expected: format!("{:?}", expected.data_type()),
+                found: format!("{:?}", found.data_type()),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// pq2bed reads an input parquet file and converts it to BED or bedGraph. The number of BED
+/// columns follows the columns present in the file, and any `track` or `browser` lines stored in
+/// the file metadata are written before the records.
+///
+/// # Arguments
+///
+/// * `input` - The path to the input Parquet file.
+/// * `output` - The path to the output BED file.
+/// * `rejects` - What to do with records that fail to parse.
+pub fn pq2bed<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = SerializedFileReader::new(ParquetSource::open(&input)?)?;
+    let file_metadata = reader.metadata().file_metadata();
+
+    let column_names: Vec<String> = file_metadata
+        .schema_descr()
+        .root_schema()
+        .get_fields()
+        .iter()
+        .map(|f| f.name().to_string())
+        .collect();
+
+    let kind = if column_names.iter().any(|c| c == "value") {
+        bed::BedKind::BedGraph
+    } else {
+        let width = bed::BED_COLUMNS
+            .iter()
+            .take_while(|c| column_names.iter().any(|n| n == *c))
+            .count();
+
+        if width < 3 {
+            return Err(missing_column(bed::BED_COLUMNS[width]));
+        }
+        bed::BedKind::Bed(width)
+    };
+
+    check_bed_schema(
+        &parquet_to_arrow_schema(file_metadata.schema_descr(), None)?,
+        kind,
+    )?;
+
+    let mut writer = io::BufWriter::new(output::create_raw(output)?);
+
+    let header_lines = file_metadata
+        .key_value_metadata()
+        .and_then(|kvs| kvs.iter().find(|kv| kv.key == BED_HEADER_METADATA_KEY))
+        .and_then(|kv| kv.value.as_ref());
+
+    if let Some(lines) = header_lines {
+        writeln!(writer, "{}", lines)?;
+    }
+
+    for record in rejects.filter("parquet", rows(&reader, bed_record_from_row)?) {
+        writeln!(writer, "{}", bed::format_record(kind, &record?))?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads the SAM header stored in the metadata of a parquet file written by `bam2pq` or
+/// `sam2pq`. Files without one get an empty header, which only suits unmapped alignments.
+pub(crate) fn read_sam_header<R: FileReader>(reader: &R) -> Result<sam::Header, BrrrrError> {
+    let header = reader
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|kvs| kvs.iter().find(|kv| kv.key == SAM_HEADER_METADATA_KEY))
+        .and_then(|kv| kv.value.as_ref());
+
+    match header {
+        Some(header) => Ok(header
+            .parse()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?),
+        None => Ok(sam::Header::default()),
+    }
+}
+
+fn sam_field_from_row(row: &Row) -> Result<(String, SamField), BrrrrError> {
+    let mut tag = String::new();
+    let mut ty = String::new();
+    let mut value = None;
+
+    for (e, (key, _)) in row.get_column_iter().enumerate() {
+        match key.as_str() {
+            "tag" => tag = row.get_string(e)?.to_string(),
+            "ty" => ty = row.get_string(e)?.to_string(),
+            "int_value" => {
+                if let Ok(v) = row.get_long(e) {
+                    value = Some(SamValue::Integer(v));
+                }
+            }
+            "float_value" => {
+                if let Ok(v) = row.get_float(e) {
+                    value = Some(SamValue::Float(v));
+                }
+            }
+            "string_value" => {
+                if let Ok(v) = row.get_string(e) {
+                    value = Some(SamValue::String(v.to_string()));
+                }
+            }
+            "int_array" => {
+                if let Ok(list) = row.get_list(e) {
+                    let values = (0..list.len())
+                        .map(|i| list.get_long(i))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    value = Some(SamValue::IntegerArray(values));
+                }
+            }
+            "float_array" => {
+                if let Ok(list) = row.get_list(e) {
+                    let values = (0..list.len())
+                        .map(|i| list.get_float(i))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    value = Some(SamValue::FloatArray(values));
+                }
+            }
+            _ => continue,
+        }
+    }
+
+    match value {
+        Some(value) => Ok((tag, SamField { ty, value })),
+        None => Err(BrrrrError::IOError(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("missing value for SAM data field {}", tag),
+        ))),
+    }
+}
+
+pub(crate) fn sam_record_from_row(row: &Row) -> Result<SamRecord, BrrrrError> {
+    let mut record = SamRecord::default();
+
+    for (e, (key, _)) in row.get_column_iter().enumerate() {
+        match key.as_str() {
+            "read_name" => record.read_name = row.get_string(e).ok().cloned(),
+            "flags" => record.flags = row.get_ushort(e)?,
+            "reference_sequence" => record.reference_sequence = row.get_string(e).ok().cloned(),
+            "alignment_start" => record.alignment_start = row.get_long(e).ok().map(|v| v as usize),
+            "mapping_quality" => record.mapping_quality = row.get_ubyte(e).ok(),
+            "cigar" => {
+                let list = row.get_list(e)?;
+                let operations = (0..list.len())
+                    .map(|i| {
+                        let op = list.get_group(i)?;
+                        Ok(Operation::new(
+                            op.get_string(0)?.to_string(),
+                            op.get_long(1)? as usize,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, BrrrrError>>()?;
+                record.cigar = Cigar::new(operations);
+            }
+            "mate_reference_sequence" => {
+                record.mate_reference_sequence = row.get_string(e).ok().cloned()
+            }
+            "mate_alignment_start" => {
+                record.mate_alignment_start = row.get_long(e).ok().map(|v| v as usize)
+            }
+            "template_length" => record.template_length = row.get_int(e)?,
+            "sequence" => record.sequence = row.get_string(e)?.to_string(),
+            "quality_scores" => {
+                let list = row.get_list(e)?;
+                record.quality_scores = (0..list.len())
+                    .map(|i| list.get_ubyte(i))
+                    .collect::<Result<Vec<_>, _>>()?;
+            }
+            "data" => {
+                let list = row.get_list(e)?;
+                let fields = (0..list.len())
+                    .map(|i| sam_field_from_row(list.get_group(i)?))
+                    .collect::<Result<IndexMap<_, _>, _>>()?;
+                record.data = Data::new(fields);
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(record)
+}
+
+/// pq2sam reads a parquet file written by `bam2pq` or `sam2pq` and converts it to SAM, using the
+/// SAM header stored in the file metadata.
+///
+/// # Arguments
+///
+/// * `input` - The path to the input Parquet file.
+/// * `output` - The path to the output SAM file.
+/// * `rejects` - What to do with records that fail to parse.
+pub fn pq2sam<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = SerializedFileReader::new(ParquetSource::open(&input)?)?;
+    let header = read_sam_header(&reader)?;
+
+    let mut writer = sam::Writer::new(io::BufWriter::new(output::create_raw(output)?));
+    writer.write_header(&header)?;
+
+    let records = rows(&reader, |row| {
+        Ok(sam_record_from_row(row)?.to_alignment_record(header.reference_sequences())?)
+    })?;
+
+    for record in rejects.filter("parquet", records) {
+        writer.write_record(&header, &record?)?;
+    }
+
+    writer.get_mut().flush()?;
+
+    Ok(())
+}
+
+/// pq2bam reads a parquet file written by `bam2pq` or `sam2pq` and converts it to a BGZF
+/// compressed BAM, using the SAM header stored in the file metadata.
+///
+/// # Arguments
+///
+/// * `input` - The path to the input Parquet file.
+/// * `output` - The path to the output BAM file.
+/// * `write_index` - Whether to also write a `.bai` index next to the output. This requires the
+///   alignments to be sorted by coordinate, and an output file rather than stdout.
+/// * `rejects` - What to do with records that fail to parse.
+pub fn pq2bam<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    write_index: bool,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    if write_index && input::is_stdio(&output) {
+        return Err(BrrrrError::IOError(io::Error::new(
+            ErrorKind::InvalidInput,
+            "a BAM index can't be written next to stdout",
+        )));
+    }
+
+    let reader = SerializedFileReader::new(ParquetSource::open(&input)?)?;
+    let header = read_sam_header(&reader)?;
+
+    let mut writer = bam::Writer::new(output::create_raw(&output)?);
+    writer.write_header(&header)?;
+    writer.write_reference_sequences(header.reference_sequences())?;
+
+    let mut indexer = bam::bai::Index::builder();
+    let mut last_position = None;
+
+    let records = rows(&reader, |row| {
+        Ok(sam_record_from_row(row)?.to_alignment_record(header.reference_sequences())?)
+    })?;
+
+    for record in rejects.filter("parquet", records) {
+        let record = record?;
+
+        let start = writer.get_ref().virtual_position();
+        writer.write_record(&header, &record)?;
+        let end = writer.get_ref().virtual_position();
+
+        if write_index {
+            // Unmapped alignments sort after all mapped ones.
+            let position = (
+                record.reference_sequence_id().unwrap_or(usize::MAX),
+                record.alignment_start(),
+            );
+
+            if last_position.is_some_and(|last| position < last) {
+                return Err(BrrrrError::IOError(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "alignments must be sorted by coordinate to write a BAM index",
+                )));
+            }
+            last_position = Some(position);
+
+            indexer.add_record(&record, Chunk::new(start, end))?;
+        }
+    }
+
+    writer.try_finish()?;
+
+    if write_index {
+        let index = indexer.build(header.reference_sequences().len());
+
+        let mut index_path = output.as_ref().as_os_str().to_owned();
+        index_path.push(".bai");
+        bam::bai::write(index_path, &index)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::BufReader};
+
+    use arrow::array::ArrayRef;
+    use itertools::Itertools;
+    use noodles::fasta::{self, record::Definition, record::Sequence};
+    use noodles::fastq;
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+
+    use super::*;
+    use crate::filter;
+    use crate::paired;
+    use crate::parquet_writer::{
+        bam2pq, bed2pq, fa2pq, fq2pq, gff2pq, paired_fq2pq, sam2pq, BioFileCompression,
+    };
+    use crate::quality::{PhredOffset, QualityOptions};
+    use crate::rejects::ErrorPolicy;
+    use crate::writer::RecordWriter;
+    use noodles::csi::BinningIndex;
+
+    #[test]
+    fn parquet_bam_base_test() {
+        let mut initial_bam = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        initial_bam.push("resources/little.bam");
+
+        let temp_dir = env::temp_dir();
+        let initial_parquet = temp_dir.join("initial_bam_parquet.parquet");
+        let second_bam = temp_dir.join("second_bam.bam");
+
+        bam2pq(
+            &initial_bam,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .expect("bam2pq failed");
+        pq2bam(&initial_parquet, &second_bam, true, &mut Rejects::default())
+            .expect("pq2bam failed");
+
+        let read_bam = |path: &std::path::PathBuf| {
+            let mut reader = bam::Reader::new(File::open(path).expect("error"));
+            reader.read_header().expect("error");
+            let reference_sequences = reader.read_reference_sequences().expect("error");
+            let records = reader
+                .records()
+                .collect::<io::Result<Vec<_>>>()
+                .expect("error");
+            (reference_sequences, records)
+        };
+
+        let (initial_reference_sequences, initial_records) = read_bam(&initial_bam);
+        let (second_reference_sequences, second_records) = read_bam(&second_bam);
+
+        assert_eq!(initial_reference_sequences, second_reference_sequences);
+        assert_eq!(initial_records, second_records);
+
+        let mut index_path = second_bam.into_os_string();
+        index_path.push(".bai");
+        let index = bam::bai::read(index_path).expect("error reading index");
+        assert_eq!(
+            index.reference_sequences().len(),
+            initial_reference_sequences.len()
+        );
+    }
+
+    #[test]
+    fn parquet_sam_base_test() {
+        let temp_dir = env::temp_dir();
+        let initial_sam = temp_dir.join("initial_sam.sam");
+        let initial_parquet = temp_dir.join("initial_sam_parquet.parquet");
+        let second_sam = temp_dir.join("second_sam.sam");
 
-            match (id, description, sequence) {
-                (Some(i), d, Some(s)) => {
-                    let definition = fasta::record::Definition::new(i, d);
+        let contents = "@HD\tVN:1.6\tSO:coordinate\n\
+            @SQ\tSN:sq0\tLN:100\n\
+            r0\t99\tsq0\t5\t30\t3M1I2M\t=\t20\t21\tACGTAC\tIIII#I\tNM:i:1\tXA:Z:hi\tZB:B:s,-1,2\n\
+            r1\t4\t*\t0\t255\t*\t*\t0\t0\tAC\t*\n";
+        std::fs::write(&initial_sam, contents).expect("error");
 
-                    let sequence = fasta::record::Sequence::from(s.as_bytes().to_vec());
-                    let record = fasta::Record::new(definition, sequence);
-                    writer.write_record(&record)?
-                }
-                (_, _, _) => {
-                    return Err(BrrrrError::IOError(io::Error::new(
-                        io::ErrorKind::Unsupported,
-                        format!(
-                            "Unexpected parsing for id: {}",
-                            id.unwrap_or(&String::from("unknown id")),
-                        ),
-                    )))
-                }
-            };
-        }
+        sam2pq(
+            &initial_sam,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("sam2pq failed");
+        pq2sam(&initial_parquet, &second_sam, &mut Rejects::default()).expect("pq2sam failed");
+
+        let round_tripped = std::fs::read_to_string(&second_sam).expect("error");
+        assert_eq!(round_tripped, contents);
     }
 
-    Ok(())
-}
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_flush_errors() {
+        let temp_dir = env::temp_dir();
+        let sam = temp_dir.join("flush_errors.sam");
+        let sam_parquet = temp_dir.join("flush_errors_sam.parquet");
+        let bed = temp_dir.join("flush_errors.bed");
+        let bed_parquet = temp_dir.join("flush_errors_bed.parquet");
 
-/// pq2fq reads an input parquet file and converts it to FASTQ.
-///
-/// # Arguments
-///
-/// * `input` - The path to the input Parquet file.
-/// * `output` - The path to the output FASTQ file.
-pub fn pq2fq<P: AsRef<Path>>(input: P, output: P) -> Result<(), BrrrrError> {
-    let output_file = File::create(output)?;
-    let handle = io::BufWriter::new(output_file);
-    let mut writer = fastq::Writer::new(handle);
-
-    if let Ok(file) = File::open(&input) {
-        let reader = SerializedFileReader::new(file)?;
-
-        for row in reader.into_iter() {
-            let mut id = None;
-            let mut description = None;
-            let mut sequence = None;
-            let mut quality = None;
-
-            for (e, (key, _)) in row.get_column_iter().enumerate() {
-                match key.as_str() {
-                    "id" => id = Some(row.get_string(e)?),
-                    "sequence" => sequence = Some(row.get_string(e)?),
-                    "quality" => quality = Some(row.get_string(e)?),
-                    "description" => {
-                        description = match row.get_string(e) {
-                            Ok(v) => Some(v.to_string()),
-                            Err(_) => None,
-                        };
-                    }
-                    _ => continue,
-                }
-            }
+        std::fs::write(
+            &sam,
+            "@SQ\tSN:sq0\tLN:100\nr0\t4\t*\t0\t255\t*\t*\t0\t0\tAC\t*\n",
+        )
+        .expect("error");
+        std::fs::write(&bed, "chr1\t10\t20\n").expect("error");
 
-            match (id, description, sequence, quality) {
-                (Some(i), _, Some(s), Some(q)) => {
-                    let record = fastq::Record::new(i.as_bytes(), s.as_bytes(), q.as_bytes());
-                    writer.write_record(&record)?;
-                }
-                (_, _, _, _) => {
-                    panic!(
-                        "unable to handle values passed in id, description, sequence, or quality"
-                    )
-                }
-            };
-        }
+        let options = Compression::UNCOMPRESSED.into();
+        sam2pq(&sam, &sam_parquet, &options, None, &mut Rejects::default()).unwrap();
+        bed2pq(
+            &bed,
+            &bed_parquet,
+            &options,
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        // Writes to /dev/full succeed until the buffered output is flushed.
+        let full = std::path::PathBuf::from("/dev/full");
+        assert!(pq2sam(&sam_parquet, &full, &mut Rejects::default()).is_err());
+        assert!(pq2bed(&bed_parquet, &full, &mut Rejects::default()).is_err());
     }
-    Ok(())
-}
 
-/// pq2gff reads an input parquet file and convers it to GFF.
-///
-/// # Arguments
-///
-/// * `input` - The path to the input Parquet file.
-/// * `output` - The path to the output GFF file.
+    #[test]
+    fn parquet_bed_base_test() {
+        let temp_dir = env::temp_dir();
+        let initial_bed = temp_dir.join("initial_bed.bed");
+        let initial_parquet = temp_dir.join("initial_bed_parquet.parquet");
+        let second_bed = temp_dir.join("second_bed.bed");
 
-pub fn pq2gff<P: AsRef<Path>>(input: P, output: P) -> Result<(), BrrrrError> {
-    let output_file = File::create(output)?;
-    let handle = io::BufWriter::new(output_file);
-    let mut writer = gff::Writer::new(handle);
+        let contents = "track name=pairedReads\tuseScore=1\n\
+            chr1\t10\t200\tfeat0\t0\t-\t20\t180\t255,0,0\t2\t50,40\t0,150\n\
+            chr2\t5\t25\tfeat1\t.\t+\t5\t25\t0\t1\t20\t0\n";
+        std::fs::write(&initial_bed, contents).expect("error");
 
-    if let Ok(file) = File::open(&input) {
-        let reader = SerializedFileReader::new(file)?;
+        bed2pq(
+            &initial_bed,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("bed2pq failed");
+        pq2bed(&initial_parquet, &second_bed, &mut Rejects::default()).expect("pq2bed failed");
 
-        for row in reader.into_iter() {
-            let mut gff_record_builder = gff::Record::builder();
+        let round_tripped = std::fs::read_to_string(&second_bed).expect("error");
+        assert_eq!(round_tripped, contents);
+    }
 
-            for (e, (key, _)) in row.get_column_iter().enumerate() {
-                match key.as_str() {
-                    "seqname" => {
-                        gff_record_builder = gff_record_builder
-                            .set_reference_sequence_name(row.get_string(e)?.to_string())
-                    }
-                    "source" => {
-                        gff_record_builder =
-                            gff_record_builder.set_source(row.get_string(e)?.to_string())
-                    }
-                    "feature" => {
-                        gff_record_builder =
-                            gff_record_builder.set_type(row.get_string(e)?.to_string())
-                    }
-                    "start" => {
-                        let int_position = row.get_long(e)?;
-                        let position =
-                            core::Position::new(int_position as usize).ok_or_else(|| {
-                                io::Error::new(ErrorKind::Other, "Error parsing start.")
-                            })?;
-
-                        gff_record_builder = gff_record_builder.set_start(position);
-                    }
-                    "end" => {
-                        let int_position = row.get_long(e)?;
-                        let position =
-                            core::Position::new(int_position as usize).ok_or_else(|| {
-                                io::Error::new(ErrorKind::NotFound, "Error parsing end.")
-                            })?;
-
-                        gff_record_builder = gff_record_builder.set_end(position);
-                    }
-                    "score" => match row.get_long(e) {
-                        Ok(score) => {
-                            gff_record_builder = gff_record_builder.set_score(score as f32);
-                        }
-                        _ => continue,
-                    },
-                    "stand" => {
-                        let strand = row.get_string(e)?;
-                        let n_strand = strand.parse::<Strand>().expect("unable to parse strand");
-                        gff_record_builder = gff_record_builder.set_strand(n_strand);
-                    }
-                    "frame" => {
-                        let frame = row.get_string(e);
-                        match frame {
-                            Ok(f) => {
-                                let phase = f.parse::<Phase>().expect("unable to parse phase");
-                                gff_record_builder = gff_record_builder.set_phase(phase);
-                            }
-                            _ => continue,
-                        }
-                    }
-                    "attribute" => {
-                        let parquet_map = row.get_map(e)?;
-                        let entries: Vec<Entry> = parquet_map
-                            .entries()
-                            .iter()
-                            .map(|entry| {
-                                let (key, value) = &entry;
-
-                                // TODO(trent): why is does this need trim_matches?
-                                Entry::new(
-                                    key.to_string().trim_matches('"'),
-                                    value.to_string().trim_matches('"'),
-                                )
-                            })
-                            .collect();
-
-                        let attributes = Attributes::from(entries);
-                        gff_record_builder = gff_record_builder.set_attributes(attributes);
-                    }
-                    _ => continue,
-                }
-            }
+    #[test]
+    fn test_pq2bed_invalid_columns() {
+        let temp_dir = env::temp_dir();
+        let parquet = temp_dir.join("invalid_columns_bed.parquet");
+        let bed = temp_dir.join("invalid_columns_bed.bed");
+
+        let write_parquet = |starts: Vec<i64>, score: ArrayRef| {
+            let batch = RecordBatch::try_from_iter(vec![
+                (
+                    "chrom",
+                    Arc::new(StringArray::from(vec!["chr1", "chr2"])) as ArrayRef,
+                ),
+                ("start", Arc::new(Int64Array::from(starts)) as ArrayRef),
+                ("end", Arc::new(Int64Array::from(vec![20, 30])) as ArrayRef),
+                (
+                    "name",
+                    Arc::new(StringArray::from(vec![Some("a"), None])) as ArrayRef,
+                ),
+                ("score", score),
+            ])
+            .unwrap();
+
+            let file = File::create(&parquet).unwrap();
+            let mut writer = ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+        };
+
+        // A negative start is an invalid record rather than wrapping around.
+        write_parquet(
+            vec![-1, 10],
+            Arc::new(Int64Array::from(vec![Some(5), None])),
+        );
+        assert!(pq2bed(&parquet, &bed, &mut Rejects::default()).is_err());
+
+        let mut rejects = Rejects::new(ErrorPolicy::Skip);
+        pq2bed(&parquet, &bed, &mut rejects).expect("pq2bed failed");
+        assert_eq!(rejects.total(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&bed).unwrap(),
+            "chr2\t10\t30\t.\t.\n"
+        );
 
-            writer.write_record(&gff_record_builder.build())?;
+        // An optional column of the wrong type is a schema mismatch rather than read as null.
+        write_parquet(
+            vec![0, 10],
+            Arc::new(StringArray::from(vec!["5", "6"])) as ArrayRef,
+        );
+        match pq2bed(&parquet, &bed, &mut Rejects::new(ErrorPolicy::Skip)) {
+            Err(BrrrrError::SchemaMismatch { column, .. }) => assert_eq!(column, "score"),
+            result => panic!("expected a schema mismatch, got {:?}", result),
         }
     }
 
-    Ok(())
-}
+    #[test]
+    fn parquet_bedgraph_base_test() {
+        let temp_dir = env::temp_dir();
+        let initial_bed = temp_dir.join("initial_bedgraph.bedgraph");
+        let initial_parquet = temp_dir.join("initial_bedgraph_parquet.parquet");
+        let second_bed = temp_dir.join("second_bedgraph.bedgraph");
 
-#[cfg(test)]
-mod tests {
-    use std::{env, io::BufReader};
+        let contents = "chr1\t0\t100\t0.5\nchr1\t100\t200\t1.25\n";
+        std::fs::write(&initial_bed, contents).expect("error");
 
-    use itertools::Itertools;
-    use noodles::fasta::{self, record::Definition, record::Sequence};
-    use noodles::fastq;
-    use parquet::basic::Compression;
+        bed2pq(
+            &initial_bed,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("bed2pq failed");
+        pq2bed(&initial_parquet, &second_bed, &mut Rejects::default()).expect("pq2bed failed");
 
-    use super::*;
-    use crate::parquet_writer::{fa2pq, fq2pq, gff2pq, BioFileCompression};
+        let round_tripped = std::fs::read_to_string(&second_bed).expect("error");
+        assert_eq!(round_tripped, contents);
+    }
 
     #[test]
     fn parquet_gff_base_test() {
@@ -246,53 +1965,329 @@ mod tests {
         let initial_parquet = temp_dir.join("initial_gff_parquet.parquet");
         let second_gff = temp_dir.join("second_gff.gff");
 
-        let s = "sq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tgene_id=ndls0;gene_name=gene0";
-        let gff_record = s.parse::<gff::Record>().expect("parse error");
+        let s = "sq0\tNOODLES\tgene\t8\t13\t.\t+\t.\tgene_id=ndls0;gene_name=gene0;Parent=a,b;Note=x%2Cy";
+        let gff_record = gff::parse_record(gff::Dialect::Gff3, s).expect("parse error");
 
-        let mut writer = gff::Writer::new(File::create(&initital_gff).expect("error"));
-        writer.write_record(&gff_record).expect("error");
+        std::fs::write(&initital_gff, format!("{}\n", s)).expect("error");
 
-        gff2pq(&initital_gff, &initial_parquet, Compression::UNCOMPRESSED).expect("gff2pq failed");
+        gff2pq(
+            &initital_gff,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("gff2pq failed");
         assert!(&initial_parquet.exists());
-        pq2gff(&initial_parquet, &second_gff).expect("pq2gff failed");
+        pq2gff(
+            &initial_parquet,
+            &second_gff,
+            None,
+            &ColumnMap::new(),
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("pq2gff failed");
 
         let mut reader = gff::Reader::new(BufReader::new(File::open(&second_gff).expect("error")));
-        let recs = reader.records().collect_vec();
+        let header = reader.read_header(None).expect("error");
+        assert_eq!(header.dialect, gff::Dialect::Gff3);
+        let recs = reader.records(&header).collect_vec();
         assert_eq!(recs.len(), 1);
 
-        let actual_record = recs.get(0);
-        if let Some(ar) = actual_record {
-            assert!(ar.is_ok(), "{:?}", ar);
+        let found_gff_record = recs[0].as_ref().expect("could not match gff");
 
-            match ar {
-                Ok(found_gff_record) => {
-                    assert_eq!(
-                        found_gff_record.reference_sequence_name(),
-                        gff_record.reference_sequence_name()
-                    );
-
-                    let mut found_keys: Vec<&str> = found_gff_record
-                        .attributes()
-                        .into_iter()
-                        .map(|e| e.key())
-                        .collect();
-                    let mut gff_keys: Vec<&str> = gff_record
-                        .attributes()
-                        .into_iter()
-                        .map(|e| e.key())
-                        .collect();
-                    assert_eq!(found_keys.sort(), gff_keys.sort());
-
-                    assert_eq!(found_gff_record.source(), gff_record.source());
-                    assert_eq!(found_gff_record.ty(), gff_record.ty());
-                    assert_eq!(found_gff_record.start(), gff_record.start());
-                    assert_eq!(found_gff_record.end(), gff_record.end());
-                    assert_eq!(found_gff_record.score(), gff_record.score());
-                    assert_eq!(found_gff_record.phase(), gff_record.phase());
-                }
-                _ => panic!("could not match gff"),
-            }
+        assert_eq!(found_gff_record.seqname, gff_record.seqname);
+        assert_eq!(found_gff_record.attribute, gff_record.attribute);
+        assert_eq!(found_gff_record.attribute["Parent"], vec!["a", "b"]);
+        assert_eq!(found_gff_record.attribute["Note"], vec!["x,y"]);
+
+        assert_eq!(found_gff_record.source, gff_record.source);
+        assert_eq!(found_gff_record.feature, gff_record.feature);
+        assert_eq!(found_gff_record.start, gff_record.start);
+        assert_eq!(found_gff_record.end, gff_record.end);
+        assert_eq!(found_gff_record.score, gff_record.score);
+        assert_eq!(found_gff_record.frame, gff_record.frame);
+    }
+
+    #[test]
+    fn parquet_gff_round_trip_test() {
+        let temp_dir = env::temp_dir();
+        let initial_gff = temp_dir.join("round_trip.gff");
+        let initial_parquet = temp_dir.join("round_trip_gff.parquet");
+        let second_gff = temp_dir.join("round_trip_second.gff");
+
+        let contents = "##gff-version 3
+##sequence-region sq0 1 20
+sq0\tsrc\tCDS\t1\t9\t12.5\t-\t2\tParent=t0,t1;ID=c0;Name=z;Alias=a
+sq0\tsrc\tCDS\t10\t20\t0.001\t+\t0\tID=c1
+###
+##sequence-region sq1 1 100
+sq1\tsrc\tgene\t5\t8\t123456789.25\t?\t.\tID=g0
+# a comment
+sq1\tsrc\tregion\t1\t100\t1.23456789e-10\t.\t.\tID=r0
+sq1\tsrc\tregion\t1\t100\t-3\t.\t.\tID=r1
+###
+";
+        std::fs::write(&initial_gff, contents).expect("error");
+
+        gff2pq(
+            &initial_gff,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("gff2pq failed");
+        pq2gff(
+            &initial_parquet,
+            &second_gff,
+            None,
+            &ColumnMap::new(),
+            &ParquetReadOptions {
+                batch_size: 3,
+                threads: 2,
+                ..Default::default()
+            },
+            &mut Rejects::default(),
+        )
+        .expect("pq2gff failed");
+
+        let round_tripped = std::fs::read_to_string(&second_gff).expect("error");
+        assert_eq!(round_tripped, contents);
+
+        let reader = SerializedFileReader::new(File::open(&initial_parquet).unwrap()).unwrap();
+        let row = reader.get_row_iter(None).unwrap().next().unwrap();
+        assert!(row
+            .get_column_iter()
+            .any(|(key, field)| key == "score" && *field == Field::Double(12.5)));
+
+        let filters = [
+            (
+                RecordFilter {
+                    ids: Some(["c1", "g0", "r0"].iter().map(|id| id.to_string()).collect()),
+                    min_length: Some(10),
+                    ..Default::default()
+                },
+                "sq0\tsrc\tCDS\t10\t20\t0.001\t+\t0\tID=c1
+###
+##sequence-region sq1 1 100
+sq1\tsrc\tregion\t1\t100\t1.23456789e-10\t.\t.\tID=r0
+",
+            ),
+            (
+                RecordFilter {
+                    predicate: Some("seqname == sq0 and score < 1".parse().unwrap()),
+                    ..Default::default()
+                },
+                "sq0\tsrc\tCDS\t10\t20\t0.001\t+\t0\tID=c1
+###
+##sequence-region sq1 1 100
+",
+            ),
+        ];
+
+        // The directives after a record are written with it, and the header always is.
+        for (filter, records) in filters {
+            let options = ParquetReadOptions {
+                filter,
+                ..Default::default()
+            };
+            pq2gff(
+                &initial_parquet,
+                &second_gff,
+                None,
+                &ColumnMap::new(),
+                &options,
+                &mut Rejects::default(),
+            )
+            .expect("pq2gff failed");
+
+            let filtered = std::fs::read_to_string(&second_gff).expect("error");
+            assert_eq!(
+                filtered,
+                format!("##gff-version 3\n##sequence-region sq0 1 20\n{}", records)
+            );
+        }
+    }
+
+    #[test]
+    fn parquet_gff_column_map_test() {
+        #[derive(serde::Serialize)]
+        struct Row {
+            chrom: String,
+            source: String,
+            kind: String,
+            start: i32,
+            end: i32,
+            score: Option<f64>,
+            strand: Option<String>,
+        }
+
+        let temp_dir = env::temp_dir();
+        let input = temp_dir.join("gff_column_map.parquet");
+        let output = temp_dir.join("gff_column_map.gff");
+
+        let mut writer = crate::parquet_writer::ParquetRecordWriter::new(
+            File::create(&input).unwrap(),
+            parquet::file::properties::WriterProperties::builder().build(),
+        );
+        for (score, strand) in [(Some(0.5), Some("-")), (None, None)] {
+            writer
+                .write_serde_record(Row {
+                    chrom: String::from("chr1"),
+                    source: String::from("src"),
+                    kind: String::from("exon"),
+                    start: 3,
+                    end: 7,
+                    score,
+                    strand: strand.map(String::from),
+                })
+                .unwrap();
         }
+        writer.close().unwrap();
+
+        let columns = ColumnMap::new()
+            .with_column("seqname", "chrom")
+            .with_column("feature", "kind");
+        pq2gff(
+            &input,
+            &output,
+            None,
+            &columns,
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "##gff-version 3
+chr1\tsrc\texon\t3\t7\t0.5\t-\t.\t.
+chr1\tsrc\texon\t3\t7\t.\t.\t.\t.
+"
+        );
+
+        let result = pq2gff(
+            &input,
+            &output,
+            None,
+            &ColumnMap::new(),
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        );
+        assert!(matches!(result, Err(BrrrrError::MissingColumn(column)) if column == "seqname"));
+    }
+
+    #[test]
+    fn parquet_gtf_base_test() {
+        let temp_dir = env::temp_dir();
+        let initial_gtf = temp_dir.join("initial_gtf.gtf");
+        let initial_parquet = temp_dir.join("initial_gtf_parquet.parquet");
+        let second_gtf = temp_dir.join("second_gtf.gtf");
+        let second_gff = temp_dir.join("second_gtf.gff");
+
+        // The Ensembl line quotes exon_number and the GENCODE one doesn't, and both are written
+        // back as they were.
+        let s = "X7\tAUGUSTUS\tintron\t1\t102\t1\t+\t.\tgene_id \"g1\"; tag \"a\"; tag \"b\";
+1\tensembl\texon\t65419\t65433\t.\t+\t.\tgene_id \"g2\"; exon_number \"1\"; transcript_support_level \"1\";
+chr1\tHAVANA\texon\t11869\t12227\t.\t+\t.\tgene_id \"g3\"; exon_number 1; level 2;
+";
+        std::fs::write(&initial_gtf, s).expect("error");
+
+        gff2pq(
+            &initial_gtf,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            None,
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("gff2pq failed");
+
+        pq2gff(
+            &initial_parquet,
+            &second_gtf,
+            None,
+            &ColumnMap::new(),
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("pq2gff failed");
+        assert_eq!(std::fs::read_to_string(&second_gtf).expect("error"), s);
+
+        let mut reader = gff::Reader::new(BufReader::new(File::open(&second_gtf).expect("error")));
+        let header = reader.read_header(None).expect("error");
+        assert_eq!(header.dialect, gff::Dialect::Gtf);
+
+        let record = reader.records(&header).next().unwrap().expect("error");
+        assert_eq!(record.attribute["gene_id"], vec!["g1"]);
+        assert_eq!(record.attribute["tag"], vec!["a", "b"]);
+
+        pq2gff(
+            &initial_parquet,
+            &second_gff,
+            Some(gff::Dialect::Gff3),
+            &ColumnMap::new(),
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("pq2gff failed");
+        let gff = std::fs::read_to_string(&second_gff).expect("error");
+        let mut lines = gff.lines();
+        assert_eq!(lines.next(), Some("##gff-version 3"));
+
+        let record = gff::parse_record(gff::Dialect::Gff3, lines.next().unwrap()).expect("error");
+        assert_eq!(record.attribute["tag"], vec!["a", "b"]);
+    }
+
+    #[test]
+    fn parquet_gff_directives_test() {
+        let temp_dir = env::temp_dir();
+        let initial_gff = temp_dir.join("initial_directives.gff");
+        let initial_parquet = temp_dir.join("initial_directives.parquet");
+        let fasta_parquet = temp_dir.join("initial_directives_fasta.parquet");
+        let second_gff = temp_dir.join("second_directives.gff");
+
+        let header = "##gff-version 3.1.26\n##sequence-region sq0 1 20\n# made by a test";
+        let record = "sq0\tNOODLES\tgene\t8\t13\t.\t.\t.\tID=g0";
+        let contents = format!("{}\n{}\n##FASTA\n>sq0 first\nACGTACGTAC\n", header, record);
+        std::fs::write(&initial_gff, contents).expect("error");
+
+        gff2pq(
+            &initial_gff,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            Some(&fasta_parquet),
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("gff2pq failed");
+
+        pq2gff(
+            &initial_parquet,
+            &second_gff,
+            None,
+            &ColumnMap::new(),
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("pq2gff failed");
+        let round_tripped = std::fs::read_to_string(&second_gff).expect("error");
+        assert_eq!(round_tripped, format!("{}\n{}\n", header, record));
+
+        let reader = SerializedFileReader::new(File::open(&fasta_parquet).unwrap()).unwrap();
+        let rows: Vec<Row> = reader.into_iter().collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_string(0).unwrap(), "sq0");
+        assert_eq!(rows[0].get_string(1).unwrap(), "first");
+        assert_eq!(rows[0].get_string(2).unwrap(), "ACGTACGTAC");
     }
 
     #[test]
@@ -307,9 +2302,25 @@ mod tests {
         let mut writer = fastq::Writer::new(File::create(&initital_fasta).expect("error"));
         writer.write_record(&r).expect("error");
 
-        fq2pq(&initital_fasta, &initial_parquet, Compression::UNCOMPRESSED).expect("fa2pq failed");
+        fq2pq(
+            &initital_fasta,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            &QualityOptions::default(),
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("fa2pq failed");
         assert!(&initial_parquet.exists());
-        pq2fq(&initial_parquet, &second_fasta).expect("fa2pq failed");
+        pq2fq(
+            &initial_parquet,
+            &second_fasta,
+            &ColumnMap::new(),
+            None,
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("fa2pq failed");
 
         let mut reader =
             fastq::Reader::new(BufReader::new(File::open(&second_fasta).expect("error")));
@@ -317,12 +2328,241 @@ mod tests {
         let recs = reader.records().collect_vec();
         assert_eq!(recs.len(), 1);
 
-        let actual_record = recs.get(0);
+        let actual_record = recs.first();
         if let Some(ar) = actual_record {
             assert!(ar.is_ok());
         }
     }
 
+    #[test]
+    fn parquet_fastq_phred_test() {
+        let temp_dir = env::temp_dir();
+        let initial_fastq = temp_dir.join("initial_phred64.fastq");
+        let initial_parquet = temp_dir.join("initial_phred64.parquet");
+        let second_fastq = temp_dir.join("second_phred64.fastq");
+
+        std::fs::write(&initial_fastq, "@r0\nAGCT\n+\n@J^h\n").expect("error");
+
+        let quality_options = QualityOptions {
+            phred_scores: true,
+            offset: PhredOffset::Phred64,
+            summary: true,
+        };
+        fq2pq(
+            &initial_fastq,
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            &quality_options,
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("fq2pq failed");
+
+        let reader = SerializedFileReader::new(File::open(&initial_parquet).unwrap()).unwrap();
+        let row = reader.into_iter().next().expect("no rows");
+        let scores: Vec<u8> = (0..4)
+            .map(|i| row.get_list(3).unwrap().get_ubyte(i).unwrap())
+            .collect();
+        assert_eq!(scores, vec![0, 10, 30, 40]);
+        assert_eq!(row.get_double(4).unwrap(), 20.0);
+        assert_eq!(row.get_ubyte(5).unwrap(), 0);
+
+        pq2fq(
+            &initial_parquet,
+            &second_fastq,
+            &ColumnMap::new(),
+            None,
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("pq2fq failed");
+        let round_tripped = std::fs::read_to_string(&second_fastq).expect("error");
+        assert_eq!(round_tripped, "@r0\nAGCT\n+\n!+?I\n");
+    }
+
+    #[test]
+    fn parquet_paired_fastq_test() {
+        let temp_dir = env::temp_dir();
+        let initial_parquet = temp_dir.join("initial_paired.parquet");
+        let r1_fastq = temp_dir.join("paired_r1.fastq");
+        let r2_fastq = temp_dir.join("paired_r2.fastq");
+
+        let r1 = b"@a/1\nAC\n+\nI5\n@b/1\nGT\n+\nII\n" as &[u8];
+        let r2 = b"@a/2\nTT\n+\n5I\n@b/2\nGG\n+\nII\n" as &[u8];
+        let quality_options = QualityOptions {
+            phred_scores: true,
+            ..Default::default()
+        };
+
+        paired_fq2pq(
+            paired::Reader::new(r1, r2),
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            &quality_options,
+            &mut Rejects::default(),
+        )
+        .expect("paired_fq2pq failed");
+
+        let reader = SerializedFileReader::new(File::open(&initial_parquet).unwrap()).unwrap();
+        let columns: Vec<String> = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .root_schema()
+            .get_fields()
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                "id",
+                "r1_sequence",
+                "r1_quality",
+                "r2_sequence",
+                "r2_quality"
+            ]
+        );
+
+        paired_pq2fq(
+            &initial_parquet,
+            &r1_fastq,
+            &r2_fastq,
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("paired_pq2fq failed");
+        assert_eq!(
+            std::fs::read_to_string(&r1_fastq).unwrap(),
+            "@a\nAC\n+\nI5\n@b\nGT\n+\nII\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&r2_fastq).unwrap(),
+            "@a\nTT\n+\n5I\n@b\nGG\n+\nII\n"
+        );
+
+        let options = ParquetReadOptions {
+            batch_size: 1,
+            filter: RecordFilter {
+                predicate: Some("r2_sequence == 'GG'".parse().unwrap()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        paired_pq2fq(
+            &initial_parquet,
+            &r1_fastq,
+            &r2_fastq,
+            &options,
+            &mut Rejects::default(),
+        )
+        .expect("paired_pq2fq failed");
+        assert_eq!(
+            std::fs::read_to_string(&r1_fastq).unwrap(),
+            "@b\nGT\n+\nII\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&r2_fastq).unwrap(),
+            "@b\nGG\n+\nII\n"
+        );
+
+        let r2 = b"@a/2\nTT\n+\n5I\n" as &[u8];
+        let result = paired_fq2pq(
+            paired::Reader::new(r1, r2),
+            &initial_parquet,
+            &Compression::UNCOMPRESSED.into(),
+            &quality_options,
+            &mut Rejects::default(),
+        );
+        assert!(matches!(result, Err(BrrrrError::MissingMate(name)) if name == "b/1"));
+    }
+
+    #[test]
+    fn parquet_paired_fastq_gzip_test() {
+        let temp_dir = env::temp_dir();
+        let r1_gz = temp_dir.join("paired_gzip_r1.fastq.gz");
+        let r2_gz = temp_dir.join("paired_gzip_r2.fastq.gz");
+        let interleaved_gz = temp_dir.join("paired_gzip_interleaved.fastq.gz");
+        let initial_parquet = temp_dir.join("paired_gzip.parquet");
+        let r1_fastq = temp_dir.join("paired_gzip_r1.fastq");
+        let r2_fastq = temp_dir.join("paired_gzip_r2.fastq");
+
+        let write_gzip = |path: &Path, data: &[u8]| {
+            let mut gz = flate2::write::GzEncoder::new(
+                File::create(path).unwrap(),
+                flate2::Compression::default(),
+            );
+            gz.write_all(data).unwrap();
+            gz.finish().unwrap();
+        };
+        write_gzip(&r1_gz, b"@a/1\nAC\n+\nI5\n");
+        write_gzip(&r2_gz, b"@a/2\nTT\n+\n5I\n");
+        write_gzip(&interleaved_gz, b"@a/1\nAC\n+\nI5\n@a/2\nTT\n+\n5I\n");
+
+        for reader in [
+            paired::Reader::open(&r1_gz, &r2_gz, None).unwrap(),
+            paired::Reader::open_interleaved(&interleaved_gz, None).unwrap(),
+        ] {
+            paired_fq2pq(
+                reader,
+                &initial_parquet,
+                &Compression::UNCOMPRESSED.into(),
+                &QualityOptions::default(),
+                &mut Rejects::default(),
+            )
+            .expect("paired_fq2pq failed");
+
+            paired_pq2fq(
+                &initial_parquet,
+                &r1_fastq,
+                &r2_fastq,
+                &ParquetReadOptions::default(),
+                &mut Rejects::default(),
+            )
+            .expect("paired_pq2fq failed");
+            assert_eq!(
+                std::fs::read_to_string(&r1_fastq).unwrap(),
+                "@a\nAC\n+\nI5\n"
+            );
+            assert_eq!(
+                std::fs::read_to_string(&r2_fastq).unwrap(),
+                "@a\nTT\n+\n5I\n"
+            );
+        }
+    }
+
+    #[test]
+    fn parquet_memory_source_test() {
+        let temp_dir = env::temp_dir();
+        let fasta_path = temp_dir.join("memory_source.fasta");
+        let parquet_path = temp_dir.join("memory_source.parquet");
+
+        File::create(&fasta_path)
+            .and_then(|mut f| f.write_all(b">a\nATCG\n>b\nGG\n"))
+            .unwrap();
+        fa2pq(
+            &fasta_path,
+            &parquet_path,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let source =
+            ParquetSource::Memory(SliceableCursor::new(std::fs::read(&parquet_path).unwrap()));
+
+        // The path is only reopened for a file, so stdin's is never read.
+        let ids = read_records(Path::new("-"), &source, &Header::Fasta)
+            .unwrap()
+            .map(|record| match record.unwrap() {
+                Record::Fasta(record) => record.id,
+                record => panic!("expected a FASTA record, found {:?}", record),
+            })
+            .collect_vec();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
     #[test]
     fn parquet_fasta_base_test() {
         let temp_dir = env::temp_dir();
@@ -341,13 +2581,22 @@ mod tests {
         fa2pq(
             &initital_fasta,
             &initial_parquet,
-            Compression::UNCOMPRESSED,
-            BioFileCompression::UNCOMPRESSED,
+            &Compression::UNCOMPRESSED.into(),
+            Some(BioFileCompression::UNCOMPRESSED),
+            &mut Rejects::default(),
         )
         .expect("fa2pq failed");
 
         assert!(&initial_parquet.exists());
-        pq2fa(&initial_parquet, &second_fasta).expect("fa2pq failed");
+        pq2fa(
+            &initial_parquet,
+            &second_fasta,
+            &ColumnMap::new(),
+            None,
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        )
+        .expect("fa2pq failed");
 
         let mut reader =
             fasta::Reader::new(BufReader::new(File::open(&second_fasta).expect("error")));
@@ -355,7 +2604,7 @@ mod tests {
         let recs = reader.records().collect_vec();
         assert_eq!(recs.len(), 1);
 
-        let actual_record = recs.get(0);
+        let actual_record = recs.first();
         if let Some(ar) = actual_record {
             assert!(ar.is_ok());
 
@@ -365,4 +2614,253 @@ mod tests {
             };
         }
     }
+
+    #[test]
+    fn parquet_fasta_column_map_test() {
+        #[derive(serde::Serialize)]
+        struct Row {
+            accession: Option<String>,
+            organism: String,
+            length: i64,
+            seq: String,
+        }
+
+        let temp_dir = env::temp_dir();
+        let input = temp_dir.join("column_map.parquet");
+        let output = temp_dir.join("column_map.fasta");
+
+        let mut writer = crate::parquet_writer::ParquetRecordWriter::new(
+            File::create(&input).unwrap(),
+            parquet::file::properties::WriterProperties::builder().build(),
+        );
+        for (accession, seq) in [(Some("a"), "ATCG"), (None, "GG"), (Some("c"), "T")] {
+            writer
+                .write_serde_record(Row {
+                    accession: accession.map(String::from),
+                    organism: String::from("E. coli"),
+                    length: seq.len() as i64,
+                    seq: seq.to_string(),
+                })
+                .unwrap();
+        }
+        writer.close().unwrap();
+
+        let columns = ColumnMap::new()
+            .with_column("id", "accession")
+            .with_column("sequence", "seq");
+        let template: Template = "{organism} len={length}".parse().unwrap();
+
+        let mut rejects = Rejects::new(ErrorPolicy::Skip);
+        pq2fa(
+            &input,
+            &output,
+            &columns,
+            Some(&template),
+            &ParquetReadOptions::default(),
+            &mut rejects,
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            ">a E. coli len=4\nATCG\n>c E. coli len=1\nT\n"
+        );
+        assert_eq!(rejects.total(), 1);
+
+        let result = pq2fa(
+            &input,
+            &output,
+            &ColumnMap::new(),
+            None,
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        );
+        assert!(matches!(result, Err(BrrrrError::MissingColumn(column)) if column == "id"));
+
+        let template: Template = "{strain}".parse().unwrap();
+        let result = pq2fa(
+            &input,
+            &output,
+            &columns,
+            Some(&template),
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        );
+        assert!(matches!(result, Err(BrrrrError::MissingColumn(column)) if column == "strain"));
+
+        let missing = temp_dir.join("column_map_missing.parquet");
+        let result = pq2fa(
+            &missing,
+            &output,
+            &columns,
+            None,
+            &ParquetReadOptions::default(),
+            &mut Rejects::default(),
+        );
+        assert!(matches!(result, Err(BrrrrError::IOError(e)) if e.kind() == ErrorKind::NotFound));
+    }
+    /// Writes seven FASTA records with IDs `r0` to `r6` to `path` in row groups of two, and
+    /// returns them.
+    fn write_row_groups(path: &Path) -> Vec<FastaRecord> {
+        let props = parquet::file::properties::WriterProperties::builder()
+            .set_max_row_group_size(2)
+            .build();
+        let mut writer =
+            crate::parquet_writer::ParquetRecordWriter::new(File::create(path).unwrap(), props);
+
+        let records: Vec<FastaRecord> = (0..7)
+            .map(|i| FastaRecord {
+                id: format!("r{}", i),
+                description: (i != 5).then(|| String::from("d")),
+                sequence: "ACGT"[..i % 4 + 1].to_string(),
+            })
+            .collect();
+        for record in &records {
+            writer.write_serde_record(record).unwrap();
+        }
+        writer.close().unwrap();
+
+        records
+    }
+
+    #[test]
+    fn parquet_fasta_row_groups_test() {
+        let temp_dir = env::temp_dir();
+        let input = temp_dir.join("row_groups.parquet");
+        let output = temp_dir.join("row_groups.fasta");
+
+        let expected: String = write_row_groups(&input).iter().map(fasta_string).collect();
+
+        let reader = SerializedFileReader::new(File::open(&input).unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 4);
+
+        for threads in [1, 3] {
+            let options = ParquetReadOptions {
+                batch_size: 1,
+                threads,
+                ..Default::default()
+            };
+            pq2fa(
+                &input,
+                &output,
+                &ColumnMap::new(),
+                None,
+                &options,
+                &mut Rejects::default(),
+            )
+            .unwrap();
+            assert_eq!(std::fs::read_to_string(&output).unwrap(), expected);
+        }
+
+        // The null sequence is in the third row group, and is reported at its row in the file.
+        let columns = ColumnMap::new().with_column("sequence", "description");
+        let options = ParquetReadOptions {
+            threads: 2,
+            ..Default::default()
+        };
+        let result = pq2fa(
+            &input,
+            &output,
+            &columns,
+            None,
+            &options,
+            &mut Rejects::default(),
+        );
+        assert!(
+            matches!(result, Err(BrrrrError::ParseError { location, .. }) if location.index == 6)
+        );
+    }
+
+    #[test]
+    fn parquet_fasta_filter_test() {
+        let temp_dir = env::temp_dir();
+        let input = temp_dir.join("filter.parquet");
+        let output = temp_dir.join("filter.fasta");
+        let records = write_row_groups(&input);
+
+        let filters = [
+            (
+                RecordFilter {
+                    ids: Some(filter::read_ids(b"r1\nr4\nr9\n" as &[u8]).unwrap()),
+                    ..Default::default()
+                },
+                vec![1, 4],
+            ),
+            (
+                RecordFilter {
+                    min_length: Some(3),
+                    max_length: Some(3),
+                    ..Default::default()
+                },
+                vec![2, 6],
+            ),
+            (
+                RecordFilter {
+                    predicate: Some("id >= 'r3' and description == d".parse().unwrap()),
+                    ..Default::default()
+                },
+                vec![3, 4, 6],
+            ),
+        ];
+
+        for (filter, kept) in filters {
+            let options = ParquetReadOptions {
+                threads: 2,
+                filter,
+                ..Default::default()
+            };
+            pq2fa(
+                &input,
+                &output,
+                &ColumnMap::new(),
+                None,
+                &options,
+                &mut Rejects::default(),
+            )
+            .unwrap();
+
+            let expected: String = kept.iter().map(|&i| fasta_string(&records[i])).collect();
+            assert_eq!(std::fs::read_to_string(&output).unwrap(), expected);
+        }
+
+        // Only the second and third row groups can have IDs from r2 to r4.
+        let reader = SerializedFileReader::new(File::open(&input).unwrap()).unwrap();
+        let filter = RecordFilter {
+            ids: Some(["r2", "r4"].iter().map(|id| id.to_string()).collect()),
+            predicate: Some("id <= r3".parse().unwrap()),
+            ..Default::default()
+        };
+        let columns = ColumnMap::new();
+        let matches: Vec<bool> = reader
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|row_group| row_group_may_match(row_group, &filter, &sequence_selection(&columns)))
+            .collect();
+        assert_eq!(matches, vec![false, true, false, false]);
+
+        let options = ParquetReadOptions {
+            filter: RecordFilter {
+                predicate: Some("organism == 'E. coli'".parse().unwrap()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = pq2fa(
+            &input,
+            &output,
+            &ColumnMap::new(),
+            None,
+            &options,
+            &mut Rejects::default(),
+        );
+        assert!(matches!(result, Err(BrrrrError::MissingColumn(column)) if column == "organism"));
+    }
+
+    fn fasta_string(record: &FastaRecord) -> String {
+        match &record.description {
+            Some(description) => format!(">{} {}\n{}\n", record.id, description, record.sequence),
+            None => format!(">{}\n{}\n", record.id, record.sequence),
+        }
+    }
 }
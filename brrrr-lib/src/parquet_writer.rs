@@ -1,57 +1,365 @@
 // (c) Copyright 2020 Trent Hauck
 // All Rights Reserved
 
-use std::fs;
+use std::collections::BTreeMap;
+use std::io;
 use std::io::BufRead;
-use std::io::BufReader;
+use std::io::Write;
 use std::path::Path;
 use std::result::Result;
-use std::sync::Arc;
 
-use flate2::bufread::GzDecoder;
-use itertools::Itertools;
-use noodles::fasta;
-use noodles::fastq;
-use noodles::gff;
+use noodles::bam;
+use noodles::sam;
 
-use arrow::array::*;
 use arrow::datatypes::*;
-use arrow::record_batch::RecordBatch;
 use parquet::arrow::arrow_writer::ArrowWriter;
-use parquet::basic::Compression;
-use parquet::file::properties::WriterProperties;
+use parquet::basic::{Compression, Encoding};
+use parquet::errors::ParquetError;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::{WriterProperties, WriterPropertiesBuilder};
+use parquet::schema::types::ColumnPath;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
 
+use crate::arrow_writer::{self, RecordBatchBuffer};
+use crate::bcf;
+use crate::bed;
+use crate::cram;
 use crate::errors::BrrrrError;
-use crate::types::{FastaRecord, FastqRecord, GffRecord};
+use crate::gff;
+use crate::input;
+use crate::output;
+use crate::paired;
+use crate::quality::QualityOptions;
+use crate::reader::{FastaReader, FastqReader, GffReader, RecordReader};
+use crate::rejects::Rejects;
+use crate::types::{
+    FastaRecord, FastqRecord, GffRecord, Operation, SamRecord, SamValue, VcfRecord, VcfValue,
+};
+use crate::vcf;
+use crate::writer::RecordWriter;
 
-#[derive(Debug, Copy, Clone)]
-pub enum BioFileCompression {
-    UNCOMPRESSED,
-    GZIP,
+pub use crate::input::BioFileCompression;
+
+/// The default maximum number of records per row group.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 1024 * 1024;
+
+/// The default target size of a data page, in bytes.
+pub const DEFAULT_DATA_PAGE_SIZE: usize = 1024 * 1024;
+
+/// ParquetOptions sets how the writers lay out, encode and compress their parquet files.
+///
+/// Columns are named by their path in the parquet schema, with nested parts joined by `.`.
+/// parquet 15, which brrrr writes with, always compresses zstd at level 1 and doesn't write
+/// bloom filters, so neither can be set yet.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::parquet_writer::ParquetOptions;
+/// use parquet::basic::{Compression, Encoding};
+///
+/// let mut options = ParquetOptions::from(Compression::ZSTD);
+/// options.row_group_size = 10_000;
+/// options.column_dictionary.insert(String::from("id"), false);
+/// options
+///     .column_encodings
+///     .insert(String::from("id"), Encoding::DELTA_BYTE_ARRAY);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParquetOptions {
+    /// The compression codec.
+    pub compression: Compression,
+    /// The maximum number of records per row group. The records are also built into record
+    /// batches of at most this many, so smaller row groups use less memory while writing.
+    pub row_group_size: usize,
+    /// The target size of a data page, in bytes.
+    pub data_page_size: usize,
+    /// Whether the columns are dictionary encoded, unless set in `column_dictionary`.
+    pub dictionary: bool,
+    /// Whether a column is dictionary encoded, by column.
+    pub column_dictionary: BTreeMap<String, bool>,
+    /// The encoding of a column, by column. A dictionary encoded column only falls back to it
+    /// once its dictionary is full, so turn off the dictionary of a column to always use it,
+    /// e.g. `DELTA_BYTE_ARRAY` for IDs.
+    pub column_encodings: BTreeMap<String, Encoding>,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::UNCOMPRESSED,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            data_page_size: DEFAULT_DATA_PAGE_SIZE,
+            dictionary: true,
+            column_dictionary: BTreeMap::new(),
+            column_encodings: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<Compression> for ParquetOptions {
+    fn from(compression: Compression) -> Self {
+        Self {
+            compression,
+            ..Default::default()
+        }
+    }
 }
 
-/// Converts a GFF file to Parquet.
+impl ParquetOptions {
+    /// The number of records built into each record batch.
+    fn batch_size(&self) -> usize {
+        self.row_group_size.clamp(1, DEFAULT_ROW_GROUP_SIZE)
+    }
+
+    /// Returns the writer properties for a file with `schema`, with statistics enabled. Returns
+    /// an error if a column that's set isn't in `schema`, or if a column encoding is a dictionary
+    /// encoding.
+    pub fn writer_properties(
+        &self,
+        schema: &Schema,
+    ) -> Result<WriterPropertiesBuilder, BrrrrError> {
+        if self.row_group_size == 0 || self.data_page_size == 0 {
+            return Err(ParquetError::General(String::from(
+                "the row group and data page sizes must be greater than 0",
+            ))
+            .into());
+        }
+
+        let mut props = WriterProperties::builder()
+            .set_compression(self.compression)
+            .set_statistics_enabled(true)
+            .set_max_row_group_size(self.row_group_size)
+            .set_data_pagesize_limit(self.data_page_size)
+            .set_dictionary_enabled(self.dictionary);
+
+        for (column, enabled) in &self.column_dictionary {
+            props = props.set_column_dictionary_enabled(column_path(schema, column)?, *enabled);
+        }
+
+        for (column, encoding) in &self.column_encodings {
+            if matches!(
+                encoding,
+                Encoding::PLAIN_DICTIONARY | Encoding::RLE_DICTIONARY
+            ) {
+                return Err(ParquetError::General(format!(
+                    "{} can't be set as the encoding of {}, set its dictionary instead",
+                    encoding, column
+                ))
+                .into());
+            }
+
+            props = props.set_column_encoding(column_path(schema, column)?, *encoding);
+        }
+
+        Ok(props)
+    }
+}
+
+/// Returns the path of `column`, whose first part must be a field of `schema`.
+fn column_path(schema: &Schema, column: &str) -> Result<ColumnPath, BrrrrError> {
+    let parts: Vec<String> = column.split('.').map(String::from).collect();
+
+    if schema.field_with_name(&parts[0]).is_err() {
+        return Err(BrrrrError::MissingColumn(column.to_string()));
+    }
+
+    Ok(ColumnPath::new(parts))
+}
+
+/// Parses the name of a parquet encoding, e.g. `DELTA_BYTE_ARRAY`, ignoring case. Only the
+/// encodings that parquet 15 can write are accepted.
+pub fn parse_encoding(s: &str) -> io::Result<Encoding> {
+    match s.to_ascii_uppercase().as_str() {
+        "PLAIN" => Ok(Encoding::PLAIN),
+        "RLE" => Ok(Encoding::RLE),
+        "DELTA_BINARY_PACKED" => Ok(Encoding::DELTA_BINARY_PACKED),
+        "DELTA_LENGTH_BYTE_ARRAY" => Ok(Encoding::DELTA_LENGTH_BYTE_ARRAY),
+        "DELTA_BYTE_ARRAY" => Ok(Encoding::DELTA_BYTE_ARRAY),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown encoding: {}", s),
+        )),
+    }
+}
+
+/// ParquetRecordWriter writes records to parquet, buffering them into record batches with a
+/// `RecordBatchBuffer`. The schema is inferred from the records unless it's set with
+/// `with_schema`.
+pub struct ParquetRecordWriter<W: Write> {
+    inner: Option<W>,
+    props: Option<WriterProperties>,
+    writer: Option<ArrowWriter<W>>,
+    batches: RecordBatchBuffer,
+}
+
+impl<W: Write> ParquetRecordWriter<W> {
+    /// Creates a new ParquetRecordWriter with a writer and the parquet writer properties.
+    pub fn new(w: W, props: WriterProperties) -> Self {
+        Self {
+            inner: Some(w),
+            props: Some(props),
+            writer: None,
+            batches: RecordBatchBuffer::new(arrow_writer::DEFAULT_BATCH_SIZE),
+        }
+    }
+
+    /// Sets the schema of the file rather than inferring it from the records.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.batches = self.batches.with_schema(schema);
+        self
+    }
+
+    /// Sets the number of records buffered before they are written as a record batch.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batches = self.batches.with_batch_size(batch_size);
+        self
+    }
+
+    fn writer(&mut self) -> Result<&mut ArrowWriter<W>, BrrrrError> {
+        if let Some(inner) = self.inner.take() {
+            let schema = self.batches.schema();
+            self.writer = Some(ArrowWriter::try_new(inner, schema, self.props.take())?);
+        }
+
+        match self.writer.as_mut() {
+            Some(writer) => Ok(writer),
+            None => Err(ParquetError::General(String::from("the writer is closed")).into()),
+        }
+    }
+
+    fn write_batch(&mut self) -> Result<(), BrrrrError> {
+        if let Some(batch) = self.batches.flush()? {
+            self.writer()?.write(&batch)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffered records and the file footer.
+    pub fn close(mut self) -> Result<(), BrrrrError> {
+        self.write_batch()?;
+        self.writer()?;
+
+        if let Some(writer) = self.writer {
+            writer.close()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for ParquetRecordWriter<W> {
+    /// Buffers the record, writing a record batch once a batch is full.
+    fn write_serde_record<S: Serialize>(&mut self, r: S) -> io::Result<()> {
+        self.batches.push(r)?;
+
+        if self.batches.is_full() {
+            self.write_batch().map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The Parquet key-value metadata key that holds the GFF dialect, i.e. `gff3` or `gtf`.
+pub const GFF_DIALECT_METADATA_KEY: &str = "gff_dialect";
+
+/// The Parquet key-value metadata key that holds the GFF directives and comments.
+pub const GFF_HEADER_METADATA_KEY: &str = "gff_header";
+
+/// Converts a GFF3 or GTF file to Parquet. The dialect and the directives and comments before
+/// the first record are stored in the file metadata, and later ones in the `directives` column
+/// of the record they follow, so that pq2gff writes them back.
 ///
 /// # Arguments
 /// * `input` The path to the input GFF file.
 /// * `output` The path to the output parquet file.
-/// * `parquet_compression` The parquet compression to use.
+/// * `options` How the parquet file is written.
+/// * `dialect` The GFF dialect, or None to detect it from the input.
+/// * `fasta_output` The path to write the sequences after a `##FASTA` directive to, as parquet
+///   with the same columns as fa2pq. Without it, the sequences are skipped.
+/// * `bio_file_compression` The compression of the input, or None to detect it.
+/// * `rejects` What to do with records that fail to parse.
 pub fn gff2pq<P: AsRef<Path>>(
     input: P,
     output: P,
-    parquet_compression: Compression,
+    options: &ParquetOptions,
+    dialect: Option<gff::Dialect>,
+    fasta_output: Option<P>,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
 ) -> Result<(), BrrrrError> {
-    let props = WriterProperties::builder()
-        .set_compression(parquet_compression)
-        .set_statistics_enabled(true);
+    let mut reader = GffReader::new(input::open(input, bio_file_compression)?, dialect)?;
+    let header = reader.header().clone();
+
+    write_gff_records_to_file(
+        &header,
+        rejects.filter("GFF", reader.by_ref()),
+        output,
+        options,
+    )?;
+
+    if let Some(fasta_output) = fasta_output {
+        match reader.fasta_reader() {
+            Some(fasta_reader) => write_fasta_records_to_file(
+                rejects.filter("FASTA", fasta_reader),
+                fasta_output,
+                options,
+            )?,
+            None => write_fasta_records_to_file(std::iter::empty(), fasta_output, options)?,
+        }
+    }
+
+    Ok(())
+}
 
-    let file_schema = Schema::new(vec![
+/// Writes GFF records to a parquet file, storing the dialect and directives of `header` in the
+/// file metadata.
+pub(crate) fn write_gff_records_to_file<P, I>(
+    header: &gff::Header,
+    records: I,
+    output: P,
+    options: &ParquetOptions,
+) -> Result<(), BrrrrError>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Result<GffRecord, BrrrrError>>,
+{
+    let metadata = gff_metadata(header)
+        .into_iter()
+        .map(|(key, value)| KeyValue::new(key, value))
+        .collect();
+
+    let writer = create_record_writer(output, gff_schema(), options, metadata)?;
+    write_records(records, writer)
+}
+
+/// Returns the file metadata that holds the dialect and directives of `header`.
+pub(crate) fn gff_metadata(header: &gff::Header) -> Vec<(String, String)> {
+    let mut metadata = vec![(
+        GFF_DIALECT_METADATA_KEY.to_string(),
+        header.dialect.to_string(),
+    )];
+
+    if !header.lines.is_empty() {
+        metadata.push((GFF_HEADER_METADATA_KEY.to_string(), header.lines.join("\n")));
+    }
+
+    metadata
+}
+
+/// Returns the Arrow schema for GFF records, which gff2pq and gff2arrow write and pq2gff and
+/// arrow2gff read.
+pub fn gff_schema() -> Schema {
+    Schema::new(vec![
         Field::new("seqname", DataType::Utf8, false),
         Field::new("source", DataType::Utf8, true),
         Field::new("feature", DataType::Utf8, false),
         Field::new("start", DataType::Int64, false),
         Field::new("end", DataType::Int64, false),
-        Field::new("score", DataType::Int64, true),
+        Field::new("score", DataType::Float64, true),
         Field::new("strand", DataType::Utf8, false),
         Field::new("frame", DataType::Utf8, true),
         Field::new(
@@ -61,7 +369,11 @@ pub fn gff2pq<P: AsRef<Path>>(
                     "entries",
                     DataType::Struct(vec![
                         Field::new("keys", DataType::Utf8, false),
-                        Field::new("values", DataType::Utf8, true),
+                        Field::new(
+                            "values",
+                            DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+                            true,
+                        ),
                     ]),
                     false,
                 )),
@@ -69,278 +381,1415 @@ pub fn gff2pq<P: AsRef<Path>>(
             ),
             false,
         ),
-    ]);
-
-    let input_file = fs::File::open(input)?;
-    let mut reader = gff::Reader::new(BufReader::new(input_file));
-
-    let records = reader.records();
-
-    let file = fs::File::create(output)?;
-    let mut writer =
-        ArrowWriter::try_new(file, Arc::new(file_schema.clone()), Some(props.build()))?;
-    let chunk_size = 2usize.pow(20);
-
-    for chunk in records.into_iter().chunks(chunk_size).into_iter() {
-        let mut seqname_builder = StringBuilder::new(2048);
-        let mut source_builder = StringBuilder::new(2048);
-        let mut feature_builder = StringBuilder::new(2048);
-        let mut start_builder = Int64Builder::new(2048);
-        let mut end_builder = Int64Builder::new(2048);
-        let mut score_builder = Int64Builder::new(2048);
-        let mut strand_builder = StringBuilder::new(2048);
-        let mut frame_builder = StringBuilder::new(2048);
-
-        let key_builder = StringBuilder::new(2048);
-        let value_builder = StringBuilder::new(2048);
-        let mut attribute_builder = MapBuilder::new(None, key_builder, value_builder);
-
-        for chunk_i in chunk {
-            let record = chunk_i?;
-
-            let gff_type = GffRecord::from(record);
-
-            seqname_builder.append_value(gff_type.seqname)?;
-            source_builder.append_value(gff_type.source)?;
-            feature_builder.append_value(gff_type.feature)?;
-            start_builder.append_value(gff_type.start as i64)?;
-            end_builder.append_value(gff_type.end as i64)?;
-
-            match gff_type.score {
-                Some(score) => score_builder.append_value(score as i64)?,
-                None => score_builder.append_null()?,
-            }
+        Field::new(
+            "unquoted_attributes",
+            DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+            true,
+        ),
+        Field::new(
+            "directives",
+            DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+            true,
+        ),
+    ])
+}
 
-            strand_builder.append_value(gff_type.strand)?;
+/// Writes FASTA records to a parquet file with the id, description and sequence columns.
+pub(crate) fn write_fasta_records_to_file<P, I>(
+    records: I,
+    output: P,
+    options: &ParquetOptions,
+) -> Result<(), BrrrrError>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Result<FastaRecord, BrrrrError>>,
+{
+    let writer = create_record_writer(output, fasta_schema(), options, Vec::new())?;
+    write_records(records, writer)
+}
 
-            match gff_type.frame {
-                Some(frame) => frame_builder.append_value(frame)?,
-                None => frame_builder.append_null()?,
-            }
+/// Creates a ParquetRecordWriter for `output` with `schema`, the writer properties and batch
+/// size of `options`, and `metadata` as the file's key-value metadata.
+fn create_record_writer<P: AsRef<Path>>(
+    output: P,
+    schema: Schema,
+    options: &ParquetOptions,
+    metadata: Vec<KeyValue>,
+) -> Result<ParquetRecordWriter<Box<dyn Write>>, BrrrrError> {
+    let mut props = options.writer_properties(&schema)?;
 
-            let record_key_builder = attribute_builder.keys();
-            for k in gff_type.attribute.keys() {
-                record_key_builder.append_value(k)?;
-            }
+    if !metadata.is_empty() {
+        props = props.set_key_value_metadata(Some(metadata));
+    }
 
-            let record_value_builder = attribute_builder.values();
-            for v in gff_type.attribute.values() {
-                record_value_builder.append_value(v)?;
-            }
+    let file = output::create_raw(output)?;
+    Ok(ParquetRecordWriter::new(file, props.build())
+        .with_schema(schema)
+        .with_batch_size(options.batch_size()))
+}
 
-            attribute_builder.append(true)?;
-        }
+/// Writes every record with `writer` and closes it.
+fn write_records<S, I, W>(records: I, mut writer: ParquetRecordWriter<W>) -> Result<(), BrrrrError>
+where
+    S: Serialize,
+    I: Iterator<Item = Result<S, BrrrrError>>,
+    W: Write,
+{
+    for record in records {
+        writer.write_serde_record(record?)?;
+    }
 
-        let seqname_array = seqname_builder.finish();
-        let source_array = source_builder.finish();
-        let feature_array = feature_builder.finish();
-        let start_array = start_builder.finish();
-        let end_array = end_builder.finish();
-        let score_array = score_builder.finish();
-        let strand_array = strand_builder.finish();
-        let frame_array = frame_builder.finish();
-        let attribute_array = attribute_builder.finish();
-
-        let rb = RecordBatch::try_new(
-            Arc::new(file_schema.clone()),
-            vec![
-                Arc::new(seqname_array),
-                Arc::new(source_array),
-                Arc::new(feature_array),
-                Arc::new(start_array),
-                Arc::new(end_array),
-                Arc::new(score_array),
-                Arc::new(strand_array),
-                Arc::new(frame_array),
-                Arc::new(attribute_array),
-            ],
-        )?;
+    writer.close()
+}
+
+/// Returns the Arrow schema for FASTA records.
+pub fn fasta_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("description", DataType::Utf8, true),
+        Field::new("sequence", DataType::Utf8, false),
+    ])
+}
+
+/// Converts a FASTA file to Parquet.
+///
+/// # Arguments
+/// * `input` The the path to the input fasta file.
+/// * `output` The the path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `bio_file_compression` The compression for the input bio file, or None to detect it.
+/// * `rejects` What to do with records that fail to parse.
+pub fn fa2pq<P: AsRef<Path>>(
+    input: &P,
+    output: &P,
+    options: &ParquetOptions,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reader = FastaReader::new(input::open(input, bio_file_compression)?);
+    write_fasta_records_to_file(rejects.filter("FASTA", reader), output, options)
+}
+
+/// Returns the sequence, quality and quality summary fields of FASTQ reads, with each name
+/// starting with `prefix`.
+fn fastq_read_fields(prefix: &str, quality_options: &QualityOptions) -> Vec<Field> {
+    let quality_type = if quality_options.phred_scores {
+        DataType::List(Box::new(Field::new("item", DataType::UInt8, true)))
+    } else {
+        DataType::Utf8
+    };
 
-        writer.write(&rb)?;
+    let mut fields = vec![
+        Field::new(&format!("{}sequence", prefix), DataType::Utf8, false),
+        Field::new(&format!("{}quality", prefix), quality_type, false),
+    ];
+
+    if quality_options.summary {
+        fields.push(Field::new(
+            &format!("{}mean_quality", prefix),
+            DataType::Float64,
+            true,
+        ));
+        fields.push(Field::new(
+            &format!("{}min_quality", prefix),
+            DataType::UInt8,
+            true,
+        ));
+        fields.push(Field::new(
+            &format!("{}expected_errors", prefix),
+            DataType::Float64,
+            false,
+        ));
     }
 
-    writer.close()?;
+    fields
+}
 
-    Ok(())
+/// Returns the Arrow schema for FASTQ records written with `quality_options`.
+pub fn fastq_schema(quality_options: &QualityOptions) -> Schema {
+    let mut fields = vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("description", DataType::Utf8, true),
+    ];
+    let mut read_fields = fastq_read_fields("", quality_options);
+
+    // The sequence column has always come before the description.
+    fields.insert(1, read_fields.remove(0));
+    fields.extend(read_fields);
+
+    Schema::new(fields)
 }
 
-fn write_records_to_file<P: AsRef<Path>, R: BufRead>(
-    mut reader: fasta::Reader<R>,
+/// Returns the Arrow schema for paired-end FASTQ fragments written with `quality_options`, with
+/// the R1 and R2 columns prefixed by `r1_` and `r2_`.
+pub fn paired_fastq_schema(quality_options: &QualityOptions) -> Schema {
+    let mut fields = vec![Field::new("id", DataType::Utf8, false)];
+    fields.extend(fastq_read_fields("r1_", quality_options));
+    fields.extend(fastq_read_fields("r2_", quality_options));
+
+    Schema::new(fields)
+}
+
+/// Converts a FASTQ file to Parquet.
+///
+/// # Arguments
+/// * `input` The path to the input FASTQ file.
+/// * `output` The path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `quality_options` How the quality scores are written.
+/// * `bio_file_compression` The compression of the input, or None to detect it.
+/// * `rejects` What to do with records that fail to parse.
+pub fn fq2pq<P: AsRef<Path>>(
+    input: P,
     output: P,
-    parquet_compression: Compression,
+    options: &ParquetOptions,
+    quality_options: &QualityOptions,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
 ) -> Result<(), BrrrrError> {
-    let file_schema = Schema::new(vec![
-        Field::new("id", DataType::Utf8, false),
-        Field::new("description", DataType::Utf8, true),
-        Field::new("sequence", DataType::Utf8, false),
-    ]);
-
-    let props = WriterProperties::builder()
-        .set_compression(parquet_compression)
-        .set_statistics_enabled(true);
-
-    let file = fs::File::create(output)?;
-    let mut writer =
-        ArrowWriter::try_new(file, Arc::new(file_schema.clone()), Some(props.build()))?;
-
-    let chunk_size = 2usize.pow(20);
-    for chunk in reader.records().into_iter().chunks(chunk_size).into_iter() {
-        let mut id_builder = Vec::with_capacity(chunk_size);
-        let mut description_builder = StringBuilder::new(2048);
-        let mut seq_builder = Vec::with_capacity(chunk_size);
-
-        for chunk_i in chunk {
-            let record = match chunk_i {
-                Ok(r) => FastaRecord::from(r),
-                Err(error) => panic!("{}", error),
-            };
+    let reader = FastqReader::new(input::open(input, bio_file_compression)?, quality_options);
+    write_fastq_records_to_file(
+        rejects.filter("FASTQ", reader),
+        output,
+        options,
+        quality_options,
+    )
+}
+
+/// Writes FASTQ records to a parquet file with the columns of `fastq_schema`. The quality of
+/// each record must match `quality_options`, i.e. be Phred scores if `phred_scores` is set.
+pub(crate) fn write_fastq_records_to_file<P, I>(
+    records: I,
+    output: P,
+    options: &ParquetOptions,
+    quality_options: &QualityOptions,
+) -> Result<(), BrrrrError>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Result<FastqRecord, BrrrrError>>,
+{
+    let schema = fastq_schema(quality_options);
+    let writer = create_record_writer(output, schema, options, Vec::new())?;
+    write_records(records, writer)
+}
+
+/// Converts paired-end FASTQ to Parquet, with one row per fragment. The `id` column holds the
+/// fragment name, i.e. the read name without any `/1` or `/2` suffix or description, and the
+/// reads are in `r1_` and `r2_` columns.
+///
+/// # Arguments
+/// * `reader` The paired-end reader over the R1 and R2 or interleaved input.
+/// * `output` The path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `quality_options` How the quality scores are written.
+/// * `rejects` What to do with records that fail to parse.
+pub fn paired_fq2pq<R: BufRead, P: AsRef<Path>>(
+    mut reader: paired::Reader<R>,
+    output: P,
+    options: &ParquetOptions,
+    quality_options: &QualityOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let schema = paired_fastq_schema(quality_options);
+    let mut writer = create_record_writer(output, schema, options, Vec::new())?;
 
-            id_builder.push(record.id);
-            match record.description {
-                Some(x) => description_builder
-                    .append_value(x)
-                    .expect("Couldn't append description."),
-                _ => description_builder
-                    .append_null()
-                    .expect("Couldn't append null description."),
+    let fragments = reader.records().map(|pair| -> Result<_, BrrrrError> {
+        let (r1, r2) = pair?;
+        let id = paired::name_string(paired::fragment_name(r1.name()));
+
+        Ok((
+            id,
+            FastqRecord::new(&r1, quality_options)?,
+            FastqRecord::new(&r2, quality_options)?,
+        ))
+    });
+
+    for fragment in rejects.filter("FASTQ", fragments) {
+        let (id, r1, r2) = fragment?;
+        writer.write_serde_record(PairedFastqRow {
+            id: &id,
+            r1: &r1,
+            r2: &r2,
+        })?;
+    }
+
+    writer.close()
+}
+
+/// The columns of `paired_fastq_schema` for one fragment.
+struct PairedFastqRow<'a> {
+    id: &'a str,
+    r1: &'a FastqRecord,
+    r2: &'a FastqRecord,
+}
+
+impl<'a> Serialize for PairedFastqRow<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut row = serializer.serialize_struct("PairedFastqRow", 11)?;
+        row.serialize_field("id", self.id)?;
+        row.serialize_field("r1_sequence", &self.r1.sequence)?;
+        row.serialize_field("r1_quality", &self.r1.quality)?;
+        row.serialize_field("r1_mean_quality", &self.r1.mean_quality)?;
+        row.serialize_field("r1_min_quality", &self.r1.min_quality)?;
+        row.serialize_field("r1_expected_errors", &self.r1.expected_errors)?;
+        row.serialize_field("r2_sequence", &self.r2.sequence)?;
+        row.serialize_field("r2_quality", &self.r2.quality)?;
+        row.serialize_field("r2_mean_quality", &self.r2.mean_quality)?;
+        row.serialize_field("r2_min_quality", &self.r2.min_quality)?;
+        row.serialize_field("r2_expected_errors", &self.r2.expected_errors)?;
+        row.end()
+    }
+}
+
+/// Returns the Arrow type for an INFO or FORMAT definition. Scalar fields map to a single
+/// value, while every other `Number` maps to a list.
+fn vcf_value_data_type(definition: &vcf::Definition) -> DataType {
+    let item_type = match definition.ty {
+        vcf::ValueType::Integer => DataType::Int32,
+        vcf::ValueType::Float => DataType::Float32,
+        vcf::ValueType::Flag => DataType::Boolean,
+        vcf::ValueType::Character | vcf::ValueType::String => DataType::Utf8,
+    };
+
+    if definition.is_scalar() {
+        item_type
+    } else {
+        DataType::List(Box::new(Field::new("item", item_type, true)))
+    }
+}
+
+fn vcf_definition_fields(definitions: &[vcf::Definition]) -> Vec<Field> {
+    definitions
+        .iter()
+        .map(|d| Field::new(&d.id, vcf_value_data_type(d), true))
+        .collect()
+}
+
+/// Returns the Arrow schema for VCF records described by `header`.
+///
+/// INFO fields are typed children of the `info` struct, and each sample is a struct of its
+/// typed FORMAT fields within `samples`. Either column is left out if the header has nothing
+/// to put in it.
+pub fn vcf_schema(header: &vcf::Header) -> Schema {
+    let string_list = DataType::List(Box::new(Field::new("item", DataType::Utf8, true)));
+
+    let mut fields = vec![
+        Field::new("chrom", DataType::Utf8, false),
+        Field::new("pos", DataType::Int64, false),
+        Field::new("id", DataType::Utf8, true),
+        Field::new("reference", DataType::Utf8, false),
+        Field::new("alternate", string_list.clone(), false),
+        Field::new("quality", DataType::Float32, true),
+        Field::new("filter", string_list, false),
+    ];
+
+    if !header.infos.is_empty() {
+        fields.push(Field::new(
+            "info",
+            DataType::Struct(vcf_definition_fields(&header.infos)),
+            false,
+        ));
+    }
+
+    fields.push(Field::new("info_extra", vcf_extra_type(), false));
+
+    if !header.formats.is_empty() && !header.sample_names.is_empty() {
+        let format_type = DataType::Struct(vcf_definition_fields(&header.formats));
+        let sample_fields = header
+            .sample_names
+            .iter()
+            .map(|name| Field::new(name, format_type.clone(), false))
+            .collect();
+
+        fields.push(Field::new(
+            "samples",
+            DataType::Struct(sample_fields),
+            false,
+        ));
+    }
+
+    if !header.sample_names.is_empty() {
+        let sample_fields = header
+            .sample_names
+            .iter()
+            .map(|name| Field::new(name, vcf_extra_type(), false))
+            .collect();
+
+        fields.push(Field::new(
+            "samples_extra",
+            DataType::Struct(sample_fields),
+            false,
+        ));
+    }
+
+    Schema::new(fields)
+}
+
+/// The type of the `info_extra` and `samples_extra` columns, which map each INFO or FORMAT key
+/// that isn't declared in the header to its value as written in the VCF, or null for a flag.
+fn vcf_extra_type() -> DataType {
+    DataType::Map(
+        Box::new(Field::new(
+            "entries",
+            DataType::Struct(vec![
+                Field::new("keys", DataType::Utf8, false),
+                Field::new("values", DataType::Utf8, true),
+            ]),
+            false,
+        )),
+        false,
+    )
+}
+
+/// The columns of `vcf_schema` for one VCF record.
+struct VcfRow<'a> {
+    header: &'a vcf::Header,
+    record: &'a VcfRecord,
+}
+
+impl<'a> Serialize for VcfRow<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (header, record) = (self.header, self.record);
+
+        let mut row = serializer.serialize_struct("VcfRow", 11)?;
+        row.serialize_field("chrom", &record.chrom)?;
+        row.serialize_field("pos", &record.pos)?;
+        row.serialize_field("id", &record.id)?;
+        row.serialize_field("reference", &record.reference)?;
+        row.serialize_field("alternate", &record.alternate)?;
+        row.serialize_field("quality", &record.quality)?;
+        row.serialize_field("filter", &record.filter)?;
+        row.serialize_field("info", &VcfValues::typed(&header.infos, Some(&record.info)))?;
+        row.serialize_field(
+            "info_extra",
+            &VcfValues::extra(&header.infos, Some(&record.info)),
+        )?;
+        row.serialize_field("samples", &VcfSamples::new(header, record, false))?;
+        row.serialize_field("samples_extra", &VcfSamples::new(header, record, true))?;
+        row.end()
+    }
+}
+
+/// The INFO or FORMAT values of a record or sample. The typed values are a struct with one
+/// field per definition, and the extra values map each key that isn't declared in
+/// `definitions` to its value as written in the VCF, or null for a flag.
+struct VcfValues<'a> {
+    definitions: &'a [vcf::Definition],
+    values: Option<&'a BTreeMap<String, VcfValue>>,
+    extra: bool,
+}
+
+impl<'a> VcfValues<'a> {
+    fn typed(
+        definitions: &'a [vcf::Definition],
+        values: Option<&'a BTreeMap<String, VcfValue>>,
+    ) -> Self {
+        Self {
+            definitions,
+            values,
+            extra: false,
+        }
+    }
+
+    fn extra(
+        definitions: &'a [vcf::Definition],
+        values: Option<&'a BTreeMap<String, VcfValue>>,
+    ) -> Self {
+        Self {
+            definitions,
+            values,
+            extra: true,
+        }
+    }
+}
+
+impl<'a> Serialize for VcfValues<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self.extra, self.values) {
+            (true, Some(values)) => {
+                serializer.collect_map(vcf::undeclared(self.definitions, values))
             }
-            seq_builder.push(record.sequence);
+            (true, None) => serializer.collect_map(std::iter::empty::<(&str, &str)>()),
+            (false, values) => serializer.collect_map(self.definitions.iter().map(|d| {
+                let value = values.and_then(|v| v.get(&d.id));
+                (&d.id, VcfTypedValue(d, value))
+            })),
         }
+    }
+}
 
-        let id_array = StringArray::from(id_builder);
-        let desc_array = description_builder.finish();
-        let seq_array = StringArray::from(seq_builder);
+/// The typed or extra FORMAT values of each sample, by sample name.
+struct VcfSamples<'a> {
+    header: &'a vcf::Header,
+    record: &'a VcfRecord,
+    extra: bool,
+}
 
-        let rb = RecordBatch::try_new(
-            Arc::new(file_schema.clone()),
-            vec![
-                Arc::new(id_array),
-                Arc::new(desc_array),
-                Arc::new(seq_array),
-            ],
-        )?;
+impl<'a> VcfSamples<'a> {
+    fn new(header: &'a vcf::Header, record: &'a VcfRecord, extra: bool) -> Self {
+        Self {
+            header,
+            record,
+            extra,
+        }
+    }
+}
 
-        writer.write(&rb)?;
+impl<'a> Serialize for VcfSamples<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.header.sample_names.iter().map(|name| {
+            let values = VcfValues {
+                definitions: &self.header.formats,
+                values: self.record.samples.get(name),
+                extra: self.extra,
+            };
+            (name, values)
+        }))
     }
+}
 
-    writer.close()?;
-    Ok(())
+/// An INFO or FORMAT value coerced to the type of its definition, as `vcf_value_data_type`
+/// gives it, so a scalar written to a list column becomes a single element list, and vice
+/// versa.
+struct VcfTypedValue<'a>(&'a vcf::Definition, Option<&'a VcfValue>);
+
+impl<'a> Serialize for VcfTypedValue<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let VcfTypedValue(definition, value) = *self;
+
+        match (definition.ty, definition.is_scalar(), value) {
+            (vcf::ValueType::Flag, _, value) => {
+                serializer.serialize_bool(matches!(value, Some(VcfValue::Flag(true))))
+            }
+            (vcf::ValueType::Integer, true, value) => match value {
+                Some(VcfValue::Integer(i)) => Some(*i),
+                Some(VcfValue::IntegerArray(v)) => v.first().copied().flatten(),
+                _ => None,
+            }
+            .serialize(serializer),
+            (vcf::ValueType::Float, true, value) => match value {
+                Some(VcfValue::Float(f)) => Some(*f),
+                Some(VcfValue::Integer(i)) => Some(*i as f32),
+                Some(VcfValue::FloatArray(v)) => v.first().copied().flatten(),
+                _ => None,
+            }
+            .serialize(serializer),
+            (_, true, Some(value)) => serializer.collect_str(value),
+            (_, _, None) => serializer.serialize_none(),
+            (vcf::ValueType::Integer, false, Some(value)) => match value {
+                VcfValue::Integer(i) => [*i].serialize(serializer),
+                VcfValue::IntegerArray(v) => v.serialize(serializer),
+                _ => serializer.collect_seq(std::iter::empty::<i32>()),
+            },
+            (vcf::ValueType::Float, false, Some(value)) => match value {
+                VcfValue::Float(f) => [*f].serialize(serializer),
+                VcfValue::FloatArray(v) => v.serialize(serializer),
+                _ => serializer.collect_seq(std::iter::empty::<f32>()),
+            },
+            (_, false, Some(value)) => match value {
+                VcfValue::StringArray(v) => v.serialize(serializer),
+                value => [value.to_string()].serialize(serializer),
+            },
+        }
+    }
 }
 
-/// Converts a FASTA file to Parquet.
+fn write_vcf_records_to_file<P, I>(
+    header: &vcf::Header,
+    records: I,
+    output: P,
+    options: &ParquetOptions,
+) -> Result<(), BrrrrError>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Result<VcfRecord, BrrrrError>>,
+{
+    let mut writer = create_record_writer(output, vcf_schema(header), options, Vec::new())?;
+
+    for record in records {
+        writer.write_serde_record(VcfRow {
+            header,
+            record: &record?,
+        })?;
+    }
+
+    writer.close()
+}
+
+/// Converts a VCF file to Parquet. The INFO and FORMAT keys declared in the header are typed
+/// columns, and any other keys are kept in the `info_extra` and `samples_extra` map columns.
 ///
 /// # Arguments
-/// * `input` The the path to the input fasta file.
-/// * `output` The the path to the output parquet file.
-/// * `parquet_compression` The parquet compression to use.
-/// * `bio_file_compression` The compression for the input bio file.
-pub fn fa2pq<P: AsRef<Path>>(
-    input: &P,
-    output: &P,
-    parquet_compression: Compression,
-    bio_file_compression: BioFileCompression,
+/// * `input` The path to the input VCF file.
+/// * `output` The path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `bio_file_compression` The compression of the input, or None to detect it.
+/// * `rejects` What to do with records that fail to parse.
+pub fn vcf2pq<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    options: &ParquetOptions,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
 ) -> Result<(), BrrrrError> {
-    match bio_file_compression {
-        BioFileCompression::GZIP => {
-            let file = fs::File::open(input)?;
-            let gz = GzDecoder::new(BufReader::new(file));
-            let reader = fasta::Reader::new(BufReader::new(gz));
-            write_records_to_file(reader, output, parquet_compression)
-        }
-        BioFileCompression::UNCOMPRESSED => {
-            let file = fs::File::open(input)?;
-            let reader = fasta::Reader::new(BufReader::new(file));
-            write_records_to_file(reader, output, parquet_compression)
+    let mut reader = vcf::Reader::new(input::open(input, bio_file_compression)?);
+    let header = reader.read_header()?;
+
+    write_vcf_records_to_file(
+        &header,
+        rejects.filter("VCF", reader.records(&header)),
+        output,
+        options,
+    )
+}
+
+/// Converts a BCF file to Parquet. The INFO and FORMAT keys declared in the header are typed
+/// columns, and any other keys are kept in the `info_extra` and `samples_extra` map columns.
+///
+/// # Arguments
+/// * `input` The path to the input BCF file.
+/// * `output` The path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `rejects` What to do with records that fail to parse.
+pub fn bcf2pq<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    options: &ParquetOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = bcf::Reader::new(input::open_raw(input)?);
+    let header = reader.read_header()?;
+
+    write_vcf_records_to_file(
+        &header,
+        rejects.filter("BCF", reader.records(&header)),
+        output,
+        options,
+    )
+}
+
+/// The Parquet key-value metadata key that holds BED `track` and `browser` lines.
+pub const BED_HEADER_METADATA_KEY: &str = "bed_header";
+
+/// Returns the Arrow schema for a BED file of the given kind. Only the columns present in the
+/// file are included, and BED12 blocks are list columns.
+pub fn bed_schema(kind: bed::BedKind) -> Schema {
+    let fields = kind
+        .columns()
+        .iter()
+        .map(|name| match *name {
+            "chrom" => Field::new(name, DataType::Utf8, false),
+            "start" | "end" => Field::new(name, DataType::Int64, false),
+            "name" | "strand" | "item_rgb" => Field::new(name, DataType::Utf8, true),
+            "value" => Field::new(name, DataType::Float64, true),
+            "block_sizes" | "block_starts" => Field::new(
+                name,
+                DataType::List(Box::new(Field::new("item", DataType::Int64, true))),
+                true,
+            ),
+            _ => Field::new(name, DataType::Int64, true),
+        })
+        .collect();
+
+    Schema::new(fields)
+}
+
+/// Converts a BED or bedGraph file to Parquet.
+///
+/// # Arguments
+/// * `input` The path to the input BED file.
+/// * `output` The path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `format` Whether the input is BED or bedGraph. When `None`, it's inferred from a `.bedgraph`
+///   or `.bg` extension, or a `type=bedGraph` track line.
+/// * `bio_file_compression` The compression of the input, or None to detect it.
+/// * `rejects` What to do with records that fail to parse.
+pub fn bed2pq<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    options: &ParquetOptions,
+    format: Option<bed::BedFormat>,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let format = format.or_else(|| bed::BedFormat::from_extension(&input));
+    let mut reader = bed::Reader::new(input::open(input, bio_file_compression)?);
+    let header = reader.read_header(format)?;
+
+    let mut metadata = Vec::new();
+    if !header.lines.is_empty() {
+        metadata.push(KeyValue::new(
+            BED_HEADER_METADATA_KEY.to_string(),
+            header.lines.join("\n"),
+        ));
+    }
+
+    let writer = create_record_writer(output, bed_schema(header.kind), options, metadata)?;
+    write_records(rejects.filter("BED", reader.records(&header)), writer)
+}
+
+/// The Parquet key-value metadata key that holds the SAM header of an alignment file.
+pub const SAM_HEADER_METADATA_KEY: &str = "sam_header";
+
+fn sam_cigar_fields() -> Vec<Field> {
+    vec![
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("len", DataType::Int64, false),
+    ]
+}
+
+fn sam_data_fields() -> Vec<Field> {
+    vec![
+        Field::new("tag", DataType::Utf8, false),
+        Field::new("ty", DataType::Utf8, false),
+        Field::new("int_value", DataType::Int64, true),
+        Field::new("float_value", DataType::Float32, true),
+        Field::new("string_value", DataType::Utf8, true),
+        Field::new(
+            "int_array",
+            DataType::List(Box::new(Field::new("item", DataType::Int64, true))),
+            true,
+        ),
+        Field::new(
+            "float_array",
+            DataType::List(Box::new(Field::new("item", DataType::Float32, true))),
+            true,
+        ),
+    ]
+}
+
+/// Returns the Arrow schema for `SamRecord`. The CIGAR is a list of `kind`/`len` structs, the
+/// quality scores are a list of raw Phred scores, and the auxiliary data is a list of fields,
+/// each holding the tag, the SAM type and the value in the matching typed child.
+///
+/// The auxiliary data is a list of structs rather than a Parquet MAP, as the MAP support in this
+/// version of parquet drops all but the first entry when the values are structs.
+pub fn sam_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("read_name", DataType::Utf8, true),
+        Field::new("flags", DataType::UInt16, false),
+        Field::new("reference_sequence", DataType::Utf8, true),
+        Field::new("alignment_start", DataType::Int64, true),
+        Field::new("mapping_quality", DataType::UInt8, true),
+        Field::new(
+            "cigar",
+            DataType::List(Box::new(Field::new(
+                "item",
+                DataType::Struct(sam_cigar_fields()),
+                true,
+            ))),
+            false,
+        ),
+        Field::new("mate_reference_sequence", DataType::Utf8, true),
+        Field::new("mate_alignment_start", DataType::Int64, true),
+        Field::new("template_length", DataType::Int32, false),
+        Field::new("sequence", DataType::Utf8, false),
+        Field::new(
+            "quality_scores",
+            DataType::List(Box::new(Field::new("item", DataType::UInt8, true))),
+            false,
+        ),
+        Field::new(
+            "data",
+            DataType::List(Box::new(Field::new(
+                "item",
+                DataType::Struct(sam_data_fields()),
+                true,
+            ))),
+            false,
+        ),
+    ])
+}
+
+/// The columns of `sam_schema` for one alignment record, which is written with a
+/// `RecordBatchBuffer`.
+#[derive(Serialize)]
+pub(crate) struct SamRow<'a> {
+    read_name: Option<&'a str>,
+    flags: u16,
+    reference_sequence: Option<&'a str>,
+    alignment_start: Option<usize>,
+    mapping_quality: Option<u8>,
+    cigar: &'a [Operation],
+    mate_reference_sequence: Option<&'a str>,
+    mate_alignment_start: Option<usize>,
+    template_length: i32,
+    sequence: &'a str,
+    quality_scores: &'a [u8],
+    data: Vec<SamDataRow<'a>>,
+}
+
+/// A typed auxiliary field, with every value child but the one matching the value left null.
+#[derive(Serialize)]
+struct SamDataRow<'a> {
+    tag: &'a str,
+    ty: &'a str,
+    int_value: Option<i64>,
+    float_value: Option<f32>,
+    string_value: Option<&'a str>,
+    int_array: Option<&'a [i64]>,
+    float_array: Option<&'a [f32]>,
+}
+
+impl<'a> From<&'a SamRecord> for SamRow<'a> {
+    fn from(record: &'a SamRecord) -> Self {
+        let data = record
+            .data
+            .fields
+            .iter()
+            .map(|(tag, field)| {
+                let mut row = SamDataRow {
+                    tag,
+                    ty: &field.ty,
+                    int_value: None,
+                    float_value: None,
+                    string_value: None,
+                    int_array: None,
+                    float_array: None,
+                };
+
+                match &field.value {
+                    SamValue::Integer(i) => row.int_value = Some(*i),
+                    SamValue::Float(f) => row.float_value = Some(*f),
+                    SamValue::String(s) => row.string_value = Some(s),
+                    SamValue::IntegerArray(values) => row.int_array = Some(values),
+                    SamValue::FloatArray(values) => row.float_array = Some(values),
+                }
+
+                row
+            })
+            .collect();
+
+        SamRow {
+            read_name: record.read_name.as_deref(),
+            flags: record.flags,
+            reference_sequence: record.reference_sequence.as_deref(),
+            alignment_start: record.alignment_start,
+            mapping_quality: record.mapping_quality,
+            cigar: &record.cigar.operations,
+            mate_reference_sequence: record.mate_reference_sequence.as_deref(),
+            mate_alignment_start: record.mate_alignment_start,
+            template_length: record.template_length,
+            sequence: &record.sequence,
+            quality_scores: &record.quality_scores,
+            data,
         }
     }
 }
 
-/// Converts a FASTQ file to Parquet.
+/// Writes alignment records to a parquet file, storing `header` in the file metadata.
+pub(crate) fn write_sam_records_to_file<P, I>(
+    header: &sam::Header,
+    records: I,
+    output: P,
+    options: &ParquetOptions,
+) -> Result<(), BrrrrError>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Result<SamRecord, BrrrrError>>,
+{
+    let metadata = vec![KeyValue::new(
+        SAM_HEADER_METADATA_KEY.to_string(),
+        header.to_string(),
+    )];
+
+    let mut writer = create_record_writer(output, sam_schema(), options, metadata)?;
+    for record in records {
+        writer.write_serde_record(SamRow::from(&record?))?;
+    }
+
+    writer.close()
+}
+
+/// Converts a BAM file to Parquet. Reference sequence IDs are resolved to their names.
 ///
 /// # Arguments
-/// * `input` The string representing the path to the input fasta file.
-/// * `output` The string representing the path to the output parquet file.
-/// * `parquet_compression` The parquet compression to use.
-pub fn fq2pq<P: AsRef<Path>>(
+/// * `input` The path to the input BAM file.
+/// * `output` The path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `rejects` What to do with records that fail to parse.
+pub fn bam2pq<P: AsRef<Path>>(
     input: P,
     output: P,
-    parquet_compression: Compression,
+    options: &ParquetOptions,
+    rejects: &mut Rejects,
 ) -> Result<(), BrrrrError> {
-    let file_schema = Schema::new(vec![
-        Field::new("id", DataType::Utf8, false),
-        Field::new("sequence", DataType::Utf8, false),
-        Field::new("description", DataType::Utf8, true),
-        Field::new("quality", DataType::Utf8, false),
-    ]);
+    let mut reader = bam::Reader::new(input::open_raw(input)?);
 
-    let props = WriterProperties::builder()
-        .set_compression(parquet_compression)
-        .set_statistics_enabled(true);
+    let mut header: sam::Header = reader
+        .read_header()?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    *header.reference_sequences_mut() = reader.read_reference_sequences()?;
 
-    let input_file = fs::File::open(input).expect("Error opening file.");
-    let mut reader = fastq::Reader::new(BufReader::new(input_file));
+    let records = reader
+        .records()
+        .map(|record| record.map(|r| SamRecord::new(&r, header.reference_sequences())));
 
-    let records = reader.records();
+    write_sam_records_to_file(&header, rejects.filter("BAM", records), output, options)
+}
 
-    let file = fs::File::create(output)?;
-    let mut writer =
-        ArrowWriter::try_new(file, Arc::new(file_schema.clone()), Some(props.build()))?;
+/// Converts a SAM file to Parquet. Reference sequence IDs are resolved to their names.
+///
+/// # Arguments
+/// * `input` The path to the input SAM file.
+/// * `output` The path to the output parquet file.
+/// * `options` How the parquet file is written.
+/// * `bio_file_compression` The compression of the input, or None to detect it.
+/// * `rejects` What to do with records that fail to parse.
+pub fn sam2pq<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    options: &ParquetOptions,
+    bio_file_compression: Option<BioFileCompression>,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let mut reader = sam::Reader::new(input::open(input, bio_file_compression)?);
 
-    let chunk_size = 2usize.pow(20);
-    for chunk in records.into_iter().chunks(chunk_size).into_iter() {
-        let mut id_builder = StringBuilder::new(2048);
-        let mut description_builder = StringBuilder::new(2048);
-        let mut seq_builder = StringBuilder::new(2048);
-        let mut quality_builder = StringBuilder::new(2048);
+    let header: sam::Header = reader
+        .read_header()?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-        for chunk_i in chunk {
-            let record = match chunk_i {
-                Ok(r) => r,
-                Err(error) => panic!("{}", error),
-            };
+    let lines = reader
+        .into_inner()
+        .lines()
+        .filter(|line| !matches!(line, Ok(l) if l.is_empty()));
+
+    let records = lines.map(|line| -> io::Result<SamRecord> {
+        let line = line?;
+        let mut record = sam::alignment::Record::default();
+        sam::Reader::new(line.as_bytes()).read_record(&header, &mut record)?;
+
+        // The noodles SAM reader only keeps the first data field, so the fields after the
+        // mandatory eleven columns are parsed here instead.
+        if let Some(data) = line.splitn(12, '\t').nth(11) {
+            *record.data_mut() = data
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+
+        Ok(SamRecord::new(&record, header.reference_sequences()))
+    });
+
+    write_sam_records_to_file(&header, rejects.filter("SAM", records), output, options)
+}
+
+/// Converts a CRAM file to Parquet, decoding the records against a local reference FASTA.
+///
+/// # Arguments
+/// * `input` The path to the input CRAM file.
+/// * `output` The path to the output parquet file.
+/// * `reference` The path to the reference FASTA, which needs a `.fai` index next to it.
+/// * `options` How the parquet file is written.
+/// * `rejects` What to do with records that fail to parse.
+pub fn cram2pq<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    reference: P,
+    options: &ParquetOptions,
+    rejects: &mut Rejects,
+) -> Result<(), BrrrrError> {
+    let reference = cram::Reference::from_path(reference)?;
+
+    let mut reader = cram::Reader::new(input::open_raw(input)?, reference);
+    let header = reader.read_header()?;
+
+    write_sam_records_to_file(
+        &header,
+        rejects.filter("CRAM", reader.records(&header)),
+        output,
+        options,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use arrow::array::*;
+    use arrow::record_batch::RecordBatch;
+    use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
+    use parquet::file::reader::SerializedFileReader;
+
+    use super::*;
+
+    fn read_batches<P: AsRef<Path>>(path: P) -> Vec<RecordBatch> {
+        let file = File::open(path).expect("error opening parquet");
+        let reader = SerializedFileReader::new(file).expect("error reading parquet");
+        let mut arrow_reader = ParquetFileArrowReader::new(Arc::new(reader));
+
+        arrow_reader
+            .get_record_reader(1024)
+            .expect("error reading batches")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("error reading batch")
+    }
+
+    fn struct_column<'a>(array: &'a ArrayRef, name: &str) -> &'a ArrayRef {
+        array
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .and_then(|s| s.column_by_name(name))
+            .expect("missing struct column")
+    }
+
+    #[test]
+    fn test_vcf2pq() {
+        let temp_dir = env::temp_dir();
+        let vcf_path = temp_dir.join("vcf2pq_test.vcf");
+        let parquet_path = temp_dir.join("vcf2pq_test.parquet");
+
+        let mut vcf_file = File::create(&vcf_path).expect("error");
+        vcf_file
+            .write_all(
+                b"##fileformat=VCFv4.3
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1
+sq0\t1\trs1\tA\tC,G\t13.5\tPASS\tDP=8;AF=0.25,0.5\tGT\t0/1
+sq0\t9\t.\tT\t.\t.\t.\t.\tGT\t./.
+",
+            )
+            .expect("error");
+
+        vcf2pq(
+            &vcf_path,
+            &parquet_path,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            &mut Rejects::default(),
+        )
+        .expect("vcf2pq failed");
+
+        let batches = read_batches(&parquet_path);
+        assert_eq!(batches.len(), 1);
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        let info = batch.column(batch.schema().index_of("info").unwrap());
+        let dp = struct_column(info, "DP")
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(dp.value(0), 8);
+        assert!(dp.is_null(1));
+
+        let af = struct_column(info, "AF")
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(af.value_length(0), 2);
+        assert!(af.is_null(1));
+
+        let samples = batch.column(batch.schema().index_of("samples").unwrap());
+        let gt = struct_column(struct_column(samples, "NA1"), "GT")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(gt.value(0), "0/1");
+        assert_eq!(gt.value(1), "./.");
+    }
+
+    #[test]
+    fn test_vcf2pq_undeclared() {
+        use parquet::file::reader::FileReader;
+        use parquet::record::Field as RowField;
+
+        let temp_dir = env::temp_dir();
+        let vcf_path = temp_dir.join("vcf2pq_undeclared.vcf");
+        let parquet_path = temp_dir.join("vcf2pq_undeclared.parquet");
+
+        // Neither header declares a key, so every INFO and FORMAT value is undeclared.
+        std::fs::write(
+            &vcf_path,
+            "##fileformat=VCFv4.3
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1
+sq0\t1\trs1\tA\tC\t.\tPASS\tDP=8;SOMATIC;AF=0.1,0.2\tGT\t0/1
+",
+        )
+        .unwrap();
+
+        vcf2pq(
+            &vcf_path,
+            &parquet_path,
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&parquet_path).unwrap()).unwrap();
+        let row = reader.get_row_iter(None).unwrap().next().unwrap();
 
-            let fastq_record = FastqRecord::from(record);
+        let entries = |field: &RowField| match field {
+            RowField::MapInternal(map) => map
+                .entries()
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>(),
+            field => panic!("expected a map, found {}", field),
+        };
+        let column = |name: &str| {
+            row.get_column_iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, field)| field.clone())
+                .unwrap()
+        };
 
-            id_builder
-                .append_value(fastq_record.id)
-                .expect("Couldn't append id.");
+        assert_eq!(
+            entries(&column("info_extra")),
+            vec![
+                (String::from("\"AF\""), String::from("\"0.1,0.2\"")),
+                (String::from("\"DP\""), String::from("\"8\"")),
+                (String::from("\"SOMATIC\""), String::from("null")),
+            ]
+        );
 
-            match fastq_record.description {
-                Some(x) => description_builder
-                    .append_value(x)
-                    .expect("Couldn't append description."),
-                _ => description_builder
-                    .append_null()
-                    .expect("Couldn't append null description."),
+        match column("samples_extra") {
+            RowField::Group(samples) => {
+                let (name, na1) = samples.get_column_iter().next().unwrap();
+                assert_eq!(name, "NA1");
+                assert_eq!(
+                    entries(na1),
+                    vec![(String::from("\"GT\""), String::from("\"0/1\""))]
+                );
             }
+            field => panic!("expected a group, found {}", field),
+        }
+    }
+
+    #[test]
+    fn test_bcf2pq() {
+        let mut bcf_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        bcf_path.push("resources/little.bcf");
+        let parquet_path = env::temp_dir().join("bcf2pq_test.parquet");
+
+        bcf2pq(
+            &bcf_path,
+            &parquet_path,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .expect("bcf2pq failed");
+
+        let batches = read_batches(&parquet_path);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let pos = batch
+            .column(batch.schema().index_of("pos").unwrap())
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(pos.values(), &[1, 5]);
+
+        let quality = batch
+            .column(batch.schema().index_of("quality").unwrap())
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .unwrap();
+        assert_eq!(quality.value(0), 13.5);
+        assert!(quality.is_null(1));
+
+        let info = batch.column(batch.schema().index_of("info").unwrap());
+        let db = struct_column(info, "DB")
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert!(db.value(0));
+        assert!(!db.value(1));
+
+        let samples = batch.column(batch.schema().index_of("samples").unwrap());
+        let gt = struct_column(struct_column(samples, "NA2"), "GT")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(gt.value(0), "1|1");
+        assert_eq!(gt.value(1), "0/0");
+
+        let ad = struct_column(struct_column(samples, "NA1"), "AD")
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        let ad_values = ad.value(0);
+        let ad_values = ad_values.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(ad_values.values(), &[3, 5]);
+        assert!(ad.is_null(1));
+    }
+
+    #[test]
+    fn test_sam2pq() {
+        let temp_dir = env::temp_dir();
+        let input = temp_dir.join("test_sam2pq.sam");
+        let output = temp_dir.join("test_sam2pq.parquet");
+
+        let mut f = File::create(&input).unwrap();
+        f.write_all(
+            b"@HD\tVN:1.6\n@SQ\tSN:sq0\tLN:100\n@SQ\tSN:sq1\tLN:50\n\
+            r0\t99\tsq1\t5\t30\t3M1I2M\t=\t20\t21\tACGTAC\tIIII#I\tNM:i:1\tXA:Z:hi\tZB:B:s,-1,2\n\
+            r1\t4\t*\t0\t255\t*\t*\t0\t0\tAC\t*\n",
+        )
+        .unwrap();
+
+        sam2pq(
+            &input,
+            &output,
+            &ParquetOptions {
+                compression: Compression::SNAPPY,
+                row_group_size: 1,
+                ..Default::default()
+            },
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let file = File::open(&output).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        assert_eq!(
+            parquet::file::reader::FileReader::num_row_groups(&reader),
+            2
+        );
+
+        let batch = RecordBatch::concat(&Arc::new(sam_schema()), &read_batches(&output)).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let reference_sequence = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(reference_sequence.value(0), "sq1");
+        assert!(reference_sequence.is_null(1));
+
+        let mate_reference_sequence = batch
+            .column(6)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(mate_reference_sequence.value(0), "sq1");
+
+        let cigar = batch
+            .column(5)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        let ops = cigar.value(0);
+        let kinds = struct_column(&ops, "kind")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let lens = struct_column(&ops, "len")
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(
+            kinds.iter().flatten().collect::<Vec<_>>(),
+            vec!["M", "I", "M"]
+        );
+        assert_eq!(lens.values(), &[3, 1, 2]);
+        assert_eq!(cigar.value_length(1), 0);
+
+        let quality_scores = batch
+            .column(10)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        let scores = quality_scores.value(0);
+        let scores = scores.as_any().downcast_ref::<UInt8Array>().unwrap();
+        assert_eq!(scores.values(), &[40, 40, 40, 40, 2, 40]);
+        assert_eq!(quality_scores.value_length(1), 0);
+
+        let data = batch
+            .column(11)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(data.value_length(0), 3);
+        assert_eq!(data.value_length(1), 0);
+
+        let fields = data.value(0);
+        let tags = struct_column(&fields, "tag")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            tags.iter().flatten().collect::<Vec<_>>(),
+            vec!["NM", "XA", "ZB"]
+        );
+
+        let types = struct_column(&fields, "ty")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            types.iter().flatten().collect::<Vec<_>>(),
+            vec!["C", "Z", "B:s"]
+        );
+
+        let ints = struct_column(&fields, "int_value")
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(ints.value(0), 1);
+        assert!(ints.is_null(1));
 
-            seq_builder
-                .append_value(fastq_record.sequence)
-                .expect("Couldn't add sequence.");
+        let strings = struct_column(&fields, "string_value")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(strings.value(1), "hi");
 
-            quality_builder
-                .append_value(fastq_record.quality)
-                .expect("Couldn't add sequence.");
+        let int_arrays = struct_column(&fields, "int_array")
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        let int_array = int_arrays.value(2);
+        let int_array = int_array.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(int_array.values(), &[-1, 2]);
+    }
+
+    #[test]
+    fn test_bam2pq() {
+        let mut input = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        input.push("resources/little.bam");
+        let output = env::temp_dir().join("test_bam2pq.parquet");
+
+        bam2pq(
+            &input,
+            &output,
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        let batches = read_batches(&output);
+        assert_eq!(batches[0].schema().as_ref(), &sam_schema());
+
+        let read_names = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(read_names.value(0), "8");
+    }
+
+    #[test]
+    fn test_parquet_record_writer() {
+        let mut input = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        input.push("resources/little.bam");
+        let output = env::temp_dir().join("test_parquet_record_writer.parquet");
+
+        let reader = crate::reader::BamReader::new(File::open(&input).unwrap()).unwrap();
+        let mut writer = ParquetRecordWriter::new(
+            File::create(&output).unwrap(),
+            WriterProperties::builder().build(),
+        );
+
+        let mut count = 0;
+        for record in reader {
+            writer.write_serde_record(record.unwrap()).unwrap();
+            count += 1;
         }
+        writer.close().unwrap();
 
-        let id_array = id_builder.finish();
-        let desc_array = description_builder.finish();
-        let seq_array = seq_builder.finish();
-        let quality_array = quality_builder.finish();
+        let batches = read_batches(&output);
+        let schema = batches[0].schema();
 
-        let rb = RecordBatch::try_new(
-            Arc::new(file_schema.clone()),
-            vec![
-                Arc::new(id_array),
-                Arc::new(seq_array),
-                Arc::new(desc_array),
-                Arc::new(quality_array),
-            ],
-        )?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), count);
+        assert_eq!(schema.field(0).name(), "read_name");
+        assert_eq!(schema.field(1).data_type(), &DataType::UInt16);
+        assert!(matches!(schema.field(5).data_type(), DataType::Struct(_)));
+    }
+
+    #[test]
+    fn test_cram2pq() {
+        let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let cram_output = env::temp_dir().join("test_cram2pq.parquet");
+        let sam_output = env::temp_dir().join("test_cram2pq_sam.parquet");
 
-        writer.write(&rb)?;
+        cram2pq(
+            resources.join("little.cram"),
+            cram_output.clone(),
+            resources.join("little.fa"),
+            &Compression::UNCOMPRESSED.into(),
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        sam2pq(
+            resources.join("little.sam"),
+            sam_output.clone(),
+            &Compression::UNCOMPRESSED.into(),
+            None,
+            &mut Rejects::default(),
+        )
+        .unwrap();
+
+        // little.cram was encoded from little.sam, so both decode to the same records.
+        let cram_batches = read_batches(&cram_output);
+        assert_eq!(cram_batches[0].num_rows(), 5);
+        assert_eq!(cram_batches, read_batches(&sam_output));
     }
 
-    writer.close()?;
-    Ok(())
+    #[test]
+    fn test_parquet_options() {
+        let temp_dir = env::temp_dir();
+        let input = temp_dir.join("test_parquet_options.fasta");
+        let output = temp_dir.join("test_parquet_options.parquet");
+        std::fs::write(&input, ">a\nACGT\n>b\nAC\n>c\nA\n").unwrap();
+
+        let mut options = ParquetOptions {
+            row_group_size: 2,
+            data_page_size: 1024,
+            dictionary: false,
+            ..Default::default()
+        };
+        options
+            .column_dictionary
+            .insert(String::from("sequence"), true);
+        options
+            .column_encodings
+            .insert(String::from("id"), Encoding::DELTA_BYTE_ARRAY);
+
+        fa2pq(&input, &output, &options, None, &mut Rejects::default()).unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&output).unwrap()).unwrap();
+        let metadata = parquet::file::reader::FileReader::metadata(&reader);
+        assert_eq!(metadata.num_row_groups(), 2);
+
+        let row_group = metadata.row_group(0);
+        assert!(row_group
+            .column(0)
+            .encodings()
+            .contains(&Encoding::DELTA_BYTE_ARRAY));
+        assert!(row_group.column(1).dictionary_page_offset().is_none());
+        assert!(row_group.column(2).dictionary_page_offset().is_some());
+
+        let batches = read_batches(&output);
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+
+        options
+            .column_encodings
+            .insert(String::from("nope"), Encoding::PLAIN);
+        assert!(matches!(
+            options.writer_properties(&fasta_schema()),
+            Err(BrrrrError::MissingColumn(_))
+        ));
+
+        options
+            .column_encodings
+            .insert(String::from("id"), Encoding::RLE_DICTIONARY);
+        options.column_encodings.remove("nope");
+        assert!(options.writer_properties(&fasta_schema()).is_err());
+
+        assert!(parse_encoding("delta_byte_array").is_ok());
+        assert!(parse_encoding("RLE_DICTIONARY").is_err());
+    }
 }
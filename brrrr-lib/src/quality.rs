@@ -0,0 +1,140 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::io;
+
+/// The offset that Phred+33 quality scores are written with.
+const PHRED33: u8 = 33;
+
+/// The highest Phred score that can be written as a printable ASCII character with Phred+33.
+const MAX_PHRED: u8 = b'~' - PHRED33;
+
+/// The ASCII offset of the quality scores in a FASTQ file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhredOffset {
+    /// Sanger and Illumina 1.8+, where `!` is a Phred score of 0.
+    #[default]
+    Phred33,
+    /// Illumina 1.3 to 1.7, where `@` is a Phred score of 0.
+    Phred64,
+}
+
+impl PhredOffset {
+    /// Returns the ASCII value of a Phred score of 0.
+    pub fn value(&self) -> u8 {
+        match self {
+            PhredOffset::Phred33 => PHRED33,
+            PhredOffset::Phred64 => 64,
+        }
+    }
+}
+
+/// How FASTQ quality scores are written by fq2pq, fq2jsonl and fq2csv. The default keeps the
+/// quality as the ASCII string from the input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QualityOptions {
+    /// Write the quality as a list of Phred scores rather than an ASCII string.
+    pub phred_scores: bool,
+    /// The offset of the input quality scores. Phred+64 input written as an ASCII string is
+    /// re-encoded as Phred+33.
+    pub offset: PhredOffset,
+    /// Add the mean quality, min quality and expected errors of each read.
+    pub summary: bool,
+}
+
+/// Per-read quality statistics.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QualitySummary {
+    /// The mean Phred score, or None for an empty read.
+    pub mean_quality: Option<f64>,
+    /// The lowest Phred score, or None for an empty read.
+    pub min_quality: Option<u8>,
+    /// The expected number of base call errors, i.e. the sum of `10^(-q/10)`.
+    pub expected_errors: f64,
+}
+
+/// Decodes ASCII quality scores into Phred scores.
+pub fn decode(quality: &[u8], offset: PhredOffset) -> io::Result<Vec<u8>> {
+    quality
+        .iter()
+        .map(|&c| match c.checked_sub(offset.value()) {
+            Some(score) if c <= b'~' => Ok(score),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "invalid quality score {:?} for an offset of {}",
+                    c as char,
+                    offset.value()
+                ),
+            )),
+        })
+        .collect()
+}
+
+/// Encodes Phred scores as Phred+33 ASCII quality scores.
+pub fn encode(scores: &[u8]) -> io::Result<Vec<u8>> {
+    scores
+        .iter()
+        .map(|&score| {
+            if score <= MAX_PHRED {
+                Ok(score + PHRED33)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid Phred score: {}", score),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Computes the quality statistics of a read from its Phred scores.
+pub fn summarize(scores: &[u8]) -> QualitySummary {
+    if scores.is_empty() {
+        return QualitySummary::default();
+    }
+
+    let total: u64 = scores.iter().map(|&s| u64::from(s)).sum();
+    let expected_errors = scores
+        .iter()
+        .map(|&s| 10f64.powf(-f64::from(s) / 10.0))
+        .sum();
+
+    QualitySummary {
+        mean_quality: Some(total as f64 / scores.len() as f64),
+        min_quality: scores.iter().min().copied(),
+        expected_errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_and_encode() {
+        assert_eq!(
+            decode(b"!+5I", PhredOffset::Phred33).unwrap(),
+            vec![0, 10, 20, 40]
+        );
+        assert_eq!(
+            decode(b"@J^h", PhredOffset::Phred64).unwrap(),
+            vec![0, 10, 30, 40]
+        );
+        assert!(decode(b"5", PhredOffset::Phred64).is_err());
+
+        assert_eq!(encode(&[0, 10, 20, 40]).unwrap(), b"!+5I");
+        assert!(encode(&[94]).is_err());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let summary = summarize(&[10, 20, 30]);
+
+        assert_eq!(summary.mean_quality, Some(20.0));
+        assert_eq!(summary.min_quality, Some(10));
+        assert!((summary.expected_errors - 0.111).abs() < 1e-9);
+
+        assert_eq!(summarize(&[]), QualitySummary::default());
+    }
+}
@@ -0,0 +1,714 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::convert::TryFrom;
+use std::io::{self, BufRead, ErrorKind, Read};
+use std::iter::Peekable;
+use std::path::Path;
+
+use noodles::bam;
+use noodles::bgzf;
+use noodles::fasta;
+use noodles::fastq;
+use noodles::sam;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde::Serialize;
+
+use crate::errors::{BrrrrError, RecordLocation};
+use crate::gff;
+use crate::parquet_reader::{self, ParquetSource};
+use crate::quality::QualityOptions;
+use crate::types::{FastaRecord, FastqRecord, GffRecord, Quality, SamRecord};
+use crate::writer::RecordWriter;
+
+/// A RecordReader is an iterator over the typed records of an underlying source, along with the
+/// header that precedes them.
+///
+/// Implement this trait to read a format of your own, and pass the reader to `convert` to write
+/// its records with any `RecordWriter`.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::json_writer::JsonRecordWriter;
+/// use brrrr_lib::reader::{self, FastaReader, RecordReader};
+///
+/// let reader = FastaReader::new(b">A\nATCG\n" as &[u8]);
+/// assert_eq!(reader.header(), &());
+///
+/// let mut output = Vec::new();
+/// reader::convert(reader, &mut JsonRecordWriter::new(&mut output)).unwrap();
+/// ```
+pub trait RecordReader: Iterator {
+    /// The header of the source, e.g. the directives of a GFF file.
+    type Header;
+
+    /// Returns the header, which is read when the reader is created.
+    fn header(&self) -> &Self::Header;
+}
+
+/// Writes every record of `records` with `writer`. Writing stops without an error when the
+/// output is closed, e.g. when piping to `head`.
+pub fn convert<I, S, E, W>(records: I, writer: &mut W) -> Result<(), BrrrrError>
+where
+    I: IntoIterator<Item = Result<S, E>>,
+    S: Serialize,
+    BrrrrError: From<E>,
+    W: RecordWriter,
+{
+    for record in records {
+        if let Err(e) = writer.write_serde_record(record?) {
+            match e.kind() {
+                ErrorKind::BrokenPipe => break,
+                _ => return Err(BrrrrError::IOError(e)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A record read by `convert::read`. Any writer that can hold this kind of record can write it,
+/// e.g. FASTQ records can be written as FASTA by dropping their quality.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Record {
+    Fasta(FastaRecord),
+    Fastq(FastqRecord),
+    Gff(GffRecord),
+    Sam(SamRecord),
+}
+
+impl Record {
+    /// Returns the kind of record as it is written in messages, e.g. `FASTQ`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Record::Fasta(_) => "FASTA",
+            Record::Fastq(_) => "FASTQ",
+            Record::Gff(_) => "GFF",
+            Record::Sam(_) => "SAM",
+        }
+    }
+}
+
+/// The kind of records in an input, along with the header a writer needs to write them.
+#[derive(Clone, Debug)]
+pub enum Header {
+    Fasta,
+    Fastq,
+    Gff(gff::Header),
+    Sam(Box<sam::Header>),
+}
+
+impl Header {
+    /// Returns the kind of records as it is written in messages, e.g. `FASTQ`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Header::Fasta => "FASTA",
+            Header::Fastq => "FASTQ",
+            Header::Gff(_) => "GFF",
+            Header::Sam(_) => "SAM",
+        }
+    }
+
+    /// Returns the header of the records written by a brrrr converter with `columns`, e.g. the
+    /// keys of a jsonl object or the header of a csv.
+    fn from_columns<S: AsRef<str>>(columns: &[S]) -> Result<Header, BrrrrError> {
+        let has = |name: &str| columns.iter().any(|c| c.as_ref() == name);
+
+        if has("seqname") {
+            Ok(Header::Gff(gff::Header {
+                lines: Vec::new(),
+                dialect: gff::Dialect::default(),
+            }))
+        } else if has("flags") {
+            Ok(Header::Sam(Box::default()))
+        } else if has("quality") {
+            Ok(Header::Fastq)
+        } else if has("sequence") {
+            Ok(Header::Fasta)
+        } else {
+            Err(BrrrrError::UnknownFormat(String::from(
+                "the input does not have the columns of FASTA, FASTQ, GFF or SAM records",
+            )))
+        }
+    }
+}
+
+/// Reads FASTA records.
+pub struct FastaReader<R> {
+    inner: fasta::Reader<R>,
+    index: usize,
+    offset: u64,
+}
+
+impl<R: BufRead> FastaReader<R> {
+    /// Creates a new FastaReader.
+    pub fn new(inner: R) -> Self {
+        Self::from(fasta::Reader::new(inner))
+    }
+
+    /// Reads the next record, keeping its ID and raw text so far in `id` and `raw` for errors.
+    fn read_record(
+        &mut self,
+        id: &mut Option<String>,
+        raw: &mut String,
+    ) -> Result<Option<FastaRecord>, BrrrrError> {
+        match self.inner.read_definition(raw)? {
+            0 => return Ok(None),
+            n => self.offset += n as u64,
+        }
+
+        let definition: fasta::record::Definition = raw
+            .parse()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        *id = Some(definition.name().to_string());
+
+        let mut sequence = Vec::new();
+        self.offset += self.inner.read_sequence(&mut sequence)? as u64;
+        raw.push('\n');
+        raw.push_str(&String::from_utf8_lossy(&sequence));
+
+        let record = fasta::Record::new(definition, fasta::record::Sequence::from(sequence));
+        FastaRecord::try_from(record).map(Some)
+    }
+}
+
+impl<R: BufRead> From<fasta::Reader<R>> for FastaReader<R> {
+    fn from(inner: fasta::Reader<R>) -> Self {
+        Self {
+            inner,
+            index: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for FastaReader<R> {
+    type Item = Result<FastaRecord, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.index += 1;
+        let offset = self.offset;
+        let mut id = None;
+        let mut raw = String::new();
+
+        let record = self.read_record(&mut id, &mut raw).map_err(|e| {
+            let location = RecordLocation {
+                index: self.index,
+                offset: Some(offset),
+                id,
+            };
+            e.at("FASTA", location, Some(raw).filter(|r| !r.is_empty()))
+        });
+
+        record.transpose()
+    }
+}
+
+impl<R: BufRead> RecordReader for FastaReader<R> {
+    type Header = ();
+
+    fn header(&self) -> &Self::Header {
+        &()
+    }
+}
+
+/// Reads FASTQ records, decoding the quality scores as set by its `QualityOptions`.
+pub struct FastqReader<R> {
+    inner: fastq::Reader<R>,
+    quality_options: QualityOptions,
+    index: usize,
+    offset: u64,
+}
+
+impl<R: BufRead> FastqReader<R> {
+    /// Creates a new FastqReader.
+    pub fn new(inner: R, quality_options: &QualityOptions) -> Self {
+        Self {
+            inner: fastq::Reader::new(inner),
+            quality_options: *quality_options,
+            index: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for FastqReader<R> {
+    type Item = Result<FastqRecord, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = fastq::Record::default();
+        self.index += 1;
+        let offset = self.offset;
+
+        let result = match self.inner.read_record(&mut record) {
+            Ok(0) => return None,
+            Ok(n) => {
+                self.offset += n as u64;
+                FastqRecord::new(&record, &self.quality_options)
+            }
+            Err(e) => Err(e.into()),
+        };
+
+        Some(result.map_err(|e| {
+            let id = String::from_utf8_lossy(record.name()).into_owned();
+            let raw = format!(
+                "@{}\n{}\n+\n{}",
+                id,
+                String::from_utf8_lossy(record.sequence()),
+                String::from_utf8_lossy(record.quality_scores())
+            );
+            let location = RecordLocation {
+                index: self.index,
+                offset: Some(offset),
+                id: Some(id).filter(|id| !id.is_empty()),
+            };
+            e.at("FASTQ", location, Some(raw))
+        }))
+    }
+}
+
+impl<R: BufRead> RecordReader for FastqReader<R> {
+    type Header = ();
+
+    fn header(&self) -> &Self::Header {
+        &()
+    }
+}
+
+/// Reads GFF3 or GTF records, with the directives before the first record as its header.
+pub struct GffReader<R> {
+    inner: gff::Reader<R>,
+    header: gff::Header,
+    index: usize,
+}
+
+impl<R: BufRead> GffReader<R> {
+    /// Creates a new GffReader, reading the header in `dialect`, or in the dialect detected
+    /// from the input if None.
+    pub fn new(inner: R, dialect: Option<gff::Dialect>) -> io::Result<Self> {
+        let mut inner = gff::Reader::new(inner);
+        let header = inner.read_header(dialect)?;
+
+        Ok(Self {
+            inner,
+            header,
+            index: 0,
+        })
+    }
+
+    /// Returns a reader over the sequences that follow a `##FASTA` directive, or None if the
+    /// records have not been read to a `##FASTA` directive.
+    pub fn fasta_reader(&mut self) -> Option<FastaReader<&mut R>> {
+        self.inner.fasta_reader().map(FastaReader::from)
+    }
+}
+
+impl<R: BufRead> Iterator for GffReader<R> {
+    type Item = Result<GffRecord, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.index += 1;
+
+        let (offset, line) = match self.inner.read_record_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => return None,
+            Err(e) => {
+                let location = RecordLocation {
+                    index: self.index,
+                    ..Default::default()
+                };
+                return Some(Err(BrrrrError::from(e).at("GFF", location, None)));
+            }
+        };
+
+        let record = gff::parse_record(self.header.dialect, &line).and_then(|mut record| {
+            record.directives = self.inner.read_directives()?;
+            Ok(record)
+        });
+        let record = record.map_err(|e| {
+            let location = RecordLocation {
+                index: self.index,
+                offset: Some(offset),
+                id: None,
+            };
+            BrrrrError::from(e).at("GFF", location, Some(line))
+        });
+        Some(record)
+    }
+}
+
+impl<R: BufRead> RecordReader for GffReader<R> {
+    type Header = gff::Header;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
+
+/// Reads BAM records, resolving reference sequence IDs to their names.
+pub struct BamReader<R> {
+    inner: bam::Reader<bgzf::Reader<R>>,
+    header: sam::Header,
+    index: usize,
+}
+
+impl<R: Read> BamReader<R> {
+    /// Creates a new BamReader, reading the SAM header and reference sequences.
+    pub fn new(inner: R) -> io::Result<Self> {
+        let mut inner = bam::Reader::new(inner);
+
+        let mut header: sam::Header = inner
+            .read_header()?
+            .parse()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        *header.reference_sequences_mut() = inner.read_reference_sequences()?;
+
+        Ok(Self {
+            inner,
+            header,
+            index: 0,
+        })
+    }
+}
+
+impl<R: Read> Iterator for BamReader<R> {
+    type Item = Result<SamRecord, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = sam::alignment::Record::default();
+        self.index += 1;
+
+        match self.inner.read_record(&mut record) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(SamRecord::new(
+                &record,
+                self.header.reference_sequences(),
+            ))),
+            Err(e) => {
+                let location = RecordLocation {
+                    index: self.index,
+                    ..Default::default()
+                };
+                Some(Err(BrrrrError::from(e).at("BAM", location, None)))
+            }
+        }
+    }
+}
+
+impl<R: Read> RecordReader for BamReader<R> {
+    type Header = sam::Header;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
+
+/// Reads the FASTA, FASTQ, GFF or alignment records of a parquet file written by brrrr. The kind
+/// of records follows the columns of the file, and the GFF and SAM headers are read from its
+/// metadata. FASTA, FASTQ and GFF records are read in record batches of only their columns.
+pub struct ParquetReader {
+    records: Box<dyn Iterator<Item = Result<Record, BrrrrError>>>,
+    header: Header,
+}
+
+impl ParquetReader {
+    /// Opens the parquet file at `path`, or reads stdin into memory if `path` is `-`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BrrrrError> {
+        let source = ParquetSource::open(&path)?;
+        let reader = SerializedFileReader::new(source.reopen(&path)?)?;
+
+        let columns: Vec<String> = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .root_schema()
+            .get_fields()
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect();
+
+        let header = match Header::from_columns(&columns)? {
+            Header::Gff(_) => Header::Gff(parquet_reader::gff_header(&reader)?),
+            Header::Sam(_) => Header::Sam(Box::new(parquet_reader::read_sam_header(&reader)?)),
+            header => header,
+        };
+
+        Ok(Self {
+            records: parquet_reader::read_records(path.as_ref(), &source, &header)?,
+            header,
+        })
+    }
+}
+
+impl Iterator for ParquetReader {
+    type Item = Result<Record, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next()
+    }
+}
+
+impl RecordReader for ParquetReader {
+    type Header = Header;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
+
+/// Reads the records of jsonl written by brrrr, where the kind of records follows the keys of
+/// the first object. Empty lines are skipped.
+pub struct JsonlReader<R: BufRead> {
+    lines: Peekable<io::Lines<R>>,
+    index: usize,
+    header: Header,
+    from_line: fn(&str) -> serde_json::Result<Record>,
+}
+
+impl<R: BufRead> JsonlReader<R> {
+    /// Creates a new JsonlReader. An empty input is read as FASTA.
+    pub fn new(inner: R) -> Result<Self, BrrrrError> {
+        let mut lines = inner.lines().peekable();
+
+        while lines
+            .peek()
+            .is_some_and(|l| l.as_ref().is_ok_and(|l| l.trim().is_empty()))
+        {
+            lines.next();
+        }
+
+        let header = match lines.peek() {
+            Some(Ok(line)) => match serde_json::from_str(line).map_err(io::Error::from)? {
+                serde_json::Value::Object(object) => {
+                    Header::from_columns(&object.keys().collect::<Vec<_>>())?
+                }
+                _ => Header::from_columns::<&str>(&[])?,
+            },
+            _ => Header::Fasta,
+        };
+
+        let from_line: fn(&str) -> serde_json::Result<Record> = match header {
+            Header::Fasta => |l| serde_json::from_str(l).map(Record::Fasta),
+            Header::Fastq => |l| serde_json::from_str(l).map(Record::Fastq),
+            Header::Gff(_) => |l| serde_json::from_str(l).map(Record::Gff),
+            Header::Sam(_) => |l| serde_json::from_str(l).map(Record::Sam),
+        };
+
+        Ok(Self {
+            lines,
+            index: 0,
+            header,
+            from_line,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for JsonlReader<R> {
+    type Item = Result<Record, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+
+            if !line.trim().is_empty() {
+                self.index += 1;
+
+                let record = (self.from_line)(&line).map_err(|e| {
+                    let location = RecordLocation {
+                        index: self.index,
+                        ..Default::default()
+                    };
+                    BrrrrError::from(io::Error::from(e)).at("jsonl", location, Some(line))
+                });
+                return Some(record);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> RecordReader for JsonlReader<R> {
+    type Header = Header;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
+
+/// Reads the FASTA or FASTQ records of csv written by fa2csv or fq2csv, where the kind of
+/// records follows the header row. A quality with spaces is read as the Phred scores written by
+/// fq2csv with `phred_scores` set.
+pub struct CsvReader<R> {
+    records: csv::StringRecordsIntoIter<R>,
+    columns: csv::StringRecord,
+    header: Header,
+}
+
+impl<R: Read> CsvReader<R> {
+    /// Creates a new CsvReader.
+    pub fn new(inner: R) -> Result<Self, BrrrrError> {
+        let mut reader = csv::Reader::from_reader(inner);
+        let columns = reader.headers().map_err(io::Error::from)?.clone();
+
+        let header = match Header::from_columns(&columns.iter().collect::<Vec<_>>())? {
+            header @ (Header::Fasta | Header::Fastq) => header,
+            header => {
+                return Err(BrrrrError::UnsupportedConversion {
+                    records: header.kind().to_string(),
+                    format: String::from("csv"),
+                })
+            }
+        };
+
+        Ok(Self {
+            records: reader.into_records(),
+            columns,
+            header,
+        })
+    }
+
+    fn read_record(&self, record: &csv::StringRecord) -> csv::Result<Record> {
+        match self.header {
+            Header::Fastq => {
+                let record = record.deserialize(Some(&self.columns))?;
+                Ok(Record::Fastq(fastq_from_csv(record)))
+            }
+            _ => Ok(Record::Fasta(record.deserialize(Some(&self.columns))?)),
+        }
+    }
+}
+
+pub(crate) fn fastq_from_csv(mut record: FastqRecord) -> FastqRecord {
+    if let Quality::Ascii(quality) = &record.quality {
+        if quality.contains(' ') {
+            let scores: Result<Vec<u8>, _> = quality.split(' ').map(str::parse).collect();
+            if let Ok(scores) = scores {
+                record.quality = Quality::Phred(scores);
+            }
+        }
+    }
+
+    record
+}
+
+impl<R: Read> Iterator for CsvReader<R> {
+    type Item = Result<Record, BrrrrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, raw) = match self.records.next()? {
+            Ok(row) => match self.read_record(&row) {
+                Ok(record) => return Some(Ok(record)),
+                Err(e) => (e, Some(row.iter().collect::<Vec<_>>().join(","))),
+            },
+            Err(e) => (e, None),
+        };
+
+        let position = e.position().cloned();
+        let location = RecordLocation {
+            // The header row is record 0, so the data records count from 1.
+            index: position.as_ref().map_or(0, |p| p.record() as usize),
+            offset: position.as_ref().map(|p| p.byte()),
+            id: None,
+        };
+        Some(Err(
+            BrrrrError::from(io::Error::from(e)).at("csv", location, raw)
+        ))
+    }
+}
+
+impl<R: Read> RecordReader for CsvReader<R> {
+    type Header = Header;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json_writer::JsonRecordWriter;
+
+    use super::*;
+
+    #[test]
+    fn test_jsonl_and_csv_readers() {
+        let jsonl =
+            b"{\"id\":\"r0\",\"description\":null,\"sequence\":\"AC\",\"quality\":[10,20]}\n\n"
+                as &[u8];
+
+        let reader = JsonlReader::new(jsonl).unwrap();
+        assert!(matches!(reader.header(), Header::Fastq));
+
+        let records: Vec<_> = reader.map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 1);
+        assert!(
+            matches!(&records[0], Record::Fastq(r) if r.quality == Quality::Phred(vec![10, 20]))
+        );
+
+        let csv = b"id,description,sequence,quality\nr0,,AC,10 20\n" as &[u8];
+
+        let reader = CsvReader::new(csv).unwrap();
+        let records: Vec<_> = reader.map(|r| r.unwrap()).collect();
+        assert!(
+            matches!(&records[0], Record::Fastq(r) if r.quality == Quality::Phred(vec![10, 20]))
+        );
+
+        let csv = b"chrom,start,end\nchr1,0,10\n" as &[u8];
+        assert!(matches!(
+            CsvReader::new(csv),
+            Err(BrrrrError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_convert() {
+        let gff = b"##gff-version 3\nchr1\tsrc\tgene\t1\t10\t.\t+\t.\tID=g1\n" as &[u8];
+
+        let reader = GffReader::new(gff, None).unwrap();
+        assert_eq!(reader.header().lines, vec!["##gff-version 3"]);
+
+        let mut output = Vec::new();
+        convert(reader, &mut JsonRecordWriter::new(&mut output)).unwrap();
+
+        let records = JsonlReader::new(&output[..]).unwrap();
+        assert!(matches!(records.header(), Header::Gff(_)));
+        assert_eq!(records.count(), 1);
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let fastq = b"@r0\nAC\n+\nII\n@r1\nACGT\n+\nII\n" as &[u8];
+
+        let mut reader = FastqReader::new(fastq, &QualityOptions::default());
+        assert!(reader.next().unwrap().is_ok());
+
+        match reader.next().unwrap() {
+            Err(BrrrrError::ParseError {
+                format, location, ..
+            }) => {
+                assert_eq!(format, "FASTQ");
+                assert_eq!(
+                    location,
+                    RecordLocation {
+                        index: 2,
+                        offset: Some(12),
+                        id: Some(String::from("r1")),
+                    }
+                );
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let fasta = b">r0\nAC\n>r1\nA\xffC\n" as &[u8];
+        let errors: Vec<_> = FastaReader::new(fasta).filter_map(Result::err).collect();
+        assert_eq!(
+            errors[0].to_string(),
+            "cannot parse FASTA record 2 (r1) at byte 7: invalid encoding in sequence: \
+             invalid utf-8 sequence of 1 bytes from index 1"
+        );
+    }
+}
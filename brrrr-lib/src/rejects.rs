@@ -0,0 +1,270 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, ErrorKind, Write};
+
+use serde::Serialize;
+
+use crate::errors::{BrrrrError, RecordLocation};
+
+/// What a converter does with a record that fails to parse.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop at the first bad record.
+    #[default]
+    Strict,
+    /// Skip bad records, counting them by error type.
+    Skip,
+    /// Skip bad records, and also write each one with its error to the reject log.
+    SkipAndLog,
+}
+
+/// A rejected record as it is written to the reject log.
+#[derive(Serialize)]
+struct Reject<'a> {
+    format: &'a str,
+    record: usize,
+    offset: Option<u64>,
+    id: Option<&'a str>,
+    error: String,
+    raw: Option<&'a str>,
+}
+
+/// Rejects applies an ErrorPolicy to the records a converter reads. Records that fail to parse
+/// are counted by error type, and written as jsonl to the reject log under SkipAndLog. Errors
+/// that aren't about a single record, e.g. a missing column, always stop the conversion.
+///
+/// # Examples
+///
+/// ```
+/// use brrrr_lib::json_writer;
+/// use brrrr_lib::rejects::{ErrorPolicy, Rejects};
+///
+/// let input = b"@r0\nAC\n+\nII\n@r1\nAC\n+\nI\n" as &[u8];
+/// let mut rejects = Rejects::new(ErrorPolicy::Skip);
+///
+/// let mut output = Vec::new();
+/// json_writer::fq2jsonl(input, &mut output, &Default::default(), None, &mut rejects).unwrap();
+/// assert_eq!(rejects.total(), 1);
+/// ```
+pub struct Rejects {
+    policy: ErrorPolicy,
+    log: Option<Box<dyn Write>>,
+    counts: BTreeMap<String, usize>,
+}
+
+impl Default for Rejects {
+    fn default() -> Self {
+        Rejects::new(ErrorPolicy::Strict)
+    }
+}
+
+impl Rejects {
+    /// Creates a new Rejects, which under SkipAndLog skips records without a log until one is
+    /// set with `with_log`.
+    pub fn new(policy: ErrorPolicy) -> Self {
+        Self {
+            policy,
+            log: None,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the reject log that SkipAndLog writes rejected records to.
+    pub fn with_log<W: Write + 'static>(mut self, log: W) -> Self {
+        self.log = Some(Box::new(log));
+        self
+    }
+
+    /// Returns the ErrorPolicy.
+    pub fn policy(&self) -> ErrorPolicy {
+        self.policy
+    }
+
+    /// Returns the number of rejected records for each error type.
+    pub fn counts(&self) -> &BTreeMap<String, usize> {
+        &self.counts
+    }
+
+    /// Returns the number of rejected records.
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// Flushes the reject log.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.log {
+            Some(log) => log.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `records` without the ones that fail to parse, unless the policy is Strict.
+    /// Record errors that aren't a ParseError already are wrapped in one for `format`, numbering
+    /// the records from the start of `records`.
+    pub fn filter<'a, I, T, E>(
+        &'a mut self,
+        format: &'a str,
+        records: I,
+    ) -> impl Iterator<Item = Result<T, BrrrrError>> + 'a
+    where
+        I: IntoIterator<Item = Result<T, E>>,
+        I::IntoIter: 'a,
+        BrrrrError: From<E>,
+    {
+        records
+            .into_iter()
+            .enumerate()
+            .filter_map(move |(i, record)| match record {
+                Ok(record) => Some(Ok(record)),
+                Err(e) => {
+                    let mut e = BrrrrError::from(e);
+
+                    if is_record_error(&e) {
+                        let location = RecordLocation {
+                            index: i + 1,
+                            ..Default::default()
+                        };
+                        e = e.at(format, location, None);
+                    }
+
+                    self.reject(e).err().map(Err)
+                }
+            })
+    }
+
+    /// Counts and logs `e` if the policy skips it, or returns it if not.
+    fn reject(&mut self, e: BrrrrError) -> Result<(), BrrrrError> {
+        if self.policy == ErrorPolicy::Strict || !is_record_error(&e) {
+            return Err(e);
+        }
+
+        *self.counts.entry(error_type(&e)).or_insert(0) += 1;
+
+        if let (ErrorPolicy::SkipAndLog, Some(log)) = (self.policy, &mut self.log) {
+            if let BrrrrError::ParseError {
+                format,
+                location,
+                raw,
+                source,
+            } = &e
+            {
+                let reject = Reject {
+                    format,
+                    record: location.index,
+                    offset: location.offset,
+                    id: location.id.as_deref(),
+                    error: source.to_string(),
+                    raw: raw.as_deref(),
+                };
+
+                serde_json::to_writer(&mut *log, &reject).map_err(io::Error::from)?;
+                log.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rejects {
+    /// Writes the summary of rejected records, e.g. `rejected 3 records: 2 invalid data, 1
+    /// invalid encoding`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        write!(
+            f,
+            "rejected {} record{}",
+            total,
+            if total == 1 { "" } else { "s" }
+        )?;
+
+        let counts: Vec<String> = self
+            .counts
+            .iter()
+            .map(|(error_type, count)| format!("{} {}", count, error_type))
+            .collect();
+
+        if !counts.is_empty() {
+            write!(f, ": {}", counts.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns true if `e` is about a single record, so the records after it can still be read.
+fn is_record_error(e: &BrrrrError) -> bool {
+    match e {
+        BrrrrError::ParseError { source, .. } => is_record_error(source),
+        BrrrrError::IOError(e) => {
+            matches!(e.kind(), ErrorKind::InvalidData | ErrorKind::UnexpectedEof)
+        }
+        BrrrrError::InvalidEncoding { .. } => true,
+        _ => false,
+    }
+}
+
+/// Returns the type that `e` is counted under in the summary.
+fn error_type(e: &BrrrrError) -> String {
+    match e {
+        BrrrrError::ParseError { source, .. } => error_type(source),
+        BrrrrError::IOError(e) => e.kind().to_string(),
+        BrrrrError::InvalidEncoding { .. } => String::from("invalid encoding"),
+        e => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::reader::FastqReader;
+
+    /// A reject log that can still be read after it's moved into a Rejects.
+    #[derive(Clone, Default)]
+    struct SharedLog(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const FASTQ: &[u8] = b"@r0\nAC\n+\nII\n@r1\nAC\n+\nI\n@r2\nGT\n+\nII\n";
+
+    #[test]
+    fn test_policies() {
+        let read = |rejects: &mut Rejects| {
+            let reader = FastqReader::new(FASTQ, &Default::default());
+            rejects
+                .filter("FASTQ", reader)
+                .map(|r| r.map(|record| record.id))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        assert!(read(&mut Rejects::default()).is_err());
+
+        let mut rejects = Rejects::new(ErrorPolicy::Skip);
+        assert_eq!(read(&mut rejects).unwrap(), vec!["r0", "r2"]);
+        assert_eq!(rejects.to_string(), "rejected 1 record: 1 invalid data");
+
+        let log = SharedLog::default();
+        let mut rejects = Rejects::new(ErrorPolicy::SkipAndLog).with_log(log.clone());
+        assert_eq!(read(&mut rejects).unwrap(), vec!["r0", "r2"]);
+
+        let logged: serde_json::Value = serde_json::from_slice(&log.0.borrow()).unwrap();
+        assert_eq!(logged["record"], 2);
+        assert_eq!(logged["offset"], 12);
+        assert_eq!(logged["id"], "r1");
+        assert_eq!(logged["raw"], "@r1\nAC\n+\nI");
+    }
+}
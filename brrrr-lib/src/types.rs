@@ -1,15 +1,25 @@
 // (c) Copyright 2022 Trent Hauck
 // All Rights Reserved
 
+use indexmap::IndexMap;
+use noodles::core;
 use noodles::fasta;
 use noodles::fastq;
 use noodles::gff;
 use noodles::sam::alignment;
+use noodles::sam::header::ReferenceSequences;
+use noodles::sam::record::{cigar, data, Flags, MappingQuality, QualityScores, Sequence};
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
 use std::str;
 
+use crate::errors::BrrrrError;
+use crate::quality::{self, QualityOptions};
+
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct FastaRecord {
     pub id: String,
@@ -17,15 +27,46 @@ pub struct FastaRecord {
     pub sequence: String,
 }
 
-impl From<fasta::Record> for FastaRecord {
-    fn from(src: fasta::Record) -> FastaRecord {
-        let seq = src.sequence();
-        let ss = str::from_utf8(&seq.as_ref()).unwrap();
+impl TryFrom<fasta::Record> for FastaRecord {
+    type Error = BrrrrError;
 
-        FastaRecord {
+    fn try_from(src: fasta::Record) -> Result<FastaRecord, BrrrrError> {
+        Ok(FastaRecord {
             id: src.name().to_string(),
-            description: src.description().map_or(None, |i| Some(i.to_string())),
-            sequence: String::from(ss),
+            description: src.description().map(|i| i.to_string()),
+            sequence: utf8("sequence", src.sequence().as_ref())?.to_string(),
+        })
+    }
+}
+
+/// Reads `value` as UTF-8, with an InvalidEncoding error naming `field` if it isn't.
+pub(crate) fn utf8<'a>(field: &str, value: &'a [u8]) -> Result<&'a str, BrrrrError> {
+    str::from_utf8(value).map_err(|e| BrrrrError::InvalidEncoding {
+        field: field.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// FASTQ quality scores, either as the ASCII string from the file or as Phred scores.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Quality {
+    Ascii(String),
+    Phred(Vec<u8>),
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Quality::Ascii(String::new())
+    }
+}
+
+impl Quality {
+    /// Returns the quality as ASCII scores, encoding Phred scores as Phred+33.
+    pub fn to_ascii(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Quality::Ascii(quality) => Ok(quality.as_bytes().to_vec()),
+            Quality::Phred(scores) => quality::encode(scores),
         }
     }
 }
@@ -35,63 +76,115 @@ pub struct FastqRecord {
     pub id: String,
     pub description: Option<String>,
     pub sequence: String,
-    pub quality: String,
+    pub quality: Quality,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_quality: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_quality: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_errors: Option<f64>,
 }
 
-impl From<fastq::Record> for FastqRecord {
-    fn from(src: fastq::Record) -> FastqRecord {
-        let seq = src.sequence();
+impl FastqRecord {
+    /// Converts a noodles FASTQ record, decoding the quality scores as set by `options`.
+    pub fn new(src: &fastq::Record, options: &QualityOptions) -> Result<FastqRecord, BrrrrError> {
+        if *options == QualityOptions::default() {
+            return FastqRecord::try_from(src);
+        }
 
-        let ss = str::from_utf8(&seq.as_ref()).unwrap();
+        let scores = quality::decode(src.quality_scores(), options.offset)?;
 
-        let noodles_quality = str::from_utf8(src.quality_scores()).unwrap();
-        let name = str::from_utf8(src.name()).unwrap();
+        let summary = if options.summary {
+            Some(quality::summarize(&scores))
+        } else {
+            None
+        };
 
-        FastqRecord {
-            id: String::from(name),
-            description: None,
-            sequence: String::from(ss),
-            quality: String::from(noodles_quality),
+        let quality = if options.phred_scores {
+            Quality::Phred(scores)
+        } else {
+            let ascii = quality::encode(&scores)?;
+            Quality::Ascii(String::from_utf8_lossy(&ascii).into_owned())
+        };
+
+        Ok(FastqRecord {
+            quality,
+            mean_quality: summary.and_then(|s| s.mean_quality),
+            min_quality: summary.and_then(|s| s.min_quality),
+            expected_errors: summary.map(|s| s.expected_errors),
+            ..FastqRecord::try_from(src)?
+        })
+    }
+}
+
+impl TryFrom<&fastq::Record> for FastqRecord {
+    type Error = BrrrrError;
+
+    fn try_from(src: &fastq::Record) -> Result<FastqRecord, BrrrrError> {
+        if src.sequence().len() != src.quality_scores().len() {
+            return Err(BrrrrError::from(invalid_data(format!(
+                "the sequence has {} bases but the quality has {} scores",
+                src.sequence().len(),
+                src.quality_scores().len()
+            ))));
         }
+
+        Ok(FastqRecord {
+            id: utf8("name", src.name())?.to_string(),
+            description: None,
+            sequence: utf8("sequence", src.sequence())?.to_string(),
+            quality: Quality::Ascii(utf8("quality", src.quality_scores())?.to_string()),
+            ..Default::default()
+        })
     }
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+/// A GFF record. Attributes keep their order in the file and every value, so a repeated key or a comma-separated value such
+/// as `Parent=a,b` becomes a list.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GffRecord {
     pub seqname: String,
     pub source: String,
     pub feature: String,
     pub start: usize,
     pub end: usize,
-    pub score: Option<f32>,
+    pub score: Option<f64>,
     pub strand: String,
     pub frame: Option<String>,
-    pub attribute: HashMap<String, String>,
+    pub attribute: IndexMap<String, Vec<String>>,
+    /// The GTF attributes whose values were written without quotes, e.g. `level 2`, so that they
+    /// are written back the same way. Every other GTF value is quoted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unquoted_attributes: Vec<String>,
+    /// The directives and comments that follow the record, e.g. `###` or the
+    /// `##sequence-region` of the next sequence, which are written back after it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directives: Vec<String>,
 }
 
 impl From<gff::Record> for GffRecord {
+    /// Converts a noodles GFF record. noodles has already percent-decoded the attribute values,
+    /// so an escaped comma can't be told apart from a separator here; `crate::gff::Reader`
+    /// reads the attributes from the raw line instead.
     fn from(src: gff::Record) -> GffRecord {
         let seqname = src.reference_sequence_name();
         let source = src.source();
         let feature_type = src.ty();
         let start = src.start();
         let end = src.end();
-        let score = src.score();
+        // Widened through its decimal form, so that a score of 0.1 stays 0.1 rather than
+        // 0.10000000149011612.
+        let score = src
+            .score()
+            .map(|s| s.to_string().parse().unwrap_or_else(|_| f64::from(s)));
         let strand = src.strand();
-        let phase = src.phase().map_or(None, |f| Some(f.to_string()));
+        let phase = src.phase().map(|f| f.to_string());
 
-        let mut gff_attrs = HashMap::<String, String>::new();
+        let mut gff_attrs = IndexMap::<String, Vec<String>>::new();
 
         for i in src.attributes().iter() {
-            let k = String::from(i.key());
-            let v = String::from(i.value());
-
-            // TODO: this isn't faithful to GFF3, value should be a vector
-            if gff_attrs.contains_key(&k) {
-                continue;
-            } else {
-                gff_attrs.insert(k, v);
-            }
+            let values = gff_attrs.entry(String::from(i.key())).or_default();
+            values.extend(i.value().split(',').map(String::from));
         }
 
         GffRecord {
@@ -104,14 +197,16 @@ impl From<gff::Record> for GffRecord {
             strand: String::from(strand.as_ref()),
             frame: phase,
             attribute: gff_attrs,
+            unquoted_attributes: Vec::new(),
+            directives: Vec::new(),
         }
     }
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Operation {
-    kind: String,
-    len: usize,
+    pub kind: String,
+    pub len: usize,
 }
 
 impl Operation {
@@ -120,9 +215,9 @@ impl Operation {
     }
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Cigar {
-    operations: Vec<Operation>,
+    pub operations: Vec<Operation>,
 }
 
 impl Cigar {
@@ -131,75 +226,326 @@ impl Cigar {
     }
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+/// The value of a SAM auxiliary field. Integers of every width are widened to `i64`, the width
+/// is kept in the field's type.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SamValue {
+    Integer(i64),
+    Float(f32),
+    String(String),
+    IntegerArray(Vec<i64>),
+    FloatArray(Vec<f32>),
+}
+
+/// A SAM auxiliary field, e.g. `NM:i:1`. `ty` is the SAM/BAM type code, with the subtype for
+/// arrays, e.g. `C` or `B:s`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SamField {
+    pub ty: String,
+    pub value: SamValue,
+}
+
+impl From<&data::field::Value> for SamField {
+    fn from(src: &data::field::Value) -> SamField {
+        use data::field::Value;
+
+        fn widen<T: Copy + Into<i64>>(values: &[T]) -> SamValue {
+            SamValue::IntegerArray(values.iter().map(|v| (*v).into()).collect())
+        }
+
+        let ty = match src.subtype() {
+            Some(subtype) => format!("{}:{}", char::from(src.ty()), char::from(subtype)),
+            None => char::from(src.ty()).to_string(),
+        };
+
+        let value = match src {
+            Value::Character(c) => SamValue::String(char::from(*c).to_string()),
+            Value::Float(f) => SamValue::Float(*f),
+            Value::String(s) | Value::Hex(s) => SamValue::String(s.clone()),
+            Value::Int8Array(v) => widen(v),
+            Value::UInt8Array(v) => widen(v),
+            Value::Int16Array(v) => widen(v),
+            Value::UInt16Array(v) => widen(v),
+            Value::Int32Array(v) => widen(v),
+            Value::UInt32Array(v) => widen(v),
+            Value::FloatArray(v) => SamValue::FloatArray(v.clone()),
+            v => SamValue::Integer(v.as_int().unwrap_or_default()),
+        };
+
+        SamField { ty, value }
+    }
+}
+
+impl SamField {
+    /// Converts the field back to a noodles value of the width given by `ty`.
+    pub fn to_value(&self) -> io::Result<data::field::Value> {
+        use data::field::Value;
+
+        fn narrow<T: TryFrom<i64>>(values: &[i64]) -> io::Result<Vec<T>> {
+            values
+                .iter()
+                .map(|v| T::try_from(*v).map_err(|_| invalid_data(format!("invalid value: {}", v))))
+                .collect()
+        }
+
+        let value = match (self.ty.as_str(), &self.value) {
+            ("A", SamValue::String(s)) => {
+                let c = s.chars().next().unwrap_or_default();
+                Value::try_from(c).map_err(invalid_data)?
+            }
+            ("c", SamValue::Integer(i)) => Value::Int8(narrow(&[*i])?[0]),
+            ("C", SamValue::Integer(i)) => Value::UInt8(narrow(&[*i])?[0]),
+            ("s", SamValue::Integer(i)) => Value::Int16(narrow(&[*i])?[0]),
+            ("S", SamValue::Integer(i)) => Value::UInt16(narrow(&[*i])?[0]),
+            ("i", SamValue::Integer(i)) => Value::Int32(narrow(&[*i])?[0]),
+            ("I", SamValue::Integer(i)) => Value::UInt32(narrow(&[*i])?[0]),
+            ("f", SamValue::Float(f)) => Value::Float(*f),
+            ("Z", SamValue::String(s)) => Value::String(s.clone()),
+            ("H", SamValue::String(s)) => Value::Hex(s.clone()),
+            ("B:c", SamValue::IntegerArray(v)) => Value::Int8Array(narrow(v)?),
+            ("B:C", SamValue::IntegerArray(v)) => Value::UInt8Array(narrow(v)?),
+            ("B:s", SamValue::IntegerArray(v)) => Value::Int16Array(narrow(v)?),
+            ("B:S", SamValue::IntegerArray(v)) => Value::UInt16Array(narrow(v)?),
+            ("B:i", SamValue::IntegerArray(v)) => Value::Int32Array(narrow(v)?),
+            ("B:I", SamValue::IntegerArray(v)) => Value::UInt32Array(narrow(v)?),
+            ("B:f", SamValue::FloatArray(v)) => Value::FloatArray(v.clone()),
+            (ty, value) => {
+                return Err(invalid_data(format!(
+                    "invalid SAM data value for type {}: {:?}",
+                    ty, value
+                )))
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Data {
-    fields: HashMap<String, String>,
+    pub fields: IndexMap<String, SamField>,
 }
 
 impl Data {
-    pub fn new(fields: HashMap<String, String>) -> Self {
+    pub fn new(fields: IndexMap<String, SamField>) -> Self {
         Self { fields }
     }
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SamRecord {
-    read_name: String,
-    flags: u16,
-    reference_sequence_id: Option<usize>,
-    alignment_start: Option<usize>,
-    mapping_quality: Option<u8>,
-    cigar: Cigar,
-    mate_reference_sequence_id: Option<usize>,
-    mate_alignment_start: Option<usize>,
-    template_length: i32,
-    sequence: String,
-    quality_scores: Vec<String>,
-    data: Data,
-}
-
-impl From<alignment::Record> for SamRecord {
-    fn from(src: alignment::Record) -> SamRecord {
-        let bits = src.flags().bits();
-
-        let alignment_start = src.alignment_start().map(|f| f.into());
-        let mapping_quality = src.mapping_quality().map(|f| f.into());
-        let mate_alignment_start = src.mate_alignment_start().map(|f| f.into());
-        let quality_scores = src
-            .quality_scores()
-            .as_ref()
-            .into_iter()
-            .map(|f| f.to_string())
-            .collect();
+    pub read_name: Option<String>,
+    pub flags: u16,
+    pub reference_sequence: Option<String>,
+    pub alignment_start: Option<usize>,
+    pub mapping_quality: Option<u8>,
+    pub cigar: Cigar,
+    pub mate_reference_sequence: Option<String>,
+    pub mate_alignment_start: Option<usize>,
+    pub template_length: i32,
+    pub sequence: String,
+    pub quality_scores: Vec<u8>,
+    pub data: Data,
+}
+
+impl SamRecord {
+    /// Converts a noodles alignment record, resolving the reference sequence IDs to names with
+    /// `reference_sequences`.
+    pub fn new(src: &alignment::Record, reference_sequences: &ReferenceSequences) -> SamRecord {
+        let reference_sequence_name = |id: Option<usize>| {
+            id.and_then(|i| reference_sequences.get_index(i))
+                .map(|(name, _)| name.clone())
+        };
 
         let operations = src
             .cigar()
             .as_ref()
-            .into_iter()
+            .iter()
             .map(|f| Operation::new(f.kind().to_string(), f.len()))
             .collect();
 
-        let sequence = src.sequence().to_string();
-
-        let data = src.data();
-        let fields = data
+        let fields = src
+            .data()
             .values()
-            .map(|field| (field.tag().to_string(), field.value().to_string()))
+            .map(|field| (field.tag().to_string(), SamField::from(field.value())))
             .collect();
 
         SamRecord {
-            read_name: src.read_name().unwrap().to_string(),
-            flags: bits,
-            reference_sequence_id: src.reference_sequence_id(),
-            alignment_start,
-            mapping_quality,
+            read_name: src.read_name().map(|r| r.to_string()),
+            flags: src.flags().bits(),
+            reference_sequence: reference_sequence_name(src.reference_sequence_id()),
+            alignment_start: src.alignment_start().map(usize::from),
+            mapping_quality: src.mapping_quality().map(u8::from),
             cigar: Cigar::new(operations),
-            mate_reference_sequence_id: src.mate_reference_sequence_id(),
-            mate_alignment_start,
+            mate_reference_sequence: reference_sequence_name(src.mate_reference_sequence_id()),
+            mate_alignment_start: src.mate_alignment_start().map(usize::from),
             template_length: src.template_length(),
-            quality_scores,
-            sequence,
+            sequence: src.sequence().to_string(),
+            quality_scores: src
+                .quality_scores()
+                .as_ref()
+                .iter()
+                .map(|s| u8::from(*s))
+                .collect(),
             data: Data::new(fields),
         }
     }
+
+    /// Converts back to a noodles alignment record, resolving the reference sequence names to
+    /// IDs with `reference_sequences`.
+    pub fn to_alignment_record(
+        &self,
+        reference_sequences: &ReferenceSequences,
+    ) -> io::Result<alignment::Record> {
+        let reference_sequence_id = |name: &Option<String>| {
+            name.as_ref()
+                .map(|n| {
+                    reference_sequences.get_index_of(n).ok_or_else(|| {
+                        invalid_data(format!("reference sequence {} is not in the header", n))
+                    })
+                })
+                .transpose()
+        };
+
+        let position = |p: Option<usize>| {
+            p.map(|p| core::Position::new(p).ok_or_else(|| invalid_data("invalid position")))
+                .transpose()
+        };
+
+        let operations = self
+            .cigar
+            .operations
+            .iter()
+            .map(|op| {
+                let kind = op.kind.parse().map_err(invalid_data)?;
+                Ok(cigar::Op::new(kind, op.len))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let fields = self
+            .data
+            .fields
+            .iter()
+            .map(|(tag, field)| {
+                let tag = tag.parse().map_err(invalid_data)?;
+                Ok(data::Field::new(tag, field.to_value()?))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut record = alignment::Record::default();
+
+        *record.read_name_mut() = self
+            .read_name
+            .as_ref()
+            .map(|n| n.parse())
+            .transpose()
+            .map_err(invalid_data)?;
+        *record.flags_mut() = Flags::from(self.flags);
+        *record.reference_sequence_id_mut() = reference_sequence_id(&self.reference_sequence)?;
+        *record.alignment_start_mut() = position(self.alignment_start)?;
+        *record.mapping_quality_mut() = self.mapping_quality.and_then(MappingQuality::new);
+        *record.cigar_mut() =
+            noodles::sam::record::Cigar::try_from(operations).map_err(invalid_data)?;
+        *record.mate_reference_sequence_id_mut() =
+            reference_sequence_id(&self.mate_reference_sequence)?;
+        *record.mate_alignment_start_mut() = position(self.mate_alignment_start)?;
+        *record.template_length_mut() = self.template_length;
+        *record.sequence_mut() =
+            Sequence::try_from(self.sequence.as_bytes().to_vec()).map_err(invalid_data)?;
+        *record.quality_scores_mut() =
+            QualityScores::try_from(self.quality_scores.clone()).map_err(invalid_data)?;
+        *record.data_mut() = data::Data::try_from(fields).map_err(invalid_data)?;
+
+        Ok(record)
+    }
+}
+
+/// A typed VCF INFO or FORMAT value.
+///
+/// Values are typed according to the `##INFO` and `##FORMAT` header definitions, and
+/// multi-valued fields keep per-element missing values (`.`) as `None`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VcfValue {
+    Flag(bool),
+    Integer(i32),
+    Float(f32),
+    String(String),
+    IntegerArray(Vec<Option<i32>>),
+    FloatArray(Vec<Option<f32>>),
+    StringArray(Vec<Option<String>>),
+}
+
+impl fmt::Display for VcfValue {
+    /// Formats the value as it appears in a VCF, with list elements comma separated and
+    /// missing elements written as `.`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: fmt::Display>(values: &[Option<T>]) -> String {
+            values
+                .iter()
+                .map(|v| v.as_ref().map_or(String::from("."), |v| v.to_string()))
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+
+        match self {
+            VcfValue::Flag(b) => write!(f, "{}", b),
+            VcfValue::Integer(i) => write!(f, "{}", i),
+            VcfValue::Float(x) => write!(f, "{}", x),
+            VcfValue::String(s) => f.write_str(s),
+            VcfValue::IntegerArray(v) => f.write_str(&join(v)),
+            VcfValue::FloatArray(v) => f.write_str(&join(v)),
+            VcfValue::StringArray(v) => f.write_str(&join(v)),
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct VcfRecord {
+    pub chrom: String,
+    pub pos: usize,
+    pub id: Option<String>,
+    pub reference: String,
+    pub alternate: Vec<String>,
+    pub quality: Option<f32>,
+    pub filter: Vec<String>,
+    pub info: BTreeMap<String, VcfValue>,
+    pub samples: BTreeMap<String, BTreeMap<String, VcfValue>>,
+}
+
+/// A BED or bedGraph record. Only the columns present in the source are set, so optional
+/// columns are left out of the serialized record rather than written as null.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BedRecord {
+    pub chrom: String,
+    pub start: u64,
+    pub end: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strand: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thick_start: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thick_end: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_rgb: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_sizes: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_starts: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
 }
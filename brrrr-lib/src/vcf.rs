@@ -0,0 +1,546 @@
+// (c) Copyright 2022 Trent Hauck
+// All Rights Reserved
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use crate::types::{VcfRecord, VcfValue};
+
+const MISSING: &str = ".";
+
+/// The number of values an INFO or FORMAT field holds, i.e. its `Number` attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Number {
+    Count(usize),
+    A,
+    R,
+    G,
+    Unknown,
+}
+
+impl FromStr for Number {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "A" => Ok(Number::A),
+            "R" => Ok(Number::R),
+            "G" => Ok(Number::G),
+            "." => Ok(Number::Unknown),
+            n => n.parse().map(Number::Count).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("invalid Number: {}", n))
+            }),
+        }
+    }
+}
+
+/// The type of an INFO or FORMAT field, i.e. its `Type` attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    Integer,
+    Float,
+    Flag,
+    Character,
+    String,
+}
+
+impl FromStr for ValueType {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Integer" => Ok(ValueType::Integer),
+            "Float" => Ok(ValueType::Float),
+            "Flag" => Ok(ValueType::Flag),
+            "Character" => Ok(ValueType::Character),
+            "String" => Ok(ValueType::String),
+            t => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid Type: {}", t),
+            )),
+        }
+    }
+}
+
+/// An `##INFO` or `##FORMAT` header definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    pub id: String,
+    pub number: Number,
+    pub ty: ValueType,
+    pub description: String,
+}
+
+impl Definition {
+    /// Returns true if the field holds at most one value per record (or sample).
+    pub fn is_scalar(&self) -> bool {
+        self.ty == ValueType::Flag || matches!(self.number, Number::Count(0) | Number::Count(1))
+    }
+}
+
+/// The parsed VCF header.
+///
+/// The raw meta lines are kept alongside the definitions that are needed to type records. The
+/// string and contig dictionaries are what BCF records index into.
+#[derive(Clone, Debug, Default)]
+pub struct Header {
+    pub meta: Vec<String>,
+    pub infos: Vec<Definition>,
+    pub formats: Vec<Definition>,
+    pub filters: Vec<String>,
+    pub contigs: Vec<String>,
+    pub sample_names: Vec<String>,
+    pub(crate) string_dictionary: Vec<String>,
+    pub(crate) contig_dictionary: Vec<String>,
+}
+
+impl Header {
+    /// Returns the `##INFO` definition for `id`, if there is one.
+    pub fn info(&self, id: &str) -> Option<&Definition> {
+        self.infos.iter().find(|d| d.id == id)
+    }
+
+    /// Returns the `##FORMAT` definition for `id`, if there is one.
+    pub fn format(&self, id: &str) -> Option<&Definition> {
+        self.formats.iter().find(|d| d.id == id)
+    }
+}
+
+/// Returns the entries of `values` without a definition in `definitions`, e.g. INFO keys without
+/// an `##INFO` line. Flags have no value, and other values are as they're written in a VCF.
+pub fn undeclared<'a>(
+    definitions: &'a [Definition],
+    values: &'a BTreeMap<String, VcfValue>,
+) -> impl Iterator<Item = (&'a str, Option<String>)> + 'a {
+    values
+        .iter()
+        .filter(move |(key, _)| !definitions.iter().any(|d| &d.id == *key))
+        .map(|(key, value)| match value {
+            VcfValue::Flag(_) => (key.as_str(), None),
+            value => (key.as_str(), Some(value.to_string())),
+        })
+}
+
+impl FromStr for Header {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut header = Header::default();
+
+        let mut strings = vec![(String::from("PASS"), None)];
+        let mut contigs = Vec::new();
+
+        for line in s.lines() {
+            if let Some(meta) = line.strip_prefix("##") {
+                header.meta.push(meta.to_string());
+
+                let (key, value) = match meta.split_once('=') {
+                    Some(kv) => kv,
+                    None => continue,
+                };
+
+                let fields = match value.strip_prefix('<').and_then(|v| v.strip_suffix('>')) {
+                    Some(v) => parse_structured_fields(v)?,
+                    None => continue,
+                };
+
+                let id = fields.get("ID").cloned().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("missing ID in header line: {}", line),
+                    )
+                })?;
+                let idx = fields.get("IDX").and_then(|i| i.parse::<usize>().ok());
+
+                match key {
+                    "INFO" | "FORMAT" => {
+                        let definition = parse_definition(id.clone(), &fields)?;
+                        if key == "INFO" {
+                            header.infos.push(definition);
+                        } else {
+                            header.formats.push(definition);
+                        }
+                        strings.push((id, idx));
+                    }
+                    "FILTER" => {
+                        header.filters.push(id.clone());
+                        strings.push((id, idx));
+                    }
+                    "contig" => {
+                        header.contigs.push(id.clone());
+                        contigs.push((id, idx));
+                    }
+                    _ => continue,
+                }
+            } else if let Some(columns) = line.strip_prefix('#') {
+                header.sample_names = columns.split('\t').skip(9).map(String::from).collect();
+            }
+        }
+
+        header.string_dictionary = build_dictionary(strings);
+        header.contig_dictionary = build_dictionary(contigs);
+
+        Ok(header)
+    }
+}
+
+/// Builds a BCF dictionary, honoring explicit `IDX` attributes and otherwise numbering entries
+/// in the order they first appear.
+fn build_dictionary(entries: Vec<(String, Option<usize>)>) -> Vec<String> {
+    let mut dictionary: Vec<String> = Vec::new();
+
+    for (id, idx) in entries {
+        match idx {
+            Some(i) => {
+                if dictionary.len() <= i {
+                    dictionary.resize(i + 1, String::new());
+                }
+                dictionary[i] = id;
+            }
+            None => {
+                if !dictionary.contains(&id) {
+                    dictionary.push(id);
+                }
+            }
+        }
+    }
+
+    dictionary
+}
+
+fn parse_definition(id: String, fields: &BTreeMap<String, String>) -> io::Result<Definition> {
+    let number = fields
+        .get("Number")
+        .map_or(Ok(Number::Unknown), |n| n.parse())?;
+    let ty = fields
+        .get("Type")
+        .map_or(Ok(ValueType::String), |t| t.parse())?;
+    let description = fields.get("Description").cloned().unwrap_or_default();
+
+    Ok(Definition {
+        id,
+        number,
+        ty,
+        description,
+    })
+}
+
+/// Parses the inside of a structured meta line, e.g. `ID=DP,Number=1,Description="a, b"`.
+fn parse_structured_fields(s: &str) -> io::Result<BTreeMap<String, String>> {
+    let mut fields = BTreeMap::new();
+    let mut chars = s.chars().peekable();
+
+    loop {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.is_empty() {
+            break;
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            if let Some(c) = chars.next() {
+                if c != ',' {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid structured header line: {}", s),
+                    ));
+                }
+            }
+        } else {
+            value = chars.by_ref().take_while(|c| *c != ',').collect();
+        }
+
+        fields.insert(key.trim().to_string(), value);
+    }
+
+    Ok(fields)
+}
+
+/// Reads a VCF header and its records from a buffered reader.
+pub struct Reader<R> {
+    inner: R,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a new VCF reader.
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    /// Reads the header, up to and including the `#CHROM` line.
+    pub fn read_header(&mut self) -> io::Result<Header> {
+        let mut text = String::new();
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            if self.inner.read_line(&mut line)? == 0 {
+                break;
+            }
+
+            text.push_str(&line);
+
+            if !line.starts_with("##") {
+                break;
+            }
+        }
+
+        text.parse()
+    }
+
+    /// Returns an iterator over the records, typed by `header`.
+    pub fn records<'a>(&'a mut self, header: &'a Header) -> Records<'a, R> {
+        Records {
+            reader: self,
+            header,
+            buf: String::new(),
+        }
+    }
+}
+
+/// An iterator over the records of a VCF.
+pub struct Records<'a, R> {
+    reader: &'a mut Reader<R>,
+    header: &'a Header,
+    buf: String,
+}
+
+impl<'a, R: BufRead> Iterator for Records<'a, R> {
+    type Item = io::Result<VcfRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+
+            match self.reader.inner.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    let line = self.buf.trim_end_matches(&['\n', '\r'][..]);
+                    if line.is_empty() {
+                        continue;
+                    }
+                    return Some(parse_record(self.header, line));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Parses a single tab-delimited VCF data line.
+pub fn parse_record(header: &Header, s: &str) -> io::Result<VcfRecord> {
+    let fields: Vec<&str> = s.split('\t').collect();
+
+    if fields.len() < 8 {
+        return Err(invalid_data(format!(
+            "expected at least 8 VCF columns, found {}",
+            fields.len()
+        )));
+    }
+
+    let pos = fields[1]
+        .parse()
+        .map_err(|_| invalid_data(format!("invalid POS: {}", fields[1])))?;
+
+    let quality = match fields[5] {
+        MISSING => None,
+        q => Some(
+            q.parse()
+                .map_err(|_| invalid_data(format!("invalid QUAL: {}", q)))?,
+        ),
+    };
+
+    let mut info = BTreeMap::new();
+    if fields[7] != MISSING {
+        for entry in fields[7].split(';') {
+            match entry.split_once('=') {
+                Some((key, raw)) => {
+                    if let Some(value) = parse_value(header.info(key), raw)? {
+                        info.insert(key.to_string(), value);
+                    }
+                }
+                None => {
+                    info.insert(entry.to_string(), VcfValue::Flag(true));
+                }
+            }
+        }
+    }
+
+    let mut samples = BTreeMap::new();
+    if let Some(format) = fields.get(8) {
+        let keys: Vec<&str> = format.split(':').collect();
+
+        for (name, sample) in header.sample_names.iter().zip(fields.iter().skip(9)) {
+            let mut values = BTreeMap::new();
+
+            for (key, raw) in keys.iter().zip(sample.split(':')) {
+                if let Some(value) = parse_value(header.format(key), raw)? {
+                    values.insert(key.to_string(), value);
+                }
+            }
+
+            samples.insert(name.clone(), values);
+        }
+    }
+
+    Ok(VcfRecord {
+        chrom: fields[0].to_string(),
+        pos,
+        id: parse_missing(fields[2]).map(String::from),
+        reference: fields[3].to_string(),
+        alternate: split_list(fields[4], ','),
+        quality,
+        filter: split_list(fields[6], ';'),
+        info,
+        samples,
+    })
+}
+
+fn parse_missing(s: &str) -> Option<&str> {
+    match s {
+        MISSING | "" => None,
+        s => Some(s),
+    }
+}
+
+fn split_list(s: &str, delimiter: char) -> Vec<String> {
+    match parse_missing(s) {
+        Some(s) => s.split(delimiter).map(String::from).collect(),
+        None => Vec::new(),
+    }
+}
+
+fn parse_item<T: FromStr>(key: &str, s: &str) -> io::Result<Option<T>> {
+    match parse_missing(s) {
+        Some(v) => v
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid_data(format!("invalid value for {}: {}", key, v))),
+        None => Ok(None),
+    }
+}
+
+/// Parses a raw INFO or FORMAT value according to its definition. A missing scalar value
+/// returns `None`, while missing elements of a list are kept as `None` entries.
+fn parse_value(definition: Option<&Definition>, s: &str) -> io::Result<Option<VcfValue>> {
+    let definition = match definition {
+        Some(d) => d,
+        None if s == MISSING => return Ok(None),
+        None if s.contains(',') => {
+            let values = s.split(',').map(|v| parse_missing(v).map(String::from));
+            return Ok(Some(VcfValue::StringArray(values.collect())));
+        }
+        None => return Ok(Some(VcfValue::String(s.to_string()))),
+    };
+
+    let key = definition.id.as_str();
+
+    if definition.ty == ValueType::Flag {
+        return Ok(Some(VcfValue::Flag(true)));
+    }
+
+    if definition.is_scalar() {
+        let value = match definition.ty {
+            ValueType::Integer => parse_item(key, s)?.map(VcfValue::Integer),
+            ValueType::Float => parse_item(key, s)?.map(VcfValue::Float),
+            _ => parse_missing(s).map(|v| VcfValue::String(v.to_string())),
+        };
+        return Ok(value);
+    }
+
+    if s == MISSING {
+        return Ok(None);
+    }
+
+    let items = s.split(',');
+    let value = match definition.ty {
+        ValueType::Integer => VcfValue::IntegerArray(
+            items
+                .map(|v| parse_item(key, v))
+                .collect::<io::Result<_>>()?,
+        ),
+        ValueType::Float => VcfValue::FloatArray(
+            items
+                .map(|v| parse_item(key, v))
+                .collect::<io::Result<_>>()?,
+        ),
+        _ => VcfValue::StringArray(items.map(|v| parse_missing(v).map(String::from)).collect()),
+    };
+
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "##fileformat=VCFv4.3
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total depth, all samples\">
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP membership\">
+##FILTER=<ID=q10,Description=\"Quality below 10\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allelic depths\">
+##contig=<ID=sq0,length=8>
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1\tNA2
+";
+
+    #[test]
+    fn test_parse_header() {
+        let header: Header = HEADER.parse().unwrap();
+
+        assert_eq!(header.infos.len(), 3);
+        assert_eq!(
+            header.info("DP").unwrap().description,
+            "Total depth, all samples"
+        );
+        assert_eq!(header.info("AF").unwrap().number, Number::A);
+        assert_eq!(header.format("AD").unwrap().ty, ValueType::Integer);
+        assert_eq!(header.sample_names, vec!["NA1", "NA2"]);
+        assert_eq!(header.contigs, vec!["sq0"]);
+        assert_eq!(
+            header.string_dictionary,
+            vec!["PASS", "DP", "AF", "DB", "q10", "GT", "AD"]
+        );
+    }
+
+    #[test]
+    fn test_parse_record() {
+        let header: Header = HEADER.parse().unwrap();
+        let record = parse_record(
+            &header,
+            "sq0\t1\t.\tA\tC,G\t13.5\tPASS\tDP=8;AF=0.5,.;DB\tGT:AD\t0/1:3,5,0\t./.:.",
+        )
+        .unwrap();
+
+        assert_eq!(record.id, None);
+        assert_eq!(record.alternate, vec!["C", "G"]);
+        assert_eq!(record.quality, Some(13.5));
+        assert_eq!(record.filter, vec!["PASS"]);
+        assert_eq!(record.info["DP"], VcfValue::Integer(8));
+        assert_eq!(
+            record.info["AF"],
+            VcfValue::FloatArray(vec![Some(0.5), None])
+        );
+        assert_eq!(record.info["DB"], VcfValue::Flag(true));
+        assert_eq!(
+            record.samples["NA1"]["AD"],
+            VcfValue::IntegerArray(vec![Some(3), Some(5), Some(0)])
+        );
+        assert_eq!(record.samples["NA2"]["GT"], VcfValue::String("./.".into()));
+        assert!(!record.samples["NA2"].contains_key("AD"));
+    }
+}
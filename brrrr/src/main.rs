@@ -2,17 +2,32 @@
 // All Rights Reserved
 
 use std::fs::File;
-use std::io::{stdin, stdout, BufReader};
-use std::path::PathBuf;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 
 use clap::{Parser, Subcommand};
 
+use brrrr_lib::arrow_reader;
+use brrrr_lib::arrow_writer::{self, IpcFormat};
+use brrrr_lib::bed;
+use brrrr_lib::columns::{ColumnMap, Template};
+use brrrr_lib::convert;
+use brrrr_lib::csv_reader;
 use brrrr_lib::csv_writer;
 use brrrr_lib::errors::BrrrrError;
+use brrrr_lib::filter::{self, Predicate, RecordFilter};
+use brrrr_lib::gff;
+use brrrr_lib::input::{self, BioFileCompression};
+use brrrr_lib::json_reader;
 use brrrr_lib::json_writer;
+use brrrr_lib::output::{self, OutputCompression};
+use brrrr_lib::paired;
 use brrrr_lib::parquet_reader;
-use brrrr_lib::parquet_writer;
-use parquet::basic::Compression;
+use brrrr_lib::parquet_writer::{self, ParquetOptions};
+use brrrr_lib::quality;
+use brrrr_lib::rejects::{ErrorPolicy, Rejects};
+use parquet::basic::{Compression, Encoding};
 
 /// The Enum that represents the underlying command-line tool.
 #[derive(Parser)]
@@ -25,8 +40,11 @@ use parquet::basic::Compression;
 struct Cli {
     #[clap(subcommand)]
     command: Brrrr,
+    #[clap(flatten)]
+    errors: ErrorArgs,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(clap::ValueEnum, Clone)]
 enum ParquetCompression {
     UNCOMPRESSED,
@@ -38,9 +56,9 @@ enum ParquetCompression {
     ZSTD,
 }
 
-impl Into<Compression> for ParquetCompression {
-    fn into(self) -> Compression {
-        match self {
+impl From<ParquetCompression> for Compression {
+    fn from(compression: ParquetCompression) -> Compression {
+        match compression {
             ParquetCompression::UNCOMPRESSED => Compression::UNCOMPRESSED,
             ParquetCompression::GZIP => Compression::GZIP,
             ParquetCompression::BROTLI => Compression::BROTLI,
@@ -52,187 +70,1681 @@ impl Into<Compression> for ParquetCompression {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(clap::ValueEnum, Clone)]
 enum CliBioFileCompression {
     UNCOMPRESSED,
     GZIP,
+    BGZF,
+    ZSTD,
+    BZIP2,
+    XZ,
+}
+
+impl From<CliBioFileCompression> for BioFileCompression {
+    fn from(compression: CliBioFileCompression) -> BioFileCompression {
+        match compression {
+            CliBioFileCompression::UNCOMPRESSED => BioFileCompression::UNCOMPRESSED,
+            CliBioFileCompression::GZIP => BioFileCompression::GZIP,
+            CliBioFileCompression::BGZF => BioFileCompression::BGZF,
+            CliBioFileCompression::ZSTD => BioFileCompression::ZSTD,
+            CliBioFileCompression::BZIP2 => BioFileCompression::BZIP2,
+            CliBioFileCompression::XZ => BioFileCompression::XZ,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct InputArgs {
+    /// The bio file compression, detected from the input if not given.
+    #[clap(short, long, value_enum)]
+    input_compression: Option<CliBioFileCompression>,
+}
+
+impl InputArgs {
+    fn compression(&self) -> Option<BioFileCompression> {
+        self.input_compression.clone().map(BioFileCompression::from)
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(clap::ValueEnum, Clone)]
+enum CliOutputCompression {
+    UNCOMPRESSED,
+    GZIP,
+    BGZF,
+    ZSTD,
+}
+
+impl From<CliOutputCompression> for OutputCompression {
+    fn from(compression: CliOutputCompression) -> OutputCompression {
+        match compression {
+            CliOutputCompression::UNCOMPRESSED => OutputCompression::UNCOMPRESSED,
+            CliOutputCompression::GZIP => OutputCompression::GZIP,
+            CliOutputCompression::BGZF => OutputCompression::BGZF,
+            CliOutputCompression::ZSTD => OutputCompression::ZSTD,
+        }
+    }
+}
+
+/// The options for where jsonl and csv output is written.
+#[derive(clap::Args)]
+struct OutputArgs {
+    /// The path where the output should be written to, or `-` for stdout.
+    #[clap(default_value = "-")]
+    output_file_name: PathBuf,
+    /// The output compression, inferred from the output extension if not given.
+    #[clap(long, value_enum)]
+    output_compression: Option<CliOutputCompression>,
+}
+
+impl OutputArgs {
+    /// Opens the output, runs `f` against it and finishes the compressed stream.
+    fn write_with<F>(self, f: F) -> Result<(), BrrrrError>
+    where
+        F: FnOnce(&mut output::Writer<Box<dyn Write>>) -> Result<(), BrrrrError>,
+    {
+        let compression = match self.output_compression {
+            Some(compression) => compression.into(),
+            None => OutputCompression::from_path(&self.output_file_name),
+        };
+
+        let inner: Box<dyn Write> =
+            Box::new(BufWriter::new(output::create_raw(&self.output_file_name)?));
+
+        let mut writer = output::Writer::new(inner, compression)?;
+        f(&mut writer)?;
+
+        match writer.finish() {
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+            Err(e) => Err(e.into()),
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone)]
+enum CliErrorPolicy {
+    Strict,
+    Skip,
+    SkipAndLog,
+}
+
+impl From<CliErrorPolicy> for ErrorPolicy {
+    fn from(policy: CliErrorPolicy) -> ErrorPolicy {
+        match policy {
+            CliErrorPolicy::Strict => ErrorPolicy::Strict,
+            CliErrorPolicy::Skip => ErrorPolicy::Skip,
+            CliErrorPolicy::SkipAndLog => ErrorPolicy::SkipAndLog,
+        }
+    }
+}
+
+/// The options for what converters do with records that fail to parse.
+#[derive(clap::Args)]
+struct ErrorArgs {
+    /// Stop at the first record that fails to parse, skip it, or skip it and write it to the
+    /// reject file.
+    #[clap(long, global = true, value_enum, default_value = "strict")]
+    on_error: CliErrorPolicy,
+    /// The path to write rejected records and their errors to as jsonl, for skip-and-log.
+    #[clap(long, global = true, parse(from_os_str))]
+    reject_file: Option<PathBuf>,
+}
+
+impl ErrorArgs {
+    /// Returns the Rejects for the policy, with the reject file as its log.
+    fn rejects(self) -> Result<Rejects, BrrrrError> {
+        let policy = ErrorPolicy::from(self.on_error);
+
+        match (policy, self.reject_file) {
+            (ErrorPolicy::SkipAndLog, Some(path)) => {
+                let log = BufWriter::new(File::create(path)?);
+                Ok(Rejects::new(policy).with_log(log))
+            }
+            (ErrorPolicy::SkipAndLog, None) => Err(BrrrrError::IOError(io::Error::new(
+                ErrorKind::InvalidInput,
+                "--on-error skip-and-log needs a --reject-file",
+            ))),
+            (policy, _) => Ok(Rejects::new(policy)),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(clap::ValueEnum, Clone)]
+enum CliIpcFormat {
+    FILE,
+    STREAM,
+}
+
+impl From<CliIpcFormat> for IpcFormat {
+    fn from(format: CliIpcFormat) -> IpcFormat {
+        match format {
+            CliIpcFormat::FILE => IpcFormat::FILE,
+            CliIpcFormat::STREAM => IpcFormat::STREAM,
+        }
+    }
+}
+
+/// The options for where Arrow IPC output is written.
+#[derive(clap::Args)]
+struct ArrowOutputArgs {
+    /// The path where the output should be written to, or `-` for stdout.
+    #[clap(default_value = "-")]
+    output_file_name: PathBuf,
+    /// The IPC format, stream for stdout and file for a path if not given.
+    #[clap(long, value_enum)]
+    ipc_format: Option<CliIpcFormat>,
+}
+
+impl ArrowOutputArgs {
+    /// Opens the output and runs `f` against it with the IPC format to write.
+    fn write_with<F>(self, f: F) -> Result<(), BrrrrError>
+    where
+        F: FnOnce(&mut Box<dyn Write>, IpcFormat) -> Result<(), BrrrrError>,
+    {
+        let format = match self.ipc_format {
+            Some(format) => format.into(),
+            None if input::is_stdio(&self.output_file_name) => IpcFormat::STREAM,
+            None => IpcFormat::FILE,
+        };
+
+        let mut writer: Box<dyn Write> =
+            Box::new(BufWriter::new(output::create_raw(&self.output_file_name)?));
+
+        f(&mut writer, format)?;
+
+        match writer.flush() {
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+            Err(e) => Err(e.into()),
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(clap::ValueEnum, Clone)]
+enum CliFormat {
+    FASTA,
+    FASTQ,
+    GFF,
+    GTF,
+    BAM,
+    PARQUET,
+    JSONL,
+    CSV,
+}
+
+impl From<CliFormat> for convert::Format {
+    fn from(format: CliFormat) -> convert::Format {
+        match format {
+            CliFormat::FASTA => convert::Format::Fasta,
+            CliFormat::FASTQ => convert::Format::Fastq,
+            CliFormat::GFF => convert::Format::Gff,
+            CliFormat::GTF => convert::Format::Gtf,
+            CliFormat::BAM => convert::Format::Bam,
+            CliFormat::PARQUET => convert::Format::Parquet,
+            CliFormat::JSONL => convert::Format::Jsonl,
+            CliFormat::CSV => convert::Format::Csv,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone)]
+enum CliBedKind {
+    #[clap(name = "bed")]
+    Bed,
+    #[clap(name = "bedgraph")]
+    BedGraph,
+}
+
+impl From<CliBedKind> for bed::BedFormat {
+    fn from(kind: CliBedKind) -> bed::BedFormat {
+        match kind {
+            CliBedKind::Bed => bed::BedFormat::Bed,
+            CliBedKind::BedGraph => bed::BedFormat::BedGraph,
+        }
+    }
+}
+
+/// Returns the BED format given by `kind`, falling back to the extension of `input`.
+fn bed_format(kind: Option<CliBedKind>, input: &Path) -> Option<bed::BedFormat> {
+    kind.map(Into::into)
+        .or_else(|| bed::BedFormat::from_extension(input))
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(clap::ValueEnum, Clone)]
+enum CliGffType {
+    GFF3,
+    GFF2,
+    GTF,
+}
+
+impl From<CliGffType> for gff::Dialect {
+    fn from(gff_type: CliGffType) -> gff::Dialect {
+        match gff_type {
+            CliGffType::GFF3 => gff::Dialect::Gff3,
+            CliGffType::GFF2 | CliGffType::GTF => gff::Dialect::Gtf,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone)]
+enum CliPhredOffset {
+    #[clap(name = "33")]
+    Phred33,
+    #[clap(name = "64")]
+    Phred64,
+}
+
+impl From<CliPhredOffset> for quality::PhredOffset {
+    fn from(offset: CliPhredOffset) -> quality::PhredOffset {
+        match offset {
+            CliPhredOffset::Phred33 => quality::PhredOffset::Phred33,
+            CliPhredOffset::Phred64 => quality::PhredOffset::Phred64,
+        }
+    }
+}
+
+/// The options for how FASTQ quality scores are written.
+#[derive(clap::Args)]
+struct QualityArgs {
+    /// Write the quality as a list of Phred scores rather than an ASCII string.
+    #[clap(long)]
+    phred_scores: bool,
+    /// The ASCII offset of the input quality scores.
+    #[clap(long, value_enum, default_value = "33")]
+    phred_offset: CliPhredOffset,
+    /// Add mean_quality, min_quality and expected_errors columns.
+    #[clap(long)]
+    quality_summary: bool,
 }
 
-impl Into<parquet_writer::BioFileCompression> for CliBioFileCompression {
-    fn into(self) -> parquet_writer::BioFileCompression {
-        match self {
-            CliBioFileCompression::UNCOMPRESSED => parquet_writer::BioFileCompression::UNCOMPRESSED,
-            CliBioFileCompression::GZIP => parquet_writer::BioFileCompression::GZIP,
+impl From<QualityArgs> for quality::QualityOptions {
+    fn from(args: QualityArgs) -> quality::QualityOptions {
+        quality::QualityOptions {
+            phred_scores: args.phred_scores,
+            offset: args.phred_offset.into(),
+            summary: args.quality_summary,
         }
     }
 }
 
+/// The options for which columns GFF record fields are read from.
+#[derive(clap::Args)]
+struct GffColumnArgs {
+    /// The column a GFF field is read from, e.g. `seqname=chrom`. May be given more than once.
+    #[clap(long = "col", value_name = "FIELD=COLUMN", parse(try_from_str = parse_gff_column))]
+    cols: Vec<(String, String)>,
+}
+
+impl From<GffColumnArgs> for ColumnMap {
+    fn from(args: GffColumnArgs) -> ColumnMap {
+        args.cols
+            .into_iter()
+            .fold(ColumnMap::new(), |columns, (field, column)| {
+                columns.with_column(&field, &column)
+            })
+    }
+}
+
+/// The options for which columns FASTA record fields are read from.
+#[derive(clap::Args)]
+struct FastaColumnArgs {
+    /// The column the record ID is read from.
+    #[clap(long)]
+    id_col: Option<String>,
+    /// The column the sequence is read from.
+    #[clap(long)]
+    seq_col: Option<String>,
+    /// The column the description is read from.
+    #[clap(long)]
+    desc_col: Option<String>,
+}
+
+impl From<FastaColumnArgs> for ColumnMap {
+    fn from(args: FastaColumnArgs) -> ColumnMap {
+        with_columns(
+            ColumnMap::new(),
+            [
+                ("id", args.id_col),
+                ("sequence", args.seq_col),
+                ("description", args.desc_col),
+            ],
+        )
+    }
+}
+
+/// The options for which columns FASTQ record fields are read from.
+#[derive(clap::Args)]
+struct FastqColumnArgs {
+    #[clap(flatten)]
+    fasta: FastaColumnArgs,
+    /// The column the quality is read from.
+    #[clap(long)]
+    qual_col: Option<String>,
+}
+
+impl From<FastqColumnArgs> for ColumnMap {
+    fn from(args: FastqColumnArgs) -> ColumnMap {
+        with_columns(args.fasta.into(), [("quality", args.qual_col)])
+    }
+}
+
+/// Maps each field that was given a column in `named` onto `columns`.
+fn with_columns<const N: usize>(
+    columns: ColumnMap,
+    named: [(&str, Option<String>); N],
+) -> ColumnMap {
+    named
+        .into_iter()
+        .filter_map(|(field, column)| Some((field, column?)))
+        .fold(columns, |columns, (field, column)| {
+            columns.with_column(field, &column)
+        })
+}
+
+/// The options for how a parquet file is read.
+#[derive(clap::Args)]
+struct ParquetReadArgs {
+    /// The number of rows in each record batch.
+    #[clap(long, default_value_t = parquet_reader::DEFAULT_BATCH_SIZE)]
+    batch_size: usize,
+    /// The number of row groups to decode at once, each on its own thread.
+    #[clap(long, default_value_t = 1)]
+    threads: usize,
+    /// Only write the records with an ID listed in this file, one per line. For GFF, the ID is
+    /// the `ID` attribute.
+    #[clap(long, parse(from_os_str), validator = file_exists)]
+    ids: Option<PathBuf>,
+    /// Only write records at least this long.
+    #[clap(long)]
+    min_length: Option<usize>,
+    /// Only write records at most this long.
+    #[clap(long)]
+    max_length: Option<usize>,
+    /// Only write records whose columns match, e.g. `length >= 1000 and organism == 'E. coli'`.
+    #[clap(long = "where", value_name = "PREDICATE")]
+    predicate: Option<Predicate>,
+}
+
+impl TryFrom<ParquetReadArgs> for parquet_reader::ParquetReadOptions {
+    type Error = io::Error;
+
+    fn try_from(args: ParquetReadArgs) -> io::Result<parquet_reader::ParquetReadOptions> {
+        let ids = match args.ids {
+            Some(path) => Some(filter::read_ids(input::open(path, None)?)?),
+            None => None,
+        };
+
+        Ok(parquet_reader::ParquetReadOptions {
+            batch_size: args.batch_size,
+            threads: args.threads,
+            filter: RecordFilter {
+                ids,
+                min_length: args.min_length,
+                max_length: args.max_length,
+                predicate: args.predicate,
+            },
+        })
+    }
+}
+
+/// The options for how a parquet file is written.
+#[derive(clap::Args)]
+struct ParquetWriteArgs {
+    /// The maximum number of records per row group, which is also how many are held in memory
+    /// at once.
+    #[clap(long, default_value_t = parquet_writer::DEFAULT_ROW_GROUP_SIZE)]
+    row_group_size: usize,
+    /// The target size of a data page, in bytes.
+    #[clap(long, default_value_t = parquet_writer::DEFAULT_DATA_PAGE_SIZE)]
+    data_page_size: usize,
+    /// Don't dictionary encode the columns, unless set with `--column-dictionary`.
+    #[clap(long)]
+    no_dictionary: bool,
+    /// Whether a column is dictionary encoded, e.g. `id=false`. May be given more than once.
+    #[clap(
+        long,
+        value_name = "COLUMN=BOOL",
+        parse(try_from_str = parse_column_dictionary)
+    )]
+    column_dictionary: Vec<(String, bool)>,
+    /// The encoding of a column, e.g. `id=DELTA_BYTE_ARRAY`. May be given more than once.
+    #[clap(
+        long,
+        value_name = "COLUMN=ENCODING",
+        parse(try_from_str = parse_column_encoding)
+    )]
+    column_encoding: Vec<(String, Encoding)>,
+}
+
+impl ParquetWriteArgs {
+    fn options(self, compression: ParquetCompression) -> ParquetOptions {
+        ParquetOptions {
+            compression: compression.into(),
+            row_group_size: self.row_group_size,
+            data_page_size: self.data_page_size,
+            dictionary: !self.no_dictionary,
+            column_dictionary: self.column_dictionary.into_iter().collect(),
+            column_encodings: self.column_encoding.into_iter().collect(),
+        }
+    }
+}
+
+fn parse_column_dictionary(s: &str) -> Result<(String, bool), String> {
+    match s.split_once('=') {
+        Some((column, enabled)) if !column.is_empty() => match enabled.parse() {
+            Ok(enabled) => Ok((column.to_string(), enabled)),
+            Err(_) => Err(format!("expected true or false, got {}", enabled)),
+        },
+        _ => Err(format!("expected COLUMN=BOOL, got {}", s)),
+    }
+}
+
+fn parse_column_encoding(s: &str) -> Result<(String, Encoding), String> {
+    match s.split_once('=') {
+        Some((column, encoding)) if !column.is_empty() => {
+            let encoding = parquet_writer::parse_encoding(encoding).map_err(|e| e.to_string())?;
+            Ok((column.to_string(), encoding))
+        }
+        _ => Err(format!("expected COLUMN=ENCODING, got {}", s)),
+    }
+}
+
+/// The GFF record fields that `--col` can map.
+const GFF_FIELDS: [&str; 9] = [
+    "seqname",
+    "source",
+    "feature",
+    "start",
+    "end",
+    "score",
+    "strand",
+    "frame",
+    "attribute",
+];
+
+fn parse_gff_column(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((field, _)) if !field.is_empty() && !GFF_FIELDS.contains(&field) => Err(format!(
+            "unknown GFF field {}, expected one of {}",
+            field,
+            GFF_FIELDS.join(", ")
+        )),
+        Some((field, column)) if !field.is_empty() && !column.is_empty() => {
+            Ok((field.to_string(), column.to_string()))
+        }
+        _ => Err(format!("expected FIELD=COLUMN, got {}", s)),
+    }
+}
+
 fn file_exists(p: &str) -> Result<(), String> {
     if !PathBuf::from(p).exists() {
-        return Err(format!("File path {:?} does not exist", p));
+        Err(format!("File path {:?} does not exist", p))
+    } else {
+        Ok(())
+    }
+}
+
+fn file_exists_or_stdin(p: &str) -> Result<(), String> {
+    if input::is_stdio(p) {
+        Ok(())
     } else {
-        return Ok(());
+        file_exists(p)
     }
 }
+
 #[derive(Subcommand)]
 enum Brrrr {
-    #[clap(name = "fa2pq", about = "Converts a FASTA input to parquet.")]
-    Fa2pq {
+    #[clap(
+        name = "convert",
+        about = "Converts between formats, detecting the input format from its content."
+    )]
+    Convert {
         /// The path where the input should be read from.
         #[clap(validator = file_exists)]
         input_file_name: PathBuf,
         /// The path where the output should be written to.
         output_file_name: PathBuf,
+        /// The output format, inferred from the output extension if not given.
+        #[clap(long, value_enum)]
+        to: Option<CliFormat>,
+        /// The compression mode for parquet output.
+        #[clap(long, value_enum, default_value = "uncompressed")]
+        compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+    },
+    #[clap(name = "fa2pq", about = "Converts a FASTA input to parquet.")]
+    Fa2pq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
         /// The compression mode for the parquet.
         #[clap(short, long, value_enum, default_value = "uncompressed")]
         output_compression: ParquetCompression,
-        /// The bio file compression.
-        #[clap(short, long, value_enum, default_value = "uncompressed")]
-        input_compression: CliBioFileCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+        #[clap(flatten)]
+        input: InputArgs,
     },
     #[clap(name = "pq2fa", about = "Converts a parquet file to FASTA format.")]
     Pq2Fa {
-        /// The path where the input should be read from.
-        #[clap(validator = file_exists)]
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
         input_file_name: PathBuf,
-        /// The path where the output should be written to.
+        /// The path where the output should be written to, or `-` for stdout.
         output_file_name: PathBuf,
+        #[clap(flatten)]
+        columns: FastaColumnArgs,
+        /// Compose the description from other columns, e.g. `{organism} len={length}`.
+        #[clap(long, conflicts_with = "desc-col")]
+        desc_template: Option<Template>,
+        #[clap(flatten)]
+        read: ParquetReadArgs,
     },
     #[clap(name = "pq2fq", about = "Converts a parquet file to FASTQ format.")]
     Pq2Fq {
-        /// The path where the input should be read from.
-        #[clap(validator = file_exists)]
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
         input_file_name: PathBuf,
-        /// The path where the output should be written to.
+        /// The path where the output, or the R1 reads of a paired-end input, should be written to,
+        /// or `-` for stdout.
         output_file_name: PathBuf,
+        /// The path where the R2 reads of a paired-end input should be written to. A paired-end
+        /// input is read from the columns paired_fq2pq writes, so the column options and
+        /// --desc-template can't be given with it.
+        #[clap(
+            long,
+            parse(from_os_str),
+            conflicts_with_all = &["id-col", "seq-col", "desc-col", "qual-col", "desc-template"]
+        )]
+        r2_output: Option<PathBuf>,
+        #[clap(flatten)]
+        columns: FastqColumnArgs,
+        /// Compose the description from other columns, e.g. `{organism} len={length}`.
+        #[clap(long, conflicts_with = "desc-col")]
+        desc_template: Option<Template>,
+        #[clap(flatten)]
+        read: ParquetReadArgs,
     },
     #[clap(name = "fq2pq", about = "Converts a FASTQ input to parquet.")]
     Fq2pq {
-        /// The path where the input should be read from.
-        #[clap(validator = file_exists)]
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
         input_file_name: PathBuf,
-        /// The path where the output should be written to.
+        #[clap(flatten)]
+        input: InputArgs,
+        /// The path where the output should be written to, or `-` for stdout.
         output_file_name: PathBuf,
         /// The compression mode for the parquet.
         #[clap(value_enum, default_value = "uncompressed")]
         compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+        #[clap(flatten)]
+        quality: QualityArgs,
+        /// The R2 reads of a paired-end input, which write one row per fragment.
+        #[clap(long, validator = file_exists, conflicts_with = "interleaved")]
+        r2: Option<PathBuf>,
+        /// Read the input as interleaved paired-end reads, which write one row per fragment.
+        #[clap(long)]
+        interleaved: bool,
     },
     #[clap(name = "fa2jsonl", about = "Converts a FASTA input to jsonl.")]
     Fa2jsonl {
-        #[clap(parse(from_os_str))]
-        input: Option<PathBuf>,
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
     },
     #[clap(name = "gff2pq", about = "Converts a GFF-like input to parquet.")]
     Gff2pq {
-        /// The path where the input should be read from.
-        #[clap(validator = file_exists)]
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
         input_file_name: PathBuf,
-        /// The path where the output should be written to.
+        #[clap(flatten)]
+        input: InputArgs,
+        /// The path where the output should be written to, or `-` for stdout.
         output_file_name: PathBuf,
         /// The compression mode for the parquet.
         #[clap(value_enum, default_value = "uncompressed")]
         compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+        /// The GFF dialect of the input, detected from the input if not given.
+        #[clap(long, value_enum)]
+        gff_type: Option<CliGffType>,
+        /// The path to write the sequences after a ##FASTA directive to, as parquet.
+        #[clap(long, parse(from_os_str))]
+        fasta_output: Option<PathBuf>,
     },
     #[clap(name = "gff2jsonl", about = "Converts a GFF-like input to jsonl.")]
     Gff2jsonl {
-        #[clap(parse(from_os_str))]
-        input: Option<PathBuf>,
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        /// The GFF dialect of the input, detected from the input if not given.
+        #[clap(long, value_enum)]
+        gff_type: Option<CliGffType>,
+        /// The path to write the sequences after a ##FASTA directive to, as jsonl.
+        #[clap(long, parse(from_os_str))]
+        fasta_output: Option<PathBuf>,
     },
     #[clap(name = "fq2jsonl", about = "Converts a FASTQ input to jsonl.")]
     Fq2jsonl {
-        #[clap(parse(from_os_str))]
-        input: Option<PathBuf>,
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        quality: QualityArgs,
     },
     #[clap(name = "fa2csv", about = "Converts a FASTA input to csv.")]
     Fa2csv {
-        #[clap(parse(from_os_str))]
-        input: Option<PathBuf>,
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
     },
     #[clap(name = "fq2csv", about = "Converts a FASTQ input to csv.")]
     Fq2csv {
-        #[clap(parse(from_os_str))]
-        input: Option<PathBuf>,
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        quality: QualityArgs,
     },
     #[clap(name = "bam2jsonl", about = "Converts a BAM input to jsonl.")]
     Bam2jsonl {
-        #[clap(parse(from_os_str))]
-        input: Option<PathBuf>,
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+    #[clap(name = "cram2jsonl", about = "Converts a CRAM input to jsonl.")]
+    Cram2jsonl {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        output: OutputArgs,
+        /// The reference FASTA the CRAM was encoded against, with a .fai index next to it.
+        #[clap(long, parse(from_os_str))]
+        reference: PathBuf,
+    },
+    #[clap(name = "vcf2pq", about = "Converts a VCF input to parquet.")]
+    Vcf2pq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// The compression mode for the parquet.
+        #[clap(value_enum, default_value = "uncompressed")]
+        compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+    },
+    #[clap(name = "bcf2pq", about = "Converts a BCF input to parquet.")]
+    Bcf2pq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// The compression mode for the parquet.
+        #[clap(value_enum, default_value = "uncompressed")]
+        compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+    },
+    #[clap(name = "vcf2jsonl", about = "Converts a VCF input to jsonl.")]
+    Vcf2jsonl {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+    #[clap(name = "vcf2csv", about = "Converts a VCF input to csv.")]
+    Vcf2csv {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+    #[clap(
+        name = "bed2pq",
+        about = "Converts a BED or bedGraph input to parquet."
+    )]
+    Bed2pq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// The compression mode for the parquet.
+        #[clap(value_enum, default_value = "uncompressed")]
+        compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+        /// Whether the input is BED or bedGraph. If not given, bedGraph is detected from a
+        /// `.bedgraph` or `.bg` extension or a `track type=bedGraph` line.
+        #[clap(long, value_enum)]
+        kind: Option<CliBedKind>,
+    },
+    #[clap(
+        name = "bed2jsonl",
+        about = "Converts a BED or bedGraph input to jsonl."
+    )]
+    Bed2jsonl {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        /// Whether the input is BED or bedGraph. If not given, bedGraph is detected from a
+        /// `.bedgraph` or `.bg` extension or a `track type=bedGraph` line.
+        #[clap(long, value_enum)]
+        kind: Option<CliBedKind>,
+    },
+    #[clap(name = "bed2csv", about = "Converts a BED or bedGraph input to csv.")]
+    Bed2csv {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        /// Whether the input is BED or bedGraph. If not given, bedGraph is detected from a
+        /// `.bedgraph` or `.bg` extension or a `track type=bedGraph` line.
+        #[clap(long, value_enum)]
+        kind: Option<CliBedKind>,
+    },
+    #[clap(name = "bam2pq", about = "Converts a BAM input to parquet.")]
+    Bam2pq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// The compression mode for the parquet.
+        #[clap(value_enum, default_value = "uncompressed")]
+        compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+    },
+    #[clap(name = "sam2pq", about = "Converts a SAM input to parquet.")]
+    Sam2pq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// The compression mode for the parquet.
+        #[clap(value_enum, default_value = "uncompressed")]
+        compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+    },
+    #[clap(name = "cram2pq", about = "Converts a CRAM input to parquet.")]
+    Cram2pq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// The reference FASTA the CRAM was encoded against, with a .fai index next to it.
+        #[clap(long, parse(from_os_str))]
+        reference: PathBuf,
+        /// The compression mode for the parquet.
+        #[clap(value_enum, default_value = "uncompressed")]
+        compression: ParquetCompression,
+        #[clap(flatten)]
+        write: ParquetWriteArgs,
+    },
+    #[clap(name = "pq2sam", about = "Converts a parquet file to SAM format.")]
+    Pq2Sam {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+    },
+    #[clap(name = "pq2bam", about = "Converts a parquet file to BAM format.")]
+    Pq2Bam {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// Also write a `.bai` index next to the output, which requires coordinate sorted input.
+        #[clap(long)]
+        index: bool,
+    },
+    #[clap(
+        name = "pq2bed",
+        about = "Converts a parquet file to BED or bedGraph format."
+    )]
+    Pq2Bed {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+    },
+    #[clap(
+        name = "pq2gff",
+        about = "Converts a parquet file to GFF3 or GTF format."
+    )]
+    Pq2Gff {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        /// The path where the output should be written to, or `-` for stdout.
+        output_file_name: PathBuf,
+        /// The GFF dialect to write, taken from the parquet metadata if not given.
+        #[clap(long, value_enum)]
+        gff_type: Option<CliGffType>,
+        #[clap(flatten)]
+        columns: GffColumnArgs,
+        #[clap(flatten)]
+        read: ParquetReadArgs,
+    },
+    #[clap(name = "fa2arrow", about = "Converts a FASTA input to Arrow IPC.")]
+    Fa2arrow {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: ArrowOutputArgs,
+    },
+    #[clap(name = "fq2arrow", about = "Converts a FASTQ input to Arrow IPC.")]
+    Fq2arrow {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: ArrowOutputArgs,
+        #[clap(flatten)]
+        quality: QualityArgs,
+    },
+    #[clap(name = "gff2arrow", about = "Converts a GFF-like input to Arrow IPC.")]
+    Gff2arrow {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: ArrowOutputArgs,
+        /// The GFF dialect of the input, detected from the input if not given.
+        #[clap(long, value_enum)]
+        gff_type: Option<CliGffType>,
+    },
+    #[clap(name = "bam2arrow", about = "Converts a BAM input to Arrow IPC.")]
+    Bam2arrow {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        output: ArrowOutputArgs,
+    },
+    #[clap(name = "arrow2fa", about = "Converts an Arrow IPC input to FASTA.")]
+    Arrow2fa {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+    #[clap(name = "arrow2fq", about = "Converts an Arrow IPC input to FASTQ.")]
+    Arrow2fq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+    #[clap(
+        name = "arrow2gff",
+        about = "Converts an Arrow IPC input to GFF3 or GTF."
+    )]
+    Arrow2gff {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        output: OutputArgs,
+        /// The GFF dialect to write, taken from the Arrow metadata if not given.
+        #[clap(long, value_enum)]
+        gff_type: Option<CliGffType>,
     },
+    #[clap(name = "jsonl2fa", about = "Converts a jsonl input to FASTA.")]
+    Jsonl2fa {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        columns: FastaColumnArgs,
+    },
+    #[clap(name = "jsonl2fq", about = "Converts a jsonl input to FASTQ.")]
+    Jsonl2fq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        columns: FastqColumnArgs,
+    },
+    #[clap(name = "jsonl2gff", about = "Converts a jsonl input to GFF3 or GTF.")]
+    Jsonl2gff {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        columns: GffColumnArgs,
+        /// The GFF dialect to write.
+        #[clap(long, value_enum, default_value = "gff3")]
+        gff_type: CliGffType,
+    },
+    #[clap(name = "csv2fa", about = "Converts a csv input to FASTA.")]
+    Csv2fa {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        columns: FastaColumnArgs,
+    },
+    #[clap(name = "csv2fq", about = "Converts a csv input to FASTQ.")]
+    Csv2fq {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        columns: FastqColumnArgs,
+    },
+    #[clap(name = "csv2gff", about = "Converts a csv input to GFF3 or GTF.")]
+    Csv2gff {
+        /// The path where the input should be read from, or `-` for stdin.
+        #[clap(default_value = "-", validator = file_exists_or_stdin)]
+        input_file_name: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+        #[clap(flatten)]
+        columns: GffColumnArgs,
+        /// The GFF dialect to write.
+        #[clap(long, value_enum, default_value = "gff3")]
+        gff_type: CliGffType,
+    },
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
 }
 
-fn main() -> Result<(), BrrrrError> {
+fn run() -> Result<(), BrrrrError> {
     let args = Cli::parse();
+    let mut rejects = args.errors.rejects()?;
 
-    match args.command {
+    let result = match args.command {
+        Brrrr::Convert {
+            input_file_name,
+            output_file_name,
+            to,
+            compression,
+            write,
+        } => convert::convert(
+            input_file_name,
+            output_file_name,
+            to.map(convert::Format::from),
+            &write.options(compression),
+            &mut rejects,
+        ),
         Brrrr::Fa2pq {
             input_file_name,
             output_file_name,
             output_compression,
-            input_compression,
+            write,
+            input,
         } => parquet_writer::fa2pq(
             &input_file_name,
             &output_file_name,
-            output_compression.into(),
-            input_compression.into(),
+            &write.options(output_compression),
+            input.compression(),
+            &mut rejects,
         ),
         Brrrr::Pq2Fa {
             input_file_name,
             output_file_name,
-        } => parquet_reader::pq2fa(input_file_name, output_file_name),
+            columns,
+            desc_template,
+            read,
+        } => parquet_reader::pq2fa(
+            input_file_name,
+            output_file_name,
+            &columns.into(),
+            desc_template.as_ref(),
+            &read.try_into()?,
+            &mut rejects,
+        ),
         Brrrr::Pq2Fq {
             input_file_name,
             output_file_name,
-        } => parquet_reader::pq2fq(input_file_name, output_file_name),
+            r2_output,
+            columns,
+            desc_template,
+            read,
+        } => {
+            let options = read.try_into()?;
+
+            match r2_output {
+                None => parquet_reader::pq2fq(
+                    input_file_name,
+                    output_file_name,
+                    &columns.into(),
+                    desc_template.as_ref(),
+                    &options,
+                    &mut rejects,
+                ),
+                Some(r2_output) => parquet_reader::paired_pq2fq(
+                    input_file_name,
+                    output_file_name,
+                    r2_output,
+                    &options,
+                    &mut rejects,
+                ),
+            }
+        }
         Brrrr::Fq2pq {
             input_file_name,
+            input,
             output_file_name,
             compression,
-        } => parquet_writer::fq2pq(input_file_name, output_file_name, compression.into()),
-        Brrrr::Fa2csv { input } => match input {
-            None => csv_writer::fa2csv(stdin().lock(), &mut stdout()),
-            Some(input) => {
-                let f = File::open(input)?;
-                csv_writer::fa2csv(BufReader::new(f), &mut stdout())
-            }
-        },
-        Brrrr::Fq2csv { input } => match input {
-            None => csv_writer::fq2csv(stdin().lock(), &mut stdout()),
-            Some(input) => {
-                let f = File::open(input)?;
-                csv_writer::fq2csv(BufReader::new(f), &mut stdout())
-            }
-        },
-        Brrrr::Bam2jsonl { input } => if let Some(input) = input {
-            let f = File::open(input)?;
-            json_writer::bam2jsonl(BufReader::new(f), &mut stdout())
-        } else {
-            json_writer::bam2jsonl(stdin().lock(), &mut stdout())
-        },
-        Brrrr::Fa2jsonl { input } => match input {
-            None => json_writer::fa2jsonl(stdin().lock(), &mut stdout()),
-            Some(input) => {
-                let f = File::open(input)?;
-                json_writer::fa2jsonl(BufReader::new(f), &mut stdout())
-            }
-        },
-        Brrrr::Gff2jsonl { input } => match input {
-            None => json_writer::gff2jsonl(stdin().lock(), &mut stdout()),
-            Some(input) => {
-                let f = File::open(input)?;
-                json_writer::gff2jsonl(BufReader::new(f), &mut stdout())
+            write,
+            quality,
+            r2,
+            interleaved,
+        } => {
+            let options = write.options(compression);
+            let input_compression = input.compression();
+            let reader = match r2 {
+                Some(r2) => Some(paired::Reader::open(
+                    &input_file_name,
+                    &r2,
+                    input_compression,
+                )?),
+                None if interleaved => Some(paired::Reader::open_interleaved(
+                    &input_file_name,
+                    input_compression,
+                )?),
+                None => None,
+            };
+
+            match reader {
+                Some(reader) => parquet_writer::paired_fq2pq(
+                    reader,
+                    output_file_name,
+                    &options,
+                    &quality.into(),
+                    &mut rejects,
+                ),
+                None => parquet_writer::fq2pq(
+                    input_file_name,
+                    output_file_name,
+                    &options,
+                    &quality.into(),
+                    input_compression,
+                    &mut rejects,
+                ),
             }
-        },
+        }
+        Brrrr::Fa2csv {
+            input_file_name,
+            input,
+            output,
+        } => output.write_with(|out| {
+            csv_writer::fa2csv(
+                input::open_raw(&input_file_name)?,
+                out,
+                input.compression(),
+                &mut rejects,
+            )
+        }),
+        Brrrr::Fq2csv {
+            input_file_name,
+            input,
+            output,
+            quality,
+        } => {
+            let quality_options = quality.into();
+            output.write_with(|out| {
+                csv_writer::fq2csv(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    &quality_options,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Bam2jsonl {
+            input_file_name,
+            output,
+        } => output.write_with(|out| {
+            json_writer::bam2jsonl(input::open_raw(&input_file_name)?, out, &mut rejects)
+        }),
+        Brrrr::Cram2jsonl {
+            input_file_name,
+            output,
+            reference,
+        } => output.write_with(|out| {
+            json_writer::cram2jsonl(
+                input::open_raw(&input_file_name)?,
+                reference,
+                out,
+                &mut rejects,
+            )
+        }),
+        Brrrr::Fa2jsonl {
+            input_file_name,
+            input,
+            output,
+        } => output.write_with(|out| {
+            json_writer::fa2jsonl(
+                input::open_raw(&input_file_name)?,
+                out,
+                input.compression(),
+                &mut rejects,
+            )
+        }),
+        Brrrr::Gff2jsonl {
+            input_file_name,
+            input,
+            output,
+            gff_type,
+            fasta_output,
+        } => {
+            let dialect = gff_type.map(gff::Dialect::from);
+            let mut fasta_output = match fasta_output {
+                Some(path) => Some(BufWriter::new(File::create(path)?)),
+                None => None,
+            };
+            let fasta_output = fasta_output.as_mut().map(|w| w as &mut dyn Write);
+
+            output.write_with(|out| {
+                json_writer::gff2jsonl(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    dialect,
+                    fasta_output,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
         Brrrr::Gff2pq {
             input_file_name,
+            input,
             output_file_name,
             compression,
-        } => parquet_writer::gff2pq(input_file_name, output_file_name, compression.into()),
-        Brrrr::Fq2jsonl { input } => match input {
-            None => json_writer::fq2jsonl(stdin().lock(), &mut stdout()),
-            Some(input) => {
-                let f = File::open(input)?;
-                json_writer::fq2jsonl(BufReader::new(f), &mut stdout())
-            }
-        },
+            write,
+            gff_type,
+            fasta_output,
+        } => parquet_writer::gff2pq(
+            input_file_name,
+            output_file_name,
+            &write.options(compression),
+            gff_type.map(gff::Dialect::from),
+            fasta_output,
+            input.compression(),
+            &mut rejects,
+        ),
+        Brrrr::Fq2jsonl {
+            input_file_name,
+            input,
+            output,
+            quality,
+        } => {
+            let quality_options = quality.into();
+            output.write_with(|out| {
+                json_writer::fq2jsonl(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    &quality_options,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Vcf2pq {
+            input_file_name,
+            input,
+            output_file_name,
+            compression,
+            write,
+        } => parquet_writer::vcf2pq(
+            input_file_name,
+            output_file_name,
+            &write.options(compression),
+            input.compression(),
+            &mut rejects,
+        ),
+        Brrrr::Bcf2pq {
+            input_file_name,
+            output_file_name,
+            compression,
+            write,
+        } => parquet_writer::bcf2pq(
+            input_file_name,
+            output_file_name,
+            &write.options(compression),
+            &mut rejects,
+        ),
+        Brrrr::Vcf2jsonl {
+            input_file_name,
+            input,
+            output,
+        } => output.write_with(|out| {
+            json_writer::vcf2jsonl(
+                input::open_raw(&input_file_name)?,
+                out,
+                input.compression(),
+                &mut rejects,
+            )
+        }),
+        Brrrr::Vcf2csv {
+            input_file_name,
+            input,
+            output,
+        } => output.write_with(|out| {
+            csv_writer::vcf2csv(
+                input::open_raw(&input_file_name)?,
+                out,
+                input.compression(),
+                &mut rejects,
+            )
+        }),
+        Brrrr::Bed2pq {
+            input_file_name,
+            input,
+            output_file_name,
+            compression,
+            write,
+            kind,
+        } => parquet_writer::bed2pq(
+            input_file_name,
+            output_file_name,
+            &write.options(compression),
+            kind.map(Into::into),
+            input.compression(),
+            &mut rejects,
+        ),
+        Brrrr::Bed2jsonl {
+            input_file_name,
+            input,
+            output,
+            kind,
+        } => output.write_with(|out| {
+            let format = bed_format(kind, &input_file_name);
+            json_writer::bed2jsonl(
+                input::open_raw(&input_file_name)?,
+                out,
+                format,
+                input.compression(),
+                &mut rejects,
+            )
+        }),
+        Brrrr::Bed2csv {
+            input_file_name,
+            input,
+            output,
+            kind,
+        } => output.write_with(|out| {
+            let format = bed_format(kind, &input_file_name);
+            csv_writer::bed2csv(
+                input::open_raw(&input_file_name)?,
+                out,
+                format,
+                input.compression(),
+                &mut rejects,
+            )
+        }),
+        Brrrr::Bam2pq {
+            input_file_name,
+            output_file_name,
+            compression,
+            write,
+        } => parquet_writer::bam2pq(
+            input_file_name,
+            output_file_name,
+            &write.options(compression),
+            &mut rejects,
+        ),
+        Brrrr::Sam2pq {
+            input_file_name,
+            input,
+            output_file_name,
+            compression,
+            write,
+        } => parquet_writer::sam2pq(
+            input_file_name,
+            output_file_name,
+            &write.options(compression),
+            input.compression(),
+            &mut rejects,
+        ),
+        Brrrr::Cram2pq {
+            input_file_name,
+            output_file_name,
+            reference,
+            compression,
+            write,
+        } => parquet_writer::cram2pq(
+            input_file_name,
+            output_file_name,
+            reference,
+            &write.options(compression),
+            &mut rejects,
+        ),
+        Brrrr::Pq2Sam {
+            input_file_name,
+            output_file_name,
+        } => parquet_reader::pq2sam(input_file_name, output_file_name, &mut rejects),
+        Brrrr::Pq2Bam {
+            input_file_name,
+            output_file_name,
+            index,
+        } => parquet_reader::pq2bam(input_file_name, output_file_name, index, &mut rejects),
+        Brrrr::Pq2Bed {
+            input_file_name,
+            output_file_name,
+        } => parquet_reader::pq2bed(input_file_name, output_file_name, &mut rejects),
+        Brrrr::Pq2Gff {
+            input_file_name,
+            output_file_name,
+            gff_type,
+            columns,
+            read,
+        } => parquet_reader::pq2gff(
+            input_file_name,
+            output_file_name,
+            gff_type.map(gff::Dialect::from),
+            &columns.into(),
+            &read.try_into()?,
+            &mut rejects,
+        ),
+        Brrrr::Fa2arrow {
+            input_file_name,
+            input,
+            output,
+        } => output.write_with(|out, format| {
+            arrow_writer::fa2arrow(
+                input::open_raw(&input_file_name)?,
+                out,
+                format,
+                input.compression(),
+                &mut rejects,
+            )
+        }),
+        Brrrr::Fq2arrow {
+            input_file_name,
+            input,
+            output,
+            quality,
+        } => {
+            let quality_options = quality.into();
+            output.write_with(|out, format| {
+                arrow_writer::fq2arrow(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    format,
+                    &quality_options,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Gff2arrow {
+            input_file_name,
+            input,
+            output,
+            gff_type,
+        } => {
+            let dialect = gff_type.map(gff::Dialect::from);
+            output.write_with(|out, format| {
+                arrow_writer::gff2arrow(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    format,
+                    dialect,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Bam2arrow {
+            input_file_name,
+            output,
+        } => output.write_with(|out, format| {
+            arrow_writer::bam2arrow(
+                input::open_raw(&input_file_name)?,
+                out,
+                format,
+                &mut rejects,
+            )
+        }),
+        Brrrr::Arrow2fa {
+            input_file_name,
+            output,
+        } => output.write_with(|out| {
+            arrow_reader::arrow2fa(input::open_raw(&input_file_name)?, out, &mut rejects)
+        }),
+        Brrrr::Arrow2fq {
+            input_file_name,
+            output,
+        } => output.write_with(|out| {
+            arrow_reader::arrow2fq(input::open_raw(&input_file_name)?, out, &mut rejects)
+        }),
+        Brrrr::Arrow2gff {
+            input_file_name,
+            output,
+            gff_type,
+        } => {
+            let dialect = gff_type.map(gff::Dialect::from);
+            output.write_with(|out| {
+                arrow_reader::arrow2gff(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    dialect,
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Jsonl2fa {
+            input_file_name,
+            input,
+            output,
+            columns,
+        } => {
+            let columns = ColumnMap::from(columns);
+            output.write_with(|out| {
+                json_reader::jsonl2fa(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    &columns,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Jsonl2fq {
+            input_file_name,
+            input,
+            output,
+            columns,
+        } => {
+            let columns = ColumnMap::from(columns);
+            output.write_with(|out| {
+                json_reader::jsonl2fq(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    &columns,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Jsonl2gff {
+            input_file_name,
+            input,
+            output,
+            columns,
+            gff_type,
+        } => {
+            let columns = ColumnMap::from(columns);
+            let dialect = gff::Dialect::from(gff_type);
+            output.write_with(|out| {
+                json_reader::jsonl2gff(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    dialect,
+                    &columns,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Csv2fa {
+            input_file_name,
+            input,
+            output,
+            columns,
+        } => {
+            let columns = ColumnMap::from(columns);
+            output.write_with(|out| {
+                csv_reader::csv2fa(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    &columns,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Csv2fq {
+            input_file_name,
+            input,
+            output,
+            columns,
+        } => {
+            let columns = ColumnMap::from(columns);
+            output.write_with(|out| {
+                csv_reader::csv2fq(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    &columns,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+        Brrrr::Csv2gff {
+            input_file_name,
+            input,
+            output,
+            columns,
+            gff_type,
+        } => {
+            let columns = ColumnMap::from(columns);
+            let dialect = gff::Dialect::from(gff_type);
+            output.write_with(|out| {
+                csv_reader::csv2gff(
+                    input::open_raw(&input_file_name)?,
+                    out,
+                    dialect,
+                    &columns,
+                    input.compression(),
+                    &mut rejects,
+                )
+            })
+        }
+    };
+
+    if rejects.policy() != ErrorPolicy::Strict {
+        rejects.flush()?;
+        eprintln!("{}", rejects);
+    }
+
+    // A closed stdout, e.g. from `| head`, ends the output rather than failing it.
+    match result {
+        Err(BrrrrError::IOError(e)) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
     }
 }